@@ -0,0 +1,61 @@
+//! Sampling time for nodes with full 128-entry tables over rare parents, with
+//! the common configurations listed first, listed last, and listed last but
+//! reordered from a pilot run. Lookups stop at the first matching entry, so
+//! the first and third should be well ahead of the second.
+//!
+//! Run with `cargo bench --features bench`.
+
+use std::time::Instant;
+
+use wasm_inference::Node;
+use wasm_inference::benchmarking::{compile, order_entries, sample};
+
+const NUM_PARENTS: usize = 7;
+const NUM_CHILDREN: usize = 8;
+const NUM_SAMPLES: usize = 20_000;
+const PILOT_SAMPLES: usize = 2_000;
+
+/// Rare roots, each child conditioned on all of them. `with_uniform_cpt`
+/// lists the all-false configuration, by far the most common, first.
+fn network(common_first: bool) -> anyhow::Result<Vec<Node>> {
+    let parents: Vec<String> = (0..NUM_PARENTS).map(|i| format!("P{i}")).collect();
+    let parent_ids: Vec<&str> = parents.iter().map(String::as_str).collect();
+    let mut nodes: Vec<Node> = parents
+        .iter()
+        .map(|id| Node::with_prior(id.clone(), 0.05))
+        .collect();
+    for i in 0..NUM_CHILDREN {
+        let mut child = Node::with_uniform_cpt(format!("C{i}"), &parent_ids)?;
+        if !common_first {
+            child.cpt_entries.reverse();
+        }
+        nodes.push(child);
+    }
+    Ok(nodes)
+}
+
+fn main() -> anyhow::Result<()> {
+    let mut reordered = compile(&network(false)?)?;
+    let moved = order_entries(&mut reordered, PILOT_SAMPLES, 1)?;
+    let cases = [
+        ("common entries first", compile(&network(true)?)?),
+        ("common entries last", compile(&network(false)?)?),
+        ("common entries last, reordered", reordered),
+    ];
+    println!(
+        "{NUM_SAMPLES} samples, {NUM_CHILDREN} nodes of {} entries",
+        1 << NUM_PARENTS
+    );
+    println!("reordering moved entries of {moved} nodes");
+    for (name, compiled) in &cases {
+        // One untimed pass to warm the caches.
+        sample(compiled, NUM_SAMPLES / 10, 0)?;
+        let start = Instant::now();
+        let true_count = sample(compiled, NUM_SAMPLES, 0)?;
+        println!(
+            "{name:>32}: {:>8.2} ms ({true_count} true values)",
+            start.elapsed().as_secs_f64() * 1000.0
+        );
+    }
+    Ok(())
+}
@@ -0,0 +1,15 @@
+//! Arbitrary bytes handed to the compact loader must produce an error, never
+//! a panic, and whatever loads must re-encode stably.
+#![no_main]
+
+use libfuzzer_sys::fuzz_target;
+use wasm_inference::fuzzing::{from_compact, to_compact};
+
+fuzz_target!(|data: &[u8]| {
+    let Ok(nodes) = from_compact(data) else {
+        return;
+    };
+    let encoded = to_compact(&nodes);
+    let reloaded = from_compact(&encoded).expect("re-encoded networks load");
+    assert_eq!(to_compact(&reloaded), encoded);
+});
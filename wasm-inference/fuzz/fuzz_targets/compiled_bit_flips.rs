@@ -0,0 +1,49 @@
+//! Valid compiled buffers and compact blobs with bits flipped and bytes
+//! truncated: the samplers and the loader must reject them, never panic.
+#![no_main]
+
+use libfuzzer_sys::fuzz_target;
+use wasm_inference::fuzzing::{compile, from_compact, sample_buffer, to_compact};
+use wasm_inference::{HierarchicalParam, Node};
+
+/// A diamond with a hierarchical entry, so every record kind is present.
+fn network() -> Vec<Node> {
+    let mut b = Node::with_uniform_cpt("B".into(), &["A"]).expect("one parent");
+    b.cpt_entries[0].probability_params = Some(HierarchicalParam {
+        hyperparameter_id: "H".into(),
+        p_high: 0.9,
+        p_low: 0.2,
+    });
+    vec![
+        Node::with_prior("A".into(), 0.3),
+        Node::with_prior("H".into(), 0.6),
+        b,
+        Node::with_uniform_cpt("C".into(), &["A"]).expect("one parent"),
+        Node::with_uniform_cpt("D".into(), &["B", "C"]).expect("two parents"),
+    ]
+}
+
+/// Each pair of input bytes flips one bit; an odd trailing byte truncates.
+fn mutate(mut bytes: Vec<u8>, data: &[u8]) -> Vec<u8> {
+    let mut pairs = data.chunks_exact(2);
+    for pair in pairs.by_ref() {
+        let position = usize::from(pair[0]) % bytes.len();
+        bytes[position] ^= 1 << (pair[1] % 8);
+    }
+    if let [cut] = pairs.remainder() {
+        bytes.truncate(usize::from(*cut) % bytes.len());
+    }
+    bytes
+}
+
+fuzz_target!(|data: &[u8]| {
+    let nodes = network();
+    let (buffer, num_nodes) = compile(&nodes).expect("the base network compiles");
+    let _ = sample_buffer(&mutate(buffer, data), num_nodes);
+
+    if let Ok(nodes) = from_compact(&mutate(to_compact(&nodes), data))
+        && let Ok((buffer, num_nodes)) = compile(&nodes)
+    {
+        let _ = sample_buffer(&buffer, num_nodes);
+    }
+});
@@ -0,0 +1,45 @@
+//! Networks generated from the input must compile, sample, describe and
+//! survive the compact format unchanged.
+#![no_main]
+
+use libfuzzer_sys::fuzz_target;
+use std::collections::BTreeSet;
+use wasm_inference::Node;
+use wasm_inference::fuzzing::{compile, describe, from_compact, sample_buffer, to_compact};
+
+/// Up to 16 nodes, each with up to three earlier nodes as parents and a full
+/// table whose probabilities are read from the input.
+fn generate(data: &[u8]) -> Vec<Node> {
+    let mut bytes = data.iter().copied();
+    let mut next = move || bytes.next().unwrap_or(0);
+    let num_nodes = usize::from(next() % 16) + 1;
+    let mut nodes: Vec<Node> = Vec::with_capacity(num_nodes);
+    for index in 0..num_nodes {
+        let num_parents = if index == 0 { 0 } else { next() % 4 };
+        let parent_ids: Vec<String> = (0..num_parents)
+            .map(|_| nodes[usize::from(next()) % index].id.clone())
+            .collect::<BTreeSet<_>>()
+            .into_iter()
+            .collect();
+        let parent_ids: Vec<&str> = parent_ids.iter().map(String::as_str).collect();
+        let mut node = Node::with_uniform_cpt(format!("n{index}"), &parent_ids)
+            .expect("at most 3 parents");
+        for entry in &mut node.cpt_entries {
+            entry.probability = f64::from(next()) / 255.0;
+            entry.is_probability_of_true = next() % 2 == 0;
+        }
+        nodes.push(node);
+    }
+    nodes
+}
+
+fuzz_target!(|data: &[u8]| {
+    let nodes = generate(data);
+    let (buffer, num_nodes) = compile(&nodes).expect("generated networks compile");
+    sample_buffer(&buffer, num_nodes).expect("compiled networks sample");
+    describe(&nodes).expect("generated networks have complete tables");
+
+    let reloaded = from_compact(&to_compact(&nodes)).expect("encoded networks load");
+    let (reloaded_buffer, _) = compile(&reloaded).expect("reloaded networks compile");
+    assert_eq!(reloaded_buffer, buffer);
+});
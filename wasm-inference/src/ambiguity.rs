@@ -0,0 +1,151 @@
+//! How much the first-match rule matters: CPT entries that overlap are
+//! resolved by order, and a more specific entry listed after a wildcard one
+//! is never reached where they overlap.
+
+use anyhow::{Result, anyhow};
+use rand::{Rng, SeedableRng};
+use rand_xoshiro::Xoshiro128Plus;
+use serde::Serialize;
+
+use crate::lookup::find_node;
+use crate::marginals::estimate_marginals;
+use crate::serialize::serialize_network;
+use crate::{CptEntry, Node};
+
+#[derive(Serialize)]
+#[serde(rename_all = "camelCase")]
+pub struct NodeImpact {
+    pub node_id: String,
+    /// Target marginal with only this node's entries reordered.
+    pub reordered: f64,
+    pub shift: f64,
+}
+
+#[derive(Serialize)]
+#[serde(rename_all = "camelCase")]
+pub struct AmbiguityImpact {
+    /// Target marginal with every CPT resolved first-match as given.
+    pub baseline: f64,
+    /// Nodes where a more specific entry is shadowed by an earlier one with a
+    /// different probability.
+    pub ambiguous_nodes: Vec<String>,
+    /// Ambiguous nodes moving the target by more than the threshold, largest
+    /// shift first.
+    pub impacts: Vec<NodeImpact>,
+    /// Target marginal with every ambiguous node reordered at once.
+    pub all_reordered: f64,
+}
+
+/// Target marginal under first-match resolution as given against
+/// most-specific-first resolution, for each ambiguous node and for all of
+/// them together.
+///
+/// Most-specific-first sorts entries by how many parents they fix, keeping
+/// the given order among ties. Every run uses the same random numbers, so
+/// shifts reflect the reordering rather than sampling noise.
+pub fn ambiguity_impact(
+    nodes: &[Node],
+    target_node_id: &str,
+    num_samples: usize,
+    threshold: f64,
+    rng: &mut Xoshiro128Plus,
+) -> Result<AmbiguityImpact> {
+    find_node(nodes, "Target", target_node_id)?;
+    let common_seed: u64 = rng.random();
+    let target_marginal = |nodes: &[Node]| -> Result<f64> {
+        let mut stream = Xoshiro128Plus::seed_from_u64(common_seed);
+        let marginals = estimate_marginals(
+            &serialize_network(nodes)?,
+            num_samples,
+            &[],
+            &[],
+            &mut stream,
+        )?;
+        marginals
+            .get(target_node_id)
+            .copied()
+            .ok_or_else(|| anyhow!("Target node {target_node_id} not found"))
+    };
+    let baseline = target_marginal(nodes)?;
+
+    let ambiguous: Vec<usize> = (0..nodes.len())
+        .filter(|&i| is_ambiguous(&nodes[i].cpt_entries))
+        .collect();
+    let mut impacts = Vec::new();
+    let mut all = nodes.to_vec();
+    for &i in &ambiguous {
+        let mut reordered = nodes.to_vec();
+        most_specific_first(&mut reordered[i].cpt_entries);
+        most_specific_first(&mut all[i].cpt_entries);
+        let marginal = target_marginal(&reordered)?;
+        if (marginal - baseline).abs() > threshold {
+            impacts.push(NodeImpact {
+                node_id: nodes[i].id.clone(),
+                reordered: marginal,
+                shift: marginal - baseline,
+            });
+        }
+    }
+    impacts.sort_by(|a, b| b.shift.abs().total_cmp(&a.shift.abs()));
+
+    Ok(AmbiguityImpact {
+        baseline,
+        ambiguous_nodes: ambiguous.iter().map(|&i| nodes[i].id.clone()).collect(),
+        impacts,
+        all_reordered: if ambiguous.is_empty() {
+            baseline
+        } else {
+            target_marginal(&all)?
+        },
+    })
+}
+
+fn specificity(entry: &CptEntry) -> usize {
+    entry
+        .parent_states
+        .values()
+        .filter(|state| state.is_some())
+        .count()
+}
+
+fn most_specific_first(entries: &mut [CptEntry]) {
+    entries.sort_by_key(|entry| std::cmp::Reverse(specificity(entry)));
+}
+
+/// Whether some entry is shadowed, where it overlaps an earlier and less
+/// specific one, by a different probability.
+fn is_ambiguous(entries: &[CptEntry]) -> bool {
+    entries.iter().enumerate().any(|(j, later)| {
+        entries[..j].iter().any(|earlier| {
+            specificity(later) > specificity(earlier)
+                && overlaps(earlier, later)
+                && !same_probability(earlier, later)
+        })
+    })
+}
+
+/// Whether some parent assignment matches both entries.
+fn overlaps(a: &CptEntry, b: &CptEntry) -> bool {
+    a.parent_states.iter().all(|(parent, state)| {
+        match (state, b.parent_states.get(parent).copied().flatten()) {
+            (Some(a), Some(b)) => *a == b,
+            _ => true,
+        }
+    })
+}
+
+/// Exact comparison: entries copied from one another are what this is for.
+#[allow(clippy::float_cmp)]
+fn same_probability(a: &CptEntry, b: &CptEntry) -> bool {
+    match (&a.probability_params, &b.probability_params) {
+        (None, None) => a.probability_of_true() == b.probability_of_true(),
+        (Some(a_params), Some(b_params)) => {
+            a_params.hyperparameter_id == b_params.hyperparameter_id
+                && a.as_probability_of_true(a_params.p_high)
+                    == b.as_probability_of_true(b_params.p_high)
+                && a.as_probability_of_true(a_params.p_low)
+                    == b.as_probability_of_true(b_params.p_low)
+        }
+        _ => false,
+    }
+}
@@ -0,0 +1,115 @@
+use anyhow::{Result, bail};
+use rand::Rng;
+use rand_xoshiro::Xoshiro128Plus;
+
+use crate::Node;
+use crate::bit_set::BitSet;
+use crate::lookup::resolve;
+use crate::sample;
+use crate::serialize::serialize_network;
+
+/// Exponent of the annealing schedule `beta_t = (t / T)^POWER`, which spends
+/// more steps near the prior where the tempered targets change fastest.
+const SCHEDULE_POWER: f64 = 4.0;
+
+/// Estimates `log P(evidence)` with annealed importance sampling.
+///
+/// Chains start from forward samples of the prior and are annealed towards the
+/// posterior by tempering the evidence: at inverse temperature `beta`, an
+/// assignment that contradicts `k` evidence nodes keeps weight `(1 - beta)^k`.
+/// Each step applies one Gibbs sweep targeting the tempered distribution.
+pub fn ais(
+    nodes: &[Node],
+    evidence: &[(String, bool)],
+    num_chains: usize,
+    num_annealing_steps: usize,
+    rng: &mut Xoshiro128Plus,
+) -> Result<f64> {
+    if num_chains == 0 || num_annealing_steps == 0 {
+        bail!("AIS needs at least one chain and one annealing step");
+    }
+    let serialized = serialize_network(nodes)?;
+    let num_nodes = serialized.num_nodes();
+    let evidence: Vec<(u8, bool)> = evidence
+        .iter()
+        .map(|(node_id, value)| {
+            resolve(&serialized, "Evidence", node_id)
+                .map(|idx| (idx, *value))
+                .map_err(anyhow::Error::from)
+        })
+        .collect::<Result<_>>()?;
+
+    let log_tempered_evidence = |assignment: &BitSet, beta: f64| -> f64 {
+        let mismatches = evidence
+            .iter()
+            .filter(|&&(node, value)| assignment.contains(node) != value)
+            .count();
+        if mismatches == 0 {
+            0.0
+        } else {
+            #[allow(clippy::cast_precision_loss)]
+            let mismatches = mismatches as f64;
+            mismatches * (1.0 - beta).ln()
+        }
+    };
+
+    let mut log_weights = Vec::with_capacity(num_chains);
+    for _ in 0..num_chains {
+        let mut assignment = sample::sample(&serialized.data, &serialized.offsets, &[], rng)?;
+        let mut log_weight = 0.0;
+        let mut previous_beta = 0.0;
+
+        for step in 1..=num_annealing_steps {
+            #[allow(clippy::cast_precision_loss)]
+            let beta = (step as f64 / num_annealing_steps as f64).powf(SCHEDULE_POWER);
+            log_weight += log_tempered_evidence(&assignment, beta)
+                - log_tempered_evidence(&assignment, previous_beta);
+            if log_weight == f64::NEG_INFINITY {
+                break;
+            }
+            previous_beta = beta;
+
+            for node in 0..num_nodes {
+                let mut log_target = [0.0; 2];
+                for (value, log_target) in [false, true].into_iter().zip(&mut log_target) {
+                    if value {
+                        assignment.insert(node);
+                    } else {
+                        assignment.remove(node);
+                    }
+                    *log_target =
+                        sample::log_joint(&serialized.data, &serialized.offsets, &assignment)?
+                            + log_tempered_evidence(&assignment, beta);
+                }
+                let [log_false, log_true] = log_target;
+                let take_true = if log_true == f64::NEG_INFINITY {
+                    false
+                } else if log_false == f64::NEG_INFINITY {
+                    true
+                } else {
+                    let p_true = 1.0 / (1.0 + (log_false - log_true).exp());
+                    rng.random_bool(p_true)
+                };
+                if take_true {
+                    assignment.insert(node);
+                } else {
+                    assignment.remove(node);
+                }
+            }
+        }
+        log_weights.push(log_weight);
+    }
+
+    Ok(log_mean_exp(&log_weights))
+}
+
+fn log_mean_exp(log_values: &[f64]) -> f64 {
+    let max = log_values.iter().copied().fold(f64::NEG_INFINITY, f64::max);
+    if max == f64::NEG_INFINITY {
+        return f64::NEG_INFINITY;
+    }
+    let sum: f64 = log_values.iter().map(|v| (v - max).exp()).sum();
+    #[allow(clippy::cast_precision_loss)]
+    let count = log_values.len() as f64;
+    max + (sum / count).ln()
+}
@@ -0,0 +1,148 @@
+use anyhow::{Result, bail};
+use serde::{Deserialize, Serialize};
+use std::collections::{BTreeMap, BTreeSet};
+
+use crate::Node;
+use crate::lookup::{NodeNotFound, resolve, suggestions};
+use crate::sample::Override;
+use crate::serialize::SerializedNetwork;
+
+/// A named bundle of the assumptions a query is run under.
+///
+/// Interventions are hard `do()` settings, evidence conditions on observed
+/// values, and clamps replace a node's CPT with a fixed probability.
+#[derive(Clone, Default, PartialEq, Serialize, Deserialize)]
+#[serde(rename_all = "camelCase", default)]
+pub struct AssumptionSet {
+    pub name: Option<String>,
+    pub interventions: BTreeMap<String, bool>,
+    pub evidence: BTreeMap<String, bool>,
+    pub clamps: BTreeMap<String, f64>,
+}
+
+impl AssumptionSet {
+    /// Merges in the `observed` flags carried by the payload and checks that
+    /// every referenced node exists and is assumed at most one way.
+    ///
+    /// Explicit evidence takes precedence over a node's `observed` flag.
+    pub fn resolve(&self, nodes: &[Node]) -> Result<Self> {
+        let mut resolved = self.clone();
+        for node in nodes {
+            if let Some(observed) = node.observed {
+                resolved.evidence.entry(node.id.clone()).or_insert(observed);
+            }
+        }
+
+        let node_ids: BTreeSet<&str> = nodes.iter().map(|n| n.id.as_str()).collect();
+        let referenced: [(&'static str, Vec<&String>); 3] = [
+            ("Intervention", resolved.interventions.keys().collect()),
+            ("Evidence", resolved.evidence.keys().collect()),
+            ("Clamp", resolved.clamps.keys().collect()),
+        ];
+        for (role, ids) in referenced {
+            if let Some(node_id) = ids.into_iter().find(|id| !node_ids.contains(id.as_str())) {
+                return Err(NodeNotFound {
+                    role,
+                    node_id: node_id.clone(),
+                    suggestions: suggestions(node_id, node_ids.iter().copied()),
+                }
+                .into());
+            }
+        }
+        for node_id in resolved.clamps.keys() {
+            if resolved.interventions.contains_key(node_id) {
+                bail!("Node {node_id} is both intervened on and clamped");
+            }
+        }
+        for (node_id, &probability) in &resolved.clamps {
+            if !(0.0..=1.0).contains(&probability) {
+                bail!("Clamp for node {node_id} must be within [0, 1], got {probability}");
+            }
+        }
+        Ok(resolved)
+    }
+
+    pub(crate) fn overrides(
+        &self,
+        serialized: &SerializedNetwork,
+    ) -> Result<Vec<Option<Override>>> {
+        let mut overrides = vec![None; usize::from(serialized.num_nodes())];
+        for (node_id, &value) in &self.interventions {
+            overrides[usize::from(resolve(serialized, "Intervention", node_id)?)] =
+                Some(Override::Value(value));
+        }
+        for (node_id, &probability) in &self.clamps {
+            #[allow(clippy::cast_possible_truncation)]
+            let probability = probability as f32;
+            overrides[usize::from(resolve(serialized, "Clamp", node_id)?)] =
+                Some(Override::Probability(probability));
+        }
+        Ok(overrides)
+    }
+
+    pub(crate) fn evidence_indices(
+        &self,
+        serialized: &SerializedNetwork,
+    ) -> Result<Vec<(u8, bool)>> {
+        self.evidence
+            .iter()
+            .map(|(node_id, &value)| Ok((resolve(serialized, "Evidence", node_id)?, value)))
+            .collect()
+    }
+}
+
+#[derive(Serialize)]
+#[serde(rename_all = "camelCase")]
+pub struct AssumptionDiff {
+    pub name: Option<Change<String>>,
+    pub interventions: Vec<NodeChange<bool>>,
+    pub evidence: Vec<NodeChange<bool>>,
+    pub clamps: Vec<NodeChange<f64>>,
+}
+
+#[derive(Serialize)]
+pub struct Change<T> {
+    pub before: Option<T>,
+    pub after: Option<T>,
+}
+
+#[derive(Serialize)]
+#[serde(rename_all = "camelCase")]
+pub struct NodeChange<T> {
+    pub node_id: String,
+    pub before: Option<T>,
+    pub after: Option<T>,
+}
+
+/// Describes how `b` differs from `a`, listing only assumptions that were
+/// added, removed, or changed.
+pub fn diff_assumptions(a: &AssumptionSet, b: &AssumptionSet) -> AssumptionDiff {
+    AssumptionDiff {
+        name: (a.name != b.name).then(|| Change {
+            before: a.name.clone(),
+            after: b.name.clone(),
+        }),
+        interventions: diff_maps(&a.interventions, &b.interventions),
+        evidence: diff_maps(&a.evidence, &b.evidence),
+        clamps: diff_maps(&a.clamps, &b.clamps),
+    }
+}
+
+fn diff_maps<T: Copy + PartialEq>(
+    a: &BTreeMap<String, T>,
+    b: &BTreeMap<String, T>,
+) -> Vec<NodeChange<T>> {
+    let node_ids: BTreeSet<&String> = a.keys().chain(b.keys()).collect();
+    node_ids
+        .into_iter()
+        .filter_map(|node_id| {
+            let before = a.get(node_id).copied();
+            let after = b.get(node_id).copied();
+            (before != after).then(|| NodeChange {
+                node_id: node_id.clone(),
+                before,
+                after,
+            })
+        })
+        .collect()
+}
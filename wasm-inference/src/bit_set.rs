@@ -1,9 +1,13 @@
+#[derive(Clone, Copy, PartialEq, Eq)]
 pub(crate) struct BitSet([u8; 32]);
 
 impl BitSet {
     pub(crate) fn new() -> Self {
         Self([0; 32])
     }
+    pub(crate) fn as_bytes(&self) -> &[u8; 32] {
+        &self.0
+    }
     pub(crate) fn insert(&mut self, value: u8) -> bool {
         let byte_index = (value / 8) as usize;
         let bit_index = value % 8;
@@ -18,4 +22,18 @@ impl BitSet {
         let mask = 1 << bit_index;
         (self.0[byte_index] & mask) != 0
     }
+    pub(crate) fn remove(&mut self, value: u8) -> bool {
+        let byte_index = (value / 8) as usize;
+        let bit_index = value % 8;
+        let mask = 1 << bit_index;
+        let was_present = (self.0[byte_index] & mask) != 0;
+        self.0[byte_index] &= !mask;
+        was_present
+    }
+    pub(crate) fn symmetric_difference(&self, other: &Self) -> Self {
+        Self(std::array::from_fn(|i| self.0[i] ^ other.0[i]))
+    }
+    pub(crate) fn is_empty(&self) -> bool {
+        self.0.iter().all(|&byte| byte == 0)
+    }
 }
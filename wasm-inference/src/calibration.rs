@@ -0,0 +1,359 @@
+//! Checks a network's probabilities against data: baseline marginals against
+//! known base rates, and CPT predictions against observed rows.
+
+use anyhow::{Result, anyhow, bail};
+use rand::{Rng, SeedableRng};
+use rand_xoshiro::Xoshiro128Plus;
+use serde::Serialize;
+use std::collections::{BTreeSet, HashMap};
+
+use crate::Node;
+use crate::learning::DataRow;
+use crate::marginals::estimate_marginals;
+use crate::serialize::{get_node_parents, serialize_network};
+use crate::structure::ancestors;
+
+/// Half-width of the central difference used to estimate sensitivities.
+const STEP: f64 = 0.05;
+/// Upper end of a perturbed entry. `random_bool(1.0)` draws no random
+/// number, which would shift every later draw of the sample.
+const HIGHEST: f64 = 1.0 - 1e-6;
+/// Adjustments reported per calibrated node.
+const MAX_ADJUSTMENTS: usize = 3;
+/// Equal-width bins of predicted probability for the calibration error.
+const NUM_BINS: usize = 10;
+
+#[derive(Serialize)]
+#[serde(rename_all = "camelCase")]
+pub struct Adjustment {
+    pub node_id: String,
+    /// Index into the node's `cptEntries`.
+    pub entry_index: usize,
+    pub current: f64,
+    /// `P(true)` for the entry that closes the gap, to first order.
+    pub suggested: f64,
+    /// `d P(target) / d P(true)` of the entry.
+    pub sensitivity: f64,
+    /// Mean absolute error over every calibrated node after the change, to
+    /// first order.
+    pub predicted_mean_absolute_error: f64,
+}
+
+#[derive(Serialize)]
+#[serde(rename_all = "camelCase")]
+pub struct NodeCalibration {
+    pub node_id: String,
+    pub observed_frequency: f64,
+    pub estimate: f64,
+    pub absolute_error: f64,
+    /// Single-entry changes that close the gap, smallest change first.
+    /// Entries whose required value falls outside `[0, 1]` are left out.
+    pub adjustments: Vec<Adjustment>,
+}
+
+#[derive(Serialize)]
+#[serde(rename_all = "camelCase")]
+pub struct CalibrationReport {
+    /// One per calibrated node, largest error first.
+    pub nodes: Vec<NodeCalibration>,
+    pub mean_absolute_error: f64,
+}
+
+/// Compares baseline marginals with `observed_frequencies` (node ID to base
+/// rate).
+///
+/// Sensitivities of every calibrated node to every fixed CPT entry upstream
+/// of one are central differences of width `2 * STEP`, each side sampled with
+/// the same random numbers so the difference reflects the change rather than
+/// noise. This costs two runs of `num_samples` per such entry. Hierarchical
+/// entries are not single parameters and are never suggested.
+pub fn calibration_report(
+    nodes: &[Node],
+    observed_frequencies: &HashMap<String, f64>,
+    num_samples: usize,
+    rng: &mut Xoshiro128Plus,
+) -> Result<CalibrationReport> {
+    if observed_frequencies.is_empty() {
+        bail!("At least one observed frequency is required");
+    }
+    let serialized = serialize_network(nodes)?;
+    let mut upstream = BTreeSet::new();
+    for (node_id, &frequency) in observed_frequencies {
+        if !(0.0..=1.0).contains(&frequency) {
+            bail!("Observed frequency for {node_id} must be within [0, 1], got {frequency}");
+        }
+        let index = serialized
+            .index_of(node_id)
+            .ok_or_else(|| anyhow!("Calibrated node {node_id} not found"))?;
+        upstream.insert(node_id.clone());
+        upstream.extend(ancestors(&serialized, index));
+    }
+
+    let common_seed: u64 = rng.random();
+    let estimate = |nodes: &[Node]| -> Result<HashMap<String, f64>> {
+        let mut stream = Xoshiro128Plus::seed_from_u64(common_seed);
+        estimate_marginals(
+            &serialize_network(nodes)?,
+            num_samples,
+            &[],
+            &[],
+            &mut stream,
+        )
+    };
+    let baseline = estimate(nodes)?;
+    let gaps: HashMap<&str, f64> = observed_frequencies
+        .iter()
+        .map(|(id, &frequency)| (id.as_str(), frequency - baseline[id]))
+        .collect();
+    #[allow(clippy::cast_precision_loss)]
+    let mean_absolute_error_with = |shift: &dyn Fn(&str) -> f64| {
+        gaps.iter()
+            .map(|(&id, gap)| (gap - shift(id)).abs())
+            .sum::<f64>()
+            / gaps.len() as f64
+    };
+
+    let targets: Vec<&str> = observed_frequencies.keys().map(String::as_str).collect();
+    let sensitivities = sensitivities(nodes, &upstream, &targets, estimate)?;
+
+    let mut report: Vec<NodeCalibration> = observed_frequencies
+        .iter()
+        .map(|(node_id, &observed_frequency)| {
+            let gap = gaps[node_id.as_str()];
+            let mut adjustments: Vec<Adjustment> = sensitivities
+                .iter()
+                .filter(|sensitivity| sensitivity.slopes[node_id] != 0.0)
+                .map(|sensitivity| {
+                    let slope = sensitivity.slopes[node_id];
+                    let change = gap / slope;
+                    Adjustment {
+                        node_id: nodes[sensitivity.node].id.clone(),
+                        entry_index: sensitivity.entry,
+                        current: sensitivity.current,
+                        suggested: sensitivity.current + change,
+                        sensitivity: slope,
+                        predicted_mean_absolute_error: mean_absolute_error_with(&|id| {
+                            sensitivity.slopes[id] * change
+                        }),
+                    }
+                })
+                .filter(|adjustment| (0.0..=1.0).contains(&adjustment.suggested))
+                .collect();
+            adjustments.sort_by(|a, b| {
+                (a.suggested - a.current)
+                    .abs()
+                    .total_cmp(&(b.suggested - b.current).abs())
+                    .then_with(|| a.node_id.cmp(&b.node_id))
+                    .then_with(|| a.entry_index.cmp(&b.entry_index))
+            });
+            adjustments.truncate(MAX_ADJUSTMENTS);
+            NodeCalibration {
+                node_id: node_id.clone(),
+                observed_frequency,
+                estimate: baseline[node_id],
+                absolute_error: gap.abs(),
+                adjustments,
+            }
+        })
+        .collect();
+    report.sort_by(|a, b| {
+        b.absolute_error
+            .total_cmp(&a.absolute_error)
+            .then_with(|| a.node_id.cmp(&b.node_id))
+    });
+
+    Ok(CalibrationReport {
+        nodes: report,
+        mean_absolute_error: mean_absolute_error_with(&|_| 0.0),
+    })
+}
+
+/// Slopes of the target nodes with respect to one CPT entry.
+pub(crate) struct Sensitivity {
+    /// Index into the nodes.
+    pub node: usize,
+    /// Index into the node's `cptEntries`.
+    pub entry: usize,
+    pub current: f64,
+    pub slopes: HashMap<String, f64>,
+}
+
+/// Central-difference slopes of each of `targets` with respect to every fixed
+/// entry of the `upstream` nodes, with `estimate` giving marginals.
+pub(crate) fn sensitivities(
+    nodes: &[Node],
+    upstream: &BTreeSet<String>,
+    targets: &[&str],
+    estimate: impl Fn(&[Node]) -> Result<HashMap<String, f64>>,
+) -> Result<Vec<Sensitivity>> {
+    let mut sensitivities = Vec::new();
+    let mut perturbed = nodes.to_vec();
+    for (node_index, node) in nodes.iter().enumerate() {
+        if !upstream.contains(&node.id) {
+            continue;
+        }
+        for (entry_index, entry) in node.cpt_entries.iter().enumerate() {
+            if entry.probability_params.is_some() {
+                continue;
+            }
+            let current = entry.probability_of_true();
+            let (low, high) = ((current - STEP).max(0.0), (current + STEP).min(HIGHEST));
+            let mut at = |probability| {
+                perturbed[node_index].cpt_entries[entry_index].set_probability_of_true(probability);
+                estimate(&perturbed)
+            };
+            let (below, above) = (at(low)?, at(high)?);
+            perturbed[node_index].cpt_entries[entry_index] = entry.clone();
+            let slopes = targets
+                .iter()
+                .map(|&id| (id.to_owned(), (above[id] - below[id]) / (high - low)))
+                .collect();
+            sensitivities.push(Sensitivity {
+                node: node_index,
+                entry: entry_index,
+                current,
+                slopes,
+            });
+        }
+    }
+    Ok(sensitivities)
+}
+
+#[derive(Serialize)]
+#[serde(rename_all = "camelCase")]
+pub struct CalibrationResult {
+    /// Rows with the node and all of its parents observed.
+    pub rows: usize,
+    pub predicted_mean: f64,
+    pub observed_mean: f64,
+    /// Expected calibration error: over deciles of predicted probability, the
+    /// row-weighted gap between mean prediction and observed frequency.
+    pub calibration_error: f64,
+}
+
+/// Per-node reliability of the CPTs on `data`.
+///
+/// Each row predicts a node with the `P(true)` of the entry its parent values
+/// select, as the sampler would. Rows missing the node or any of its parents
+/// are skipped for that node, and nodes no row covers are left out.
+pub fn compute_calibration(
+    nodes: &[Node],
+    data: &[DataRow],
+) -> Result<HashMap<String, CalibrationResult>> {
+    serialize_network(nodes)?;
+    let mut results = HashMap::new();
+    for node in nodes {
+        let parents = get_node_parents(node);
+        // (predicted sum, observed count, rows) per bin.
+        let mut bins = [(0.0, 0.0, 0usize); NUM_BINS];
+        for row in data {
+            let Some(&observed) = row.get(&node.id) else {
+                continue;
+            };
+            if !parents.iter().all(|&parent| row.contains_key(parent)) {
+                continue;
+            }
+            let Some(predicted) = node.probability_given(|parent| row[parent]) else {
+                continue;
+            };
+            #[allow(clippy::cast_possible_truncation, clippy::cast_sign_loss)]
+            let bin = ((predicted * 10.0) as usize).min(NUM_BINS - 1);
+            bins[bin].0 += predicted;
+            bins[bin].1 += f64::from(u8::from(observed));
+            bins[bin].2 += 1;
+        }
+
+        let rows: usize = bins.iter().map(|&(_, _, rows)| rows).sum();
+        if rows == 0 {
+            continue;
+        }
+        #[allow(clippy::cast_precision_loss)]
+        let total = rows as f64;
+        let calibration_error = bins
+            .iter()
+            .map(|&(predicted, observed, _)| (predicted - observed).abs() / total)
+            .sum();
+        results.insert(
+            node.id.clone(),
+            CalibrationResult {
+                rows,
+                predicted_mean: bins.iter().map(|&(predicted, _, _)| predicted).sum::<f64>()
+                    / total,
+                observed_mean: bins.iter().map(|&(_, observed, _)| observed).sum::<f64>() / total,
+                calibration_error,
+            },
+        );
+    }
+    Ok(results)
+}
+
+/// Predicted probabilities are kept this far from 0 and 1 in the log loss,
+/// so one confident miss costs a lot rather than infinity.
+const LOG_LOSS_MARGIN: f64 = 1e-15;
+
+#[derive(Serialize)]
+#[serde(rename_all = "camelCase")]
+pub struct PredictionScores {
+    /// Rows with the outcome and all of its parents observed.
+    pub rows: usize,
+    /// `1 - SS_res / SS_tot`; NaN when every row has the same outcome.
+    pub r2: f64,
+    pub brier_score: f64,
+    /// Mean negative log-likelihood of the observed outcomes, in nats.
+    pub log_loss: f64,
+}
+
+/// How well the outcome's CPT predicts it on `data`, using the `P(true)` its
+/// observed parent values select (the same lookup as [`compute_calibration`],
+/// with no sampling). Rows missing the outcome or a parent are skipped.
+pub fn score_predictions(
+    nodes: &[Node],
+    data: &[DataRow],
+    outcome_id: &str,
+) -> Result<PredictionScores> {
+    serialize_network(nodes)?;
+    let outcome = nodes
+        .iter()
+        .find(|node| node.id == outcome_id)
+        .ok_or_else(|| anyhow!("Outcome node {outcome_id} not found"))?;
+    let parents = get_node_parents(outcome);
+    let pairs: Vec<(f64, f64)> = data
+        .iter()
+        .filter(|row| parents.iter().all(|&parent| row.contains_key(parent)))
+        .filter_map(|row| {
+            let observed = f64::from(u8::from(*row.get(outcome_id)?));
+            let predicted = outcome.probability_given(|parent| row[parent])?;
+            Some((observed, predicted))
+        })
+        .collect();
+    if pairs.is_empty() {
+        bail!("No row observes {outcome_id} together with all of its parents");
+    }
+
+    #[allow(clippy::cast_precision_loss)]
+    let rows = pairs.len() as f64;
+    let mean_observed = pairs.iter().map(|&(y, _)| y).sum::<f64>() / rows;
+    let ss_res: f64 = pairs.iter().map(|&(y, p)| (y - p).powi(2)).sum();
+    let ss_tot: f64 = pairs
+        .iter()
+        .map(|&(y, _)| (y - mean_observed).powi(2))
+        .sum();
+    let log_loss = -pairs
+        .iter()
+        .map(|&(y, p)| {
+            let p = p.clamp(LOG_LOSS_MARGIN, 1.0 - LOG_LOSS_MARGIN);
+            y * p.ln() + (1.0 - y) * (1.0 - p).ln()
+        })
+        .sum::<f64>()
+        / rows;
+    Ok(PredictionScores {
+        rows: pairs.len(),
+        r2: if ss_tot > 0.0 {
+            1.0 - ss_res / ss_tot
+        } else {
+            f64::NAN
+        },
+        brier_score: ss_res / rows,
+        log_loss,
+    })
+}
@@ -0,0 +1,1405 @@
+use anyhow::{Result, anyhow, bail};
+use rand::{Rng, SeedableRng};
+use rand_xoshiro::Xoshiro128Plus;
+use serde::Serialize;
+use std::collections::{BTreeMap, BTreeSet, HashMap};
+
+use crate::assumptions::AssumptionSet;
+use crate::exact::{MAX_EXACT_NODES, exact_joint, exact_marginals};
+use crate::learning::DataRow;
+use crate::lookup::resolve;
+use crate::marginals::{Algorithm, estimate_marginals, estimate_marginals_with, intervention};
+use crate::sample::{self, Override};
+use crate::serialize::{get_node_parents, serialize_network};
+use crate::structure;
+use crate::{CptEntry, Node};
+
+/// Multiplicative effect of `do(X=true)` over `do(X=false)` on `P(Y)`.
+///
+/// A zero control arm yields infinity (or NaN when both arms are zero).
+pub(crate) fn causal_risk_ratio(p_do_true: f64, p_do_false: f64) -> f64 {
+    p_do_true / p_do_false
+}
+
+/// Ratio of the odds of `Y` under `do(X=true)` and `do(X=false)`.
+///
+/// Undefined (NaN) when the control arm is certain, infinite when it is
+/// impossible or the treated arm is certain.
+pub(crate) fn causal_odds_ratio(p_do_true: f64, p_do_false: f64) -> f64 {
+    if p_do_false >= 1.0 {
+        return f64::NAN;
+    }
+    if p_do_true >= 1.0 {
+        return f64::INFINITY;
+    }
+    let odds_true = p_do_true / (1.0 - p_do_true);
+    let odds_false = p_do_false / (1.0 - p_do_false);
+    odds_true / odds_false
+}
+
+/// Attribution is exponential in the number of ancestors, so it is capped.
+const MAX_ATTRIBUTION_ANCESTORS: usize = 8;
+const NUM_PERMUTATIONS: usize = 256;
+
+/// Shapley attribution of `P(query)` to interventions on `ancestor_ids`.
+///
+/// The value of a coalition `S` is `P(query | do(S = true))`. Permutations are
+/// sampled uniformly and each ancestor is credited with its marginal
+/// contribution when added, so the attributions sum to the total effect
+/// `P(query | do(all = true)) - P(query)`. Every coalition is evaluated once,
+/// with common random numbers, so differences reflect the interventions
+/// rather than sampling noise.
+pub fn shapley_causal_attribution(
+    nodes: &[Node],
+    num_samples: usize,
+    query_node_id: &str,
+    ancestor_ids: &[String],
+    rng: &mut Xoshiro128Plus,
+) -> Result<HashMap<String, f64>> {
+    if ancestor_ids.len() > MAX_ATTRIBUTION_ANCESTORS {
+        bail!(
+            "Causal attribution supports at most {MAX_ATTRIBUTION_ANCESTORS} ancestors, got {}",
+            ancestor_ids.len()
+        );
+    }
+    let serialized = serialize_network(nodes)?;
+    resolve(&serialized, "Query", query_node_id)?;
+    let ancestors: Vec<u8> = ancestor_ids
+        .iter()
+        .map(|id| resolve(&serialized, "Ancestor", id))
+        .collect::<Result<_, _>>()?;
+
+    let common_seed: u64 = rng.random();
+    let mut coalition_values: HashMap<usize, f64> = HashMap::new();
+    let mut value_of = |coalition: usize| -> Result<f64> {
+        if let Some(&value) = coalition_values.get(&coalition) {
+            return Ok(value);
+        }
+        let mut overrides = vec![None; usize::from(serialized.num_nodes())];
+        for (i, &ancestor) in ancestors.iter().enumerate() {
+            if coalition & (1 << i) != 0 {
+                overrides[usize::from(ancestor)] = Some(Override::Value(true));
+            }
+        }
+        let mut rng = Xoshiro128Plus::seed_from_u64(common_seed);
+        let marginals = estimate_marginals(&serialized, num_samples, &overrides, &[], &mut rng)?;
+        let value = marginals[query_node_id];
+        coalition_values.insert(coalition, value);
+        Ok(value)
+    };
+
+    let mut attributions = vec![0.0; ancestors.len()];
+    let mut permutation: Vec<usize> = (0..ancestors.len()).collect();
+    for _ in 0..NUM_PERMUTATIONS {
+        for i in (1..permutation.len()).rev() {
+            permutation.swap(i, rng.random_range(0..=i));
+        }
+        let mut coalition = 0;
+        let mut previous_value = value_of(coalition)?;
+        for &i in &permutation {
+            coalition |= 1 << i;
+            let value = value_of(coalition)?;
+            attributions[i] += value - previous_value;
+            previous_value = value;
+        }
+    }
+
+    #[allow(clippy::cast_precision_loss)]
+    let num_permutations = NUM_PERMUTATIONS as f64;
+    Ok(ancestor_ids
+        .iter()
+        .cloned()
+        .zip(
+            attributions
+                .into_iter()
+                .map(|total| total / num_permutations),
+        )
+        .collect())
+}
+
+#[derive(Serialize)]
+#[serde(rename_all = "camelCase")]
+pub struct ComplianceEffect {
+    /// Intent-to-treat effect: outcome in the assigned arm minus control.
+    pub itt: f64,
+    /// Average treatment effect under full compliance.
+    pub ate: f64,
+    /// `itt - ate`, the dilution caused by units that don't comply.
+    pub noncompliance_bias: f64,
+}
+
+/// Intent-to-treat effect of assigning `treatment_id` when only a fraction of
+/// units comply.
+///
+/// Compliers receive `do(X=true)`; the rest select into treatment naturally,
+/// so the assigned arm's outcome is
+/// `c * P(Y | do(X=true)) + (1 - c) * P(Y)`, compared against the
+/// control arm `P(Y | do(X=false))`.
+pub fn average_causal_effect_with_compliance(
+    nodes: &[Node],
+    num_samples: usize,
+    treatment_id: &str,
+    outcome_id: &str,
+    compliance_rate: f64,
+    rng: &mut Xoshiro128Plus,
+) -> Result<ComplianceEffect> {
+    if !(0.0..=1.0).contains(&compliance_rate) {
+        bail!("Compliance rate must be within [0, 1], got {compliance_rate}");
+    }
+    let serialized = serialize_network(nodes)?;
+    let treatment = resolve(&serialized, "Treatment", treatment_id)?;
+    resolve(&serialized, "Outcome", outcome_id)?;
+    let num_nodes = serialized.num_nodes();
+
+    let mut outcome_under = |overrides: &[Option<Override>]| -> Result<f64> {
+        Ok(estimate_marginals(&serialized, num_samples, overrides, &[], rng)?[outcome_id])
+    };
+    let p_treated = outcome_under(&intervention(num_nodes, treatment, true))?;
+    let p_control = outcome_under(&intervention(num_nodes, treatment, false))?;
+    let p_natural = outcome_under(&[])?;
+
+    let assigned = compliance_rate * p_treated + (1.0 - compliance_rate) * p_natural;
+    let itt = assigned - p_control;
+    let ate = p_treated - p_control;
+    Ok(ComplianceEffect {
+        itt,
+        ate,
+        noncompliance_bias: itt - ate,
+    })
+}
+
+/// Treatment parent sets larger than this have too many contexts to check.
+const MAX_MONOTONICITY_PARENTS: usize = 8;
+/// Standard errors an effect may fall on the wrong side of zero before it is
+/// counted as a violation rather than sampling noise.
+const MONOTONICITY_TOLERANCE: f64 = 3.0;
+
+#[derive(Serialize)]
+#[serde(rename_all = "camelCase")]
+pub struct MonotonicityResult {
+    pub is_monotone: bool,
+    /// Share of the treatment's parent contexts, weighted by how often they
+    /// occur, whose effect agrees with the overall direction.
+    pub fraction_monotone: f64,
+    /// The context with the strongest effect against the overall direction.
+    pub violating_context: Option<HashMap<String, bool>>,
+}
+
+/// Checks that `do(X=true)` moves `P(Y)` in the same direction in every
+/// context of the treatment's parents.
+///
+/// Both arms are sampled from the same random stream, so each pair of draws
+/// shares its parent context, and samples are grouped by that context.
+/// Contexts that never occur are skipped.
+pub fn check_monotonicity(
+    nodes: &[Node],
+    num_samples: usize,
+    treatment_id: &str,
+    outcome_id: &str,
+    rng: &mut Xoshiro128Plus,
+) -> Result<MonotonicityResult> {
+    let serialized = serialize_network(nodes)?;
+    let treatment = resolve(&serialized, "Treatment", treatment_id)?;
+    let outcome = resolve(&serialized, "Outcome", outcome_id)?;
+    let treatment_node = nodes
+        .iter()
+        .find(|n| n.id == treatment_id)
+        .ok_or_else(|| anyhow!("Treatment node {treatment_id} not found"))?;
+    let mut parent_ids = get_node_parents(treatment_node);
+    parent_ids.sort_unstable();
+    if parent_ids.len() > MAX_MONOTONICITY_PARENTS {
+        bail!(
+            "Treatment {treatment_id} has {} parents; at most {MAX_MONOTONICITY_PARENTS} are supported",
+            parent_ids.len()
+        );
+    }
+    let parents: Vec<u8> = parent_ids
+        .iter()
+        .map(|id| resolve(&serialized, "Parent", id))
+        .collect::<Result<_, _>>()?;
+
+    let num_nodes = serialized.num_nodes();
+    // Per context: [samples, outcome true under do(X=false), under do(X=true)].
+    let mut counts = vec![[0usize; 3]; 1 << parents.len()];
+    let treated = intervention(num_nodes, treatment, true);
+    let control = intervention(num_nodes, treatment, false);
+    for _ in 0..num_samples {
+        let mut control_rng = rng.clone();
+        let treated_sample = sample::sample(&serialized.data, &serialized.offsets, &treated, rng)
+            .map_err(|e| anyhow!("Sampling failed: {e}"))?;
+        let control_sample = sample::sample(
+            &serialized.data,
+            &serialized.offsets,
+            &control,
+            &mut control_rng,
+        )
+        .map_err(|e| anyhow!("Sampling failed: {e}"))?;
+        let context = parents
+            .iter()
+            .enumerate()
+            .filter(|&(_, &parent)| treated_sample.contains(parent))
+            .fold(0, |context, (i, _)| context | (1 << i));
+        let tally = &mut counts[context];
+        tally[0] += 1;
+        tally[1] += usize::from(control_sample.contains(outcome));
+        tally[2] += usize::from(treated_sample.contains(outcome));
+    }
+
+    #[allow(clippy::cast_precision_loss)]
+    let effects: Vec<(usize, f64, f64, f64)> = counts
+        .iter()
+        .enumerate()
+        .filter(|(_, tally)| tally[0] > 0)
+        .map(|(context, &[n, control_true, treated_true])| {
+            let n = n as f64;
+            let p_control = control_true as f64 / n;
+            let p_treated = treated_true as f64 / n;
+            let standard_error =
+                ((p_control * (1.0 - p_control) + p_treated * (1.0 - p_treated)) / n).sqrt();
+            (context, n, p_treated - p_control, standard_error)
+        })
+        .collect();
+
+    let total_weight: f64 = effects.iter().map(|&(_, n, _, _)| n).sum();
+    let average_effect: f64 = effects.iter().map(|&(_, n, effect, _)| n * effect).sum();
+    let direction = if average_effect < 0.0 { -1.0 } else { 1.0 };
+
+    let mut agreeing_weight = 0.0;
+    let mut worst: Option<(usize, f64)> = None;
+    for &(context, n, effect, standard_error) in &effects {
+        let against = -direction * effect;
+        if against > MONOTONICITY_TOLERANCE * standard_error {
+            if worst.is_none_or(|(_, worst_against)| against > worst_against) {
+                worst = Some((context, against));
+            }
+        } else {
+            agreeing_weight += n;
+        }
+    }
+
+    Ok(MonotonicityResult {
+        is_monotone: worst.is_none(),
+        fraction_monotone: if total_weight > 0.0 {
+            agreeing_weight / total_weight
+        } else {
+            1.0
+        },
+        violating_context: worst.map(|(context, _)| {
+            parent_ids
+                .iter()
+                .enumerate()
+                .map(|(i, &id)| (id.to_string(), context & (1 << i) != 0))
+                .collect()
+        }),
+    })
+}
+
+#[derive(Serialize)]
+#[serde(rename_all = "camelCase")]
+#[allow(clippy::struct_field_names)]
+pub struct ConfoundingBounds {
+    pub lower_ate: f64,
+    pub upper_ate: f64,
+    pub original_ate: f64,
+}
+
+/// Range of average treatment effects consistent with a hypothetical hidden
+/// confounder of the given strength.
+///
+/// A virtual root `U ~ Bernoulli(0.5)` is added as a parent of both the
+/// treatment and the outcome, shifting each CPT entry by
+/// `±strength * min(p, 1 - p)` so the marginal CPTs are unchanged. In the
+/// augmented network the gap between the observational contrast
+/// `P(Y | X=1) - P(Y | X=0)` and the interventional ATE is the bias such a
+/// confounder would introduce; removing it from the original ATE, for a
+/// confounder pushing the outcome either way, gives the bounds.
+pub fn sensitivity_to_confounding(
+    nodes: &[Node],
+    num_samples: usize,
+    treatment_id: &str,
+    outcome_id: &str,
+    confounder_strength: f64,
+    rng: &mut Xoshiro128Plus,
+) -> Result<ConfoundingBounds> {
+    if !(0.0..=1.0).contains(&confounder_strength) {
+        bail!("Confounder strength must be within [0, 1], got {confounder_strength}");
+    }
+    if treatment_id == outcome_id {
+        bail!("Treatment and outcome must be different nodes");
+    }
+    let original_ate = average_treatment_effect(nodes, num_samples, treatment_id, outcome_id, rng)?;
+    if confounder_strength == 0.0 {
+        // No confounder, no bias; estimating it anyway would only add noise.
+        return Ok(ConfoundingBounds {
+            lower_ate: original_ate,
+            upper_ate: original_ate,
+            original_ate,
+        });
+    }
+
+    let mut confounder_id = String::from("U");
+    while nodes.iter().any(|n| n.id == confounder_id) {
+        confounder_id.push('\'');
+    }
+    let mut adjusted = vec![original_ate];
+    for outcome_direction in [1.0, -1.0] {
+        let mut augmented = nodes.to_vec();
+        for node in &mut augmented {
+            let direction = if node.id == treatment_id {
+                1.0
+            } else if node.id == outcome_id {
+                outcome_direction
+            } else {
+                continue;
+            };
+            confound(node, &confounder_id, direction * confounder_strength)?;
+        }
+        augmented.push(Node::with_prior(confounder_id.clone(), 0.5));
+
+        let ate = average_treatment_effect(&augmented, num_samples, treatment_id, outcome_id, rng)?;
+        let serialized = serialize_network(&augmented)?;
+        let treatment = resolve(&serialized, "Treatment", treatment_id)?;
+        let mut observed_outcome = |value: bool| -> Result<f64> {
+            let (marginals, _) = estimate_marginals_with(
+                Algorithm::Auto,
+                &serialized,
+                num_samples,
+                &[],
+                &[(treatment, value)],
+                rng,
+            )?;
+            Ok(marginals[outcome_id])
+        };
+        let observational_contrast = observed_outcome(true)? - observed_outcome(false)?;
+        adjusted.push((original_ate - (observational_contrast - ate)).clamp(-1.0, 1.0));
+    }
+
+    Ok(ConfoundingBounds {
+        lower_ate: adjusted.iter().copied().fold(f64::INFINITY, f64::min),
+        upper_ate: adjusted.iter().copied().fold(f64::NEG_INFINITY, f64::max),
+        original_ate,
+    })
+}
+
+fn average_treatment_effect(
+    nodes: &[Node],
+    num_samples: usize,
+    treatment_id: &str,
+    outcome_id: &str,
+    rng: &mut Xoshiro128Plus,
+) -> Result<f64> {
+    let serialized = serialize_network(nodes)?;
+    let treatment = resolve(&serialized, "Treatment", treatment_id)?;
+    resolve(&serialized, "Outcome", outcome_id)?;
+    let num_nodes = serialized.num_nodes();
+    let mut outcome_under = |value: bool| -> Result<f64> {
+        let overrides = intervention(num_nodes, treatment, value);
+        Ok(estimate_marginals(&serialized, num_samples, &overrides, &[], rng)?[outcome_id])
+    };
+    Ok(outcome_under(true)? - outcome_under(false)?)
+}
+
+#[derive(Serialize)]
+#[serde(rename_all = "camelCase")]
+pub struct DoLevel {
+    pub treatment_value: String,
+    pub outcome_probability: f64,
+}
+
+#[derive(Serialize)]
+#[serde(rename_all = "camelCase")]
+pub struct DoDistribution {
+    /// One per treatment value, sorted by value.
+    pub distribution: Vec<DoLevel>,
+    /// Mean of the finite differences between adjacent levels, taking the
+    /// levels one unit apart.
+    pub average_marginal_effect: f64,
+}
+
+/// `P(outcome | do(treatment = v))` for every value `v` of the treatment.
+///
+/// Nodes are binary, so the levels are `false` and `true`; each level is
+/// sampled with the same random numbers. The result shape does not assume
+/// two levels.
+pub fn do_distribution(
+    nodes: &[Node],
+    num_samples: usize,
+    treatment_id: &str,
+    outcome_id: &str,
+    rng: &mut Xoshiro128Plus,
+) -> Result<DoDistribution> {
+    let serialized = serialize_network(nodes)?;
+    let treatment = resolve(&serialized, "Treatment", treatment_id)?;
+    resolve(&serialized, "Outcome", outcome_id)?;
+    let num_nodes = serialized.num_nodes();
+    let common_seed: u64 = rng.random();
+    let distribution = [false, true]
+        .into_iter()
+        .map(|value| {
+            let overrides = intervention(num_nodes, treatment, value);
+            let mut stream = Xoshiro128Plus::seed_from_u64(common_seed);
+            let marginals =
+                estimate_marginals(&serialized, num_samples, &overrides, &[], &mut stream)?;
+            Ok(DoLevel {
+                treatment_value: value.to_string(),
+                outcome_probability: marginals[outcome_id],
+            })
+        })
+        .collect::<Result<Vec<_>>>()?;
+
+    #[allow(clippy::cast_precision_loss)]
+    let average_marginal_effect = distribution
+        .windows(2)
+        .map(|pair| pair[1].outcome_probability - pair[0].outcome_probability)
+        .sum::<f64>()
+        / (distribution.len() - 1) as f64;
+    Ok(DoDistribution {
+        distribution,
+        average_marginal_effect,
+    })
+}
+
+/// Splits every CPT entry of `node` on the confounder, raising `P(true)` when
+/// it is true and lowering it by the same amount when false.
+///
+/// Deterministic entries are not moved by the shift, so they stay single
+/// rows matching either value of the confounder.
+fn confound(node: &mut Node, confounder_id: &str, strength: f64) -> Result<()> {
+    let is_deterministic = |entry: &CptEntry| {
+        let mut probabilities = vec![entry.probability];
+        if let Some(params) = &entry.probability_params {
+            probabilities = vec![params.p_high, params.p_low];
+        }
+        probabilities
+            .iter()
+            .all(|&probability| probability.min(1.0 - probability) <= 0.0)
+    };
+    let num_entries: usize = node
+        .cpt_entries
+        .iter()
+        .map(|entry| if is_deterministic(entry) { 1 } else { 2 })
+        .sum();
+    if num_entries > usize::from(u8::MAX) {
+        bail!(
+            "Confounding node {id} splits its CPT into {num_entries} entries, more than the {} \
+             a node can have",
+            u8::MAX,
+            id = node.id
+        );
+    }
+    node.cpt_entries = node
+        .cpt_entries
+        .iter()
+        .flat_map(|entry| {
+            if is_deterministic(entry) {
+                return vec![entry.clone()];
+            }
+            [(true, strength), (false, -strength)]
+                .map(|(confounder, strength)| {
+                    let mut split = entry.clone();
+                    split
+                        .parent_states
+                        .insert(confounder_id.to_string(), Some(confounder));
+                    split.map_probabilities(|probability| {
+                        probability + strength * probability.min(1.0 - probability).max(0.0)
+                    });
+                    split
+                })
+                .into()
+        })
+        .collect();
+    Ok(())
+}
+
+/// At most `2^3 = 8` strata are estimated per call.
+const MAX_STRATIFICATION_NODES: usize = 3;
+
+#[derive(Serialize)]
+#[serde(rename_all = "camelCase")]
+pub struct StratumEffect {
+    pub stratum: HashMap<String, bool>,
+    pub ate: f64,
+    /// Share of samples falling in the stratum.
+    pub weight: f64,
+}
+
+#[derive(Serialize)]
+#[serde(rename_all = "camelCase")]
+pub struct Heterogeneity {
+    /// Strata sorted by descending absolute effect.
+    pub strata: Vec<StratumEffect>,
+    /// Variance of the stratum ATEs, weighted by stratum size.
+    pub heterogeneity_index: f64,
+}
+
+/// Conditional average treatment effects within every combination of the
+/// stratification nodes' values.
+///
+/// Within each arm, samples are grouped by the stratum they land in, so a
+/// stratum's ATE is `P(Y | do(X=1), S=s) - P(Y | do(X=0), S=s)`. Strata
+/// missing from either arm are omitted.
+pub fn interventional_heterogeneity(
+    nodes: &[Node],
+    num_samples: usize,
+    treatment_id: &str,
+    outcome_id: &str,
+    stratification_ids: &[String],
+    rng: &mut Xoshiro128Plus,
+) -> Result<Heterogeneity> {
+    if stratification_ids.len() > MAX_STRATIFICATION_NODES {
+        bail!(
+            "Heterogeneity supports at most {MAX_STRATIFICATION_NODES} stratification nodes, got {}",
+            stratification_ids.len()
+        );
+    }
+    let serialized = serialize_network(nodes)?;
+    let treatment = resolve(&serialized, "Treatment", treatment_id)?;
+    let outcome = resolve(&serialized, "Outcome", outcome_id)?;
+    let strata_nodes: Vec<u8> = stratification_ids
+        .iter()
+        .map(|id| resolve(&serialized, "Stratification", id))
+        .collect::<Result<_, _>>()?;
+
+    let num_nodes = serialized.num_nodes();
+    let num_strata = 1 << strata_nodes.len();
+    // Per arm (control, treated) and stratum: [samples, outcome true].
+    let mut counts = [vec![[0usize; 2]; num_strata], vec![[0usize; 2]; num_strata]];
+    for _ in 0..num_samples {
+        for (arm, tallies) in counts.iter_mut().enumerate() {
+            let overrides = intervention(num_nodes, treatment, arm == 1);
+            let sample_result =
+                sample::sample(&serialized.data, &serialized.offsets, &overrides, rng)
+                    .map_err(|e| anyhow!("Sampling failed: {e}"))?;
+            let stratum = strata_nodes
+                .iter()
+                .enumerate()
+                .filter(|&(_, &node)| sample_result.contains(node))
+                .fold(0, |stratum, (i, _)| stratum | (1 << i));
+            tallies[stratum][0] += 1;
+            tallies[stratum][1] += usize::from(sample_result.contains(outcome));
+        }
+    }
+
+    #[allow(clippy::cast_precision_loss)]
+    let mut strata: Vec<StratumEffect> = (0..num_strata)
+        .filter(|&stratum| counts[0][stratum][0] > 0 && counts[1][stratum][0] > 0)
+        .map(|stratum| {
+            let rate = |[n, y]: [usize; 2]| y as f64 / n as f64;
+            StratumEffect {
+                stratum: stratification_ids
+                    .iter()
+                    .enumerate()
+                    .map(|(i, id)| (id.clone(), stratum & (1 << i) != 0))
+                    .collect(),
+                ate: rate(counts[1][stratum]) - rate(counts[0][stratum]),
+                weight: (counts[0][stratum][0] + counts[1][stratum][0]) as f64
+                    / (2 * num_samples) as f64,
+            }
+        })
+        .collect();
+    strata.sort_by(|a, b| b.ate.abs().total_cmp(&a.ate.abs()));
+
+    let total_weight: f64 = strata.iter().map(|s| s.weight).sum();
+    let heterogeneity_index = if total_weight > 0.0 {
+        let mean = strata.iter().map(|s| s.weight * s.ate).sum::<f64>() / total_weight;
+        strata
+            .iter()
+            .map(|s| s.weight * (s.ate - mean).powi(2))
+            .sum::<f64>()
+            / total_weight
+    } else {
+        0.0
+    };
+
+    Ok(Heterogeneity {
+        strata,
+        heterogeneity_index,
+    })
+}
+
+#[derive(Serialize)]
+#[serde(rename_all = "camelCase")]
+pub struct SweepPoint {
+    /// Probability the swept node was clamped to.
+    pub p: f64,
+    pub target_marginal: f64,
+    pub std_error: f64,
+}
+
+/// `P(target)` as the clamp on `node_id` moves evenly from 0 to 1.
+///
+/// Every step reuses one buffer of uniforms for the swept node, which is set to
+/// `u < p`, and the same per-sample random streams for the rest of the
+/// network. A sample only changes when the clamp crosses its uniform, so the
+/// curve is smooth rather than carrying independent noise per step.
+pub fn sweep_soft_intervention(
+    nodes: &[Node],
+    node_id: &str,
+    target_node_id: &str,
+    steps: usize,
+    num_samples_per_step: usize,
+    rng: &mut Xoshiro128Plus,
+) -> Result<Vec<SweepPoint>> {
+    if steps < 2 {
+        bail!("A sweep needs at least 2 steps, got {steps}");
+    }
+    if num_samples_per_step == 0 {
+        bail!("A sweep needs at least one sample per step");
+    }
+    let serialized = serialize_network(nodes)?;
+    let swept = resolve(&serialized, "Swept", node_id)?;
+    let target = resolve(&serialized, "Target", target_node_id)?;
+    let num_nodes = serialized.num_nodes();
+
+    let common_seed: u64 = rng.random();
+    let noise: Vec<f64> = (0..num_samples_per_step).map(|_| rng.random()).collect();
+    let mut overrides = vec![None; usize::from(num_nodes)];
+
+    #[allow(clippy::cast_precision_loss)]
+    (0..steps)
+        .map(|step| {
+            let p = step as f64 / (steps - 1) as f64;
+            let mut target_true = 0usize;
+            for (i, &u) in (0u64..).zip(&noise) {
+                overrides[usize::from(swept)] = Some(Override::Value(u < p));
+                let mut stream = Xoshiro128Plus::seed_from_u64(common_seed.wrapping_add(i));
+                let sample_result = sample::sample(
+                    &serialized.data,
+                    &serialized.offsets,
+                    &overrides,
+                    &mut stream,
+                )
+                .map_err(|e| anyhow!("Sampling failed: {e}"))?;
+                target_true += usize::from(sample_result.contains(target));
+            }
+            let n = num_samples_per_step as f64;
+            let target_marginal = target_true as f64 / n;
+            Ok(SweepPoint {
+                p,
+                target_marginal,
+                std_error: (target_marginal * (1.0 - target_marginal) / n).sqrt(),
+            })
+        })
+        .collect()
+}
+
+#[derive(Serialize)]
+#[serde(rename_all = "camelCase")]
+pub struct MediationResult {
+    /// `E[Y(1)] - E[Y(0)]`.
+    pub total_effect: f64,
+    /// Natural direct effect `E[Y(1, M(0))] - E[Y(0)]`.
+    pub direct_effect: f64,
+    /// Natural indirect effect, `total_effect - direct_effect`.
+    pub indirect_effect: f64,
+    /// `indirect_effect / total_effect`; NaN when there is no total effect.
+    pub proportion_mediated: f64,
+}
+
+/// Splits the effect of `treatment` on `outcome` into the part that travels
+/// through `mediator` and the rest.
+///
+/// Each unit draws one uniform per node shared by all of its worlds: `do(X=0)`,
+/// `do(X=1)`, and `do(X=1)` with the mediator held at its `do(X=0)` value.
+/// The proportion can fall outside `[0, 1]` when the paths act in opposite
+/// directions.
+pub fn mediation_proportion(
+    nodes: &[Node],
+    num_samples: usize,
+    treatment_id: &str,
+    mediator_id: &str,
+    outcome_id: &str,
+    rng: &mut Xoshiro128Plus,
+) -> Result<MediationResult> {
+    if treatment_id == mediator_id || treatment_id == outcome_id || mediator_id == outcome_id {
+        bail!("Treatment, mediator and outcome must be different nodes");
+    }
+    if num_samples == 0 {
+        bail!("Mediation analysis needs at least one sample");
+    }
+    let serialized = serialize_network(nodes)?;
+    let index = |role, id| resolve(&serialized, role, id);
+    let treatment = index("Treatment", treatment_id)?;
+    let mediator = index("Mediator", mediator_id)?;
+    let outcome = index("Outcome", outcome_id)?;
+    let num_nodes = serialized.num_nodes();
+    let control = intervention(num_nodes, treatment, false);
+    let treated = intervention(num_nodes, treatment, true);
+
+    let common_seed: u64 = rng.random();
+    // Units with Y true in worlds Y(0), Y(1) and Y(1, M(0)).
+    let (mut control_true, mut treated_true, mut direct_true) = (0usize, 0usize, 0usize);
+    for i in 0..num_samples as u64 {
+        let unit = || Xoshiro128Plus::seed_from_u64(common_seed.wrapping_add(i));
+        let [y0, y1] = sample::sample_twin(
+            &serialized.data,
+            &serialized.offsets,
+            [&control, &treated],
+            &mut unit(),
+        )?;
+        let [_, y1_m0] = sample::sample_nested(
+            &serialized.data,
+            &serialized.offsets,
+            [&control, &treated],
+            Some(mediator),
+            &mut unit(),
+        )?;
+        control_true += usize::from(y0.contains(outcome));
+        treated_true += usize::from(y1.contains(outcome));
+        direct_true += usize::from(y1_m0.contains(outcome));
+    }
+
+    #[allow(clippy::cast_precision_loss)]
+    let rate = |count: usize| count as f64 / num_samples as f64;
+    let total_effect = rate(treated_true) - rate(control_true);
+    let direct_effect = rate(direct_true) - rate(control_true);
+    let indirect_effect = total_effect - direct_effect;
+    Ok(MediationResult {
+        total_effect,
+        direct_effect,
+        indirect_effect,
+        proportion_mediated: if total_effect.abs() < f64::EPSILON {
+            f64::NAN
+        } else {
+            indirect_effect / total_effect
+        },
+    })
+}
+
+/// Treatment probabilities are kept this far from 0 and 1.
+const DOSE_MARGIN: f64 = 0.001;
+
+/// `(t, P(outcome))` with the treatment's CPT replaced by `P(true) = t`, for
+/// each `t` in `treatment_probabilities` after clamping it to
+/// `[0.001, 0.999]`.
+///
+/// Every dose is sampled with the same random numbers, so the curve moves
+/// only where a dose changes a sample.
+pub fn dose_response(
+    nodes: &[Node],
+    num_samples: usize,
+    treatment_id: &str,
+    outcome_id: &str,
+    treatment_probabilities: &[f64],
+    rng: &mut Xoshiro128Plus,
+) -> Result<Vec<(f64, f64)>> {
+    let serialized = serialize_network(nodes)?;
+    let treatment = resolve(&serialized, "Treatment", treatment_id)?;
+    resolve(&serialized, "Outcome", outcome_id)?;
+    if let Some(dose) = treatment_probabilities.iter().find(|dose| dose.is_nan()) {
+        bail!("Treatment probabilities must be numbers, got {dose}");
+    }
+    let common_seed: u64 = rng.random();
+    let mut overrides = vec![None; usize::from(serialized.num_nodes())];
+    treatment_probabilities
+        .iter()
+        .map(|&dose| {
+            let dose = dose.clamp(DOSE_MARGIN, 1.0 - DOSE_MARGIN);
+            #[allow(clippy::cast_possible_truncation)]
+            let clamp = Override::Probability(dose as f32);
+            overrides[usize::from(treatment)] = Some(clamp);
+            let mut stream = Xoshiro128Plus::seed_from_u64(common_seed);
+            let marginals =
+                estimate_marginals(&serialized, num_samples, &overrides, &[], &mut stream)?;
+            Ok((dose, marginals[outcome_id]))
+        })
+        .collect()
+}
+
+#[derive(Serialize)]
+#[serde(rename_all = "camelCase")]
+pub struct OutcomeImpact {
+    pub node_id: String,
+    /// `P(outcome | do(node = true)) - P(outcome | do(node = false))`.
+    pub effect: f64,
+    pub outcome_if_true: f64,
+    pub outcome_if_false: f64,
+    /// Whether the node has a directed path to the outcome. Other nodes can't
+    /// affect it, so their effect is exactly zero and both arms are the
+    /// outcome's baseline marginal.
+    pub is_ancestor: bool,
+}
+
+/// Every node other than the outcome ranked by the size of its total effect
+/// on it, largest first, as a tornado chart draws them.
+///
+/// Only ancestors of the outcome are sampled, each pair of arms with the same
+/// random numbers so the effect reflects the intervention rather than noise.
+/// Everything else costs one shared baseline run.
+pub fn rank_outcome_impacts(
+    nodes: &[Node],
+    num_samples: usize,
+    outcome_id: &str,
+    rng: &mut Xoshiro128Plus,
+) -> Result<Vec<OutcomeImpact>> {
+    let serialized = serialize_network(nodes)?;
+    let outcome = resolve(&serialized, "Outcome", outcome_id)?;
+    let ancestors = structure::ancestors(&serialized, outcome);
+    let common_seed: u64 = rng.random();
+    let outcome_given = |overrides: &[Option<Override>]| -> Result<f64> {
+        let mut stream = Xoshiro128Plus::seed_from_u64(common_seed);
+        let marginals = estimate_marginals(&serialized, num_samples, overrides, &[], &mut stream)?;
+        Ok(marginals[outcome_id])
+    };
+    let baseline = if ancestors.len() + 1 < serialized.topo_order.len() {
+        outcome_given(&[])?
+    } else {
+        f64::NAN
+    };
+
+    let mut impacts = (0..serialized.num_nodes())
+        .filter(|&node| node != outcome)
+        .map(|node| {
+            let node_id = &serialized.topo_order[usize::from(node)];
+            let is_ancestor = ancestors.contains(node_id);
+            let (outcome_if_true, outcome_if_false) = if is_ancestor {
+                let num_nodes = serialized.num_nodes();
+                (
+                    outcome_given(&intervention(num_nodes, node, true))?,
+                    outcome_given(&intervention(num_nodes, node, false))?,
+                )
+            } else {
+                (baseline, baseline)
+            };
+            Ok(OutcomeImpact {
+                node_id: node_id.clone(),
+                effect: if is_ancestor {
+                    outcome_if_true - outcome_if_false
+                } else {
+                    0.0
+                },
+                outcome_if_true,
+                outcome_if_false,
+                is_ancestor,
+            })
+        })
+        .collect::<Result<Vec<_>>>()?;
+    impacts.sort_by(|a, b| b.effect.abs().total_cmp(&a.effect.abs()));
+    Ok(impacts)
+}
+
+/// `P(outcome_{treatment = counterfactual_treatment} | observation)` for one
+/// observed unit, by abduction, action and prediction on a twin network.
+///
+/// Each node's noise is the uniform it shares across the twin worlds. Draws
+/// whose factual world contradicts `observation` are rejected (abduction),
+/// the counterfactual world sets the treatment (action), and the outcome is
+/// read from it (prediction), averaged over the accepted draws. Observing
+/// every node makes acceptance as rare as the observation itself, so
+/// `num_samples` counts draws rather than accepted ones.
+pub fn counterfactual_outcome(
+    nodes: &[Node],
+    observation: &HashMap<String, bool>,
+    treatment_id: &str,
+    counterfactual_treatment: bool,
+    outcome_id: &str,
+    num_samples: usize,
+    rng: &mut Xoshiro128Plus,
+) -> Result<f64> {
+    let serialized = serialize_network(nodes)?;
+    let num_nodes = serialized.num_nodes();
+    let treatment = resolve(&serialized, "Treatment", treatment_id)?;
+    let outcome = resolve(&serialized, "Outcome", outcome_id)?;
+    let observed = observation
+        .iter()
+        .map(|(node_id, &value)| {
+            resolve(&serialized, "Observed", node_id).map(|node| (node, value))
+        })
+        .collect::<Result<Vec<_>, _>>()?;
+    let action = intervention(num_nodes, treatment, counterfactual_treatment);
+
+    let mut accepted = 0usize;
+    let mut outcome_true = 0usize;
+    for _ in 0..num_samples {
+        let [factual, counterfactual] =
+            sample::sample_twin(&serialized.data, &serialized.offsets, [&[], &action], rng)?;
+        if observed
+            .iter()
+            .all(|&(node, value)| factual.contains(node) == value)
+        {
+            accepted += 1;
+            outcome_true += usize::from(counterfactual.contains(outcome));
+        }
+    }
+    if accepted == 0 {
+        bail!("None of the {num_samples} samples were consistent with the observation");
+    }
+    #[allow(clippy::cast_precision_loss)]
+    Ok(outcome_true as f64 / accepted as f64)
+}
+
+/// Percentiles (0 to 100) of the unit-level effect `Y_{X=1} - Y_{X=0}` for
+/// each outcome.
+///
+/// Each unit is a twin-network sample, so both potential outcomes share the
+/// unit's noise and a unit's effect is -1, 0 or 1. Percentiles interpolate
+/// linearly between order statistics of the per-unit effects.
+pub fn effect_percentiles(
+    nodes: &[Node],
+    num_samples: usize,
+    treatment_id: &str,
+    outcome_ids: &[String],
+    percentiles: &[f64],
+    rng: &mut Xoshiro128Plus,
+) -> Result<HashMap<String, Vec<f64>>> {
+    if num_samples == 0 {
+        bail!("Effect percentiles need at least one sample");
+    }
+    if let Some(q) = percentiles.iter().find(|q| !(0.0..=100.0).contains(*q)) {
+        bail!("Percentile {q} is outside [0, 100]");
+    }
+    let serialized = serialize_network(nodes)?;
+    let treatment = resolve(&serialized, "Treatment", treatment_id)?;
+    let outcomes = outcome_ids
+        .iter()
+        .map(|id| resolve(&serialized, "Outcome", id))
+        .collect::<Result<Vec<u8>, _>>()?;
+    let num_nodes = serialized.num_nodes();
+    let control = intervention(num_nodes, treatment, false);
+    let treated = intervention(num_nodes, treatment, true);
+
+    // Effect counts per outcome: [-1, 0, 1].
+    let mut counts = vec![[0usize; 3]; outcomes.len()];
+    for _ in 0..num_samples {
+        let [y0, y1] = sample::sample_twin(
+            &serialized.data,
+            &serialized.offsets,
+            [&control, &treated],
+            rng,
+        )
+        .map_err(|e| anyhow!("Sampling failed: {e}"))?;
+        for (outcome_counts, &outcome) in counts.iter_mut().zip(&outcomes) {
+            let effect = usize::from(y1.contains(outcome)) + 1 - usize::from(y0.contains(outcome));
+            outcome_counts[effect] += 1;
+        }
+    }
+
+    Ok(outcome_ids
+        .iter()
+        .cloned()
+        .zip(counts)
+        .map(|(id, counts)| {
+            let values = percentiles
+                .iter()
+                .map(|&q| discrete_percentile(counts, num_samples, q))
+                .collect();
+            (id, values)
+        })
+        .collect())
+}
+
+/// Quantile treatment effects `Q_{Y | do(X=1)}(tau) - Q_{Y | do(X=0)}(tau)`
+/// of `outcome_id`, keyed by the outcome, in the order of `quantiles`.
+///
+/// A binary outcome with `P(true) = p` has quantile 0 up to `tau = 1 - p`
+/// and 1 above it, so each arm's marginal determines every quantile and each
+/// effect is -1, 0 or 1. Both arms use the same random numbers.
+pub fn compute_qte(
+    nodes: &[Node],
+    num_samples: usize,
+    treatment_id: &str,
+    outcome_id: &str,
+    quantiles: &[f64],
+    rng: &mut Xoshiro128Plus,
+) -> Result<HashMap<String, Vec<f64>>> {
+    if let Some(tau) = quantiles.iter().find(|tau| !(0.0..=1.0).contains(*tau)) {
+        bail!("Quantile {tau} is outside [0, 1]");
+    }
+    let serialized = serialize_network(nodes)?;
+    let treatment = resolve(&serialized, "Treatment", treatment_id)?;
+    resolve(&serialized, "Outcome", outcome_id)?;
+    let common_seed: u64 = rng.random();
+    let arm = |value| -> Result<f64> {
+        let overrides = intervention(serialized.num_nodes(), treatment, value);
+        let mut stream = Xoshiro128Plus::seed_from_u64(common_seed);
+        let marginals = estimate_marginals(&serialized, num_samples, &overrides, &[], &mut stream)?;
+        Ok(marginals[outcome_id])
+    };
+    let (treated, control) = (arm(true)?, arm(false)?);
+    let quantile = |p: f64, tau: f64| if tau <= 1.0 - p { 0.0 } else { 1.0 };
+    let effects = quantiles
+        .iter()
+        .map(|&tau| quantile(treated, tau) - quantile(control, tau))
+        .collect();
+    Ok(HashMap::from([(outcome_id.to_string(), effects)]))
+}
+
+#[derive(Serialize)]
+#[serde(rename_all = "camelCase")]
+pub struct ExpectedShortfall {
+    /// `E[Y | Y in the worst alpha of outcomes, do(X=x)]`, with false as 0
+    /// and true as 1.
+    pub expected_shortfall: f64,
+    /// `P(Y=true | do(X=x))`.
+    pub probability_true: f64,
+}
+
+/// Expected shortfall of `outcome_id` at level `alpha` under
+/// `do(intervention_id = value)`: the mean outcome over the worst `alpha`
+/// fraction of worlds, taking false as worse.
+///
+/// A binary outcome with `P(false) = q` fills the worst `alpha` with false
+/// outcomes first, so the shortfall is 0 while `alpha <= q` and
+/// `(alpha - q) / alpha` beyond. Outcomes with more values would need their
+/// full distribution.
+pub fn expected_shortfall(
+    nodes: &[Node],
+    num_samples: usize,
+    intervention_id: &str,
+    value: bool,
+    outcome_id: &str,
+    alpha: f64,
+    rng: &mut Xoshiro128Plus,
+) -> Result<ExpectedShortfall> {
+    if !(alpha > 0.0 && alpha <= 1.0) {
+        bail!("alpha must be in (0, 1], got {alpha}");
+    }
+    let serialized = serialize_network(nodes)?;
+    let treatment = resolve(&serialized, "Intervention", intervention_id)?;
+    resolve(&serialized, "Outcome", outcome_id)?;
+    let overrides = intervention(serialized.num_nodes(), treatment, value);
+    let marginals = estimate_marginals(&serialized, num_samples, &overrides, &[], rng)?;
+    let probability_true = marginals[outcome_id];
+    Ok(ExpectedShortfall {
+        expected_shortfall: (alpha - (1.0 - probability_true)).max(0.0) / alpha,
+        probability_true,
+    })
+}
+
+/// Percentile `q` of `n` sorted values made of `counts[i]` copies of `i - 1`.
+#[allow(clippy::cast_precision_loss)]
+fn discrete_percentile(counts: [usize; 3], n: usize, q: f64) -> f64 {
+    let order_statistic = |rank: usize| {
+        if rank < counts[0] {
+            -1.0
+        } else if rank < counts[0] + counts[1] {
+            0.0
+        } else {
+            1.0
+        }
+    };
+    let position = q / 100.0 * (n - 1) as f64;
+    #[allow(clippy::cast_possible_truncation, clippy::cast_sign_loss)]
+    let below = position.floor() as usize;
+    let above = (below + 1).min(n - 1);
+    let low = order_statistic(below);
+    low + (position - position.floor()) * (order_statistic(above) - low)
+}
+
+/// Doubly robust (AIPW) estimate of `E[Y | do(X=1)] - E[Y | do(X=0)]` from
+/// `data`, adjusting for the treatment's parents in `nodes`.
+///
+/// `propensity_model` supplies `e = P(X | covariates)` and `outcome_model`
+/// supplies `mu_x = P(Y | X=x, covariates)`, each by exact enumeration with
+/// the row's covariates as evidence; a covariate missing from a model is
+/// left unobserved there. The estimate is consistent when either model is
+/// right. Rows missing the treatment, the outcome or a covariate are
+/// skipped.
+pub fn aipw_ate(
+    nodes: &[Node],
+    data: &[DataRow],
+    treatment_id: &str,
+    outcome_id: &str,
+    propensity_model: &[Node],
+    outcome_model: &[Node],
+) -> Result<f64> {
+    let treatment = nodes
+        .iter()
+        .find(|node| node.id == treatment_id)
+        .ok_or_else(|| anyhow!("Treatment node {treatment_id} not found"))?;
+    if !nodes.iter().any(|node| node.id == outcome_id) {
+        bail!("Outcome node {outcome_id} not found");
+    }
+    let mut covariates: Vec<&str> = get_node_parents(treatment);
+    covariates.sort_unstable();
+    if covariates.contains(&outcome_id) {
+        bail!("Outcome {outcome_id} is a parent of treatment {treatment_id}");
+    }
+    for (model, role, target) in [
+        (propensity_model, "Propensity", treatment_id),
+        (outcome_model, "Outcome", outcome_id),
+        (outcome_model, "Outcome", treatment_id),
+    ] {
+        if !model.iter().any(|node| node.id == target) {
+            bail!("{role} model has no node {target}");
+        }
+    }
+
+    // Rows sharing covariate values share predictions, so each pattern is
+    // enumerated once.
+    let mut predictions: HashMap<Vec<bool>, (f64, f64, f64)> = HashMap::new();
+    let mut total = 0.0;
+    let mut rows = 0usize;
+    for row in data {
+        let (Some(&x), Some(&y)) = (row.get(treatment_id), row.get(outcome_id)) else {
+            continue;
+        };
+        let Some(pattern) = covariates
+            .iter()
+            .map(|&id| row.get(id).copied())
+            .collect::<Option<Vec<bool>>>()
+        else {
+            continue;
+        };
+        let (e, mu0, mu1) = if let Some(&cached) = predictions.get(&pattern) {
+            cached
+        } else {
+            let evidence: Vec<(&str, bool)> =
+                covariates.iter().copied().zip(pattern.clone()).collect();
+            let predict = |model, extra, target| {
+                model_prediction(model, &evidence, treatment_id, extra, target)
+            };
+            let computed = (
+                predict(propensity_model, None, treatment_id)?,
+                predict(outcome_model, Some(false), outcome_id)?,
+                predict(outcome_model, Some(true), outcome_id)?,
+            );
+            predictions.insert(pattern.clone(), computed);
+            computed
+        };
+        if e <= 0.0 || e >= 1.0 {
+            bail!(
+                "Propensity {e} at covariates {pattern:?} leaves one arm unobservable; AIPW \
+                 needs 0 < P({treatment_id}) < 1"
+            );
+        }
+        let (x, y) = (f64::from(u8::from(x)), f64::from(u8::from(y)));
+        total += mu1 - mu0 + x * (y - mu1) / e - (1.0 - x) * (y - mu0) / (1.0 - e);
+        rows += 1;
+    }
+    if rows == 0 {
+        bail!("No row observes {treatment_id}, {outcome_id} and every covariate");
+    }
+    #[allow(clippy::cast_precision_loss)]
+    Ok(total / rows as f64)
+}
+
+/// `P(target)` under `model` given the covariates it has and, when set, the
+/// treatment value.
+fn model_prediction(
+    model: &[Node],
+    covariates: &[(&str, bool)],
+    treatment_id: &str,
+    treatment: Option<bool>,
+    target: &str,
+) -> Result<f64> {
+    let mut evidence: BTreeMap<String, bool> = covariates
+        .iter()
+        .filter(|&&(id, _)| model.iter().any(|node| node.id == id))
+        .map(|&(id, value)| (id.to_string(), value))
+        .collect();
+    if let Some(value) = treatment {
+        evidence.insert(treatment_id.to_string(), value);
+    }
+    let assumptions = AssumptionSet {
+        evidence,
+        ..AssumptionSet::default()
+    };
+    let exact = exact_marginals(model, &assumptions, &BTreeMap::new())?;
+    if exact.evidence_probability <= 0.0 {
+        bail!("A model gives probability 0 to the covariates {covariates:?}");
+    }
+    Ok(exact.marginals[target])
+}
+
+/// First-stage effects `|E[X | do(Z=1)] - E[X | do(Z=0)]|` below this make
+/// the instrument too weak for the Wald ratio to mean anything.
+const MIN_FIRST_STAGE: f64 = 0.01;
+
+/// Local average treatment effect of `treatment_id` on `outcome_id`, by the
+/// Wald estimator with `instrument_id` as the instrument:
+/// `(E[Y | do(Z=1)] - E[Y | do(Z=0)]) / (E[X | do(Z=1)] - E[X | do(Z=0)])`.
+///
+/// Fails unless the instrument is relevant (a first stage of at least
+/// 0.01) and satisfies the exclusion restriction as far as the graph shows:
+/// every directed path from it to the outcome passes through the treatment.
+/// Both arms are sampled with the same random numbers.
+pub fn iv_effect(
+    nodes: &[Node],
+    num_samples: usize,
+    instrument_id: &str,
+    treatment_id: &str,
+    outcome_id: &str,
+    rng: &mut Xoshiro128Plus,
+) -> Result<f64> {
+    if instrument_id == treatment_id || instrument_id == outcome_id || treatment_id == outcome_id {
+        bail!("Instrument, treatment and outcome must be different nodes");
+    }
+    let serialized = serialize_network(nodes)?;
+    let instrument = resolve(&serialized, "Instrument", instrument_id)?;
+    let treatment = resolve(&serialized, "Treatment", treatment_id)?;
+    let outcome = resolve(&serialized, "Outcome", outcome_id)?;
+
+    // Walk up from the outcome without passing through the treatment.
+    let mut reached = vec![false; serialized.parents.len()];
+    let mut stack = vec![outcome];
+    while let Some(node) = stack.pop() {
+        for &parent in &serialized.parents[usize::from(node)] {
+            if parent != treatment && !std::mem::replace(&mut reached[usize::from(parent)], true) {
+                stack.push(parent);
+            }
+        }
+    }
+    if reached[usize::from(instrument)] {
+        bail!(
+            "Instrument {instrument_id} has a directed path to {outcome_id} that bypasses \
+             {treatment_id}, violating the exclusion restriction"
+        );
+    }
+
+    let num_nodes = serialized.num_nodes();
+    let common_seed: u64 = rng.random();
+    let arm = |value: bool| -> Result<(f64, f64)> {
+        let overrides = intervention(num_nodes, instrument, value);
+        let mut stream = Xoshiro128Plus::seed_from_u64(common_seed);
+        let marginals = estimate_marginals(&serialized, num_samples, &overrides, &[], &mut stream)?;
+        Ok((marginals[treatment_id], marginals[outcome_id]))
+    };
+    let (x_encouraged, y_encouraged) = arm(true)?;
+    let (x_control, y_control) = arm(false)?;
+    let first_stage = x_encouraged - x_control;
+    if first_stage.abs() < MIN_FIRST_STAGE {
+        bail!(
+            "Instrument {instrument_id} moves P({treatment_id}) by only {first_stage:.4}; it is \
+             too weak to estimate an effect"
+        );
+    }
+    Ok((y_encouraged - y_control) / first_stage)
+}
+
+#[derive(Serialize)]
+#[serde(rename_all = "camelCase")]
+pub struct OptimalIntervention {
+    pub node_id: String,
+    pub value: bool,
+    /// `P(utility node)` under the intervention.
+    pub expected_utility: f64,
+}
+
+/// The single `do(X=v)` over `X` in `candidates` that maximizes
+/// `P(utility)`, taking the utility node's marginal as the expected utility
+/// of a binary outcome.
+///
+/// Every arm is sampled with the same random numbers. Ties go to the
+/// earliest candidate, and to `do(X=false)` over `do(X=true)`.
+pub fn optimal_single_intervention(
+    nodes: &[Node],
+    num_samples: usize,
+    candidates: &[String],
+    utility_node_id: &str,
+    rng: &mut Xoshiro128Plus,
+) -> Result<OptimalIntervention> {
+    if candidates.is_empty() {
+        bail!("At least one intervention candidate is required");
+    }
+    if candidates.iter().any(|id| id == utility_node_id) {
+        bail!("Utility node {utility_node_id} cannot also be an intervention candidate");
+    }
+    let serialized = serialize_network(nodes)?;
+    resolve(&serialized, "Utility", utility_node_id)?;
+    let indices = candidates
+        .iter()
+        .map(|id| resolve(&serialized, "Intervention", id))
+        .collect::<Result<Vec<u8>, _>>()?;
+
+    let num_nodes = serialized.num_nodes();
+    let common_seed: u64 = rng.random();
+    let mut best: Option<OptimalIntervention> = None;
+    for (node_id, &node) in candidates.iter().zip(&indices) {
+        for value in [false, true] {
+            let overrides = intervention(num_nodes, node, value);
+            let mut stream = Xoshiro128Plus::seed_from_u64(common_seed);
+            let expected_utility =
+                estimate_marginals(&serialized, num_samples, &overrides, &[], &mut stream)?
+                    [utility_node_id];
+            if best
+                .as_ref()
+                .is_none_or(|best| expected_utility > best.expected_utility)
+            {
+                best = Some(OptimalIntervention {
+                    node_id: node_id.clone(),
+                    value,
+                    expected_utility,
+                });
+            }
+        }
+    }
+    best.ok_or_else(|| anyhow!("At least one intervention candidate is required"))
+}
+
+#[derive(Serialize)]
+#[serde(rename_all = "camelCase")]
+pub struct PositivityViolation {
+    pub covariate_assignment: HashMap<String, bool>,
+    /// `P(treatment = true | covariate_assignment)`.
+    pub treatment_probability: f64,
+}
+
+/// Covariate assignments under which the treatment is all but determined:
+/// `P(X=true | c)` below `min_prob` or above `1 - min_prob`.
+///
+/// Computed exactly over the treatment, the covariates and their ancestors,
+/// which must number at most `MAX_EXACT_NODES`. Assignments with probability
+/// zero are skipped, since no unit can have them. Violations are listed in
+/// the order of the assignments, the first covariate varying fastest.
+pub fn check_positivity(
+    nodes: &[Node],
+    treatment_id: &str,
+    covariate_ids: &[String],
+    min_prob: f64,
+) -> Result<Vec<PositivityViolation>> {
+    if !(0.0..=0.5).contains(&min_prob) {
+        bail!("Minimum probability must be within [0, 0.5], got {min_prob}");
+    }
+    if covariate_ids.iter().any(|id| id == treatment_id) {
+        bail!("Treatment {treatment_id} cannot also be a covariate");
+    }
+    let serialized = serialize_network(nodes)?;
+    let treatment = resolve(&serialized, "Treatment", treatment_id)?;
+    let mut relevant: BTreeSet<String> = structure::ancestors(&serialized, treatment)
+        .into_iter()
+        .chain([treatment_id.to_string()])
+        .collect();
+    for id in covariate_ids {
+        let covariate = resolve(&serialized, "Covariate", id)?;
+        relevant.extend(structure::ancestors(&serialized, covariate));
+        relevant.insert(id.clone());
+    }
+    if relevant.len() > MAX_EXACT_NODES {
+        bail!(
+            "The treatment, covariates and their ancestors are {count} nodes; at most \
+             {MAX_EXACT_NODES} can be enumerated",
+            count = relevant.len()
+        );
+    }
+    let subnetwork: Vec<Node> = nodes
+        .iter()
+        .filter(|node| relevant.contains(&node.id))
+        .cloned()
+        .collect();
+    let mut variables = covariate_ids.to_vec();
+    variables.push(treatment_id.to_string());
+    let joint = exact_joint(&subnetwork, &AssumptionSet::default(), &variables)?;
+
+    let treated_bit = 1 << covariate_ids.len();
+    Ok((0..treated_bit)
+        .filter_map(|assignment| {
+            let treated = joint[assignment | treated_bit];
+            let total = joint[assignment] + treated;
+            if total <= 0.0 {
+                return None;
+            }
+            let treatment_probability = treated / total;
+            (treatment_probability < min_prob || treatment_probability > 1.0 - min_prob).then(
+                || PositivityViolation {
+                    covariate_assignment: covariate_ids
+                        .iter()
+                        .enumerate()
+                        .map(|(i, id)| (id.clone(), assignment & (1 << i) != 0))
+                        .collect(),
+                    treatment_probability,
+                },
+            )
+        })
+        .collect())
+}
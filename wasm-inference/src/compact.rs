@@ -0,0 +1,472 @@
+//! Compact storage and interchange format for the semantic model.
+//!
+//! Unlike the sampling buffer produced by [`crate::serialize`], this format
+//! keeps node IDs and the caller's node order and is meant to be stored long
+//! term. After a magic header and version byte, everything is a sequence of
+//! tagged fields `tag: u8, length: LEB128, payload`, nested for structured
+//! values. Decoders skip tags they don't know, so fields can be added without
+//! breaking older readers.
+//!
+//! Encoding is canonical: equal node arrays give equal bytes, with parent
+//! states sorted by ID, negative zero written as zero and metadata keys
+//! sorted, so stored snapshots can be compared with [`diff_compact`].
+
+use anyhow::{Result, anyhow, bail};
+use serde::Serialize;
+use std::collections::{BTreeMap, BTreeSet, HashMap};
+use winnow::{
+    Parser,
+    binary::{le_f64, le_u8, length_take},
+    token::literal,
+};
+
+use crate::serialize::get_node_parents;
+use crate::{CptEntry, HierarchicalParam, Node};
+
+const MAGIC: &[u8; 4] = b"DDCN";
+const VERSION: u8 = 1;
+
+const NODE: u8 = 1;
+
+const NODE_ID: u8 = 1;
+const NODE_CPT_ENTRY: u8 = 2;
+const NODE_OBSERVED: u8 = 3;
+const NODE_PROBABILITY_FLOOR: u8 = 4;
+const NODE_PROBABILITY_CEILING: u8 = 5;
+const NODE_LATENT: u8 = 6;
+const NODE_TITLE: u8 = 7;
+const NODE_DESCRIPTION: u8 = 8;
+/// Metadata as JSON text.
+const NODE_METADATA: u8 = 9;
+const NODE_LEAK_PROBABILITY: u8 = 10;
+
+const ENTRY_PARENT_STATE: u8 = 1;
+const ENTRY_PROBABILITY: u8 = 2;
+const ENTRY_PROBABILITY_OF_FALSE: u8 = 3;
+const ENTRY_PROBABILITY_PARAMS: u8 = 4;
+
+const PARAMS_HYPERPARAMETER_ID: u8 = 1;
+const PARAMS_HIGH: u8 = 2;
+const PARAMS_LOW: u8 = 3;
+
+const PARENT_ID: u8 = 1;
+const PARENT_VALUE: u8 = 2;
+
+const VALUE_FALSE: u8 = 0;
+const VALUE_TRUE: u8 = 1;
+const VALUE_ANY: u8 = 2;
+
+pub fn to_compact(nodes: &[Node]) -> Vec<u8> {
+    let mut buffer = MAGIC.to_vec();
+    buffer.push(VERSION);
+    for node in nodes {
+        write_field(&mut buffer, NODE, &encode_node(node));
+    }
+    buffer
+}
+
+pub fn from_compact(mut input: &[u8]) -> Result<Vec<Node>> {
+    literal(MAGIC.as_slice())
+        .parse_next(&mut input)
+        .map_err(|_: winnow::error::ContextError| anyhow!("Not a compact network (bad magic)"))?;
+    let version = le_u8
+        .parse_next(&mut input)
+        .map_err(|_: winnow::error::ContextError| anyhow!("Truncated compact header"))?;
+    if version > VERSION {
+        bail!("Compact network version {version} is newer than supported version {VERSION}");
+    }
+
+    let mut nodes = Vec::new();
+    for (tag, payload) in fields(input)? {
+        if tag == NODE {
+            nodes.push(
+                decode_node(payload)
+                    .map_err(|e| anyhow!("Node record {idx}: {e}", idx = nodes.len()))?,
+            );
+        }
+    }
+    Ok(nodes)
+}
+
+/// Nodes with more parents than this are not compared assignment by
+/// assignment.
+const MAX_DIFF_PARENTS: usize = 16;
+
+#[derive(Clone, Serialize, PartialEq, Eq, PartialOrd, Ord)]
+#[serde(rename_all = "camelCase")]
+pub struct Edge {
+    pub parent_id: String,
+    pub child_id: String,
+}
+
+#[derive(Serialize)]
+#[serde(rename_all = "camelCase")]
+pub struct NodeChange {
+    pub node_id: String,
+    /// Parent assignments whose `P(true)` differs, over the parents of both
+    /// versions. An assignment no entry matches in only one version counts.
+    pub changed_probabilities: usize,
+    /// Largest `|P_b(true) - P_a(true)|` over assignments matched in both.
+    pub max_abs_change: f64,
+}
+
+/// What changed between two compact networks, every list sorted by ID.
+#[derive(Serialize)]
+#[serde(rename_all = "camelCase")]
+pub struct CompactDiff {
+    pub added_nodes: Vec<String>,
+    pub removed_nodes: Vec<String>,
+    pub added_edges: Vec<Edge>,
+    pub removed_edges: Vec<Edge>,
+    /// Nodes in both networks with a changed probability.
+    pub changed_nodes: Vec<NodeChange>,
+}
+
+/// Summarizes how `b` differs from `a`.
+///
+/// Nodes are matched by ID and probabilities compared as sampled, one
+/// parent assignment at a time under first-match resolution, so reordering
+/// or rewriting entries without changing what they mean is not a change.
+/// Node order and display fields are ignored.
+pub fn diff_compact(a: &[u8], b: &[u8]) -> Result<CompactDiff> {
+    let decode = |bytes: &[u8], name: &str| -> Result<BTreeMap<String, Node>> {
+        Ok(from_compact(bytes)
+            .map_err(|e| anyhow!("Network {name}: {e}"))?
+            .into_iter()
+            .map(|node| (node.id.clone(), node))
+            .collect())
+    };
+    let (a, b) = (decode(a, "A")?, decode(b, "B")?);
+    let edges = |nodes: &BTreeMap<String, Node>| -> BTreeSet<Edge> {
+        nodes
+            .values()
+            .flat_map(|node| {
+                get_node_parents(node).into_iter().map(|parent_id| Edge {
+                    parent_id: parent_id.to_string(),
+                    child_id: node.id.clone(),
+                })
+            })
+            .collect()
+    };
+    let (edges_a, edges_b) = (edges(&a), edges(&b));
+
+    let mut changed_nodes = Vec::new();
+    for (id, node_a) in &a {
+        let Some(node_b) = b.get(id) else {
+            continue;
+        };
+        let change = probability_change(node_a, node_b)?;
+        if change.changed_probabilities > 0 {
+            changed_nodes.push(change);
+        }
+    }
+    Ok(CompactDiff {
+        added_nodes: b
+            .keys()
+            .filter(|id| !a.contains_key(*id))
+            .cloned()
+            .collect(),
+        removed_nodes: a
+            .keys()
+            .filter(|id| !b.contains_key(*id))
+            .cloned()
+            .collect(),
+        added_edges: edges_b.difference(&edges_a).cloned().collect(),
+        removed_edges: edges_a.difference(&edges_b).cloned().collect(),
+        changed_nodes,
+    })
+}
+
+fn probability_change(a: &Node, b: &Node) -> Result<NodeChange> {
+    let parents: Vec<&str> = get_node_parents(a)
+        .into_iter()
+        .chain(get_node_parents(b))
+        .collect::<BTreeSet<_>>()
+        .into_iter()
+        .collect();
+    if parents.len() > MAX_DIFF_PARENTS {
+        bail!(
+            "Node {id} has {count} parents across both versions; at most {MAX_DIFF_PARENTS} \
+             can be compared",
+            id = a.id,
+            count = parents.len()
+        );
+    }
+    let mut change = NodeChange {
+        node_id: a.id.clone(),
+        changed_probabilities: 0,
+        max_abs_change: 0.0,
+    };
+    for assignment in 0..1usize << parents.len() {
+        let parent_value = |parent_id: &str| {
+            parents
+                .binary_search(&parent_id)
+                .is_ok_and(|i| assignment & (1 << i) != 0)
+        };
+        match (
+            a.probability_given(parent_value),
+            b.probability_given(parent_value),
+        ) {
+            (Some(p_a), Some(p_b)) => {
+                let difference = (p_b - p_a).abs();
+                if difference > 0.0 {
+                    change.changed_probabilities += 1;
+                    change.max_abs_change = change.max_abs_change.max(difference);
+                }
+            }
+            (None, None) => {}
+            _ => change.changed_probabilities += 1,
+        }
+    }
+    Ok(change)
+}
+
+fn encode_node(node: &Node) -> Vec<u8> {
+    let mut buffer = Vec::new();
+    write_field(&mut buffer, NODE_ID, node.id.as_bytes());
+    for entry in &node.cpt_entries {
+        write_field(&mut buffer, NODE_CPT_ENTRY, &encode_entry(entry));
+    }
+    if let Some(observed) = node.observed {
+        write_field(&mut buffer, NODE_OBSERVED, &[u8::from(observed)]);
+    }
+    if let Some(floor) = node.probability_floor {
+        write_field(&mut buffer, NODE_PROBABILITY_FLOOR, &canonical(floor));
+    }
+    if let Some(ceiling) = node.probability_ceiling {
+        write_field(&mut buffer, NODE_PROBABILITY_CEILING, &canonical(ceiling));
+    }
+    if let Some(leak) = node.leak_probability {
+        write_field(&mut buffer, NODE_LEAK_PROBABILITY, &canonical(leak));
+    }
+    if node.latent {
+        write_field(&mut buffer, NODE_LATENT, &[]);
+    }
+    if let Some(title) = &node.title {
+        write_field(&mut buffer, NODE_TITLE, title.as_bytes());
+    }
+    if let Some(description) = &node.description {
+        write_field(&mut buffer, NODE_DESCRIPTION, description.as_bytes());
+    }
+    if let Some(metadata) = &node.metadata {
+        write_field(&mut buffer, NODE_METADATA, metadata.to_string().as_bytes());
+    }
+    buffer
+}
+
+fn encode_entry(entry: &CptEntry) -> Vec<u8> {
+    let mut parent_states: Vec<_> = entry.parent_states.iter().collect();
+    parent_states.sort_unstable_by_key(|(parent_id, _)| parent_id.as_str());
+
+    let mut buffer = Vec::new();
+    for (parent_id, state) in parent_states {
+        let mut parent = Vec::new();
+        write_field(&mut parent, PARENT_ID, parent_id.as_bytes());
+        let value = match state {
+            Some(false) => VALUE_FALSE,
+            Some(true) => VALUE_TRUE,
+            None => VALUE_ANY,
+        };
+        write_field(&mut parent, PARENT_VALUE, &[value]);
+        write_field(&mut buffer, ENTRY_PARENT_STATE, &parent);
+    }
+    write_field(
+        &mut buffer,
+        ENTRY_PROBABILITY,
+        &canonical(entry.probability),
+    );
+    if !entry.is_probability_of_true {
+        write_field(&mut buffer, ENTRY_PROBABILITY_OF_FALSE, &[]);
+    }
+    if let Some(params) = &entry.probability_params {
+        let mut payload = Vec::new();
+        write_field(
+            &mut payload,
+            PARAMS_HYPERPARAMETER_ID,
+            params.hyperparameter_id.as_bytes(),
+        );
+        write_field(&mut payload, PARAMS_HIGH, &canonical(params.p_high));
+        write_field(&mut payload, PARAMS_LOW, &canonical(params.p_low));
+        write_field(&mut buffer, ENTRY_PROBABILITY_PARAMS, &payload);
+    }
+    buffer
+}
+
+fn decode_node(payload: &[u8]) -> Result<Node> {
+    let mut id = None;
+    let mut cpt_entries = Vec::new();
+    let mut observed = None;
+    let mut probability_floor = None;
+    let mut probability_ceiling = None;
+    let mut leak_probability = None;
+    let mut latent = false;
+    let mut title = None;
+    let mut description = None;
+    let mut metadata = None;
+    for (tag, payload) in fields(payload)? {
+        match tag {
+            NODE_ID => id = Some(decode_string(payload)?),
+            NODE_CPT_ENTRY => cpt_entries.push(
+                decode_entry(payload)
+                    .map_err(|e| anyhow!("CPT entry {idx}: {e}", idx = cpt_entries.len()))?,
+            ),
+            NODE_OBSERVED => {
+                observed = Some(match payload.first() {
+                    Some(&VALUE_FALSE) => false,
+                    Some(&VALUE_TRUE) => true,
+                    other => bail!("invalid observed value {other:?}"),
+                });
+            }
+            NODE_PROBABILITY_FLOOR => probability_floor = Some(decode_f64(payload, "floor")?),
+            NODE_PROBABILITY_CEILING => {
+                probability_ceiling = Some(decode_f64(payload, "ceiling")?);
+            }
+            NODE_LEAK_PROBABILITY => leak_probability = Some(decode_f64(payload, "leak")?),
+            NODE_LATENT => latent = true,
+            NODE_TITLE => title = Some(decode_string(payload)?),
+            NODE_DESCRIPTION => description = Some(decode_string(payload)?),
+            NODE_METADATA => {
+                metadata = Some(
+                    serde_json::from_slice(payload)
+                        .map_err(|e| anyhow!("invalid metadata: {e}"))?,
+                );
+            }
+            _ => {}
+        }
+    }
+    Ok(Node {
+        id: id.ok_or_else(|| anyhow!("missing node ID"))?,
+        cpt_entries,
+        cpt_table: None,
+        observed,
+        probability_floor,
+        probability_ceiling,
+        leak_probability,
+        latent,
+        title,
+        description,
+        metadata,
+    })
+}
+
+fn decode_entry(payload: &[u8]) -> Result<CptEntry> {
+    let mut parent_states = HashMap::new();
+    let mut probability = None;
+    let mut is_probability_of_true = true;
+    let mut probability_params = None;
+    for (tag, payload) in fields(payload)? {
+        match tag {
+            ENTRY_PARENT_STATE => {
+                let (parent_id, state) = decode_parent_state(payload)?;
+                parent_states.insert(parent_id, state);
+            }
+            ENTRY_PROBABILITY => probability = Some(decode_f64(payload, "probability")?),
+            ENTRY_PROBABILITY_OF_FALSE => is_probability_of_true = false,
+            ENTRY_PROBABILITY_PARAMS => probability_params = Some(decode_params(payload)?),
+            _ => {}
+        }
+    }
+    Ok(CptEntry {
+        parent_states,
+        probability: probability.ok_or_else(|| anyhow!("missing probability"))?,
+        is_probability_of_true,
+        probability_params,
+    })
+}
+
+fn decode_params(payload: &[u8]) -> Result<HierarchicalParam> {
+    let mut hyperparameter_id = None;
+    let mut p_high = None;
+    let mut p_low = None;
+    for (tag, payload) in fields(payload)? {
+        match tag {
+            PARAMS_HYPERPARAMETER_ID => hyperparameter_id = Some(decode_string(payload)?),
+            PARAMS_HIGH => p_high = Some(decode_f64(payload, "high probability")?),
+            PARAMS_LOW => p_low = Some(decode_f64(payload, "low probability")?),
+            _ => {}
+        }
+    }
+    Ok(HierarchicalParam {
+        hyperparameter_id: hyperparameter_id.ok_or_else(|| anyhow!("missing hyperparameter ID"))?,
+        p_high: p_high.ok_or_else(|| anyhow!("missing high probability"))?,
+        p_low: p_low.ok_or_else(|| anyhow!("missing low probability"))?,
+    })
+}
+
+fn decode_parent_state(payload: &[u8]) -> Result<(String, Option<bool>)> {
+    let mut parent_id = None;
+    let mut state = None;
+    for (tag, payload) in fields(payload)? {
+        match tag {
+            PARENT_ID => parent_id = Some(decode_string(payload)?),
+            PARENT_VALUE => {
+                state = Some(match payload.first() {
+                    Some(&VALUE_FALSE) => Some(false),
+                    Some(&VALUE_TRUE) => Some(true),
+                    Some(&VALUE_ANY) => None,
+                    other => bail!("invalid parent state {other:?}"),
+                });
+            }
+            _ => {}
+        }
+    }
+    Ok((
+        parent_id.ok_or_else(|| anyhow!("missing parent ID"))?,
+        state.ok_or_else(|| anyhow!("missing parent state"))?,
+    ))
+}
+
+fn decode_f64(mut payload: &[u8], what: &str) -> Result<f64> {
+    le_f64
+        .parse_next(&mut payload)
+        .map_err(|_: winnow::error::ContextError| anyhow!("truncated {what}"))
+}
+
+fn decode_string(payload: &[u8]) -> Result<String> {
+    String::from_utf8(payload.to_vec()).map_err(|e| anyhow!("invalid UTF-8: {e}"))
+}
+
+/// Little-endian bytes of `value`, with negative zero as zero.
+fn canonical(value: f64) -> [u8; 8] {
+    let value = if value == 0.0 { 0.0 } else { value };
+    value.to_le_bytes()
+}
+
+fn write_field(buffer: &mut Vec<u8>, tag: u8, payload: &[u8]) {
+    buffer.push(tag);
+    let mut length = payload.len();
+    loop {
+        #[allow(clippy::cast_possible_truncation)]
+        let byte = (length & 0x7f) as u8;
+        length >>= 7;
+        if length == 0 {
+            buffer.push(byte);
+            break;
+        }
+        buffer.push(byte | 0x80);
+    }
+    buffer.extend_from_slice(payload);
+}
+
+fn fields(mut input: &[u8]) -> Result<Vec<(u8, &[u8])>> {
+    let mut fields = Vec::new();
+    while !input.is_empty() {
+        let field = (le_u8, length_take(varint))
+            .parse_next(&mut input)
+            .map_err(|_: winnow::error::ContextError| anyhow!("truncated field"))?;
+        fields.push(field);
+    }
+    Ok(fields)
+}
+
+fn varint(input: &mut &[u8]) -> winnow::Result<usize> {
+    let mut value = 0usize;
+    for shift in (0..usize::BITS).step_by(7) {
+        let byte = le_u8.parse_next(input)?;
+        value |= usize::from(byte & 0x7f) << shift;
+        if byte & 0x80 == 0 {
+            return Ok(value);
+        }
+    }
+    Err(winnow::error::ContextError::new())
+}
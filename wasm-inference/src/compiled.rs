@@ -0,0 +1,1180 @@
+//! A network compiled once and kept on the wasm side, so repeated queries
+//! don't round-trip and re-serialize the node array.
+
+use rand::SeedableRng;
+use rand_xoshiro::Xoshiro128Plus;
+use serde::{Deserialize, Serialize};
+use std::cell::RefCell;
+use std::collections::{HashMap, HashSet};
+use std::mem::size_of;
+use wasm_bindgen::prelude::*;
+
+use crate::assumptions::AssumptionSet;
+use crate::bit_set::BitSet;
+use crate::limits;
+use crate::lookup;
+use crate::marginals;
+use crate::raw_samples;
+use crate::recording::{self, LoggedQuery, MAX_LOG_CAPACITY, QueryKind, QueryLog, ReplayOutcome};
+use crate::sample::{self, Override};
+use crate::serialize::{self, SerializedNetwork, fnv1a};
+use crate::structure;
+use crate::{
+    CptEntry, InterventionResult, MarginalsResult, Node, Provenance, deserialize_nodes,
+    invalid_assumptions, layout_mismatch_error, limit_error, marginals_with_options, node_error,
+    not_found_error, options, rng_from_seed, seeded_rng, serialize_nodes,
+};
+
+#[derive(Serialize)]
+#[serde(rename_all = "camelCase")]
+pub struct UpdateResult {
+    /// Set when the whole network was compiled again, as when a parent set
+    /// changed or a node was added.
+    pub full_recompile: bool,
+}
+
+/// What a baseline run depends on besides the network itself.
+#[derive(PartialEq, Eq)]
+struct BaselineKey {
+    /// `None` for unseeded runs, which may reuse each other's baseline.
+    seed: Option<u64>,
+    num_samples: usize,
+    /// Hash of the resolved assumptions, the soft evidence and the algorithm.
+    assumptions: u64,
+}
+
+impl BaselineKey {
+    fn new(
+        options: &options::QueryOptions,
+        seed: Option<u64>,
+        num_samples: usize,
+        assumptions: &AssumptionSet,
+    ) -> Result<Self, JsValue> {
+        let fingerprint = serde_json::to_vec(&(
+            assumptions,
+            options.algorithm,
+            &options.soft_evidence,
+            options.zero_handling,
+        ))
+        .map_err(|e| JsValue::from_str(&format!("Failed to hash assumptions: {e}")))?;
+        Ok(BaselineKey {
+            seed,
+            num_samples,
+            assumptions: fnv1a(fingerprint),
+        })
+    }
+}
+
+struct CachedBaseline {
+    key: BaselineKey,
+    marginals: HashMap<String, f64>,
+}
+
+/// Samples kept from the last `retain_samples` run, for reweighting.
+struct RetainedSamples {
+    /// Only those consistent with the run's evidence.
+    samples: Vec<BitSet>,
+    overrides: Vec<Option<Override>>,
+}
+
+#[derive(Serialize)]
+#[serde(rename_all = "camelCase")]
+pub struct PerturbationResult {
+    pub marginals: HashMap<String, f64>,
+    /// `(sum w)^2 / sum w^2` over the retained samples; once this is a small
+    /// fraction of `retained_samples` the estimate is unreliable and a full
+    /// run is due.
+    pub effective_sample_size: f64,
+    pub retained_samples: usize,
+}
+
+/// Parents beyond which `preview_node_marginal` samples instead of
+/// enumerating their configurations.
+const MAX_PREVIEW_PARENTS: usize = 12;
+/// Samples for a preview's prior run or fallback when none are requested.
+const PREVIEW_SAMPLES: usize = 10_000;
+
+/// Prior marginals kept for previews when there is no baseline to reuse.
+struct CachedPriors {
+    num_samples: usize,
+    marginals: HashMap<String, f64>,
+}
+
+#[derive(Deserialize, Default)]
+#[serde(rename_all = "camelCase", default)]
+struct PreviewOptions {
+    /// Sample the edited network instead of treating the parents as
+    /// independent.
+    exact: bool,
+    num_samples: Option<f64>,
+}
+
+#[derive(Serialize, Clone, Copy)]
+#[serde(rename_all = "camelCase")]
+pub enum PreviewMethod {
+    IndependentParents,
+    Sampled,
+}
+
+#[derive(Serialize, Clone, Copy)]
+#[serde(rename_all = "camelCase")]
+pub enum PriorSource {
+    /// The cached baseline of the last intervention query, so under that
+    /// query's assumptions and evidence.
+    Baseline,
+    /// A run of the network with no evidence.
+    Prior,
+}
+
+#[derive(Serialize)]
+#[serde(rename_all = "camelCase")]
+pub struct PreviewResult {
+    pub marginal: f64,
+    pub method: PreviewMethod,
+    /// Set for `independentParents`, which ignores any dependence between
+    /// the parents and so is only exact when they are independent.
+    pub approximate: bool,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub prior_source: Option<PriorSource>,
+}
+
+#[wasm_bindgen]
+pub struct CompiledNetwork {
+    pub(crate) nodes: Vec<Node>,
+    pub(crate) serialized: SerializedNetwork,
+    /// Baseline of the last intervention query. Cleared whenever the network
+    /// changes, and only served for an identical key.
+    baseline: Option<CachedBaseline>,
+    /// Cleared whenever the network changes, like `baseline`.
+    retained: Option<RetainedSamples>,
+    /// Cleared whenever the network changes, like `baseline`.
+    priors: Option<CachedPriors>,
+    /// Queries served since `start_recording`. Behind a `RefCell` so
+    /// read-only queries can log themselves.
+    recording: RefCell<Option<QueryLog>>,
+}
+
+impl CompiledNetwork {
+    pub(crate) fn compile(nodes: Vec<Node>) -> Result<Self, JsValue> {
+        let serialized = serialize::serialize_network(&nodes)
+            .map_err(|e| JsValue::from_str(&format!("Serialization failed: {e}")))?;
+        Ok(CompiledNetwork {
+            nodes,
+            serialized,
+            baseline: None,
+            retained: None,
+            priors: None,
+            recording: RefCell::new(None),
+        })
+    }
+
+    /// Marginals from a fresh stream for `seed`, so each arm of a query (and
+    /// a cached baseline) is the same whichever arms were computed before.
+    /// Only `options`' algorithm and zero handling are used.
+    fn estimate(
+        &self,
+        options: &options::QueryOptions,
+        num_samples: usize,
+        seed: u64,
+        overrides: &[Option<Override>],
+        evidence: &[(u8, bool)],
+        soft_evidence: &[(u8, f64)],
+    ) -> Result<HashMap<String, f64>, JsValue> {
+        let mut rng = Xoshiro128Plus::seed_from_u64(seed);
+        marginals::estimate_marginals_with_soft(
+            options.algorithm,
+            &self.serialized,
+            num_samples,
+            overrides,
+            evidence,
+            soft_evidence,
+            options.zero_handling,
+            &mut rng,
+        )
+        .map(|(marginals, _)| marginals)
+        .map_err(|e| JsValue::from_str(&e.to_string()))
+    }
+
+    /// Nodes that `do(node)` cannot move: everything but `node` and what it
+    /// reaches along edges into nodes still sampled from their CPTs (an
+    /// overridden node ignores its parents). Evidence on a reached node
+    /// couples it back to its other ancestors, so then none are claimed.
+    fn unaffected_by(
+        &self,
+        node: u8,
+        overrides: &[Option<Override>],
+        evidence: &[(u8, bool)],
+        soft_evidence: &[(u8, f64)],
+    ) -> Vec<String> {
+        let children = self.serialized.children();
+        let mut reached = vec![false; children.len()];
+        reached[usize::from(node)] = true;
+        let mut stack = vec![node];
+        while let Some(next) = stack.pop() {
+            for &child in &children[usize::from(next)] {
+                if overrides[usize::from(child)].is_none()
+                    && !std::mem::replace(&mut reached[usize::from(child)], true)
+                {
+                    stack.push(child);
+                }
+            }
+        }
+        let observed = evidence
+            .iter()
+            .map(|&(index, _)| index)
+            .chain(soft_evidence.iter().map(|&(index, _)| index));
+        if observed
+            .into_iter()
+            .any(|index| reached[usize::from(index)])
+        {
+            return Vec::new();
+        }
+        self.serialized
+            .topo_order
+            .iter()
+            .zip(reached)
+            .filter(|&(_, reached)| !reached)
+            .map(|(id, _)| id.clone())
+            .collect()
+    }
+
+    fn weighted_marginals(&self, samples: &[BitSet], weights: &[f64]) -> HashMap<String, f64> {
+        let total: f64 = weights.iter().sum();
+        self.serialized
+            .topo_order
+            .iter()
+            .zip(0..)
+            .map(|(node_id, index)| {
+                let weight_true: f64 = samples
+                    .iter()
+                    .zip(weights)
+                    .filter(|(sample, _)| sample.contains(index))
+                    .map(|(_, weight)| weight)
+                    .sum();
+                (node_id.clone(), weight_true / total)
+            })
+            .collect()
+    }
+
+    /// Approximate heap footprint of the nodes and their compiled form.
+    pub(crate) fn heap_bytes(&self) -> usize {
+        let nodes: usize = self
+            .nodes
+            .iter()
+            .map(|node| {
+                let entries: usize = node
+                    .cpt_entries
+                    .iter()
+                    .map(|entry| {
+                        size_of::<CptEntry>()
+                            + entry
+                                .parent_states
+                                .keys()
+                                .map(|id| size_of::<(String, Option<bool>)>() + id.len())
+                                .sum::<usize>()
+                    })
+                    .sum();
+                size_of::<Node>() + node.id.len() + entries
+            })
+            .sum();
+        let topo_order: usize = self
+            .serialized
+            .topo_order
+            .iter()
+            .map(|id| size_of::<String>() + id.len())
+            .sum();
+        let parents: usize = self
+            .serialized
+            .parents
+            .iter()
+            .map(|parents| size_of::<Vec<u8>>() + parents.len())
+            .sum();
+        let baseline = self.baseline.as_ref().map_or(0, |cached| {
+            cached
+                .marginals
+                .keys()
+                .map(|id| size_of::<(String, f64)>() + id.len())
+                .sum()
+        });
+        let priors = self.priors.as_ref().map_or(0, |cached| {
+            cached
+                .marginals
+                .keys()
+                .map(|id| size_of::<(String, f64)>() + id.len())
+                .sum()
+        });
+        let retained = self.retained.as_ref().map_or(0, |retained| {
+            retained.samples.len() * size_of::<BitSet>()
+                + retained.overrides.len() * size_of::<Option<Override>>()
+        });
+        // Logged options are opaque JSON, so only the entries are counted.
+        let recording = self
+            .recording
+            .borrow()
+            .as_ref()
+            .map_or(0, |log| log.entries.len() * size_of::<LoggedQuery>());
+        nodes
+            + self.serialized.data.len()
+            + topo_order
+            + parents
+            + baseline
+            + priors
+            + retained
+            + recording
+    }
+
+    /// Applies edited nodes, rewriting only their records when every parent
+    /// set is unchanged. On error the handle is left as it was.
+    pub(crate) fn update(&mut self, changed: Vec<Node>) -> Result<UpdateResult, JsValue> {
+        let mut seen = HashSet::new();
+        if let Some(node) = changed.iter().find(|node| !seen.insert(node.id.as_str())) {
+            return Err(JsValue::from_str(&format!(
+                "Node {id} appears more than once in the update",
+                id = node.id
+            )));
+        }
+
+        let records = changed
+            .iter()
+            .map(|node| self.serialized.reserialize_node(node))
+            .collect::<anyhow::Result<Option<Vec<_>>>>()
+            .map_err(|e| JsValue::from_str(&format!("Serialization failed: {e}")))?;
+
+        let full_recompile = records.is_none();
+        self.baseline = None;
+        self.retained = None;
+        self.priors = None;
+        if let Some(records) = records {
+            for (index, record) in records {
+                self.serialized.replace_record(index, record);
+            }
+            for node in changed {
+                let position = self.position(&node.id);
+                self.nodes[position] = node;
+            }
+        } else {
+            let mut nodes = self.nodes.clone();
+            for node in changed {
+                match nodes.iter().position(|n| n.id == node.id) {
+                    Some(position) => nodes[position] = node,
+                    None => nodes.push(node),
+                }
+            }
+            let recording = self.recording.take();
+            *self = CompiledNetwork::compile(nodes)?;
+            self.recording = RefCell::new(recording);
+        }
+        Ok(UpdateResult { full_recompile })
+    }
+
+    /// [`CompiledNetwork::compute_intervention`], returning the seed used.
+    fn intervention(
+        &mut self,
+        options: &options::QueryOptions,
+        node_id: &str,
+    ) -> Result<(InterventionResult, u64), JsValue> {
+        if options.root_correlations.is_some() {
+            return Err(JsValue::from_str(
+                "rootCorrelations are not supported by compute_intervention",
+            ));
+        }
+        if !options.constraints.is_empty() {
+            return Err(JsValue::from_str(
+                "constraints are not supported by compute_intervention",
+            ));
+        }
+        let num_samples = options.num_samples().map_err(limit_error)?;
+        let requested_seed = options.seed().map_err(limit_error)?;
+        let assumptions = options
+            .assumptions
+            .resolve(&self.nodes)
+            .map_err(|e| invalid_assumptions(&e))?;
+        let overrides = assumptions
+            .overrides(&self.serialized)
+            .map_err(|e| node_error(&e))?;
+        let evidence = assumptions
+            .evidence_indices(&self.serialized)
+            .map_err(|e| node_error(&e))?;
+        let soft_evidence = options
+            .soft_evidence_indices(&self.serialized)
+            .map_err(|e| JsValue::from_str(&format!("Invalid soft evidence: {e}")))?;
+        let index = lookup::resolve(&self.serialized, "Intervention", node_id)
+            .map_err(|e| not_found_error(&e))?;
+        let (seed, _) = rng_from_seed(requested_seed)?;
+
+        let key = BaselineKey::new(options, requested_seed, num_samples, &assumptions)?;
+        let baseline = match &self.baseline {
+            Some(cached) if cached.key == key => cached.marginals.clone(),
+            _ => {
+                let marginals = self.estimate(
+                    options,
+                    num_samples,
+                    seed,
+                    &overrides,
+                    &evidence,
+                    &soft_evidence,
+                )?;
+                self.baseline = Some(CachedBaseline {
+                    key,
+                    marginals: marginals.clone(),
+                });
+                marginals
+            }
+        };
+
+        let arm = |value| {
+            let mut overrides = overrides.clone();
+            overrides[usize::from(index)] = Some(Override::Value(value));
+            self.estimate(
+                options,
+                num_samples,
+                seed,
+                &overrides,
+                &evidence,
+                &soft_evidence,
+            )
+        };
+        let key = |marginals| {
+            options
+                .key_by
+                .apply(&self.nodes, marginals)
+                .map_err(|e| JsValue::from_str(&e.to_string()))
+        };
+        // Both arms report the baseline for nodes out of the intervention's
+        // reach, so sampling noise never shows up as an effect. The other
+        // nodes keep their common-random-number estimates untouched.
+        let (mut true_case, mut false_case) = (arm(true)?, arm(false)?);
+        let unaffected = self.unaffected_by(index, &overrides, &evidence, &soft_evidence);
+        for node_id in &unaffected {
+            true_case.insert(node_id.clone(), baseline[node_id]);
+            false_case.insert(node_id.clone(), baseline[node_id]);
+        }
+        let mut unaffected: Vec<String> = options
+            .key_by
+            .apply(
+                &self.nodes,
+                unaffected.into_iter().map(|id| (id, ())).collect(),
+            )
+            .map_err(|e| JsValue::from_str(&e.to_string()))?
+            .into_keys()
+            .collect();
+        unaffected.sort_unstable();
+        let mut result =
+            InterventionResult::new(key(true_case)?, key(false_case)?, Some(key(baseline)?));
+        result.unaffected_nodes = Some(unaffected);
+        result.provenance = Some(Provenance {
+            assumptions,
+            fingerprint: self.serialized.fingerprint(),
+            seed,
+        });
+        Ok((result, seed))
+    }
+
+    fn marginals(&self, options: &serde_json::Value) -> Result<MarginalsResult, JsValue> {
+        marginals_with_options(&self.nodes, &self.serialized, &parse_options(options)?)
+    }
+
+    /// Logs a served query when recording, with `seed` written into its
+    /// options.
+    fn record(
+        &self,
+        kind: QueryKind,
+        node_id: Option<&str>,
+        options: &serde_json::Value,
+        seed: u64,
+        digest: String,
+    ) {
+        if let Some(log) = self.recording.borrow_mut().as_mut() {
+            let mut options = options.clone();
+            if let Some(object) = options.as_object_mut() {
+                object.insert("seed".to_string(), seed.into());
+            }
+            log.push(LoggedQuery {
+                kind,
+                node_id: node_id.map(str::to_string),
+                options,
+                seed,
+                digest,
+            });
+        }
+    }
+
+    /// Runs a logged query again, returning its new digest.
+    fn rerun(&mut self, query: &LoggedQuery) -> Result<String, JsValue> {
+        match (query.kind, &query.node_id) {
+            (QueryKind::Marginals, _) => {
+                let result = self.marginals(&query.options)?;
+                Ok(recording::digest(&[&result.marginals]))
+            }
+            (QueryKind::Intervention, Some(node_id)) => {
+                let (result, _) = self.intervention(&parse_options(&query.options)?, node_id)?;
+                Ok(intervention_digest(&result))
+            }
+            (QueryKind::Intervention, None) => {
+                Err(JsValue::from_str("Logged intervention query has no nodeId"))
+            }
+        }
+    }
+
+    /// Marginals to use as parent priors in a preview: the cached baseline
+    /// when there is one, otherwise a prior run kept until the network (or
+    /// the requested sample count) changes.
+    fn preview_priors(
+        &mut self,
+        num_samples: usize,
+    ) -> Result<(HashMap<String, f64>, PriorSource), JsValue> {
+        if let Some(cached) = &self.baseline {
+            return Ok((cached.marginals.clone(), PriorSource::Baseline));
+        }
+        match &self.priors {
+            Some(cached) if cached.num_samples == num_samples => {
+                Ok((cached.marginals.clone(), PriorSource::Prior))
+            }
+            _ => {
+                let overrides = vec![None; usize::from(self.serialized.num_nodes())];
+                let marginals = marginals::estimate_marginals(
+                    &self.serialized,
+                    num_samples,
+                    &overrides,
+                    &[],
+                    &mut seeded_rng()?,
+                )
+                .map_err(|e| JsValue::from_str(&format!("Inference failed: {e}")))?;
+                self.priors = Some(CachedPriors {
+                    num_samples,
+                    marginals: marginals.clone(),
+                });
+                Ok((marginals, PriorSource::Prior))
+            }
+        }
+    }
+
+    /// The draft node's marginal from a prior run of the network with the
+    /// draft in place.
+    fn sampled_preview(&self, draft: &Node, num_samples: usize) -> Result<f64, JsValue> {
+        let nodes: Vec<Node> = self
+            .nodes
+            .iter()
+            .map(|node| if node.id == draft.id { draft } else { node })
+            .cloned()
+            .collect();
+        let serialized = serialize::serialize_network(&nodes)
+            .map_err(|e| JsValue::from_str(&format!("Serialization failed: {e}")))?;
+        let overrides = vec![None; usize::from(serialized.num_nodes())];
+        let marginals = marginals::estimate_marginals(
+            &serialized,
+            num_samples,
+            &overrides,
+            &[],
+            &mut seeded_rng()?,
+        )
+        .map_err(|e| JsValue::from_str(&format!("Inference failed: {e}")))?;
+        Ok(marginals[&draft.id])
+    }
+
+    fn position(&self, node_id: &str) -> usize {
+        self.nodes
+            .iter()
+            .position(|node| node.id == node_id)
+            .expect("compiled nodes match the serialized network")
+    }
+}
+
+#[wasm_bindgen]
+impl CompiledNetwork {
+    #[wasm_bindgen(constructor)]
+    #[allow(clippy::missing_errors_doc)]
+    pub fn new(nodes: JsValue) -> Result<CompiledNetwork, JsValue> {
+        CompiledNetwork::compile(deserialize_nodes(nodes)?)
+    }
+
+    /// Same as `compute_marginals_with_options`, without recompiling.
+    #[allow(clippy::missing_errors_doc)]
+    pub fn compute_marginals(&self, options: JsValue) -> Result<JsValue, JsValue> {
+        let options = raw_options(options)?;
+        let result = self.marginals(&options)?;
+        self.record(
+            QueryKind::Marginals,
+            None,
+            &options,
+            result.provenance.seed,
+            recording::digest(&[&result.marginals]),
+        );
+        serde_wasm_bindgen::to_value(&result)
+            .map_err(|e| JsValue::from_str(&format!("Failed to serialize result: {e}")))
+    }
+
+    /// `do(node_id = true)` against `do(node_id = false)` under the
+    /// assumptions in `options`, with the baseline (no extra intervention)
+    /// included. The baseline is reused from the previous call when the seed,
+    /// sample count, assumptions and algorithm all match and the network has
+    /// not changed since; otherwise it is recomputed. Nodes listed in
+    /// `unaffectedNodes` report the baseline in both arms.
+    #[allow(clippy::missing_errors_doc)]
+    pub fn compute_intervention(
+        &mut self,
+        options: JsValue,
+        node_id: &str,
+    ) -> Result<JsValue, JsValue> {
+        let options = raw_options(options)?;
+        let (result, seed) = self.intervention(&parse_options(&options)?, node_id)?;
+        self.record(
+            QueryKind::Intervention,
+            Some(node_id),
+            &options,
+            seed,
+            intervention_digest(&result),
+        );
+        serde_wasm_bindgen::to_value(&result)
+            .map_err(|e| JsValue::from_str(&format!("Failed to serialize result: {e}")))
+    }
+
+    /// Whether an intervention query's baseline is being kept for reuse.
+    #[must_use]
+    pub fn has_cached_baseline(&self) -> bool {
+        self.baseline.is_some()
+    }
+
+    pub fn invalidate_cache(&mut self) {
+        self.baseline = None;
+        self.retained = None;
+        self.priors = None;
+    }
+
+    /// Starts logging every `compute_marginals` and `compute_intervention`
+    /// call, keeping the latest `capacity` (at most 10,000). Restarting
+    /// clears the log.
+    #[allow(clippy::missing_errors_doc)]
+    pub fn start_recording(&mut self, capacity: f64) -> Result<(), JsValue> {
+        let capacity =
+            limits::count("capacity", capacity, MAX_LOG_CAPACITY).map_err(limit_error)?;
+        self.recording = RefCell::new(Some(QueryLog::new(capacity)));
+        Ok(())
+    }
+
+    /// Stops logging and discards the log.
+    pub fn stop_recording(&mut self) {
+        self.recording = RefCell::new(None);
+    }
+
+    /// The log as a JSON array of `{ kind, nodeId?, options, seed, digest }`,
+    /// oldest first, for `replay`.
+    #[allow(clippy::missing_errors_doc)]
+    pub fn query_log(&self) -> Result<String, JsValue> {
+        let recording = self.recording.borrow();
+        let log = recording
+            .as_ref()
+            .ok_or_else(|| JsValue::from_str("Not recording; call start_recording first"))?;
+        serde_json::to_string(&log.entries)
+            .map_err(|e| JsValue::from_str(&format!("Failed to serialize log: {e}")))
+    }
+
+    /// Re-runs every query of a `query_log` against the network as it is
+    /// now, with the seeds they used, and reports `[{ kind, nodeId?,
+    /// previousDigest, digest, changed, error? }]`. Replays are not logged.
+    #[allow(clippy::missing_errors_doc)]
+    pub fn replay(&mut self, log: &str) -> Result<JsValue, JsValue> {
+        let log: Vec<LoggedQuery> = serde_json::from_str(log)
+            .map_err(|e| JsValue::from_str(&format!("Failed to deserialize log: {e}")))?;
+        let outcomes: Vec<ReplayOutcome> = log
+            .into_iter()
+            .map(|query| {
+                let rerun = self.rerun(&query);
+                let error = rerun
+                    .as_ref()
+                    .err()
+                    .map(|e| e.as_string().unwrap_or_else(|| format!("{e:?}")));
+                let digest = rerun.ok();
+                ReplayOutcome {
+                    changed: digest.as_ref() != Some(&query.digest),
+                    kind: query.kind,
+                    node_id: query.node_id,
+                    previous_digest: query.digest,
+                    digest,
+                    error,
+                }
+            })
+            .collect();
+        serde_wasm_bindgen::to_value(&outcomes)
+            .map_err(|e| JsValue::from_str(&format!("Failed to serialize result: {e}")))
+    }
+
+    /// Forward-samples the network under `options` and keeps the samples
+    /// consistent with its hard evidence, so `perturb_entry` can answer
+    /// without sampling again. Returns the marginals of the run, keyed by ID.
+    #[allow(clippy::missing_errors_doc)]
+    pub fn retain_samples(&mut self, options: JsValue) -> Result<JsValue, JsValue> {
+        let options: options::QueryOptions = serde_wasm_bindgen::from_value(options)
+            .map_err(|e| JsValue::from_str(&format!("Failed to deserialize options: {e}")))?;
+        if !options.soft_evidence.is_empty() || options.root_correlations.is_some() {
+            return Err(JsValue::from_str(
+                "Retained samples support hard evidence only, without soft evidence or rootCorrelations",
+            ));
+        }
+        let num_samples = options.num_samples().map_err(limit_error)?;
+        let assumptions = options
+            .assumptions
+            .resolve(&self.nodes)
+            .map_err(|e| invalid_assumptions(&e))?;
+        let overrides = assumptions
+            .overrides(&self.serialized)
+            .map_err(|e| node_error(&e))?;
+        let evidence = assumptions
+            .evidence_indices(&self.serialized)
+            .map_err(|e| node_error(&e))?;
+        let (_, mut rng) = rng_from_seed(options.seed().map_err(limit_error)?)?;
+
+        let mut samples = sample::sample_all(
+            &self.serialized.data,
+            &self.serialized.offsets,
+            num_samples,
+            &overrides,
+            &mut rng,
+        )
+        .map_err(|e| JsValue::from_str(&format!("Sampling failed: {e}")))?;
+        samples.retain(|sample| {
+            evidence
+                .iter()
+                .all(|&(node, value)| sample.contains(node) == value)
+        });
+        if samples.is_empty() {
+            return Err(JsValue::from_str(&format!(
+                "None of the {num_samples} samples were consistent with the evidence"
+            )));
+        }
+        let marginals = self.weighted_marginals(&samples, &vec![1.0; samples.len()]);
+        self.retained = Some(RetainedSamples { samples, overrides });
+        serde_wasm_bindgen::to_value(&marginals)
+            .map_err(|e| JsValue::from_str(&format!("Failed to serialize result: {e}")))
+    }
+
+    /// Marginals if entry `entry_index` of `node_id` had `probability` (in
+    /// the entry's own `isProbabilityOfTrue` convention), estimated by
+    /// reweighting the retained samples instead of sampling again. Each
+    /// sample is weighted by the ratio of its value's new and old
+    /// probability at the node, which only differs where it matched that
+    /// entry. The network itself is not changed.
+    #[allow(clippy::missing_errors_doc)]
+    pub fn perturb_entry(
+        &self,
+        node_id: &str,
+        entry_index: f64,
+        probability: f64,
+    ) -> Result<JsValue, JsValue> {
+        let retained = self.retained.as_ref().ok_or_else(|| {
+            JsValue::from_str("No retained samples; call retain_samples after the last change")
+        })?;
+        if !(0.0..=1.0).contains(&probability) {
+            return Err(JsValue::from_str(&format!(
+                "Probability must be in [0, 1], got {probability}"
+            )));
+        }
+        let index = self
+            .serialized
+            .index_of(node_id)
+            .ok_or_else(|| JsValue::from_str(&format!("Node {node_id} not found")))?;
+        let old = &self.nodes[self.position(node_id)];
+        let entry_index =
+            limits::index("entryIndex", entry_index, old.cpt_entries.len()).map_err(limit_error)?;
+        let mut new = old.clone();
+        new.cpt_entries[entry_index].probability = probability;
+        new.cpt_entries[entry_index].probability_params = None;
+
+        let clamped = retained
+            .overrides
+            .get(usize::from(index))
+            .is_some_and(Option::is_some);
+        let weights: Vec<f64> = retained
+            .samples
+            .iter()
+            .map(|sample| {
+                if clamped {
+                    return 1.0;
+                }
+                let parent_value = |parent_id: &str| {
+                    self.serialized
+                        .index_of(parent_id)
+                        .is_some_and(|parent| sample.contains(parent))
+                };
+                let likelihood = |node: &Node| {
+                    let p_true = node.probability_given(parent_value).unwrap_or(0.0);
+                    if sample.contains(index) {
+                        p_true
+                    } else {
+                        1.0 - p_true
+                    }
+                };
+                let before = likelihood(old);
+                if before > 0.0 {
+                    likelihood(&new) / before
+                } else {
+                    0.0
+                }
+            })
+            .collect();
+        let total: f64 = weights.iter().sum();
+        if total <= 0.0 {
+            return Err(JsValue::from_str(
+                "The change gives every retained sample zero probability; run a full query",
+            ));
+        }
+        let result = PerturbationResult {
+            marginals: self.weighted_marginals(&retained.samples, &weights),
+            effective_sample_size: total * total / weights.iter().map(|w| w * w).sum::<f64>(),
+            retained_samples: retained.samples.len(),
+        };
+        serde_wasm_bindgen::to_value(&result)
+            .map_err(|e| JsValue::from_str(&format!("Failed to serialize result: {e}")))
+    }
+
+    /// `node_id`'s marginal if its `cptEntries` were `draft_entries`, without
+    /// changing the network. By default the parents are treated as
+    /// independent with their cached marginals as priors (the baseline of
+    /// the last intervention query, else a prior run), and the draft is
+    /// applied to each of the 2^k parent configurations analytically. With
+    /// `options.exact`, or more than 12 parents, the edited network is
+    /// sampled instead. Options are `{ exact?, numSamples? }`; the result is
+    /// `{ marginal, method, approximate, priorSource? }`.
+    #[allow(clippy::missing_errors_doc)]
+    pub fn preview_node_marginal(
+        &mut self,
+        node_id: &str,
+        draft_entries: JsValue,
+        options: JsValue,
+    ) -> Result<JsValue, JsValue> {
+        let options: PreviewOptions = if options.is_undefined() || options.is_null() {
+            PreviewOptions::default()
+        } else {
+            serde_wasm_bindgen::from_value(options)
+                .map_err(|e| JsValue::from_str(&format!("Failed to deserialize options: {e}")))?
+        };
+        let num_samples = options.num_samples.map_or(Ok(PREVIEW_SAMPLES), |n| {
+            limits::count("numSamples", n, limits::MAX_SAMPLES).map_err(limit_error)
+        })?;
+        let entries: Vec<CptEntry> = serde_wasm_bindgen::from_value(draft_entries)
+            .map_err(|e| JsValue::from_str(&format!("Failed to deserialize entries: {e}")))?;
+        if let Some(entry) = entries
+            .iter()
+            .find(|entry| !(0.0..=1.0).contains(&entry.probability))
+        {
+            return Err(JsValue::from_str(&format!(
+                "Probability must be in [0, 1], got {}",
+                entry.probability
+            )));
+        }
+        let index = lookup::resolve(&self.serialized, "Preview", node_id)
+            .map_err(|e| not_found_error(&e))?;
+        let mut draft = self.nodes[self.position(node_id)].clone();
+        draft.cpt_entries = entries;
+        let parents: Vec<String> = self.serialized.parents[usize::from(index)]
+            .iter()
+            .map(|&parent| self.serialized.topo_order[usize::from(parent)].clone())
+            .collect();
+
+        let result = if options.exact || parents.len() > MAX_PREVIEW_PARENTS {
+            PreviewResult {
+                marginal: self.sampled_preview(&draft, num_samples)?,
+                method: PreviewMethod::Sampled,
+                approximate: false,
+                prior_source: None,
+            }
+        } else {
+            let (priors, source) = self.preview_priors(num_samples)?;
+            let mut marginal = 0.0;
+            for config in 0..1usize << parents.len() {
+                let is_true = |bit: usize| config >> bit & 1 == 1;
+                let weight: f64 = parents
+                    .iter()
+                    .enumerate()
+                    .map(|(bit, id)| {
+                        if is_true(bit) {
+                            priors[id]
+                        } else {
+                            1.0 - priors[id]
+                        }
+                    })
+                    .product();
+                if weight <= 0.0 {
+                    continue;
+                }
+                let parent_value =
+                    |id: &str| parents.iter().position(|p| p == id).is_some_and(is_true);
+                let p_true = draft.probability_given(parent_value).ok_or_else(|| {
+                    JsValue::from_str(&format!(
+                        "No draft entry of {node_id} matches a parent configuration"
+                    ))
+                })?;
+                marginal += weight * p_true;
+            }
+            PreviewResult {
+                marginal,
+                method: PreviewMethod::IndependentParents,
+                approximate: true,
+                prior_source: Some(source),
+            }
+        };
+        serde_wasm_bindgen::to_value(&result)
+            .map_err(|e| JsValue::from_str(&format!("Failed to serialize result: {e}")))
+    }
+
+    /// Replaces nodes by ID (adding unknown ones) and returns
+    /// `{ fullRecompile }`. Only the edited records are rewritten unless a
+    /// parent set changed.
+    #[allow(clippy::missing_errors_doc)]
+    pub fn update_nodes(&mut self, changed_nodes: JsValue) -> Result<JsValue, JsValue> {
+        let result = self.update(deserialize_nodes(changed_nodes)?)?;
+        serde_wasm_bindgen::to_value(&result)
+            .map_err(|e| JsValue::from_str(&format!("Failed to serialize result: {e}")))
+    }
+
+    /// Replaces one node's `cptEntries` and returns `{ fullRecompile }`. The
+    /// fast path for parameter sliders: when the parents and the number of
+    /// entries are unchanged, the node's compiled record is overwritten in
+    /// place.
+    #[allow(clippy::missing_errors_doc)]
+    pub fn update_node_cpt(&mut self, node_id: &str, entries: JsValue) -> Result<JsValue, JsValue> {
+        let entries: Vec<CptEntry> = serde_wasm_bindgen::from_value(entries)
+            .map_err(|e| JsValue::from_str(&format!("Failed to deserialize entries: {e}")))?;
+        let full_recompile =
+            serialize::update_node_cpt(&mut self.serialized, node_id, &entries, &self.nodes)
+                .map_err(|e| JsValue::from_str(&format!("Serialization failed: {e}")))?;
+        let position = self.position(node_id);
+        self.nodes[position].cpt_entries = entries;
+        self.baseline = None;
+        self.retained = None;
+        self.priors = None;
+        serde_wasm_bindgen::to_value(&UpdateResult { full_recompile })
+            .map_err(|e| JsValue::from_str(&format!("Failed to serialize result: {e}")))
+    }
+
+    /// Reorders the compiled CPT entries so those a pilot run of
+    /// `num_samples` prior samples matches most often are checked first,
+    /// and returns the IDs of the nodes that changed. Lookups stop at the
+    /// first matching entry, so this speeds up nodes with many entries; an
+    /// entry never moves past one that could match the same parent states,
+    /// so every result stays the same. The fingerprint changes, and any
+    /// recompile of a node restores its given order.
+    #[allow(clippy::missing_errors_doc)]
+    pub fn order_entries_by_frequency(&mut self, num_samples: f64) -> Result<Vec<String>, JsValue> {
+        let num_samples = limits::count("numSamples", num_samples, limits::MAX_STORED_SAMPLES)
+            .map_err(limit_error)?;
+        let mut rng = seeded_rng()?;
+        let pilot = sample::sample_all(
+            &self.serialized.data,
+            &self.serialized.offsets,
+            num_samples,
+            &[],
+            &mut rng,
+        )
+        .map_err(|e| JsValue::from_str(&format!("Sampling failed: {e}")))?;
+        let reordered = sample::order_entries_by_hits(
+            &mut self.serialized.data,
+            &self.serialized.offsets,
+            &pilot,
+        )
+        .map_err(|e| JsValue::from_str(&format!("Reordering failed: {e}")))?;
+        Ok(reordered
+            .into_iter()
+            .map(|node| self.serialized.topo_order[usize::from(node)].clone())
+            .collect())
+    }
+
+    /// `num_samples` prior samples packed one bit per node, headed by the
+    /// network's layout fingerprint so [`Self::read_raw_samples`] can refuse
+    /// them once an edit has moved the nodes.
+    #[allow(clippy::missing_errors_doc)]
+    pub fn export_raw_samples(
+        &self,
+        num_samples: f64,
+        seed: Option<f64>,
+    ) -> Result<Vec<u8>, JsValue> {
+        let num_samples = limits::count("numSamples", num_samples, limits::MAX_STORED_SAMPLES)
+            .map_err(limit_error)?;
+        let seed = seed
+            .map(|seed| limits::seed("seed", seed))
+            .transpose()
+            .map_err(limit_error)?;
+        let (_, mut rng) = rng_from_seed(seed)?;
+        let samples = sample::sample_all(
+            &self.serialized.data,
+            &self.serialized.offsets,
+            num_samples,
+            &[],
+            &mut rng,
+        )
+        .map_err(|e| JsValue::from_str(&format!("Sampling failed: {e}")))?;
+        Ok(raw_samples::encode(&self.serialized.topo_order, &samples))
+    }
+
+    /// The samples of an [`Self::export_raw_samples`] blob as one boolean
+    /// column per node ID. Throws a `LAYOUT_MISMATCH` error when the blob
+    /// was drawn under a different topological order.
+    #[allow(clippy::missing_errors_doc)]
+    pub fn read_raw_samples(&self, bytes: &[u8]) -> Result<JsValue, JsValue> {
+        let samples = raw_samples::decode(&self.serialized.topo_order, bytes).map_err(|e| {
+            match e.downcast_ref::<raw_samples::LayoutMismatch>() {
+                Some(mismatch) => layout_mismatch_error(mismatch),
+                None => JsValue::from_str(&format!("Failed to read samples: {e}")),
+            }
+        })?;
+        let columns: HashMap<&str, Vec<bool>> = self
+            .serialized
+            .topo_order
+            .iter()
+            .zip(0..)
+            .map(|(id, index)| {
+                let column = samples
+                    .iter()
+                    .map(|sample| sample.contains(index))
+                    .collect();
+                (id.as_str(), column)
+            })
+            .collect();
+        columns
+            .serialize(&serde_wasm_bindgen::Serializer::new().serialize_maps_as_objects(true))
+            .map_err(|e| JsValue::from_str(&format!("Failed to serialize result: {e}")))
+    }
+
+    #[allow(clippy::missing_errors_doc)]
+    pub fn nodes(&self) -> Result<JsValue, JsValue> {
+        serialize_nodes(&self.nodes)
+    }
+
+    #[must_use]
+    pub fn fingerprint(&self) -> String {
+        self.serialized.fingerprint()
+    }
+
+    /// Fingerprint of the topological order alone, as in `index_map`.
+    #[must_use]
+    pub fn layout_fingerprint(&self) -> String {
+        serialize::layout_fingerprint(&self.serialized.topo_order)
+    }
+
+    #[wasm_bindgen(getter)]
+    #[must_use]
+    pub fn memory_bytes(&self) -> usize {
+        self.heap_bytes()
+    }
+}
+
+#[derive(Serialize)]
+#[serde(rename_all = "camelCase")]
+pub struct NodeInfo {
+    pub num_parents: usize,
+    /// In topological order.
+    pub parent_ids: Vec<String>,
+    pub num_cpt_entries: usize,
+    pub is_root: bool,
+    pub is_leaf: bool,
+    pub topo_index: u8,
+    /// Unmodeled causes OR-ed into the CPT; not counted among the parents.
+    pub leak_probability: Option<f64>,
+}
+
+#[derive(Serialize)]
+#[serde(rename_all = "camelCase")]
+pub struct NetworkSummary {
+    pub num_nodes: usize,
+    pub num_edges: usize,
+    pub max_parents: usize,
+    pub avg_cpt_entries: f64,
+    /// Always false: a network with a cycle fails to compile.
+    pub has_cycles: bool,
+}
+
+/// Structure of one node, read from the compiled parent lists.
+#[wasm_bindgen]
+#[allow(clippy::missing_errors_doc)]
+pub fn get_node_info(
+    compiled_network: &CompiledNetwork,
+    node_id: &str,
+) -> Result<JsValue, JsValue> {
+    let serialized = &compiled_network.serialized;
+    let index = serialized
+        .index_of(node_id)
+        .ok_or_else(|| JsValue::from_str(&format!("Node {node_id} not found")))?;
+    let parents = &serialized.parents[usize::from(index)];
+    let node = &compiled_network.nodes[compiled_network.position(node_id)];
+    let info = NodeInfo {
+        num_parents: parents.len(),
+        parent_ids: parents
+            .iter()
+            .map(|&parent| serialized.topo_order[usize::from(parent)].clone())
+            .collect(),
+        num_cpt_entries: node.cpt_entries.len(),
+        is_root: parents.is_empty(),
+        is_leaf: !serialized
+            .parents
+            .iter()
+            .any(|parents| parents.contains(&index)),
+        topo_index: index,
+        leak_probability: node.leak_probability,
+    };
+    serde_wasm_bindgen::to_value(&info)
+        .map_err(|e| JsValue::from_str(&format!("Failed to serialize result: {e}")))
+}
+
+/// Pre-flight check of a query before sampling, as `{ allIdsExist,
+/// missingIds, interventionAffectsQuery, evidenceInformativeness }`. Evidence
+/// mapped to false is d-separated from every query node and could be dropped.
+#[wasm_bindgen]
+#[allow(clippy::missing_errors_doc, clippy::needless_pass_by_value)]
+pub fn validate_query(
+    compiled_network: &CompiledNetwork,
+    intervention_id: Option<String>,
+    // wasm-bindgen cannot take a `&[String]`.
+    evidence_ids: Vec<String>,
+    query_ids: Vec<String>,
+) -> Result<JsValue, JsValue> {
+    let evidence_ids: Vec<&str> = evidence_ids.iter().map(String::as_str).collect();
+    let query_ids: Vec<&str> = query_ids.iter().map(String::as_str).collect();
+    let validation = structure::validate_query(
+        &compiled_network.serialized,
+        intervention_id.as_deref(),
+        &evidence_ids,
+        &query_ids,
+    );
+    validation
+        .serialize(&serde_wasm_bindgen::Serializer::new().serialize_maps_as_objects(true))
+        .map_err(|e| JsValue::from_str(&format!("Failed to serialize result: {e}")))
+}
+
+#[wasm_bindgen]
+#[allow(clippy::missing_errors_doc)]
+pub fn get_network_summary(compiled_network: &CompiledNetwork) -> Result<JsValue, JsValue> {
+    let parents = &compiled_network.serialized.parents;
+    let nodes = &compiled_network.nodes;
+    let total_entries: usize = nodes.iter().map(|node| node.cpt_entries.len()).sum();
+    #[allow(clippy::cast_precision_loss)]
+    let summary = NetworkSummary {
+        num_nodes: nodes.len(),
+        num_edges: parents.iter().map(Vec::len).sum(),
+        max_parents: parents.iter().map(Vec::len).max().unwrap_or(0),
+        avg_cpt_entries: if nodes.is_empty() {
+            0.0
+        } else {
+            total_entries as f64 / nodes.len() as f64
+        },
+        has_cycles: false,
+    };
+    serde_wasm_bindgen::to_value(&summary)
+        .map_err(|e| JsValue::from_str(&format!("Failed to serialize result: {e}")))
+}
+
+/// Options as given, kept as JSON so recorded queries can be replayed.
+fn raw_options(options: JsValue) -> Result<serde_json::Value, JsValue> {
+    serde_wasm_bindgen::from_value(options)
+        .map_err(|e| JsValue::from_str(&format!("Failed to deserialize options: {e}")))
+}
+
+fn parse_options(options: &serde_json::Value) -> Result<options::QueryOptions, JsValue> {
+    serde_json::from_value(options.clone())
+        .map_err(|e| JsValue::from_str(&format!("Failed to deserialize options: {e}")))
+}
+
+fn intervention_digest(result: &InterventionResult) -> String {
+    let mut maps = vec![&result.true_case, &result.false_case];
+    maps.extend(&result.baseline);
+    recording::digest(&maps)
+}
@@ -0,0 +1,224 @@
+//! Proposed probabilities for the parent states a CPT leaves uncovered,
+//! from a logistic regression on the states it does cover.
+
+use anyhow::{Result, bail};
+use serde::Serialize;
+
+use crate::serialize::get_node_parents;
+use crate::statistics::invert;
+use crate::{CptEntry, Node};
+
+/// Every parent state is enumerated, so this caps the work at 1024 rows.
+const MAX_COMPLETION_PARENTS: usize = 10;
+/// Specified probabilities are kept this far from 0 and 1, where the logit
+/// is infinite and the fit would run off to infinity.
+const TARGET_MARGIN: f64 = 1e-3;
+const MAX_ITERATIONS: usize = 100;
+/// IRLS stops once no coefficient moves by more than this.
+const CONVERGENCE: f64 = 1e-10;
+/// A fit missing some specified row by more than this is not trusted to
+/// extrapolate.
+const MAX_RESIDUAL: f64 = 0.1;
+
+#[derive(Serialize)]
+#[serde(rename_all = "camelCase")]
+pub enum CompletionStatus {
+    /// Every parent state already has an entry.
+    Complete,
+    Suggested,
+    /// The specified rows can't pin down or don't fit the model; see `reason`.
+    InsufficientData,
+}
+
+#[derive(Serialize)]
+#[serde(rename_all = "camelCase")]
+pub struct SuggestedEntry {
+    #[serde(flatten)]
+    pub entry: CptEntry,
+    /// Always true, so suggestions stay recognizable once merged into a CPT.
+    pub suggested: bool,
+}
+
+#[derive(Serialize)]
+#[serde(rename_all = "camelCase")]
+pub struct CptCompletion {
+    pub status: CompletionStatus,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub reason: Option<String>,
+    /// One fully specified entry per uncovered parent state; append them
+    /// after the existing entries.
+    pub suggestions: Vec<SuggestedEntry>,
+    /// Largest gap between the fit and a specified row.
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub max_residual: Option<f64>,
+}
+
+impl CptCompletion {
+    fn complete() -> Self {
+        CptCompletion {
+            status: CompletionStatus::Complete,
+            reason: None,
+            suggestions: Vec::new(),
+            max_residual: None,
+        }
+    }
+
+    fn insufficient(reason: String, max_residual: Option<f64>) -> Self {
+        CptCompletion {
+            status: CompletionStatus::InsufficientData,
+            reason: Some(reason),
+            suggestions: Vec::new(),
+            max_residual,
+        }
+    }
+}
+
+/// Fits `logit P(true) = b0 + sum_i b_i x_i` to the covered parent states
+/// (each parent is 0 or 1) by iteratively reweighted least squares, and
+/// proposes the fitted probability for each uncovered state.
+///
+/// The model has one coefficient per parent plus an intercept, so it needs at
+/// least that many covered states, and they must vary every parent
+/// independently. Covered states that interact (say, an XOR of two parents)
+/// leave a large residual and are reported rather than extrapolated.
+pub fn suggest_cpt_completion(node: &Node) -> Result<CptCompletion> {
+    if node
+        .cpt_entries
+        .iter()
+        .any(|entry| entry.probability_params.is_some())
+    {
+        bail!(
+            "Node {id} has hierarchical entries; completion only supports fixed probabilities",
+            id = node.id
+        );
+    }
+    // Suggestions join the CPT, so fit its rows before the leak is OR-ed in.
+    let node = &Node {
+        leak_probability: None,
+        ..node.clone()
+    };
+    let mut parents = get_node_parents(node);
+    parents.sort_unstable();
+    if parents.len() > MAX_COMPLETION_PARENTS {
+        bail!(
+            "Node {id} has {count} parents; completion supports at most {MAX_COMPLETION_PARENTS}",
+            id = node.id,
+            count = parents.len()
+        );
+    }
+
+    let features = |row: usize| -> Vec<f64> {
+        std::iter::once(1.0)
+            .chain((0..parents.len()).map(|i| f64::from(u8::from(row & (1 << i) != 0))))
+            .collect()
+    };
+    let mut covered = Vec::new();
+    let mut uncovered = Vec::new();
+    for row in 0..1usize << parents.len() {
+        let parent_value = |parent_id: &str| {
+            parents
+                .iter()
+                .position(|&p| p == parent_id)
+                .is_some_and(|i| row & (1 << i) != 0)
+        };
+        match node.probability_given(parent_value) {
+            Some(probability) => covered.push((features(row), probability)),
+            None => uncovered.push(row),
+        }
+    }
+    if uncovered.is_empty() {
+        return Ok(CptCompletion::complete());
+    }
+    let num_coefficients = parents.len() + 1;
+    if covered.len() < num_coefficients {
+        return Ok(CptCompletion::insufficient(
+            format!(
+                "{covered} specified parent states can't fit {num_coefficients} coefficients",
+                covered = covered.len()
+            ),
+            None,
+        ));
+    }
+    let Some(coefficients) = fit_logistic(&covered, num_coefficients) else {
+        return Ok(CptCompletion::insufficient(
+            "The specified parent states don't vary every parent independently".to_string(),
+            None,
+        ));
+    };
+
+    let predict = |x: &[f64]| sigmoid(dot(x, &coefficients));
+    let max_residual = covered
+        .iter()
+        .map(|(x, probability)| (predict(x) - probability).abs())
+        .fold(0.0, f64::max);
+    if max_residual > MAX_RESIDUAL {
+        return Ok(CptCompletion::insufficient(
+            format!(
+                "The specified rows don't follow a logistic pattern; the fit misses one by \
+                 {max_residual}"
+            ),
+            Some(max_residual),
+        ));
+    }
+    let suggestions = uncovered
+        .into_iter()
+        .map(|row| SuggestedEntry {
+            entry: CptEntry {
+                parent_states: parents
+                    .iter()
+                    .enumerate()
+                    .map(|(i, parent)| ((*parent).to_string(), Some(row & (1 << i) != 0)))
+                    .collect(),
+                probability: predict(&features(row)),
+                is_probability_of_true: true,
+                probability_params: None,
+            },
+            suggested: true,
+        })
+        .collect();
+    Ok(CptCompletion {
+        status: CompletionStatus::Suggested,
+        reason: None,
+        suggestions,
+        max_residual: Some(max_residual),
+    })
+}
+
+/// Newton's method on the binomial log-likelihood, with the fractional
+/// targets standing in for observed frequencies. `None` when `X^T W X` is
+/// singular or the iteration doesn't settle.
+fn fit_logistic(rows: &[(Vec<f64>, f64)], num_coefficients: usize) -> Option<Vec<f64>> {
+    let mut coefficients = vec![0.0; num_coefficients];
+    for _ in 0..MAX_ITERATIONS {
+        let mut hessian = vec![vec![0.0; num_coefficients]; num_coefficients];
+        let mut gradient = vec![0.0; num_coefficients];
+        for (x, probability) in rows {
+            let target = probability.clamp(TARGET_MARGIN, 1.0 - TARGET_MARGIN);
+            let fitted = sigmoid(dot(x, &coefficients));
+            let weight = fitted * (1.0 - fitted);
+            for i in 0..num_coefficients {
+                gradient[i] += x[i] * (target - fitted);
+                for j in 0..num_coefficients {
+                    hessian[i][j] += weight * x[i] * x[j];
+                }
+            }
+        }
+        let inverse = invert(hessian)?;
+        let step: Vec<f64> = inverse.iter().map(|row| dot(row, &gradient)).collect();
+        for (coefficient, delta) in coefficients.iter_mut().zip(&step) {
+            *coefficient += delta;
+        }
+        if step.iter().all(|delta| delta.abs() < CONVERGENCE) {
+            return Some(coefficients);
+        }
+    }
+    None
+}
+
+fn dot(a: &[f64], b: &[f64]) -> f64 {
+    a.iter().zip(b).map(|(a, b)| a * b).sum()
+}
+
+fn sigmoid(x: f64) -> f64 {
+    1.0 / (1.0 + (-x).exp())
+}
@@ -0,0 +1,279 @@
+//! Logical constraints between nodes, standing in for categorical nodes:
+//! a mutually exclusive group says at most one of its nodes is true, as for
+//! the buckets of one quantity ("AGI by 2030", "AGI in 2030–2040", ...).
+//!
+//! The CPTs should already make violations impossible or nearly so;
+//! [`check_constraints`] measures how far they are from that, and a query can
+//! enforce the constraints by rejecting the samples that break them.
+
+use anyhow::{Result, anyhow, bail};
+use rand_xoshiro::Xoshiro128Plus;
+use serde::{Deserialize, Serialize};
+use std::collections::{BTreeMap, BTreeSet, HashMap};
+
+use crate::Node;
+use crate::assumptions::AssumptionSet;
+use crate::bit_set::BitSet;
+use crate::exact::{MAX_EXACT_NODES, exact_joint};
+use crate::marginals::{Algorithm, QueryMeta, estimate_marginals_by};
+use crate::sample::{self, Override};
+use crate::serialize::{SerializedNetwork, serialize_network};
+use crate::structure;
+
+/// Violation probabilities above this are reported as warnings.
+const VIOLATION_THRESHOLD: f64 = 1e-3;
+/// An enforced query gives up after rejecting this many draws per requested
+/// sample.
+const MAX_REJECTIONS_PER_SAMPLE: usize = 100;
+
+#[derive(Clone, Deserialize)]
+#[serde(tag = "type", rename_all = "camelCase")]
+pub enum Constraint {
+    /// At most one of `nodes` is true.
+    MutuallyExclusive { nodes: Vec<String> },
+}
+
+impl Constraint {
+    fn nodes(&self) -> &[String] {
+        match self {
+            Constraint::MutuallyExclusive { nodes } => nodes,
+        }
+    }
+
+    /// Whether the values of the constraint's nodes, in order, break it.
+    fn violated_by(&self, values: impl Iterator<Item = bool>) -> bool {
+        match self {
+            Constraint::MutuallyExclusive { .. } => values.filter(|&value| value).count() > 1,
+        }
+    }
+}
+
+/// Constraints by topological index, checked against whole samples.
+pub(crate) struct ResolvedConstraints(Vec<(Constraint, Vec<u8>)>);
+
+impl ResolvedConstraints {
+    pub(crate) fn new(constraints: &[Constraint], serialized: &SerializedNetwork) -> Result<Self> {
+        constraints
+            .iter()
+            .enumerate()
+            .map(|(i, constraint)| {
+                let nodes = constraint.nodes();
+                if nodes.len() < 2 {
+                    bail!("Constraint {i} needs at least two nodes");
+                }
+                let mut seen = BTreeSet::new();
+                if let Some(node_id) = nodes.iter().find(|id| !seen.insert(*id)) {
+                    bail!("Constraint {i} lists node {node_id} more than once");
+                }
+                let indices = nodes
+                    .iter()
+                    .map(|node_id| {
+                        serialized.index_of(node_id).ok_or_else(|| {
+                            anyhow!(
+                                "Constraint {i} references node {node_id} which is not in the \
+                                 node array"
+                            )
+                        })
+                    })
+                    .collect::<Result<Vec<u8>>>()?;
+                Ok((constraint.clone(), indices))
+            })
+            .collect::<Result<_>>()
+            .map(ResolvedConstraints)
+    }
+
+    fn violated_by(&self, sample: &BitSet) -> bool {
+        self.0.iter().any(|(constraint, indices)| {
+            constraint.violated_by(indices.iter().map(|&index| sample.contains(index)))
+        })
+    }
+
+    /// Rejection sampling that also rejects every draw breaking a
+    /// constraint, so the marginals are conditioned on the constraints
+    /// holding. Rejected draws come on top of `num_samples`; the fraction
+    /// rejected is returned alongside.
+    pub(crate) fn estimate_marginals(
+        &self,
+        algorithm: Algorithm,
+        serialized: &SerializedNetwork,
+        num_samples: usize,
+        overrides: &[Option<Override>],
+        evidence: &[(u8, bool)],
+        rng: &mut Xoshiro128Plus,
+    ) -> Result<(HashMap<String, f64>, QueryMeta, f64)> {
+        if algorithm == Algorithm::LikelihoodWeighting {
+            bail!("Enforcing constraints needs rejection sampling, not likelihood weighting");
+        }
+        let max_rejections = num_samples.saturating_mul(MAX_REJECTIONS_PER_SAMPLE);
+        let (mut drawn, mut rejected) = (0usize, 0usize);
+        let marginals = estimate_marginals_by(serialized, num_samples, evidence, || {
+            loop {
+                let sample = sample::sample(&serialized.data, &serialized.offsets, overrides, rng)?;
+                drawn += 1;
+                if !self.violated_by(&sample) {
+                    return Ok(sample);
+                }
+                rejected += 1;
+                if rejected > max_rejections {
+                    bail!(
+                        "Gave up after rejecting {rejected} samples that break the \
+                         constraints; the CPTs almost never satisfy them"
+                    );
+                }
+            }
+        })?;
+
+        let mut hard_evidence: Vec<u8> = evidence.iter().map(|&(node, _)| node).collect();
+        hard_evidence.sort_unstable();
+        #[allow(clippy::cast_precision_loss)]
+        let violation_rate = rejected as f64 / drawn as f64;
+        Ok((
+            marginals,
+            QueryMeta {
+                algorithm: Algorithm::Rejection,
+                pilot_acceptance: None,
+                hard_evidence: hard_evidence
+                    .into_iter()
+                    .map(|node| serialized.topo_order[usize::from(node)].clone())
+                    .collect(),
+                soft_evidence: Vec::new(),
+                zero_substitutions: None,
+            },
+            violation_rate,
+        ))
+    }
+}
+
+#[derive(Serialize)]
+#[serde(rename_all = "camelCase")]
+pub enum CheckMethod {
+    /// Enumerated over the constrained nodes and their ancestors.
+    Exact,
+    Sampled,
+}
+
+#[derive(Serialize)]
+#[serde(rename_all = "camelCase")]
+pub struct ConstraintCheck {
+    pub nodes: Vec<String>,
+    /// Prior probability that the constraint is broken.
+    pub violation_probability: f64,
+    pub method: CheckMethod,
+    /// The most likely way the constraint is broken, over its nodes.
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub example: Option<BTreeMap<String, bool>>,
+    /// Set when the violation probability exceeds 1e-3.
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub warning: Option<String>,
+}
+
+/// How likely each constraint is to be broken under the network's prior.
+///
+/// A constraint only depends on its nodes and their ancestors, so that
+/// sub-network is enumerated exactly when it has at most `MAX_EXACT_NODES`
+/// nodes; otherwise `num_samples` samples of the whole network are drawn.
+pub fn check_constraints(
+    nodes: &[Node],
+    constraints: &[Constraint],
+    num_samples: usize,
+    rng: &mut Xoshiro128Plus,
+) -> Result<Vec<ConstraintCheck>> {
+    let serialized = serialize_network(nodes)?;
+    let resolved = ResolvedConstraints::new(constraints, &serialized)?;
+    let mut samples: Option<Vec<BitSet>> = None;
+    resolved
+        .0
+        .iter()
+        .map(|(constraint, indices)| {
+            let ids = constraint.nodes();
+            let mut relevant: BTreeSet<String> = ids.iter().cloned().collect();
+            for &index in indices {
+                relevant.extend(structure::ancestors(&serialized, index));
+            }
+            // Mass of each violating configuration of the constrained
+            // nodes, in the order of `ids`.
+            let (violations, method) = if relevant.len() <= MAX_EXACT_NODES {
+                let subnetwork: Vec<Node> = nodes
+                    .iter()
+                    .filter(|node| relevant.contains(&node.id))
+                    .cloned()
+                    .collect();
+                let joint = exact_joint(&subnetwork, &AssumptionSet::default(), ids)?;
+                let violations = joint
+                    .iter()
+                    .enumerate()
+                    .filter(|&(_, &mass)| mass > 0.0)
+                    .map(|(state, &mass)| {
+                        let values: Vec<bool> =
+                            (0..ids.len()).map(|i| state & (1 << i) != 0).collect();
+                        (values, mass)
+                    })
+                    .filter(|(values, _)| constraint.violated_by(values.iter().copied()))
+                    .collect();
+                (violations, CheckMethod::Exact)
+            } else {
+                let samples = match &mut samples {
+                    Some(samples) => samples,
+                    None => samples.insert(
+                        (0..num_samples)
+                            .map(|_| {
+                                sample::sample(&serialized.data, &serialized.offsets, &[], rng)
+                            })
+                            .collect::<Result<_>>()?,
+                    ),
+                };
+                // Only violating configurations are kept, so a group of any
+                // size costs at most one entry per sample.
+                let mut violations: HashMap<Vec<bool>, f64> = HashMap::new();
+                #[allow(clippy::cast_precision_loss)]
+                let weight = 1.0 / samples.len() as f64;
+                for sample in samples.iter() {
+                    let values: Vec<bool> = indices
+                        .iter()
+                        .map(|&index| sample.contains(index))
+                        .collect();
+                    if constraint.violated_by(values.iter().copied()) {
+                        *violations.entry(values).or_default() += weight;
+                    }
+                }
+                (violations, CheckMethod::Sampled)
+            };
+            Ok(summarize(constraint, &violations, method))
+        })
+        .collect()
+}
+
+fn summarize(
+    constraint: &Constraint,
+    violations: &HashMap<Vec<bool>, f64>,
+    method: CheckMethod,
+) -> ConstraintCheck {
+    let ids = constraint.nodes();
+    let violation_probability = violations.values().sum();
+    // Ties are broken by configuration so the example does not depend on
+    // the map's iteration order.
+    let example = violations
+        .iter()
+        .max_by(|(a_values, a), (b_values, b)| a.total_cmp(b).then_with(|| b_values.cmp(a_values)))
+        .map(|(values, _)| ids.iter().cloned().zip(values.iter().copied()).collect());
+    let warning = (violation_probability > VIOLATION_THRESHOLD).then(|| {
+        let described: Vec<String> = example
+            .iter()
+            .flatten()
+            .map(|(id, value)| format!("{id}={value}"))
+            .collect();
+        format!(
+            "Nodes {nodes} should be mutually exclusive but break that with probability \
+             {violation_probability}, e.g. {{{described}}}",
+            nodes = ids.join(", "),
+            described = described.join(", ")
+        )
+    });
+    ConstraintCheck {
+        nodes: ids.to_vec(),
+        violation_probability,
+        method,
+        example,
+        warning,
+    }
+}
@@ -0,0 +1,196 @@
+//! Correlated priors over root nodes. A Bayesian network makes its roots
+//! independent; a Gaussian copula keeps each root's own `P(true)` but couples
+//! their draws, for elicited beliefs like "these two risks tend to go
+//! together" that have no common cause in the model. Results are then no
+//! longer those of the network as written.
+
+use anyhow::{Result, bail};
+use rand_xoshiro::Xoshiro128Plus;
+use serde::Deserialize;
+use std::collections::HashMap;
+
+use crate::marginals::{Algorithm, QueryMeta, estimate_marginals_by};
+use crate::sample::{self, Override};
+use crate::serialize::SerializedNetwork;
+use crate::statistics::{inverse_normal_cdf, standard_normal};
+
+/// Cholesky pivots below this are taken as zero, so singular but positive
+/// semi-definite matrices (perfect correlations) are accepted.
+const PIVOT_TOLERANCE: f64 = 1e-12;
+/// Pivots below minus this mean the matrix is not positive semi-definite.
+const PSD_TOLERANCE: f64 = 1e-9;
+/// Off-diagonal entries may differ from their transpose by this much.
+const SYMMETRY_TOLERANCE: f64 = 1e-9;
+
+/// Correlations between the latent normals behind `node_ids`, in that order.
+#[derive(Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub struct RootCorrelations {
+    pub node_ids: Vec<String>,
+    pub matrix: Vec<Vec<f64>>,
+}
+
+/// Root `i` is true when its latent normal is below `thresholds[i]`, which
+/// happens with the root's own `P(true)`.
+pub(crate) struct Copula {
+    roots: Vec<u8>,
+    thresholds: Vec<f64>,
+    /// Lower-triangular factor of the correlation matrix.
+    cholesky: Vec<Vec<f64>>,
+}
+
+impl Copula {
+    pub(crate) fn new(
+        correlations: &RootCorrelations,
+        serialized: &SerializedNetwork,
+    ) -> Result<Self> {
+        let n = correlations.node_ids.len();
+        if correlations.matrix.len() != n || correlations.matrix.iter().any(|row| row.len() != n) {
+            bail!("rootCorrelations matrix must be {n} by {n}, one row and column per node");
+        }
+        let mut roots = Vec::with_capacity(n);
+        let mut thresholds = Vec::with_capacity(n);
+        for node_id in &correlations.node_ids {
+            let Some(index) = serialized.index_of(node_id) else {
+                bail!("rootCorrelations references node {node_id} which is not in the node array");
+            };
+            if roots.contains(&index) {
+                bail!("rootCorrelations lists node {node_id} more than once");
+            }
+            if !serialized.parents[usize::from(index)].is_empty() {
+                bail!(
+                    "rootCorrelations node {node_id} has parents; only root nodes can be correlated"
+                );
+            }
+            let record = &serialized.data[serialized.offsets[usize::from(index)]
+                ..serialized.offsets[usize::from(index) + 1]];
+            roots.push(index);
+            thresholds.push(inverse_normal_cdf(f64::from(sample::root_probability(
+                record,
+            )?)));
+        }
+        for (i, row) in correlations.matrix.iter().enumerate() {
+            for (j, &value) in row.iter().enumerate() {
+                if !(-1.0..=1.0).contains(&value) {
+                    bail!("rootCorrelations entry [{i}][{j}] is {value}, outside [-1, 1]");
+                }
+                if i == j && (value - 1.0).abs() > SYMMETRY_TOLERANCE {
+                    bail!("rootCorrelations diagonal entry [{i}][{i}] must be 1, got {value}");
+                }
+                if (value - correlations.matrix[j][i]).abs() > SYMMETRY_TOLERANCE {
+                    bail!("rootCorrelations matrix is not symmetric at [{i}][{j}]");
+                }
+            }
+        }
+        Ok(Copula {
+            roots,
+            thresholds,
+            cholesky: cholesky(&correlations.matrix)?,
+        })
+    }
+
+    /// Rejection sampling with the correlated roots drawn jointly and every
+    /// other node from its CPT. Soft evidence and likelihood weighting would
+    /// need the roots' joint density, so only rejection sampling is offered.
+    pub(crate) fn estimate_marginals(
+        &self,
+        algorithm: Algorithm,
+        serialized: &SerializedNetwork,
+        num_samples: usize,
+        overrides: &[Option<Override>],
+        evidence: &[(u8, bool)],
+        rng: &mut Xoshiro128Plus,
+    ) -> Result<(HashMap<String, f64>, QueryMeta)> {
+        if algorithm == Algorithm::LikelihoodWeighting {
+            bail!(
+                "rootCorrelations need rejection sampling; likelihood weighting cannot apply them"
+            );
+        }
+        let num_nodes = serialized.num_nodes();
+        let mut overrides = overrides.to_vec();
+        overrides.resize(usize::from(num_nodes), None);
+        if let Some(&root) = self
+            .roots
+            .iter()
+            .find(|&&root| overrides[usize::from(root)].is_some())
+        {
+            bail!(
+                "Node {} is in rootCorrelations and also intervened on or clamped",
+                serialized.topo_order[usize::from(root)]
+            );
+        }
+        let mut normals = vec![0.0; self.roots.len()];
+        let marginals = estimate_marginals_by(serialized, num_samples, evidence, || {
+            for normal in &mut normals {
+                *normal = standard_normal(rng);
+            }
+            for (i, row) in self.cholesky.iter().enumerate() {
+                let latent: f64 = row.iter().zip(&normals).map(|(l, z)| l * z).sum();
+                overrides[usize::from(self.roots[i])] =
+                    Some(Override::Value(latent < self.thresholds[i]));
+            }
+            sample::sample(&serialized.data, &serialized.offsets, &overrides, rng)
+        })?;
+
+        let mut hard_evidence: Vec<u8> = evidence.iter().map(|&(node, _)| node).collect();
+        hard_evidence.sort_unstable();
+        Ok((
+            marginals,
+            QueryMeta {
+                algorithm: Algorithm::Rejection,
+                pilot_acceptance: None,
+                hard_evidence: hard_evidence
+                    .into_iter()
+                    .map(|node| serialized.topo_order[usize::from(node)].clone())
+                    .collect(),
+                soft_evidence: Vec::new(),
+                zero_substitutions: None,
+            },
+        ))
+    }
+}
+
+/// `L` with `L Lᵀ = matrix`. A zero pivot leaves its column zero, which is
+/// where a perfectly correlated variable copies earlier ones.
+fn cholesky(matrix: &[Vec<f64>]) -> Result<Vec<Vec<f64>>> {
+    let n = matrix.len();
+    let mut lower = vec![vec![0.0; n]; n];
+    for j in 0..n {
+        let pivot = matrix[j][j] - lower[j][..j].iter().map(|l| l * l).sum::<f64>();
+        if pivot < -PSD_TOLERANCE {
+            bail!(
+                "rootCorrelations matrix is not positive semi-definite, so no joint \
+                 distribution has these correlations"
+            );
+        }
+        if pivot <= PIVOT_TOLERANCE {
+            continue;
+        }
+        lower[j][j] = pivot.sqrt();
+        for i in j + 1..n {
+            let dot: f64 = lower[i][..j]
+                .iter()
+                .zip(&lower[j][..j])
+                .map(|(a, b)| a * b)
+                .sum();
+            lower[i][j] = (matrix[i][j] - dot) / lower[j][j];
+        }
+    }
+    // A zero pivot is only consistent when the rest of its column vanishes.
+    for j in 0..n {
+        for i in j + 1..n {
+            let reconstructed: f64 = lower[i][..=j]
+                .iter()
+                .zip(&lower[j][..=j])
+                .map(|(a, b)| a * b)
+                .sum();
+            if (reconstructed - matrix[i][j]).abs() > PSD_TOLERANCE.sqrt() {
+                bail!(
+                    "rootCorrelations matrix is not positive semi-definite, so no joint \
+                     distribution has these correlations"
+                );
+            }
+        }
+    }
+    Ok(lower)
+}
@@ -0,0 +1,294 @@
+//! Bulk CPT import from the spreadsheets domain experts fill in: one row per
+//! CPT entry, with columns `node, <parent>..., probability` and parent cells
+//! `TRUE`, `FALSE` or `ANY`.
+//!
+//! Rows are checked one at a time, so a sheet with a few mistakes still
+//! updates every node whose rows are all good; the rest are reported by line.
+
+use anyhow::{Result, bail};
+use serde::Serialize;
+use std::collections::{BTreeSet, HashMap};
+
+use crate::cpt_table::expand_table;
+use crate::lookup::suggestions;
+use crate::serialize::get_node_parents;
+use crate::{CptEntry, Node};
+
+/// A node's index and its row's parent states, sorted by parent.
+type RowKey = (usize, Vec<(String, Option<bool>)>);
+
+#[derive(Serialize)]
+#[serde(rename_all = "camelCase")]
+pub struct RowError {
+    /// 1-based line of the row in the CSV text.
+    pub line: usize,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub node_id: Option<String>,
+    pub message: String,
+}
+
+#[derive(Serialize)]
+#[serde(rename_all = "camelCase")]
+pub struct CptImport {
+    pub nodes: Vec<Node>,
+    /// Nodes whose entries were replaced, in network order.
+    pub updated_nodes: Vec<String>,
+    pub errors: Vec<RowError>,
+}
+
+/// Replaces the CPT entries of every node named in `csv`.
+///
+/// The header names a `node` column, a `probability` column (`P(true)`, in
+/// `[0, 1]`), and parent columns; case is ignored in `node` and
+/// `probability`. A parent cell is `TRUE`, `FALSE`, or `ANY` (or blank) for a
+/// wildcard, case-insensitively. A node's rows become its entries in file
+/// order, over the parents it already has: a row fixing a column that isn't
+/// one of them is an error.
+///
+/// A row naming an unknown node, fixing an unknown parent, giving a bad
+/// probability, or repeating an earlier row's node and parent states is
+/// reported and skipped. A node keeps its old entries when any of its rows
+/// is rejected, so it is never left with part of a table. Only a malformed
+/// header or quoting fails the whole import.
+pub fn import_cpts_csv(nodes: &[Node], csv: &str) -> Result<CptImport> {
+    let mut records = parse_csv(csv)?.into_iter();
+    let Some((_, header)) = records.next() else {
+        bail!("CSV is empty");
+    };
+    let columns = columns(&header)?;
+
+    let mut expanded = nodes.to_vec();
+    for node in &mut expanded {
+        expand_table(node)?;
+    }
+    let position: HashMap<&str, usize> = expanded
+        .iter()
+        .enumerate()
+        .map(|(i, node)| (node.id.as_str(), i))
+        .collect();
+    let parents: Vec<Vec<&str>> = expanded.iter().map(get_node_parents).collect();
+
+    let mut imported: Vec<Vec<CptEntry>> = vec![Vec::new(); expanded.len()];
+    let mut rejected = vec![false; expanded.len()];
+    // First line of each node's row for given parent states.
+    let mut first_line: HashMap<RowKey, usize> = HashMap::new();
+    let mut errors = Vec::new();
+    for (line, record) in records {
+        if record.iter().all(|cell| cell.trim().is_empty()) {
+            continue;
+        }
+        let cell = |index: usize| record.get(index).map_or("", |cell| cell.trim());
+        let node_id = cell(columns.node);
+        let mut error = |node_id: Option<&str>, message: String| {
+            errors.push(RowError {
+                line,
+                node_id: node_id.map(str::to_string),
+                message,
+            });
+        };
+        if record.len() != header.len() {
+            error(
+                Some(node_id),
+                format!(
+                    "Row has {cells} cells but the header has {expected}",
+                    cells = record.len(),
+                    expected = header.len()
+                ),
+            );
+            continue;
+        }
+        let Some(&index) = position.get(node_id) else {
+            let near = suggestions(node_id, position.keys().copied());
+            let hint = if near.is_empty() {
+                String::new()
+            } else {
+                format!("; did you mean '{}'?", near.join("' or '"))
+            };
+            error(Some(node_id), format!("Unknown node {node_id}{hint}"));
+            continue;
+        };
+        match row_entry(
+            &columns.parents,
+            &parents[index],
+            cell(columns.probability),
+            cell,
+        ) {
+            Ok(entry) => {
+                let mut key: Vec<(String, Option<bool>)> = entry
+                    .parent_states
+                    .iter()
+                    .map(|(parent, &state)| (parent.clone(), state))
+                    .collect();
+                key.sort_unstable();
+                if let Some(&earlier) = first_line.get(&(index, key.clone())) {
+                    error(
+                        Some(node_id),
+                        format!("Duplicates the row on line {earlier}"),
+                    );
+                    rejected[index] = true;
+                    continue;
+                }
+                first_line.insert((index, key), line);
+                imported[index].push(entry);
+            }
+            Err(message) => {
+                error(Some(node_id), message);
+                rejected[index] = true;
+            }
+        }
+    }
+
+    let mut updated_nodes = Vec::new();
+    let mut result = nodes.to_vec();
+    for (i, entries) in imported.into_iter().enumerate() {
+        if entries.is_empty() || rejected[i] {
+            continue;
+        }
+        result[i].cpt_table = None;
+        result[i].cpt_entries = entries;
+        updated_nodes.push(result[i].id.clone());
+    }
+    Ok(CptImport {
+        nodes: result,
+        updated_nodes,
+        errors,
+    })
+}
+
+/// Column indices of a CSV header.
+struct Columns<'a> {
+    node: usize,
+    probability: usize,
+    /// Every other column, with its trimmed name.
+    parents: Vec<(usize, &'a str)>,
+}
+
+fn columns(header: &[String]) -> Result<Columns<'_>> {
+    let column = |name: &str| {
+        let mut matching = header
+            .iter()
+            .enumerate()
+            .filter(|(_, cell)| cell.trim().eq_ignore_ascii_case(name));
+        match (matching.next(), matching.next()) {
+            (Some((index, _)), None) => Ok(index),
+            (None, _) => bail!("CSV header has no {name} column"),
+            (Some(_), Some(_)) => bail!("CSV header names column {name} more than once"),
+        }
+    };
+    let (node_column, probability_column) = (column("node")?, column("probability")?);
+    let parent_columns: Vec<(usize, &str)> = header
+        .iter()
+        .enumerate()
+        .filter(|&(index, _)| index != node_column && index != probability_column)
+        .map(|(index, cell)| (index, cell.trim()))
+        .collect();
+    let mut seen = BTreeSet::new();
+    if let Some((_, parent)) = parent_columns.iter().find(|(_, id)| !seen.insert(*id)) {
+        bail!("CSV header names column {parent} more than once");
+    }
+    Ok(Columns {
+        node: node_column,
+        probability: probability_column,
+        parents: parent_columns,
+    })
+}
+
+/// The entry a row describes, over every one of the node's parents.
+fn row_entry<'a>(
+    parent_columns: &[(usize, &str)],
+    parents: &[&str],
+    probability: &str,
+    cell: impl Fn(usize) -> &'a str,
+) -> Result<CptEntry, String> {
+    let mut parent_states: HashMap<String, Option<bool>> = parents
+        .iter()
+        .map(|&parent| (parent.to_string(), None))
+        .collect();
+    for &(index, parent) in parent_columns {
+        let state = match cell(index).to_ascii_uppercase().as_str() {
+            "TRUE" => Some(true),
+            "FALSE" => Some(false),
+            "ANY" | "" => None,
+            other => {
+                return Err(format!(
+                    "Parent {parent} has value {other:?}; expected TRUE, FALSE or ANY"
+                ));
+            }
+        };
+        if let Some(state) = state {
+            if !parents.contains(&parent) {
+                return Err(format!(
+                    "Unknown parent {parent}; it is not a parent of this node"
+                ));
+            }
+            parent_states.insert(parent.to_string(), Some(state));
+        }
+    }
+    let probability = probability
+        .parse::<f64>()
+        .ok()
+        .filter(|p| (0.0..=1.0).contains(p))
+        .ok_or_else(|| format!("Probability {probability:?} is not a number in [0, 1]"))?;
+    Ok(CptEntry {
+        parent_states,
+        probability,
+        is_probability_of_true: true,
+        probability_params: None,
+    })
+}
+
+/// Records with the 1-based line each starts on. Fields may be quoted, with
+/// `""` for a quote, and quoted fields may hold commas and line breaks.
+fn parse_csv(text: &str) -> Result<Vec<(usize, Vec<String>)>> {
+    let mut records = Vec::new();
+    let mut record = Vec::new();
+    let mut field = String::new();
+    let (mut line, mut record_line) = (1, 1);
+    let mut quoted = false;
+    let mut chars = text.chars().peekable();
+    while let Some(c) = chars.next() {
+        match c {
+            '"' if quoted => {
+                if chars.peek() == Some(&'"') {
+                    chars.next();
+                    field.push('"');
+                } else {
+                    quoted = false;
+                }
+            }
+            '"' if field.trim().is_empty() => {
+                field.clear();
+                quoted = true;
+            }
+            ',' if !quoted => record.push(std::mem::take(&mut field)),
+            '\r' if !quoted && chars.peek() == Some(&'\n') => {}
+            '\n' if !quoted => {
+                record.push(std::mem::take(&mut field));
+                records.push((record_line, std::mem::take(&mut record)));
+                line += 1;
+                record_line = line;
+            }
+            _ => {
+                if c == '\n' {
+                    line += 1;
+                }
+                field.push(c);
+            }
+        }
+    }
+    if quoted {
+        bail!("CSV line {record_line} has an unterminated quoted field");
+    }
+    if !field.is_empty() || !record.is_empty() {
+        record.push(field);
+        records.push((record_line, record));
+    }
+    // Lines before the header are skipped when blank.
+    while records
+        .first()
+        .is_some_and(|(_, record)| record.iter().all(|cell| cell.trim().is_empty()))
+    {
+        records.remove(0);
+    }
+    Ok(records)
+}
@@ -0,0 +1,117 @@
+//! CPTs given as complete truth tables, the natural shape of imported
+//! tables, converted to and from the first-match entry form.
+
+use anyhow::{Result, bail};
+use serde::{Deserialize, Serialize};
+use std::collections::HashSet;
+
+use crate::serialize::get_node_parents;
+use crate::{CptEntry, Node};
+
+/// Entries are capped at `u8::MAX`, so a table has at most 7 parents.
+const MAX_TABLE_PARENTS: usize = 7;
+
+/// `P(true)` for every assignment of `parent_order`: row `r` has
+/// `parent_order[i]` true when bit `i` of `r` is set, so the first parent
+/// alternates fastest and row 0 is all parents false.
+#[derive(Clone, Serialize, Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub struct CptTable {
+    pub parent_order: Vec<String>,
+    pub probabilities: Vec<f64>,
+}
+
+impl CptTable {
+    /// One fully specified entry per row.
+    pub(crate) fn to_entries(&self, node_id: &str) -> Result<Vec<CptEntry>> {
+        let num_parents = self.parent_order.len();
+        if num_parents > MAX_TABLE_PARENTS {
+            bail!(
+                "Node {node_id} cptTable has {num_parents} parents, maximum \
+                 {MAX_TABLE_PARENTS} supported"
+            );
+        }
+        let mut seen = HashSet::new();
+        if let Some(parent) = self.parent_order.iter().find(|id| !seen.insert(*id)) {
+            bail!("Node {node_id} cptTable lists parent {parent} more than once");
+        }
+        if self.probabilities.len() != 1 << num_parents {
+            bail!(
+                "Node {node_id} cptTable has {len} probabilities; {num_parents} parents need {rows}",
+                len = self.probabilities.len(),
+                rows = 1 << num_parents
+            );
+        }
+        Ok(self
+            .probabilities
+            .iter()
+            .enumerate()
+            .map(|(row, &probability)| CptEntry {
+                parent_states: self
+                    .parent_order
+                    .iter()
+                    .enumerate()
+                    .map(|(i, parent)| (parent.clone(), Some(row & (1 << i) != 0)))
+                    .collect(),
+                probability,
+                is_probability_of_true: true,
+                probability_params: None,
+            })
+            .collect())
+    }
+
+    /// The node's CPT as a table over its parents in ID order, or `None`
+    /// when it has hierarchical entries, more than `MAX_TABLE_PARENTS`
+    /// parents, or an assignment no entry matches.
+    pub(crate) fn from_node(node: &Node) -> Option<Self> {
+        if node
+            .cpt_entries
+            .iter()
+            .any(|entry| entry.probability_params.is_some())
+        {
+            return None;
+        }
+        let mut parent_order: Vec<String> = get_node_parents(node)
+            .into_iter()
+            .map(str::to_string)
+            .collect();
+        if parent_order.len() > MAX_TABLE_PARENTS {
+            return None;
+        }
+        parent_order.sort_unstable();
+        let probabilities = (0..1usize << parent_order.len())
+            .map(|row| {
+                node.cpt_entries
+                    .iter()
+                    .find(|entry| {
+                        entry.parent_states.iter().all(|(parent, state)| {
+                            state.is_none_or(|expected| {
+                                let i = parent_order.iter().position(|id| id == parent);
+                                i.is_some_and(|i| (row & (1 << i) != 0) == expected)
+                            })
+                        })
+                    })
+                    .map(CptEntry::probability_of_true)
+            })
+            .collect::<Option<Vec<f64>>>()?;
+        Some(CptTable {
+            parent_order,
+            probabilities,
+        })
+    }
+}
+
+/// Replaces a node's `cpt_table` with the equivalent entries.
+pub fn expand_table(node: &mut Node) -> Result<()> {
+    let Some(table) = node.cpt_table.take() else {
+        return Ok(());
+    };
+    if !node.cpt_entries.is_empty() {
+        bail!(
+            "Node {id} has both cptEntries and cptTable; give one or the other",
+            id = node.id
+        );
+    }
+    node.cpt_entries = table.to_entries(&node.id)?;
+    Ok(())
+}
@@ -0,0 +1,60 @@
+//! Synthetic datasets drawn from a network, for teaching and for checking
+//! causal intuitions against data.
+
+use anyhow::{Result, anyhow};
+use rand::Rng;
+use std::fmt::Write;
+
+use crate::marginals::intervention;
+use crate::sample;
+use crate::serialize::SerializedNetwork;
+
+/// `n` observational rows paired with `n` rows under `do(treatment = true)`,
+/// as CSV with columns `row, regime, diverged` followed by `columns`.
+///
+/// Each pair shares its random numbers, so the two rows of a pair differ only
+/// where the intervention propagates; `diverged` marks pairs that differ at
+/// all. Node values are written as `true`/`false`, the form
+/// `learn_parameters_from_csv` reads.
+pub(crate) fn paired_dataset_csv(
+    serialized: &SerializedNetwork,
+    columns: &[String],
+    n: usize,
+    treatment: u8,
+    rng: &mut impl Rng,
+) -> Result<String> {
+    let num_nodes = serialized.num_nodes();
+    let indices = columns
+        .iter()
+        .map(|id| {
+            serialized
+                .index_of(id)
+                .ok_or_else(|| anyhow!("Node {id} not found"))
+        })
+        .collect::<Result<Vec<_>>>()?;
+    let observational = vec![None; usize::from(num_nodes)];
+    let interventional = intervention(num_nodes, treatment, true);
+
+    let mut csv = String::from("row,regime,diverged");
+    for id in columns {
+        write!(csv, ",{id}")?;
+    }
+    csv.push('\n');
+    for row in 0..n {
+        let worlds = sample::sample_twin(
+            &serialized.data,
+            &serialized.offsets,
+            [&observational, &interventional],
+            rng,
+        )?;
+        let diverged = !worlds[0].symmetric_difference(&worlds[1]).is_empty();
+        for (regime, world) in ["observational", "interventional"].into_iter().zip(worlds) {
+            write!(csv, "{row},{regime},{diverged}")?;
+            for &index in &indices {
+                write!(csv, ",{}", world.contains(index))?;
+            }
+            csv.push('\n');
+        }
+    }
+    Ok(csv)
+}
@@ -0,0 +1,260 @@
+//! First-order dynamic Bayesian networks written as a single time slice:
+//! a node `X_prev` holds `X` at time `t - 1` and `X_next` holds it at `t`.
+//! Every other node is marginalized out of each step.
+
+use anyhow::{Result, bail};
+use serde::Serialize;
+use std::collections::{BTreeMap, BTreeSet};
+
+use crate::Node;
+use crate::assumptions::AssumptionSet;
+use crate::exact::exact_joint;
+
+const PREV_SUFFIX: &str = "_prev";
+const NEXT_SUFFIX: &str = "_next";
+
+/// The transition matrix has `4^n` entries, each an enumeration of the slice.
+const MAX_STATE_VARIABLES: usize = 6;
+/// Squarings of the lazy chain before giving up on a long-run distribution,
+/// so up to `2^64` steps.
+const MAX_SQUARINGS: u32 = 64;
+/// Rows of a converged power agree to within this.
+const CONVERGENCE: f64 = 1e-12;
+/// Total variation distance from the long-run distribution that counts as
+/// mixed in [`SteadyState`].
+const MIXING_THRESHOLD: f64 = 0.25;
+/// Longest mixing time searched for, as a power of two.
+const MAX_MIXING_DOUBLINGS: u32 = 40;
+/// How far a row of a transition matrix may sum from 1.
+const ROW_SUM_TOLERANCE: f64 = 1e-9;
+
+pub type Matrix = Vec<Vec<f64>>;
+
+/// `X` for every `X_prev` / `X_next` pair, sorted. Bit `i` of a state is
+/// `variables[i]`.
+pub fn state_variables(nodes: &[Node]) -> Result<Vec<String>> {
+    let ids: BTreeSet<&str> = nodes.iter().map(|node| node.id.as_str()).collect();
+    let mut variables = Vec::new();
+    for id in &ids {
+        if let Some(name) = id.strip_suffix(PREV_SUFFIX) {
+            if !ids.contains(format!("{name}{NEXT_SUFFIX}").as_str()) {
+                bail!("Node {id} has no {name}{NEXT_SUFFIX} counterpart");
+            }
+            variables.push(name.to_string());
+        } else if let Some(name) = id.strip_suffix(NEXT_SUFFIX)
+            && !ids.contains(format!("{name}{PREV_SUFFIX}").as_str())
+        {
+            bail!("Node {id} has no {name}{PREV_SUFFIX} counterpart");
+        }
+    }
+    if variables.is_empty() {
+        bail!("No state variables found; name them X{PREV_SUFFIX} and X{NEXT_SUFFIX}");
+    }
+    if variables.len() > MAX_STATE_VARIABLES {
+        bail!(
+            "Transition matrices support at most {MAX_STATE_VARIABLES} state variables, got {}",
+            variables.len()
+        );
+    }
+    Ok(variables)
+}
+
+/// `M[s_old][s_new]`, the probability of moving from `s_old` to `s_new` in
+/// one step, by exact enumeration with every `X_prev` set to `s_old`.
+pub fn compute_dbn_transition_matrix(nodes: &[Node]) -> Result<Matrix> {
+    let variables = state_variables(nodes)?;
+    let next: Vec<String> = variables
+        .iter()
+        .map(|name| format!("{name}{NEXT_SUFFIX}"))
+        .collect();
+    (0..1usize << variables.len())
+        .map(|old| {
+            let assumptions = AssumptionSet {
+                interventions: variables
+                    .iter()
+                    .enumerate()
+                    .map(|(i, name)| (format!("{name}{PREV_SUFFIX}"), old & (1 << i) != 0))
+                    .collect(),
+                ..AssumptionSet::default()
+            };
+            exact_joint(nodes, &assumptions, &next)
+        })
+        .collect()
+}
+
+pub fn multiply(a: &Matrix, b: &Matrix) -> Matrix {
+    a.iter()
+        .map(|row| {
+            (0..b.len())
+                .map(|j| row.iter().zip(b).map(|(x, b_row)| x * b_row[j]).sum())
+                .collect()
+        })
+        .collect()
+}
+
+/// `m^t` by repeated squaring.
+pub fn matrix_power(m: &Matrix, mut t: u64) -> Matrix {
+    let mut result: Matrix = (0..m.len())
+        .map(|i| {
+            (0..m.len())
+                .map(|j| if i == j { 1.0 } else { 0.0 })
+                .collect()
+        })
+        .collect();
+    let mut square = m.clone();
+    while t > 0 {
+        if t & 1 == 1 {
+            result = multiply(&result, &square);
+        }
+        t >>= 1;
+        if t > 0 {
+            square = multiply(&square, &square);
+        }
+    }
+    result
+}
+
+#[derive(Serialize)]
+#[serde(rename_all = "camelCase")]
+pub struct SteadyState {
+    pub variables: Vec<String>,
+    pub transition_matrix: Matrix,
+    /// Long-run probability of each state.
+    pub state_distribution: Vec<f64>,
+    /// Long-run `P(true)` of each state variable.
+    pub marginals: BTreeMap<String, f64>,
+    /// Fewest steps after which every starting state is closer than total
+    /// variation distance 1/4 to the long-run distribution; `None` for
+    /// periodic chains, which never settle, or beyond `2^40` steps.
+    pub mixing_time: Option<u64>,
+}
+
+/// The long-run behaviour of the chain.
+pub fn compute_dbn_steady_state(nodes: &[Node]) -> Result<SteadyState> {
+    let variables = state_variables(nodes)?;
+    let transition_matrix = compute_dbn_transition_matrix(nodes)?;
+    let state_distribution = stationary_distribution(&transition_matrix)?;
+
+    let marginals = variables
+        .iter()
+        .enumerate()
+        .map(|(i, name)| {
+            let p_true = state_distribution
+                .iter()
+                .enumerate()
+                .filter(|&(state, _)| state & (1 << i) != 0)
+                .map(|(_, p)| p)
+                .sum();
+            (name.clone(), p_true)
+        })
+        .collect();
+    let mixing_time = mixing_time(&transition_matrix, &state_distribution, MIXING_THRESHOLD);
+    Ok(SteadyState {
+        variables,
+        transition_matrix,
+        state_distribution,
+        marginals,
+        mixing_time,
+    })
+}
+
+/// The long-run distribution, found by squaring the lazy chain
+/// `(M + I) / 2`, which has the same stationary distribution but no
+/// periodicity, until its rows agree. That fails when the chain has more than
+/// one closed class of states, since the long run then depends on the start.
+fn stationary_distribution(m: &Matrix) -> Result<Vec<f64>> {
+    check_stochastic(m)?;
+    let mut lazy: Matrix = m
+        .iter()
+        .enumerate()
+        .map(|(i, row)| {
+            row.iter()
+                .enumerate()
+                .map(|(j, &p)| f64::midpoint(p, if i == j { 1.0 } else { 0.0 }))
+                .collect()
+        })
+        .collect();
+    for _ in 0..MAX_SQUARINGS {
+        lazy = multiply(&lazy, &lazy);
+        if lazy.iter().all(|row| {
+            row.iter()
+                .zip(&lazy[0])
+                .all(|(a, b)| (a - b).abs() < CONVERGENCE)
+        }) {
+            return Ok(lazy.swap_remove(0));
+        }
+    }
+    bail!("The chain has no unique long-run distribution; it depends on the starting state")
+}
+
+/// A non-empty square matrix whose rows are probability distributions.
+fn check_stochastic(m: &Matrix) -> Result<()> {
+    if m.is_empty() {
+        bail!("The transition matrix has no states");
+    }
+    if m.iter().any(|row| row.len() != m.len()) {
+        bail!("The transition matrix must be square");
+    }
+    for (i, row) in m.iter().enumerate() {
+        if let Some(p) = row.iter().find(|p| !(**p >= 0.0 && p.is_finite())) {
+            bail!("Row {i} of the transition matrix has entry {p}, not a probability");
+        }
+        let total: f64 = row.iter().sum();
+        if (total - 1.0).abs() > ROW_SUM_TOLERANCE {
+            bail!("Row {i} of the transition matrix sums to {total}, not 1");
+        }
+    }
+    Ok(())
+}
+
+/// Fewest steps after which every starting state of the chain with
+/// transition matrix `m` is closer than total variation distance `epsilon`
+/// to the long-run distribution. The worst initial distribution is always a single
+/// state, since distance is convex in the start. `None` for periodic chains
+/// or beyond `2^40` steps.
+///
+/// # Errors
+///
+/// When `epsilon` is outside `(0, 1)`, `m` is not a non-empty square matrix
+/// of probability rows, or the chain has more than one long-run distribution.
+pub fn compute_mixing_time(m: &Matrix, epsilon: f64) -> Result<Option<u64>> {
+    if !(epsilon > 0.0 && epsilon < 1.0) {
+        bail!("epsilon must be within (0, 1), got {epsilon}");
+    }
+    Ok(mixing_time(m, &stationary_distribution(m)?, epsilon))
+}
+
+/// Distance to stationarity never grows with `t`, so the mixing time is
+/// bracketed by doubling and then found by bisection.
+fn mixing_time(m: &Matrix, stationary: &[f64], epsilon: f64) -> Option<u64> {
+    let mixed = |t| {
+        matrix_power(m, t).iter().all(|row| {
+            row.iter()
+                .zip(stationary)
+                .map(|(p, q)| (p - q).abs())
+                .sum::<f64>()
+                / 2.0
+                < epsilon
+        })
+    };
+    if mixed(0) {
+        return Some(0);
+    }
+    let mut high = 1;
+    while !mixed(high) {
+        if high >= 1 << MAX_MIXING_DOUBLINGS {
+            return None;
+        }
+        high *= 2;
+    }
+    let mut low = high / 2;
+    while high - low > 1 {
+        let mid = low + (high - low) / 2;
+        if mixed(mid) {
+            high = mid;
+        } else {
+            low = mid;
+        }
+    }
+    Some(high)
+}
@@ -0,0 +1,251 @@
+//! Where two parameterizations of one agreed structure disagree, for
+//! collaborators reconciling probabilities they filled in independently.
+
+use anyhow::{Result, bail};
+use rand::{Rng, SeedableRng};
+use rand_xoshiro::Xoshiro128Plus;
+use serde::{Deserialize, Serialize};
+use std::collections::{BTreeSet, HashMap};
+
+use crate::calibration::sensitivities;
+use crate::marginals::estimate_marginals;
+use crate::serialize::{get_node_parents, serialize_network};
+use crate::structure::ancestors;
+use crate::{Node, lookup};
+
+const DEFAULT_THRESHOLD: f64 = 0.05;
+const DEFAULT_TOP_K: usize = 5;
+
+#[derive(Deserialize, Default)]
+#[serde(rename_all = "camelCase", default)]
+pub struct ComparisonOptions {
+    /// Node whose marginal the disagreements are ranked by.
+    pub target_id: Option<String>,
+    /// Smallest entry difference reported; 0.05 by default.
+    pub threshold: Option<f64>,
+    /// Entries ranked for the target; 5 by default.
+    pub top_k: Option<usize>,
+}
+
+#[derive(Serialize)]
+#[serde(rename_all = "camelCase")]
+pub struct MarginalDifference {
+    pub node_id: String,
+    pub marginal_a: f64,
+    pub marginal_b: f64,
+    /// `marginal_b - marginal_a`.
+    pub difference: f64,
+}
+
+#[derive(Serialize)]
+#[serde(rename_all = "camelCase")]
+pub struct EntryDifference {
+    pub node_id: String,
+    /// Index into the first network's `cptEntries` for the node.
+    pub entry_index: usize,
+    pub parent_states: HashMap<String, Option<bool>>,
+    /// `P(true)` in each network.
+    pub probability_a: f64,
+    pub probability_b: f64,
+    pub difference: f64,
+}
+
+#[derive(Serialize)]
+#[serde(rename_all = "camelCase")]
+pub struct EntryImpact {
+    pub node_id: String,
+    pub entry_index: usize,
+    pub difference: f64,
+    /// `d P(target) / d P(true)` of the entry in the first network.
+    pub sensitivity: f64,
+    /// `sensitivity * difference`: how far adopting the second network's
+    /// value moves the target, to first order.
+    pub target_shift: f64,
+}
+
+#[derive(Serialize)]
+#[serde(rename_all = "camelCase")]
+pub struct ParameterComparison {
+    /// Largest absolute difference first.
+    pub marginal_differences: Vec<MarginalDifference>,
+    /// Entries differing by at least the threshold, largest first.
+    pub entry_differences: Vec<EntryDifference>,
+    /// Entries of the first network with no entry for the same parent
+    /// states in the second, which are not compared.
+    pub unmatched_entries: usize,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub target_impacts: Option<Vec<EntryImpact>>,
+}
+
+/// Fails with every difference when `a` and `b` don't share node IDs and
+/// parent sets.
+fn check_same_structure(a: &[Node], b: &HashMap<&str, &Node>) -> Result<()> {
+    let ids_a: BTreeSet<&str> = a.iter().map(|node| node.id.as_str()).collect();
+    let mut differences = Vec::new();
+    for node in a {
+        let Some(other) = b.get(node.id.as_str()) else {
+            differences.push(format!("{} is only in the first network", node.id));
+            continue;
+        };
+        let parents_a: BTreeSet<&str> = get_node_parents(node).into_iter().collect();
+        let parents_b: BTreeSet<&str> = get_node_parents(other).into_iter().collect();
+        if parents_a != parents_b {
+            differences.push(format!(
+                "{} has parents {parents_a:?} in the first network but {parents_b:?} in the second",
+                node.id
+            ));
+        }
+    }
+    let mut only_b: Vec<&str> = b
+        .keys()
+        .filter(|id| !ids_a.contains(*id))
+        .copied()
+        .collect();
+    only_b.sort_unstable();
+    differences.extend(
+        only_b
+            .into_iter()
+            .map(|id| format!("{id} is only in the second network")),
+    );
+    if !differences.is_empty() {
+        bail!("Structures differ: {}", differences.join("; "));
+    }
+    Ok(())
+}
+
+/// Entries matched by identical parent states, and the number of entries of
+/// `a` without a match.
+fn entry_differences(a: &[Node], b: &[Node], threshold: f64) -> (Vec<EntryDifference>, usize) {
+    let mut differences = Vec::new();
+    let mut unmatched = 0;
+    for (node_a, node_b) in a.iter().zip(b) {
+        for (entry_index, entry) in node_a.cpt_entries.iter().enumerate() {
+            let Some(other) = node_b
+                .cpt_entries
+                .iter()
+                .find(|other| other.parent_states == entry.parent_states)
+            else {
+                unmatched += 1;
+                continue;
+            };
+            let (probability_a, probability_b) =
+                (entry.probability_of_true(), other.probability_of_true());
+            if (probability_b - probability_a).abs() >= threshold {
+                differences.push(EntryDifference {
+                    node_id: node_a.id.clone(),
+                    entry_index,
+                    parent_states: entry.parent_states.clone(),
+                    probability_a,
+                    probability_b,
+                    difference: probability_b - probability_a,
+                });
+            }
+        }
+    }
+    differences.sort_by(|x, y| y.difference.abs().total_cmp(&x.difference.abs()));
+    (differences, unmatched)
+}
+
+/// Compares `b` against `a`, which must have the same structure.
+///
+/// Marginals of both are sampled with the same random numbers, so their
+/// differences reflect the parameters rather than noise. With a target, the
+/// differing entries upstream of it are ranked by how far each would move
+/// it, from central-difference sensitivities in `a` as in calibration; this
+/// costs two more runs per fixed entry of those nodes.
+pub fn compare_parameterizations(
+    a: &[Node],
+    b: &[Node],
+    num_samples: usize,
+    options: &ComparisonOptions,
+    rng: &mut Xoshiro128Plus,
+) -> Result<ParameterComparison> {
+    let by_id_b: HashMap<&str, &Node> = b.iter().map(|node| (node.id.as_str(), node)).collect();
+    if by_id_b.len() != b.len() {
+        bail!("Duplicate node IDs detected");
+    }
+    check_same_structure(a, &by_id_b)?;
+    // In the first network's order, so both compile to the same layout.
+    let b: Vec<Node> = a
+        .iter()
+        .map(|node| by_id_b[node.id.as_str()].clone())
+        .collect();
+    let threshold = options.threshold.unwrap_or(DEFAULT_THRESHOLD);
+    if !(0.0..=1.0).contains(&threshold) {
+        bail!("threshold must be in [0, 1], got {threshold}");
+    }
+
+    let common_seed: u64 = rng.random();
+    let estimate = |nodes: &[Node]| -> Result<HashMap<String, f64>> {
+        let mut stream = Xoshiro128Plus::seed_from_u64(common_seed);
+        estimate_marginals(
+            &serialize_network(nodes)?,
+            num_samples,
+            &[],
+            &[],
+            &mut stream,
+        )
+    };
+    let (estimates_a, estimates_b) = (estimate(a)?, estimate(&b)?);
+    let mut marginal_differences: Vec<MarginalDifference> = a
+        .iter()
+        .map(|node| {
+            let (marginal_a, marginal_b) = (estimates_a[&node.id], estimates_b[&node.id]);
+            MarginalDifference {
+                node_id: node.id.clone(),
+                marginal_a,
+                marginal_b,
+                difference: marginal_b - marginal_a,
+            }
+        })
+        .collect();
+    marginal_differences.sort_by(|x, y| y.difference.abs().total_cmp(&x.difference.abs()));
+    let (entry_differences, unmatched_entries) = entry_differences(a, &b, threshold);
+
+    let target_impacts = options
+        .target_id
+        .as_deref()
+        .map(|target| target_impacts(a, &entry_differences, target, options, estimate))
+        .transpose()?;
+    Ok(ParameterComparison {
+        marginal_differences,
+        entry_differences,
+        unmatched_entries,
+        target_impacts,
+    })
+}
+
+fn target_impacts(
+    a: &[Node],
+    differences: &[EntryDifference],
+    target: &str,
+    options: &ComparisonOptions,
+    estimate: impl Fn(&[Node]) -> Result<HashMap<String, f64>>,
+) -> Result<Vec<EntryImpact>> {
+    let serialized = serialize_network(a)?;
+    let index = lookup::resolve(&serialized, "Target", target)?;
+    let mut upstream: BTreeSet<String> = ancestors(&serialized, index).into_iter().collect();
+    upstream.insert(target.to_owned());
+    upstream.retain(|id| differences.iter().any(|d| &d.node_id == id));
+
+    let slopes: HashMap<(&str, usize), f64> = sensitivities(a, &upstream, &[target], estimate)?
+        .into_iter()
+        .map(|s| ((a[s.node].id.as_str(), s.entry), s.slopes[target]))
+        .collect();
+    let mut impacts: Vec<EntryImpact> = differences
+        .iter()
+        .filter_map(|d| {
+            let sensitivity = *slopes.get(&(d.node_id.as_str(), d.entry_index))?;
+            Some(EntryImpact {
+                node_id: d.node_id.clone(),
+                entry_index: d.entry_index,
+                difference: d.difference,
+                sensitivity,
+                target_shift: sensitivity * d.difference,
+            })
+        })
+        .collect();
+    impacts.sort_by(|x, y| y.target_shift.abs().total_cmp(&x.target_shift.abs()));
+    impacts.truncate(options.top_k.unwrap_or(DEFAULT_TOP_K));
+    Ok(impacts)
+}
@@ -0,0 +1,261 @@
+//! Pearl's three rules of the do-calculus, checked structurally.
+//!
+//! For a query `P(y | do(x), z, w)` each rule names a graph derived from the
+//! network by cutting edges: `G_X̄` drops the edges into `X`, `G_Z̲` the edges
+//! out of `Z`. A rule applies when `Y` is d-separated from `Z` given `X ∪ W`
+//! in its graph, which needs no CPTs.
+
+use anyhow::{Result, anyhow, bail};
+use serde::{Deserialize, Serialize};
+
+use crate::Node;
+use crate::serialize::serialize_network;
+use crate::structure::d_separated_in;
+
+/// A network's DAG by topological index, with node IDs for reporting.
+pub(crate) struct Graph {
+    ids: Vec<String>,
+    parents: Vec<Vec<u8>>,
+}
+
+impl Graph {
+    fn index_of(&self, id: &str) -> Option<u8> {
+        self.ids
+            .iter()
+            .position(|node| node == id)
+            .and_then(|index| u8::try_from(index).ok())
+    }
+
+    fn indices(&self, ids: &[String]) -> Result<Vec<u8>> {
+        ids.iter()
+            .map(|id| {
+                self.index_of(id)
+                    .ok_or_else(|| anyhow!("Node {id} not found"))
+            })
+            .collect()
+    }
+
+    fn ids_of(&self, set: &[u8]) -> Vec<String> {
+        set.iter()
+            .map(|&node| self.ids[usize::from(node)].clone())
+            .collect()
+    }
+
+    /// The parents left once the edges into `into` and out of `out_of` are
+    /// cut.
+    fn mutilated(&self, into: &[u8], out_of: &[u8]) -> Vec<Vec<u8>> {
+        (0u8..)
+            .zip(&self.parents)
+            .map(|(node, parents)| {
+                if into.contains(&node) {
+                    Vec::new()
+                } else {
+                    parents
+                        .iter()
+                        .copied()
+                        .filter(|parent| !out_of.contains(parent))
+                        .collect()
+                }
+            })
+            .collect()
+    }
+
+    /// `targets` and every node with a directed path to one of them in
+    /// `parents`.
+    fn ancestors(parents: &[Vec<u8>], targets: &[u8]) -> Vec<u8> {
+        let mut found = targets.to_vec();
+        let mut stack = targets.to_vec();
+        while let Some(node) = stack.pop() {
+            for &parent in &parents[usize::from(node)] {
+                if !found.contains(&parent) {
+                    found.push(parent);
+                    stack.push(parent);
+                }
+            }
+        }
+        found
+    }
+}
+
+/// Whether every node of `y` is d-separated from every node of `z` given
+/// `given` in `parents`. Sets sharing a node are never separated.
+fn separated(parents: &[Vec<u8>], y: &[u8], z: &[u8], given: &[u8]) -> bool {
+    y.iter().all(|&a| {
+        z.iter()
+            .all(|&b| a != b && d_separated_in(parents, a, b, given))
+    })
+}
+
+fn union(a: &[u8], b: &[u8]) -> Vec<u8> {
+    a.iter().chain(b).copied().collect()
+}
+
+/// Rule 1, insertion or deletion of observations:
+/// `P(y | do(x), z, w) = P(y | do(x), w)` when `Y ⊥ Z | X, W` in `G_X̄`.
+pub(crate) fn apply_rule_1(graph: &Graph, x: &[u8], y: &[u8], z: &[u8], w: &[u8]) -> bool {
+    separated(&graph.mutilated(x, &[]), y, z, &union(x, w))
+}
+
+/// Rule 2, exchange of actions and observations:
+/// `P(y | do(x), do(z), w) = P(y | do(x), z, w)` when `Y ⊥ Z | X, W` in
+/// `G_X̄Z̲`.
+pub(crate) fn apply_rule_2(graph: &Graph, x: &[u8], y: &[u8], z: &[u8], w: &[u8]) -> bool {
+    separated(&graph.mutilated(x, z), y, z, &union(x, w))
+}
+
+/// Rule 3, insertion or deletion of actions:
+/// `P(y | do(x), do(z), w) = P(y | do(x), w)` when `Y ⊥ Z | X, W` in
+/// `G_X̄Z(W)̄`, where `Z(W)` is the part of `Z` that is not an ancestor of any
+/// node of `W` in `G_X̄`.
+pub(crate) fn apply_rule_3(graph: &Graph, x: &[u8], y: &[u8], z: &[u8], w: &[u8]) -> bool {
+    let without_x = graph.mutilated(x, &[]);
+    let ancestors_of_w = Graph::ancestors(&without_x, w);
+    let cut: Vec<u8> = z
+        .iter()
+        .copied()
+        .filter(|node| !ancestors_of_w.contains(node))
+        .collect();
+    separated(&graph.mutilated(&union(x, &cut), &[]), y, z, &union(x, w))
+}
+
+/// `P(outcomes | do(interventions), observations)`.
+#[derive(Clone, Deserialize, Serialize)]
+#[serde(rename_all = "camelCase")]
+pub struct CausalQuery {
+    pub outcomes: Vec<String>,
+    #[serde(default)]
+    pub interventions: Vec<String>,
+    #[serde(default)]
+    pub observations: Vec<String>,
+}
+
+impl CausalQuery {
+    fn expression(&self) -> String {
+        let mut terms: Vec<String> = self
+            .interventions
+            .iter()
+            .map(|id| format!("do({id})"))
+            .collect();
+        terms.extend(self.observations.iter().cloned());
+        let outcomes = self.outcomes.join(", ");
+        if terms.is_empty() {
+            format!("P({outcomes})")
+        } else {
+            format!("P({outcomes} | {})", terms.join(", "))
+        }
+    }
+}
+
+#[derive(Serialize)]
+#[serde(rename_all = "camelCase")]
+pub struct RuleStep {
+    /// 1, 2 or 3.
+    pub rule: u8,
+    /// The observation or action removed, or the action turned into an
+    /// observation.
+    pub node_id: String,
+    /// The query after this step.
+    pub expression: String,
+}
+
+#[derive(Serialize)]
+#[serde(rename_all = "camelCase")]
+pub struct SimplifiedQuery {
+    pub query: CausalQuery,
+    pub expression: String,
+    pub steps: Vec<RuleStep>,
+    /// Whether every action was removed, leaving a purely observational
+    /// expression.
+    pub observational: bool,
+}
+
+/// Simplifies a query by applying the rules one node at a time until none
+/// applies, preferring to delete actions (rule 3), then observations
+/// (rule 1), then to turn actions into observations (rule 2). `None` when no
+/// rule applies to the query as given.
+///
+/// Greedy application can miss a derivation that first needs a step making
+/// the expression more complex, so a query left with actions may still be
+/// identifiable.
+pub fn simplify_causal_query(
+    nodes: &[Node],
+    query: CausalQuery,
+) -> Result<Option<SimplifiedQuery>> {
+    let serialized = serialize_network(nodes)?;
+    let graph = Graph {
+        parents: serialized.parents,
+        ids: serialized.topo_order,
+    };
+    if query.outcomes.is_empty() {
+        bail!("At least one outcome is required");
+    }
+    let y = graph.indices(&query.outcomes)?;
+    let mut x = graph.indices(&query.interventions)?;
+    let mut w = graph.indices(&query.observations)?;
+    let mut all: Vec<u8> = y.iter().chain(&x).chain(&w).copied().collect();
+    all.sort_unstable();
+    if let Some(pair) = all.windows(2).find(|pair| pair[0] == pair[1]) {
+        bail!(
+            "Node {id} appears more than once in the query",
+            id = graph.ids[usize::from(pair[0])]
+        );
+    }
+
+    let mut steps = Vec::new();
+    loop {
+        let others = |set: &[u8], node: u8| -> Vec<u8> {
+            set.iter().copied().filter(|&other| other != node).collect()
+        };
+        let step = x
+            .iter()
+            .find(|&&z| apply_rule_3(&graph, &others(&x, z), &y, &[z], &w))
+            .map(|&z| (3, z))
+            .or_else(|| {
+                w.iter()
+                    .find(|&&z| apply_rule_1(&graph, &x, &y, &[z], &others(&w, z)))
+                    .map(|&z| (1, z))
+            })
+            .or_else(|| {
+                x.iter()
+                    .find(|&&z| apply_rule_2(&graph, &others(&x, z), &y, &[z], &w))
+                    .map(|&z| (2, z))
+            });
+        let Some((rule, node)) = step else {
+            break;
+        };
+        match rule {
+            1 => w.retain(|&other| other != node),
+            2 => {
+                x.retain(|&other| other != node);
+                w.push(node);
+            }
+            _ => x.retain(|&other| other != node),
+        }
+        let current = CausalQuery {
+            outcomes: query.outcomes.clone(),
+            interventions: graph.ids_of(&x),
+            observations: graph.ids_of(&w),
+        };
+        steps.push(RuleStep {
+            rule,
+            node_id: graph.ids[usize::from(node)].clone(),
+            expression: current.expression(),
+        });
+    }
+
+    let Some(last) = steps.last() else {
+        return Ok(None);
+    };
+    let expression = last.expression.clone();
+    let query = CausalQuery {
+        outcomes: query.outcomes,
+        interventions: graph.ids_of(&x),
+        observations: graph.ids_of(&w),
+    };
+    Ok(Some(SimplifiedQuery {
+        observational: query.interventions.is_empty(),
+        query,
+        expression,
+        steps,
+    }))
+}
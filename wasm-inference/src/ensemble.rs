@@ -0,0 +1,81 @@
+//! Model averaging: marginals from several candidate networks over the same
+//! nodes, combined with caller-supplied weights.
+
+use anyhow::{Result, bail};
+use serde::Serialize;
+use std::collections::{BTreeSet, HashMap};
+
+use crate::Node;
+
+/// Most networks a single ensemble query may average.
+pub const MAX_ENSEMBLE_NETWORKS: usize = 10;
+
+/// How far the weights may sum from 1.
+const WEIGHT_TOLERANCE: f64 = 1e-6;
+
+#[derive(Serialize)]
+#[serde(rename_all = "camelCase")]
+pub struct Ensemble<T> {
+    /// The weighted average.
+    pub ensemble: T,
+    /// Each network's own result, in the order the networks were given.
+    pub per_network: Vec<T>,
+}
+
+/// Checks the number of networks, that they share node IDs, and that the
+/// weights are non-negative, one per network and sum to 1.
+pub fn check(networks: &[Vec<Node>], weights: &[f64]) -> Result<()> {
+    if networks.is_empty() {
+        bail!("At least one network is required");
+    }
+    if networks.len() > MAX_ENSEMBLE_NETWORKS {
+        bail!(
+            "Ensembles support at most {MAX_ENSEMBLE_NETWORKS} networks, got {}",
+            networks.len()
+        );
+    }
+    if weights.len() != networks.len() {
+        bail!(
+            "Got {} weights for {} networks",
+            weights.len(),
+            networks.len()
+        );
+    }
+    if let Some((idx, weight)) = weights
+        .iter()
+        .enumerate()
+        .find(|(_, weight)| !weight.is_finite() || **weight < 0.0)
+    {
+        bail!("Weight {idx} is {weight}; weights must be finite and non-negative");
+    }
+    let total: f64 = weights.iter().sum();
+    if (total - 1.0).abs() > WEIGHT_TOLERANCE {
+        bail!("Weights sum to {total}, not 1");
+    }
+
+    let ids =
+        |nodes: &[Node]| -> BTreeSet<String> { nodes.iter().map(|node| node.id.clone()).collect() };
+    let expected = ids(&networks[0]);
+    for (idx, network) in networks.iter().enumerate().skip(1) {
+        let actual = ids(network);
+        if let Some(id) = expected.symmetric_difference(&actual).next() {
+            bail!("Network {idx} does not have the same node IDs as network 0 (differs at {id})");
+        }
+    }
+    Ok(())
+}
+
+/// `Σ weights[k] · maps[k][id]` for every ID of the first map.
+pub fn weighted_average(maps: &[HashMap<String, f64>], weights: &[f64]) -> HashMap<String, f64> {
+    maps[0]
+        .keys()
+        .map(|id| {
+            let average = maps
+                .iter()
+                .zip(weights)
+                .map(|(map, weight)| weight * map[id])
+                .sum();
+            (id.clone(), average)
+        })
+        .collect()
+}
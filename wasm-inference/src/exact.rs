@@ -0,0 +1,150 @@
+use anyhow::{Result, anyhow, bail};
+use std::collections::{BTreeMap, HashMap};
+
+use crate::Node;
+use crate::assumptions::AssumptionSet;
+use crate::serialize::serialize_network;
+
+/// Enumeration visits `2^n` assignments, so it is limited to small networks.
+pub(crate) const MAX_EXACT_NODES: usize = 15;
+
+pub(crate) struct ExactMarginals {
+    pub marginals: HashMap<String, f64>,
+    /// Probability of the evidence under the interventions and clamps, soft
+    /// evidence included.
+    pub evidence_probability: f64,
+}
+
+/// Exact marginals by enumerating every joint assignment.
+///
+/// `assumptions` must already be resolved against `nodes`. Intervened and
+/// clamped nodes ignore their CPTs, exactly as in the sampler. Soft evidence
+/// with likelihood ratio `L` is an observation made with probability
+/// `L / (1 + L)` when the node is true and `1 / (1 + L)` when false.
+pub(crate) fn exact_marginals(
+    nodes: &[Node],
+    assumptions: &AssumptionSet,
+    soft_evidence: &BTreeMap<String, f64>,
+) -> Result<ExactMarginals> {
+    let mut true_mass = vec![0.0; nodes.len()];
+    let (topo_order, evidence_probability) =
+        enumerate(nodes, assumptions, soft_evidence, |value, weight| {
+            for (i, mass) in true_mass.iter_mut().enumerate() {
+                if value(i) {
+                    *mass += weight;
+                }
+            }
+        })?;
+    Ok(ExactMarginals {
+        marginals: topo_order
+            .into_iter()
+            .zip(true_mass)
+            .map(|(id, mass)| (id, mass / evidence_probability))
+            .collect(),
+        evidence_probability,
+    })
+}
+
+/// Exact joint distribution of `variables`: entry `s` is the probability
+/// that `variables[i]` is true exactly when bit `i` of `s` is set.
+pub(crate) fn exact_joint(
+    nodes: &[Node],
+    assumptions: &AssumptionSet,
+    variables: &[String],
+) -> Result<Vec<f64>> {
+    let topo_order = serialize_network(nodes)?.topo_order;
+    let positions = variables
+        .iter()
+        .map(|id| {
+            topo_order
+                .iter()
+                .position(|other| other == id)
+                .ok_or_else(|| anyhow!("Node {id} not found"))
+        })
+        .collect::<Result<Vec<usize>>>()?;
+    let mut joint = vec![0.0; 1 << variables.len()];
+    let (_, evidence_probability) =
+        enumerate(nodes, assumptions, &BTreeMap::new(), |value, weight| {
+            let state = positions
+                .iter()
+                .enumerate()
+                .filter(|&(_, &position)| value(position))
+                .fold(0, |state, (i, _)| state | 1 << i);
+            joint[state] += weight;
+        })?;
+    Ok(joint
+        .into_iter()
+        .map(|mass| mass / evidence_probability)
+        .collect())
+}
+
+/// Visits every joint assignment consistent with the evidence with its
+/// weight, given as a lookup by topological index. Returns the topological
+/// order and the total weight, which is the probability of the evidence.
+fn enumerate(
+    nodes: &[Node],
+    assumptions: &AssumptionSet,
+    soft_evidence: &BTreeMap<String, f64>,
+    mut visit: impl FnMut(&dyn Fn(usize) -> bool, f64),
+) -> Result<(Vec<String>, f64)> {
+    if nodes.len() > MAX_EXACT_NODES {
+        bail!(
+            "Exact inference supports at most {MAX_EXACT_NODES} nodes, got {}",
+            nodes.len()
+        );
+    }
+    let topo_order = serialize_network(nodes)?.topo_order;
+    let nodes_by_id: HashMap<&str, &Node> = nodes.iter().map(|n| (n.id.as_str(), n)).collect();
+    let ordered: Vec<&Node> = topo_order
+        .iter()
+        .map(|id| nodes_by_id[id.as_str()])
+        .collect();
+    let position: HashMap<&str, usize> = topo_order
+        .iter()
+        .enumerate()
+        .map(|(i, id)| (id.as_str(), i))
+        .collect();
+
+    let mut evidence_probability = 0.0;
+    for assignment in 0..1usize << ordered.len() {
+        let value = |i: usize| assignment & (1 << i) != 0;
+        if assumptions
+            .evidence
+            .iter()
+            .any(|(id, &expected)| value(position[id.as_str()]) != expected)
+        {
+            continue;
+        }
+
+        // Topological order means a zero-probability prefix is seen before any
+        // CPT that the sampler could never reach.
+        let mut weight = 1.0;
+        for (i, node) in ordered.iter().enumerate() {
+            let p_true = if let Some(&forced) = assumptions.interventions.get(&node.id) {
+                if forced { 1.0 } else { 0.0 }
+            } else if let Some(&clamp) = assumptions.clamps.get(&node.id) {
+                clamp
+            } else {
+                node.probability_given(|parent_id| value(position[parent_id]))
+                    .ok_or_else(|| {
+                        anyhow!("Node {} has no CPT entry matching its parents", node.id)
+                    })?
+            };
+            weight *= if value(i) { p_true } else { 1.0 - p_true };
+            if let Some(&ratio) = soft_evidence.get(&node.id) {
+                weight *= if value(i) { ratio } else { 1.0 } / (1.0 + ratio);
+            }
+            if weight == 0.0 {
+                break;
+            }
+        }
+
+        evidence_probability += weight;
+        visit(&value, weight);
+    }
+
+    if evidence_probability == 0.0 {
+        bail!("The evidence has probability zero");
+    }
+    Ok((topo_order, evidence_probability))
+}
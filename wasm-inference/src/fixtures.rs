@@ -0,0 +1,92 @@
+//! Golden vectors: small networks with a fixed seed and the exact marginals
+//! they must produce, so integrations (and this crate) can detect any change
+//! in sampling behavior. The fixtures live in `fixtures/`; an intentional
+//! change to the sampler shows up as an edit to their `expected` values.
+
+use serde::{Deserialize, Serialize};
+use std::collections::{BTreeMap, BTreeSet};
+
+use crate::options::QueryOptions;
+use crate::serialize::serialize_network;
+use crate::{Node, cpt_table, marginals_with_options};
+
+pub const FIXTURES: [(&str, &str); 5] = [
+    ("chain", include_str!("../fixtures/chain.json")),
+    ("collider", include_str!("../fixtures/collider.json")),
+    ("wildcards", include_str!("../fixtures/wildcards.json")),
+    (
+        "intervention",
+        include_str!("../fixtures/intervention.json"),
+    ),
+    ("evidence", include_str!("../fixtures/evidence.json")),
+];
+
+#[derive(Deserialize)]
+struct Fixture {
+    nodes: Vec<Node>,
+    options: QueryOptions,
+    expected: BTreeMap<String, f64>,
+}
+
+#[derive(Serialize)]
+#[serde(rename_all = "camelCase")]
+pub struct GoldenCheck {
+    pub name: &'static str,
+    pub passed: bool,
+    /// Nodes whose marginal differs from the golden value in any bit.
+    pub mismatches: Vec<String>,
+    /// What this build produced, to paste into the fixture when a change is
+    /// intended.
+    pub actual: BTreeMap<String, f64>,
+    /// Set when the fixture could not be run at all.
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub error: Option<String>,
+}
+
+/// Runs every fixture through the same path as
+/// `compute_marginals_with_options`.
+pub fn run_golden_checks() -> Vec<GoldenCheck> {
+    FIXTURES
+        .iter()
+        .map(|&(name, json)| match run(json) {
+            Ok((expected, actual)) => {
+                let ids: BTreeSet<&String> = expected.keys().chain(actual.keys()).collect();
+                let mismatches: Vec<String> = ids
+                    .into_iter()
+                    .filter(|&id| {
+                        expected.get(id).map(|value| value.to_bits())
+                            != actual.get(id).map(|value| value.to_bits())
+                    })
+                    .cloned()
+                    .collect();
+                GoldenCheck {
+                    name,
+                    passed: mismatches.is_empty(),
+                    mismatches,
+                    actual,
+                    error: None,
+                }
+            }
+            Err(error) => GoldenCheck {
+                name,
+                passed: false,
+                mismatches: Vec::new(),
+                actual: BTreeMap::new(),
+                error: Some(error),
+            },
+        })
+        .collect()
+}
+
+type Marginals = BTreeMap<String, f64>;
+
+fn run(json: &str) -> Result<(Marginals, Marginals), String> {
+    let mut fixture: Fixture = serde_json::from_str(json).map_err(|e| e.to_string())?;
+    for node in &mut fixture.nodes {
+        cpt_table::expand_table(node).map_err(|e| e.to_string())?;
+    }
+    let serialized = serialize_network(&fixture.nodes).map_err(|e| e.to_string())?;
+    let result = marginals_with_options(&fixture.nodes, &serialized, &fixture.options)
+        .map_err(|e| e.as_string().unwrap_or_else(|| format!("{e:?}")))?;
+    Ok((fixture.expected, result.marginals.into_iter().collect()))
+}
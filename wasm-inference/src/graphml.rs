@@ -0,0 +1,236 @@
+use anyhow::{Result, bail};
+use serde::Deserialize;
+use std::collections::HashMap;
+use std::fmt::Write;
+
+use crate::Node;
+use crate::serialize::get_node_parents;
+
+/// Renders the network as a `GraphML` document for tools like Gephi or
+/// Cytoscape. Node marginals are included when provided, and titles,
+/// descriptions, metadata (as JSON text) and leak probabilities when any node
+/// has them. A leak is a node attribute, not an edge. Nodes get a `group` from
+/// their metadata's `group` string, or else its `tags` joined with commas, and
+/// edges get a `strength` when one is passed for that parent and child.
+pub fn export_graphml(
+    nodes: &[Node],
+    marginals: Option<&HashMap<String, f64>>,
+    strengths: &[EdgeStrength],
+) -> Result<String> {
+    let parents: Vec<Vec<&str>> = nodes
+        .iter()
+        .map(|node| {
+            let mut parents = get_node_parents(node);
+            parents.sort_unstable();
+            parents
+        })
+        .collect();
+
+    let out_degree = out_degrees(nodes, &parents)?;
+    let strength_of = edge_strengths(nodes, &parents, strengths)?;
+
+    let mut out = String::new();
+    writeln!(out, r#"<?xml version="1.0" encoding="UTF-8"?>"#)?;
+    writeln!(
+        out,
+        r#"<graphml xmlns="http://graphml.graphdrawing.org/xmlns">"#
+    )?;
+    if marginals.is_some() {
+        writeln!(
+            out,
+            r#"  <key id="marginal" for="node" attr.name="marginal" attr.type="double"/>"#
+        )?;
+    }
+    let annotations: Vec<&str> = ANNOTATIONS
+        .into_iter()
+        .filter(|key| nodes.iter().any(|node| annotation(node, key).is_some()))
+        .collect();
+    for key in &annotations {
+        writeln!(
+            out,
+            r#"  <key id="{key}" for="node" attr.name="{key}" attr.type="string"/>"#
+        )?;
+    }
+    let has_group = nodes.iter().any(|node| group(node).is_some());
+    if has_group {
+        writeln!(
+            out,
+            r#"  <key id="group" for="node" attr.name="group" attr.type="string"/>"#
+        )?;
+    }
+    let has_leak = nodes.iter().any(|node| node.leak_probability.is_some());
+    if has_leak {
+        writeln!(
+            out,
+            r#"  <key id="leakProbability" for="node" attr.name="leakProbability" attr.type="double"/>"#
+        )?;
+    }
+    writeln!(
+        out,
+        r#"  <key id="inDegree" for="node" attr.name="inDegree" attr.type="int"/>"#
+    )?;
+    writeln!(
+        out,
+        r#"  <key id="outDegree" for="node" attr.name="outDegree" attr.type="int"/>"#
+    )?;
+    if !strength_of.is_empty() {
+        writeln!(
+            out,
+            r#"  <key id="strength" for="edge" attr.name="strength" attr.type="double"/>"#
+        )?;
+    }
+    writeln!(out, r#"  <graph id="G" edgedefault="directed">"#)?;
+
+    for (node, node_parents) in nodes.iter().zip(&parents) {
+        writeln!(out, r#"    <node id="{}">"#, escape(&node.id))?;
+        if let Some(marginal) = marginals.and_then(|m| m.get(&node.id)) {
+            writeln!(out, r#"      <data key="marginal">{marginal}</data>"#)?;
+        }
+        for key in &annotations {
+            if let Some(value) = annotation(node, key) {
+                writeln!(out, r#"      <data key="{key}">{}</data>"#, escape(&value))?;
+            }
+        }
+        if let Some(group) = group(node) {
+            writeln!(out, r#"      <data key="group">{}</data>"#, escape(&group))?;
+        }
+        if let Some(leak) = node.leak_probability {
+            writeln!(out, r#"      <data key="leakProbability">{leak}</data>"#)?;
+        }
+        writeln!(
+            out,
+            r#"      <data key="inDegree">{}</data>"#,
+            node_parents.len()
+        )?;
+        writeln!(
+            out,
+            r#"      <data key="outDegree">{}</data>"#,
+            out_degree[node.id.as_str()]
+        )?;
+        writeln!(out, "    </node>")?;
+    }
+
+    write_edges(&mut out, nodes, &parents, &strength_of)?;
+
+    writeln!(out, "  </graph>")?;
+    writeln!(out, "</graphml>")?;
+    Ok(out)
+}
+
+/// A strength for the arc from `parent_id` to `child_id`, computed by the
+/// caller (e.g. from sensitivity or mutual information).
+#[derive(Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub struct EdgeStrength {
+    pub parent_id: String,
+    pub child_id: String,
+    pub strength: f64,
+}
+
+/// Counts each node's children, rejecting parents that aren't in `nodes`.
+fn out_degrees<'a>(nodes: &'a [Node], parents: &[Vec<&str>]) -> Result<HashMap<&'a str, usize>> {
+    let mut out_degree: HashMap<&str, usize> =
+        nodes.iter().map(|node| (node.id.as_str(), 0)).collect();
+    for (node, node_parents) in nodes.iter().zip(parents) {
+        for &parent_id in node_parents {
+            let Some(degree) = out_degree.get_mut(parent_id) else {
+                bail!(
+                    "Node {child} references parent {parent_id} which is not in the node array",
+                    child = node.id
+                );
+            };
+            *degree += 1;
+        }
+    }
+    Ok(out_degree)
+}
+
+/// Indexes the given strengths by `(parent, child)`, rejecting any that name
+/// an arc the network doesn't have.
+fn edge_strengths<'a>(
+    nodes: &[Node],
+    parents: &[Vec<&str>],
+    strengths: &'a [EdgeStrength],
+) -> Result<HashMap<(&'a str, &'a str), f64>> {
+    let mut strength_of = HashMap::new();
+    for edge in strengths {
+        let is_edge = nodes.iter().zip(parents).any(|(node, node_parents)| {
+            node.id == edge.child_id && node_parents.contains(&edge.parent_id.as_str())
+        });
+        if !is_edge {
+            bail!(
+                "Strength given for {parent} -> {child}, which is not an edge of the network",
+                parent = edge.parent_id,
+                child = edge.child_id
+            );
+        }
+        strength_of.insert(
+            (edge.parent_id.as_str(), edge.child_id.as_str()),
+            edge.strength,
+        );
+    }
+    Ok(strength_of)
+}
+
+fn write_edges(
+    out: &mut String,
+    nodes: &[Node],
+    parents: &[Vec<&str>],
+    strength_of: &HashMap<(&str, &str), f64>,
+) -> Result<()> {
+    for (node, node_parents) in nodes.iter().zip(parents) {
+        for &parent_id in node_parents {
+            let source = escape(parent_id);
+            let target = escape(&node.id);
+            match strength_of.get(&(parent_id, node.id.as_str())) {
+                Some(strength) => {
+                    writeln!(out, r#"    <edge source="{source}" target="{target}">"#)?;
+                    writeln!(out, r#"      <data key="strength">{strength}</data>"#)?;
+                    writeln!(out, "    </edge>")?;
+                }
+                None => writeln!(out, r#"    <edge source="{source}" target="{target}"/>"#)?,
+            }
+        }
+    }
+    Ok(())
+}
+
+const ANNOTATIONS: [&str; 3] = ["title", "description", "metadata"];
+
+fn annotation(node: &Node, key: &str) -> Option<String> {
+    match key {
+        "title" => node.title.clone(),
+        "description" => node.description.clone(),
+        "metadata" => node.metadata.as_ref().map(ToString::to_string),
+        _ => None,
+    }
+}
+
+fn group(node: &Node) -> Option<String> {
+    let metadata = node.metadata.as_ref()?;
+    if let Some(group) = metadata.get("group").and_then(serde_json::Value::as_str) {
+        return Some(group.to_string());
+    }
+    let tags: Vec<&str> = metadata
+        .get("tags")?
+        .as_array()?
+        .iter()
+        .filter_map(serde_json::Value::as_str)
+        .collect();
+    (!tags.is_empty()).then(|| tags.join(","))
+}
+
+fn escape(text: &str) -> String {
+    let mut escaped = String::with_capacity(text.len());
+    for c in text.chars() {
+        match c {
+            '&' => escaped.push_str("&amp;"),
+            '<' => escaped.push_str("&lt;"),
+            '>' => escaped.push_str("&gt;"),
+            '"' => escaped.push_str("&quot;"),
+            '\'' => escaped.push_str("&apos;"),
+            c => escaped.push(c),
+        }
+    }
+    escaped
+}
@@ -0,0 +1,333 @@
+//! Structural identifiability of interventional queries.
+//!
+//! Hidden nodes are projected out, leaving a graph over the observed nodes
+//! with directed edges (`a -> b` when a directed path runs from `a` to `b`
+//! through hidden nodes only) and bidirected edges (`a <-> b` when a hidden
+//! node reaches both that way). `P(outcomes | do(treatments))` is then checked
+//! with Tian's algorithm, which succeeds exactly when Shpitser and Pearl's ID
+//! algorithm does: it fails only on a hedge.
+//!
+//! Nodes marked `latent` are always hidden.
+
+use anyhow::{Result, anyhow, bail};
+use serde::Serialize;
+
+use crate::Node;
+use crate::bit_set::BitSet;
+use crate::serialize::{SerializedNetwork, serialize_network};
+
+/// The latent projection of a network, indexed by topological position.
+struct Projection {
+    num_nodes: u8,
+    observed: BitSet,
+    parents: Vec<Vec<u8>>,
+    siblings: Vec<Vec<u8>>,
+}
+
+impl Projection {
+    fn new(serialized: &SerializedNetwork, hidden: &BitSet) -> Self {
+        let num_nodes = serialized.num_nodes();
+        let children = serialized.children();
+        let mut observed = BitSet::new();
+        for node in (0..num_nodes).filter(|&node| !hidden.contains(node)) {
+            observed.insert(node);
+        }
+
+        // Nodes reached from `start` along `edges`, passing through hidden
+        // nodes only; the observed endpoints are returned.
+        let reach = |start: &[u8], edges: &[Vec<u8>]| {
+            let mut seen = BitSet::new();
+            let mut found = Vec::new();
+            let mut stack = start.to_vec();
+            while let Some(node) = stack.pop() {
+                if !seen.insert(node) {
+                    continue;
+                }
+                if hidden.contains(node) {
+                    stack.extend(&edges[usize::from(node)]);
+                } else {
+                    found.push(node);
+                }
+            }
+            found
+        };
+
+        let parents = (0..usize::from(num_nodes))
+            .map(|node| reach(&serialized.parents[node], &serialized.parents))
+            .collect();
+        let mut siblings = vec![Vec::new(); usize::from(num_nodes)];
+        for latent in (0..num_nodes).filter(|&node| hidden.contains(node)) {
+            let confounded = reach(&children[usize::from(latent)], &children);
+            for &a in &confounded {
+                for &b in &confounded {
+                    if a != b && !siblings[usize::from(a)].contains(&b) {
+                        siblings[usize::from(a)].push(b);
+                    }
+                }
+            }
+        }
+
+        Projection {
+            num_nodes,
+            observed,
+            parents,
+            siblings,
+        }
+    }
+
+    fn members(&self, set: BitSet) -> impl Iterator<Item = u8> {
+        (0..self.num_nodes).filter(move |&node| set.contains(node))
+    }
+
+    /// Nodes of `within` with a directed path inside `within` to `targets`,
+    /// including the targets themselves.
+    fn ancestors(&self, targets: BitSet, within: BitSet) -> BitSet {
+        self.closure(targets, within, &self.parents)
+    }
+
+    /// The c-component of `G[within]` containing `node`.
+    fn c_component(&self, node: u8, within: BitSet) -> BitSet {
+        let mut start = BitSet::new();
+        start.insert(node);
+        self.closure(start, within, &self.siblings)
+    }
+
+    fn closure(&self, start: BitSet, within: BitSet, edges: &[Vec<u8>]) -> BitSet {
+        let mut reached = start;
+        let mut stack: Vec<u8> = self.members(start).collect();
+        while let Some(node) = stack.pop() {
+            for &next in &edges[usize::from(node)] {
+                if within.contains(next) && reached.insert(next) {
+                    stack.push(next);
+                }
+            }
+        }
+        reached
+    }
+
+    /// Whether `Q[component]` can be computed from `Q[within]`, where
+    /// `component` is a c-component inside the c-component `within`.
+    fn identify(&self, component: BitSet, mut within: BitSet) -> bool {
+        let representative = self
+            .members(component)
+            .next()
+            .expect("c-components are non-empty");
+        loop {
+            let ancestral = self.ancestors(component, within);
+            if ancestral == component {
+                return true;
+            }
+            if ancestral == within {
+                // `component` and `within` form a hedge.
+                return false;
+            }
+            within = self.c_component(representative, ancestral);
+        }
+    }
+}
+
+/// A query checked against the latent projection.
+struct Analysis {
+    serialized: SerializedNetwork,
+    projection: Projection,
+    treatments: BitSet,
+    /// Observed ancestors of the outcomes once the treatments are removed.
+    relevant: BitSet,
+    /// The c-components of `G[relevant]`.
+    components: Vec<BitSet>,
+    identifiable: bool,
+}
+
+impl Analysis {
+    fn ids(&self, set: BitSet) -> Vec<String> {
+        self.projection
+            .members(set)
+            .map(|node| self.serialized.topo_order[usize::from(node)].clone())
+            .collect()
+    }
+}
+
+/// Nodes marked `latent` are hidden alongside `hidden_ids`.
+fn analyze(
+    nodes: &[Node],
+    treatment_ids: &[String],
+    outcome_ids: &[String],
+    hidden_ids: &[String],
+) -> Result<Analysis> {
+    let serialized = serialize_network(nodes)?;
+    let index_set = |ids: &[String], role: &str| {
+        let mut set = BitSet::new();
+        for id in ids {
+            let index = serialized
+                .index_of(id)
+                .ok_or_else(|| anyhow!("{role} node {id} not found"))?;
+            set.insert(index);
+        }
+        Ok::<_, anyhow::Error>(set)
+    };
+    let treatments = index_set(treatment_ids, "Treatment")?;
+    let outcomes = index_set(outcome_ids, "Outcome")?;
+    let mut hidden = index_set(hidden_ids, "Hidden")?;
+    if outcome_ids.is_empty() {
+        bail!("At least one outcome is required");
+    }
+    for node in nodes.iter().filter(|node| node.latent) {
+        hidden.insert(
+            serialized
+                .index_of(&node.id)
+                .expect("every node is serialized"),
+        );
+    }
+
+    let projection = Projection::new(&serialized, &hidden);
+    for node in 0..projection.num_nodes {
+        if hidden.contains(node) && (treatments.contains(node) || outcomes.contains(node)) {
+            bail!(
+                "Node {id} cannot be both hidden and a treatment or outcome",
+                id = serialized.topo_order[usize::from(node)]
+            );
+        }
+        if treatments.contains(node) && outcomes.contains(node) {
+            bail!(
+                "Node {id} cannot be both a treatment and an outcome",
+                id = serialized.topo_order[usize::from(node)]
+            );
+        }
+    }
+
+    let mut untreated = projection.observed;
+    for treatment in projection.members(treatments) {
+        untreated.remove(treatment);
+    }
+    let relevant = projection.ancestors(outcomes, untreated);
+
+    let mut components = Vec::new();
+    let mut identifiable = true;
+    let mut remaining = relevant;
+    while let Some(node) = projection.members(remaining).next() {
+        let component = projection.c_component(node, relevant);
+        let district = projection.c_component(node, projection.observed);
+        identifiable &= projection.identify(component, district);
+        for member in projection.members(component) {
+            remaining.remove(member);
+        }
+        components.push(component);
+    }
+    Ok(Analysis {
+        serialized,
+        projection,
+        treatments,
+        relevant,
+        components,
+        identifiable,
+    })
+}
+
+/// Whether `P(outcomes | do(treatments))` is determined by the distribution
+/// of the nodes not listed in `hidden_ids` (or marked `latent`).
+pub fn is_identifiable(
+    nodes: &[Node],
+    treatment_ids: &[String],
+    outcome_ids: &[String],
+    hidden_ids: &[String],
+) -> Result<bool> {
+    analyze(nodes, treatment_ids, outcome_ids, hidden_ids).map(|analysis| analysis.identifiable)
+}
+
+#[derive(Serialize)]
+#[serde(rename_all = "camelCase")]
+pub struct Identification {
+    pub identifiable: bool,
+    /// Parents of the treatment in the projection. Set when the treatment
+    /// shares no hidden common cause with another node, so adjusting for its
+    /// parents closes every backdoor path.
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub adjustment_set: Option<Vec<String>>,
+    /// Observed ancestors of the outcome once the treatment is removed, split
+    /// into c-components; the effect factorizes over these.
+    pub c_components: Vec<Vec<String>>,
+    /// The estimand, using the adjustment set when there is one and the
+    /// c-component factorization otherwise. `None` when not identifiable.
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub formula: Option<String>,
+}
+
+/// Whether `P(outcome | do(treatment))` is identifiable when the nodes marked
+/// `latent` are unobserved, and if so how.
+pub fn identify(nodes: &[Node], treatment_id: &str, outcome_id: &str) -> Result<Identification> {
+    let analysis = analyze(
+        nodes,
+        &[treatment_id.to_string()],
+        &[outcome_id.to_string()],
+        &[],
+    )?;
+    let c_components: Vec<Vec<String>> = analysis
+        .components
+        .iter()
+        .map(|&component| analysis.ids(component))
+        .collect();
+    if !analysis.identifiable {
+        return Ok(Identification {
+            identifiable: false,
+            adjustment_set: None,
+            c_components,
+            formula: None,
+        });
+    }
+
+    let projection = &analysis.projection;
+    let treatment = projection
+        .members(analysis.treatments)
+        .next()
+        .expect("the treatment was found");
+    let affects_outcome = projection
+        .ancestors(analysis.relevant, projection.observed)
+        .contains(treatment);
+    let adjustment_set = projection.siblings[usize::from(treatment)]
+        .is_empty()
+        .then(|| {
+            let mut parents = BitSet::new();
+            for &parent in &projection.parents[usize::from(treatment)] {
+                parents.insert(parent);
+            }
+            analysis.ids(parents)
+        });
+
+    let formula = if !affects_outcome {
+        format!("P({outcome_id} | do({treatment_id})) = P({outcome_id})")
+    } else if let Some(set) = adjustment_set.as_ref().filter(|set| !set.is_empty()) {
+        let set = set.join(", ");
+        format!(
+            "P({outcome_id} | do({treatment_id})) = Σ_{{{set}}} \
+             P({outcome_id} | {treatment_id}, {set}) P({set})"
+        )
+    } else if adjustment_set.is_some() {
+        format!("P({outcome_id} | do({treatment_id})) = P({outcome_id} | {treatment_id})")
+    } else {
+        let summed: Vec<&str> = c_components
+            .iter()
+            .flatten()
+            .map(String::as_str)
+            .filter(|&id| id != outcome_id)
+            .collect();
+        let factors: Vec<String> = c_components
+            .iter()
+            .map(|component| format!("Q[{{{}}}]", component.join(", ")))
+            .collect();
+        let sum = if summed.is_empty() {
+            String::new()
+        } else {
+            format!("Σ_{{{}}} ", summed.join(", "))
+        };
+        format!(
+            "P({outcome_id} | do({treatment_id})) = {sum}{}",
+            factors.join(" ")
+        )
+    };
+    Ok(Identification {
+        identifiable: true,
+        adjustment_set,
+        c_components,
+        formula: Some(formula),
+    })
+}
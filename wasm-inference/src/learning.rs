@@ -0,0 +1,639 @@
+use anyhow::{Result, anyhow, bail};
+use rand_xoshiro::Xoshiro128Plus;
+use serde::Serialize;
+use std::collections::{BTreeSet, HashMap};
+use std::io::BufRead;
+
+use crate::lookup::find_node;
+use crate::marginals::estimate_marginals;
+use crate::serialize::{get_node_parents, serialize_network};
+use crate::statistics::chi_squared_sf;
+use crate::structure::d_separated;
+use crate::validate::{MAX_COMPLETENESS_PARENTS, reachable_entries};
+use crate::{CptEntry, Node};
+
+pub type DataRow = HashMap<String, bool>;
+
+/// Learns a network structure from data with the PC algorithm.
+///
+/// Edges are removed whenever a G-test fails to reject conditional
+/// independence at level `alpha`, v-structures are oriented from the recorded
+/// separating sets and Meek's rules propagate them. The edges that leaves
+/// undirected are then oriented into a DAG in the same equivalence class, so
+/// no v-structure appears that the tests did not find. The result is
+/// structure only: each node has a single entry with every parent a
+/// wildcard, to be filled in by parameter learning.
+pub fn pc_algorithm(data: &[DataRow], node_ids: &[String], alpha: f64) -> Result<Vec<Node>> {
+    if !(0.0..=1.0).contains(&alpha) {
+        bail!("alpha must be within [0, 1], got {alpha}");
+    }
+    let n = node_ids.len();
+    let mut adjacent = vec![vec![true; n]; n];
+    for (i, row) in adjacent.iter_mut().enumerate() {
+        row[i] = false;
+    }
+    let mut sepsets: HashMap<(usize, usize), Vec<usize>> = HashMap::new();
+
+    let mut conditioning_size = 0;
+    loop {
+        let mut any_testable = false;
+        for x in 0..n {
+            for y in 0..n {
+                if !adjacent[x][y] {
+                    continue;
+                }
+                let neighbors: Vec<usize> = (0..n).filter(|&k| k != y && adjacent[x][k]).collect();
+                if neighbors.len() < conditioning_size {
+                    continue;
+                }
+                any_testable = true;
+                for subset in subsets(&neighbors, conditioning_size) {
+                    let given: Vec<String> = subset.iter().map(|&k| node_ids[k].clone()).collect();
+                    let p_value = g_test(data, &node_ids[x], &node_ids[y], &given);
+                    if p_value > alpha {
+                        adjacent[x][y] = false;
+                        adjacent[y][x] = false;
+                        sepsets.insert((x.min(y), x.max(y)), subset);
+                        break;
+                    }
+                }
+            }
+        }
+        if !any_testable {
+            break;
+        }
+        conditioning_size += 1;
+    }
+
+    let mut graph = Pdag::new(adjacent);
+    graph.orient_v_structures(&sepsets);
+    graph.apply_meek_rules();
+    let parents = graph.extend()?;
+
+    Ok(node_ids
+        .iter()
+        .zip(parents)
+        .map(|(id, parents)| {
+            let parent_states = parents
+                .into_iter()
+                .map(|parent| (node_ids[parent].clone(), None))
+                .collect();
+            let mut node = Node::with_prior(id.clone(), 0.5);
+            node.cpt_entries = vec![CptEntry::uniform(parent_states)];
+            node
+        })
+        .collect())
+}
+
+/// A partially directed graph: `directed[a][b]` means `a -> b`, and an
+/// adjacent pair directed neither way is undirected.
+struct Pdag {
+    adjacent: Vec<Vec<bool>>,
+    directed: Vec<Vec<bool>>,
+}
+
+impl Pdag {
+    fn new(adjacent: Vec<Vec<bool>>) -> Self {
+        let n = adjacent.len();
+        Self {
+            adjacent,
+            directed: vec![vec![false; n]; n],
+        }
+    }
+
+    fn undirected(&self, a: usize, b: usize) -> bool {
+        self.adjacent[a][b] && !self.directed[a][b] && !self.directed[b][a]
+    }
+
+    /// `x -> z <- y` for every non-adjacent `x`, `y` whose separating set
+    /// leaves out their common neighbour `z`. An edge already compelled the
+    /// other way by an earlier v-structure is left as it is.
+    fn orient_v_structures(&mut self, sepsets: &HashMap<(usize, usize), Vec<usize>>) {
+        let n = self.adjacent.len();
+        for z in 0..n {
+            for x in 0..n {
+                for y in (x + 1)..n {
+                    if !self.adjacent[x][z] || !self.adjacent[y][z] || self.adjacent[x][y] {
+                        continue;
+                    }
+                    let separates = sepsets
+                        .get(&(x, y))
+                        .is_some_and(|sepset| sepset.contains(&z));
+                    if !separates {
+                        for parent in [x, y] {
+                            if !self.directed[z][parent] {
+                                self.directed[parent][z] = true;
+                            }
+                        }
+                    }
+                }
+            }
+        }
+    }
+
+    /// Meek's rules R1-R3, until none applies: orientations every DAG with
+    /// the same skeleton and v-structures shares.
+    fn apply_meek_rules(&mut self) {
+        let n = self.adjacent.len();
+        let mut changed = true;
+        while changed {
+            changed = false;
+            for a in 0..n {
+                for b in 0..n {
+                    if !self.undirected(a, b) {
+                        continue;
+                    }
+                    // R1: c -> a - b with c, b non-adjacent, or a new
+                    // v-structure would form at a.
+                    let r1 = (0..n).any(|c| self.directed[c][a] && !self.adjacent[c][b]);
+                    // R2: a -> c -> b, or a cycle would form.
+                    let r2 = (0..n).any(|c| self.directed[a][c] && self.directed[c][b]);
+                    // R3: a - c -> b and a - d -> b with c, d non-adjacent.
+                    let r3 = (0..n).any(|c| {
+                        self.undirected(a, c)
+                            && self.directed[c][b]
+                            && (c + 1..n).any(|d| {
+                                self.undirected(a, d) && self.directed[d][b] && !self.adjacent[c][d]
+                            })
+                    });
+                    if r1 || r2 || r3 {
+                        self.directed[a][b] = true;
+                        changed = true;
+                    }
+                }
+            }
+        }
+    }
+
+    /// Each node's parents in a DAG consistent with the graph (Dor and
+    /// Tarsi): repeatedly take the earliest node with no outgoing edge whose
+    /// undirected neighbours are adjacent to all its other neighbours, point
+    /// its undirected edges into it and remove it. This adds no v-structure
+    /// and no cycle.
+    fn extend(mut self) -> Result<Vec<Vec<usize>>> {
+        let n = self.adjacent.len();
+        let mut parents = vec![Vec::new(); n];
+        let mut remaining: BTreeSet<usize> = (0..n).collect();
+        while !remaining.is_empty() {
+            let sink = remaining.iter().copied().find(|&x| {
+                let neighbours: Vec<usize> = remaining
+                    .iter()
+                    .copied()
+                    .filter(|&y| self.adjacent[x][y])
+                    .collect();
+                neighbours.iter().all(|&y| !self.directed[x][y])
+                    && neighbours
+                        .iter()
+                        .filter(|&&y| self.undirected(x, y))
+                        .all(|&y| {
+                            neighbours
+                                .iter()
+                                .all(|&other| other == y || self.adjacent[y][other])
+                        })
+            });
+            let Some(sink) = sink else {
+                bail!("Conflicting v-structures leave no acyclic orientation of the learned graph");
+            };
+            remaining.remove(&sink);
+            for &other in &remaining {
+                if self.adjacent[other][sink] {
+                    parents[sink].push(other);
+                    self.adjacent[other][sink] = false;
+                    self.adjacent[sink][other] = false;
+                }
+            }
+            parents[sink].sort_unstable();
+        }
+        Ok(parents)
+    }
+}
+
+/// Conditional independence test of `x` and `y` given `given`.
+///
+/// Returns the p-value of the G statistic summed over every observed
+/// configuration of `given`. Rows missing any of the variables are skipped.
+pub(crate) fn g_test(data: &[DataRow], x: &str, y: &str, given: &[String]) -> f64 {
+    let mut strata: HashMap<Vec<bool>, [[f64; 2]; 2]> = HashMap::new();
+    for row in data {
+        let (Some(&x_value), Some(&y_value)) = (row.get(x), row.get(y)) else {
+            continue;
+        };
+        let Some(stratum) = given
+            .iter()
+            .map(|id| row.get(id).copied())
+            .collect::<Option<Vec<bool>>>()
+        else {
+            continue;
+        };
+        strata.entry(stratum).or_default()[usize::from(x_value)][usize::from(y_value)] += 1.0;
+    }
+
+    let mut statistic = 0.0;
+    let mut degrees_of_freedom = 0.0;
+    for counts in strata.values() {
+        let row_totals = [counts[0][0] + counts[0][1], counts[1][0] + counts[1][1]];
+        let column_totals = [counts[0][0] + counts[1][0], counts[0][1] + counts[1][1]];
+        let total = row_totals[0] + row_totals[1];
+        if row_totals.contains(&0.0) || column_totals.contains(&0.0) {
+            continue;
+        }
+        degrees_of_freedom += 1.0;
+        for (i, row) in counts.iter().enumerate() {
+            for (j, &observed) in row.iter().enumerate() {
+                if observed > 0.0 {
+                    let expected = row_totals[i] * column_totals[j] / total;
+                    statistic += 2.0 * observed * (observed / expected).ln();
+                }
+            }
+        }
+    }
+
+    if degrees_of_freedom == 0.0 {
+        return 1.0;
+    }
+    chi_squared_sf(statistic, degrees_of_freedom)
+}
+
+#[derive(Serialize)]
+#[serde(rename_all = "camelCase")]
+pub struct FaithfulnessViolation {
+    pub x: String,
+    pub y: String,
+    pub z: Vec<String>,
+    pub p_value: f64,
+    /// Whether the graph d-separates `x` and `y` given `z` (and the data
+    /// shows dependence), rather than connecting them (and the data shows
+    /// independence).
+    pub d_separated: bool,
+}
+
+/// Conditional independences in `data` that the graph disagrees with.
+///
+/// Each pair of nodes present in the data is tested once, given the parents
+/// of the later node (in topological order) other than the earlier one. By
+/// the local Markov property the pair is d-separated there exactly when the
+/// nodes are not adjacent, so this covers every edge the data contradicts
+/// and every missing edge it calls for without enumerating conditioning sets.
+/// Parents absent from the data are dropped from the conditioning set.
+pub fn check_faithfulness(
+    nodes: &[Node],
+    data: &[DataRow],
+    alpha: f64,
+) -> Result<Vec<FaithfulnessViolation>> {
+    if !(0.0..=1.0).contains(&alpha) {
+        bail!("alpha must be within [0, 1], got {alpha}");
+    }
+    let serialized = serialize_network(nodes)?;
+    let measured: BTreeSet<&str> = data
+        .iter()
+        .flat_map(|row| row.keys().map(String::as_str))
+        .collect();
+    let ids = &serialized.topo_order;
+    let mut violations = Vec::new();
+    for (later, y) in ids.iter().enumerate() {
+        if !measured.contains(y.as_str()) {
+            continue;
+        }
+        for (earlier, x) in ids[..later].iter().enumerate() {
+            if !measured.contains(x.as_str()) {
+                continue;
+            }
+            let given: Vec<u8> = serialized.parents[later]
+                .iter()
+                .copied()
+                .filter(|&parent| {
+                    usize::from(parent) != earlier
+                        && measured.contains(ids[usize::from(parent)].as_str())
+                })
+                .collect();
+            let z: Vec<String> = given
+                .iter()
+                .map(|&node| ids[usize::from(node)].clone())
+                .collect();
+            let index = |i| u8::try_from(i).expect("serialize_network caps networks at 255 nodes");
+            let separated = d_separated(&serialized, index(earlier), index(later), &given);
+            let p_value = g_test(data, x, y, &z);
+            if separated == (p_value <= alpha) {
+                violations.push(FaithfulnessViolation {
+                    x: x.clone(),
+                    y: y.clone(),
+                    z,
+                    p_value,
+                    d_separated: separated,
+                });
+            }
+        }
+    }
+    Ok(violations)
+}
+
+/// Natural log of the probability `nodes` gives to `data`, each row a
+/// complete assignment of every node.
+pub fn log_likelihood(nodes: &[Node], data: &[DataRow]) -> Result<f64> {
+    serialize_network(nodes)?;
+    let mut total = 0.0;
+    for (i, row) in data.iter().enumerate() {
+        for node in nodes {
+            let value = |id: &str| {
+                row.get(id)
+                    .copied()
+                    .ok_or_else(|| anyhow!("Row {i} has no value for {id}"))
+            };
+            let observed = value(&node.id)?;
+            for parent_id in get_node_parents(node) {
+                value(parent_id)?;
+            }
+            let p_true = node
+                .probability_given(|parent_id| row[parent_id])
+                .ok_or_else(|| anyhow!("Node {id} has no CPT entry for row {i}", id = node.id))?;
+            total += if observed { p_true } else { 1.0 - p_true }.ln();
+        }
+    }
+    Ok(total)
+}
+
+/// Free parameters of `nodes`: one per CPT entry that some parent
+/// assignment reaches, or per entry for nodes with too many parents to
+/// enumerate.
+pub(crate) fn free_parameters(nodes: &[Node]) -> usize {
+    nodes
+        .iter()
+        .map(|node| {
+            let mut parents = get_node_parents(node);
+            parents.sort_unstable();
+            if parents.len() > MAX_COMPLETENESS_PARENTS {
+                node.cpt_entries.len()
+            } else {
+                reachable_entries(node, &parents)
+                    .into_iter()
+                    .filter(|&fires| fires)
+                    .count()
+            }
+        })
+        .sum()
+}
+
+#[derive(Serialize)]
+#[serde(rename_all = "camelCase")]
+pub struct LikelihoodRatioTest {
+    /// `log L(H1) - log L(H0)`.
+    pub log_lr: f64,
+    pub df: usize,
+    /// `P(chi-squared(df) > 2 * log_lr)`.
+    pub p_value: f64,
+}
+
+/// Likelihood ratio test of `nodes_h0` against the richer `nodes_h1`.
+///
+/// The p-value relies on Wilks' chi-squared approximation, which assumes
+/// H0 is nested in H1 and both carry maximum-likelihood parameters for
+/// `data`; `df` is the difference in [`free_parameters`].
+pub fn likelihood_ratio_test(
+    nodes_h0: &[Node],
+    nodes_h1: &[Node],
+    data: &[DataRow],
+) -> Result<LikelihoodRatioTest> {
+    if data.is_empty() {
+        bail!("The likelihood ratio test needs at least one row");
+    }
+    let (params_h0, params_h1) = (free_parameters(nodes_h0), free_parameters(nodes_h1));
+    if params_h1 <= params_h0 {
+        bail!("H1 must have more free parameters than H0 (H0 has {params_h0}, H1 has {params_h1})");
+    }
+    let log_lr = log_likelihood(nodes_h1, data)? - log_likelihood(nodes_h0, data)?;
+    if log_lr.is_nan() {
+        bail!("Both hypotheses give the data probability zero");
+    }
+    let df = params_h1 - params_h0;
+    #[allow(clippy::cast_precision_loss)]
+    let p_value = if log_lr == f64::INFINITY {
+        0.0
+    } else {
+        chi_squared_sf(2.0 * log_lr, df as f64)
+    };
+    Ok(LikelihoodRatioTest {
+        log_lr,
+        df,
+        p_value,
+    })
+}
+
+/// Adjusts CPTs by iterative proportional fitting until the sampled marginals
+/// of the targeted nodes are within `tol` of `target_marginals`.
+///
+/// Each iteration estimates the current marginals and rescales every entry of
+/// a targeted node, weighting its true mass by `target / current` and its false
+/// mass by `(1 - target) / (1 - current)`. The DAG is left untouched. Returns
+/// the number of iterations used; `tol` should exceed the sampling noise of
+/// `num_samples` or the fit cannot be confirmed.
+pub fn fit_marginals(
+    nodes: &mut [Node],
+    target_marginals: &HashMap<String, f64>,
+    num_samples: usize,
+    max_iter: usize,
+    tol: f64,
+    rng: &mut Xoshiro128Plus,
+) -> Result<usize> {
+    if num_samples == 0 {
+        bail!("Fitting needs at least one sample per iteration");
+    }
+    for (node_id, &target) in target_marginals {
+        find_node(nodes, "Target", node_id)?;
+        if !(0.0..=1.0).contains(&target) {
+            bail!("Target marginal for {node_id} must be within [0, 1], got {target}");
+        }
+    }
+
+    #[allow(clippy::cast_precision_loss)]
+    let resolution = 1.0 / num_samples as f64;
+    let mut worst_gap = f64::INFINITY;
+    for iteration in 0..=max_iter {
+        let serialized = serialize_network(nodes)?;
+        let current = estimate_marginals(&serialized, num_samples, &[], &[], rng)?;
+        worst_gap = target_marginals
+            .iter()
+            .map(|(node_id, &target)| (current[node_id] - target).abs())
+            .fold(0.0, f64::max);
+        if worst_gap <= tol {
+            return Ok(iteration);
+        }
+        if iteration == max_iter {
+            break;
+        }
+
+        for node in nodes.iter_mut() {
+            let Some(&target) = target_marginals.get(&node.id) else {
+                continue;
+            };
+            // Unseen outcomes are treated as one sample's worth of mass so
+            // the scaling stays finite.
+            let current = current[&node.id].clamp(resolution, 1.0 - resolution);
+            let true_scale = target / current;
+            let false_scale = (1.0 - target) / (1.0 - current);
+            for entry in &mut node.cpt_entries {
+                entry.map_probabilities(|probability| {
+                    let true_mass = probability * true_scale;
+                    let false_mass = (1.0 - probability) * false_scale;
+                    let total = true_mass + false_mass;
+                    if total > 0.0 {
+                        true_mass / total
+                    } else {
+                        probability
+                    }
+                });
+            }
+        }
+    }
+    bail!("Marginals did not converge within {max_iter} iterations (largest gap {worst_gap})")
+}
+
+/// Running `P(true)` of one CPT entry: Welford's running mean over 0/1
+/// values, so no row needs to be kept.
+#[derive(Clone, Copy, Default)]
+struct RunningMean {
+    count: u64,
+    mean: f64,
+}
+
+impl RunningMean {
+    fn push(&mut self, value: bool) {
+        self.count += 1;
+        #[allow(clippy::cast_precision_loss)]
+        let count = self.count as f64;
+        self.mean += (f64::from(u8::from(value)) - self.mean) / count;
+    }
+}
+
+/// Maximum-likelihood CPTs for `skeleton`, streamed from CSV one row at a time.
+///
+/// The header row names node IDs; columns for other IDs are ignored and nodes
+/// without a column are treated as always missing. Cells are `true`/`false`,
+/// `1`/`0`, or `?` for missing. Each row counts toward the first CPT entry
+/// matching its parent values (the entry the sampler would use), and is
+/// skipped for a node when the node or any of its parents is missing. Entries
+/// no row reaches keep their skeleton probability, and hierarchical entries
+/// keep their parameters, since one frequency cannot stand in for both.
+pub fn learn_parameters_from_csv(skeleton: &[Node], csv: impl BufRead) -> Result<Vec<Node>> {
+    serialize_network(skeleton)?;
+    let mut lines = csv.lines().enumerate();
+    let header = loop {
+        match lines.next() {
+            Some((_, line)) if line.as_ref().is_ok_and(|l| l.trim().is_empty()) => {}
+            Some((_, line)) => break line?,
+            None => bail!("CSV is empty"),
+        }
+    };
+    let columns: Vec<&str> = header.split(',').map(csv_cell).collect();
+    let mut column_of: HashMap<&str, usize> = HashMap::new();
+    for (index, &column) in columns.iter().enumerate() {
+        if column_of.insert(column, index).is_some() {
+            bail!("CSV header names column {column} more than once");
+        }
+    }
+    // For each node, its column and its parents' columns.
+    let layout = skeleton
+        .iter()
+        .map(|node| {
+            let parents: Vec<(&str, Option<usize>)> = get_node_parents(node)
+                .into_iter()
+                .map(|parent| (parent, column_of.get(parent).copied()))
+                .collect();
+            (column_of.get(node.id.as_str()).copied(), parents)
+        })
+        .collect::<Vec<_>>();
+
+    let mut estimates: Vec<Vec<RunningMean>> = skeleton
+        .iter()
+        .map(|node| vec![RunningMean::default(); node.cpt_entries.len()])
+        .collect();
+    let mut row = Vec::with_capacity(columns.len());
+    for (line_index, line) in lines {
+        let line = line?;
+        if line.trim().is_empty() {
+            continue;
+        }
+        row.clear();
+        for cell in line.split(',').map(csv_cell) {
+            row.push(match cell {
+                "true" | "1" => Some(true),
+                "false" | "0" => Some(false),
+                "?" => None,
+                _ => bail!(
+                    "CSV line {line} has value {cell:?}; expected true, false, 1, 0 or ?",
+                    line = line_index + 1
+                ),
+            });
+        }
+        if row.len() != columns.len() {
+            bail!(
+                "CSV line {line} has {cells} cells but the header has {expected}",
+                line = line_index + 1,
+                cells = row.len(),
+                expected = columns.len()
+            );
+        }
+
+        for ((node, (column, parents)), estimates) in
+            skeleton.iter().zip(&layout).zip(&mut estimates)
+        {
+            let Some(value) = column.and_then(|c| row[c]) else {
+                continue;
+            };
+            let Some(parent_values) = parents
+                .iter()
+                .map(|&(parent, column)| column.and_then(|c| row[c]).map(|v| (parent, v)))
+                .collect::<Option<HashMap<&str, bool>>>()
+            else {
+                continue;
+            };
+            let matching = node.cpt_entries.iter().position(|entry| {
+                entry.parent_states.iter().all(|(parent, state)| {
+                    state.is_none_or(|state| parent_values[parent.as_str()] == state)
+                })
+            });
+            if let Some(index) = matching {
+                estimates[index].push(value);
+            }
+        }
+    }
+
+    Ok(skeleton
+        .iter()
+        .zip(estimates)
+        .map(|(node, estimates)| {
+            let mut node = node.clone();
+            for (entry, estimate) in node.cpt_entries.iter_mut().zip(estimates) {
+                if estimate.count > 0 && entry.probability_params.is_none() {
+                    entry.set_probability_of_true(estimate.mean);
+                }
+            }
+            node
+        })
+        .collect())
+}
+
+fn csv_cell(cell: &str) -> &str {
+    let cell = cell.trim();
+    cell.strip_prefix('"')
+        .and_then(|c| c.strip_suffix('"'))
+        .unwrap_or(cell)
+}
+
+fn subsets(items: &[usize], size: usize) -> Vec<Vec<usize>> {
+    if size == 0 {
+        return vec![Vec::new()];
+    }
+    if items.len() < size {
+        return Vec::new();
+    }
+    let (first, rest) = (items[0], &items[1..]);
+    let mut result: Vec<Vec<usize>> = subsets(rest, size - 1)
+        .into_iter()
+        .map(|mut subset| {
+            subset.insert(0, first);
+            subset
+        })
+        .collect();
+    result.extend(subsets(rest, size));
+    result
+}
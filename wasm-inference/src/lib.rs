@@ -1,12 +1,164 @@
 use rand::SeedableRng;
 use rand_xoshiro::Xoshiro128Plus;
 use serde::{Deserialize, Serialize};
-use std::collections::HashMap;
+use std::collections::{BTreeMap, HashMap};
 use wasm_bindgen::prelude::*;
 
+use limits::{MAX_ROWS, MAX_SAMPLES, MAX_SEED, MAX_STEPS, MAX_STORED_SAMPLES};
+
+mod ambiguity;
+mod annealing;
+mod assumptions;
 mod bit_set;
+mod calibration;
+mod causal;
+mod compact;
+mod compiled;
+mod completion;
+mod constraints;
+mod copula;
+mod cpt_import;
+mod cpt_table;
+mod dataset;
+mod dbn;
+mod disagreement;
+mod do_calculus;
+mod ensemble;
+mod exact;
+mod fixtures;
+mod graphml;
+mod identification;
+mod learning;
+mod limits;
+mod lookup;
+mod marginals;
+mod options;
+mod power;
+mod progress;
+mod raw_samples;
+mod recording;
+mod reduction;
+mod rng_trace;
 mod sample;
+mod scenarios;
+mod self_check;
 mod serialize;
+mod statistics;
+mod structure;
+mod validate;
+mod workspace;
+
+pub use compiled::{CompiledNetwork, get_network_summary, get_node_info, validate_query};
+pub use cpt_table::CptTable;
+pub use dbn::compute_mixing_time;
+pub use serialize::{layout_fingerprint, serialize_network_to_writer};
+pub use workspace::Workspace;
+
+/// Entry points for the cargo-fuzz targets in `fuzz/`, which need the
+/// parsers without going through `JsValue`. Run a target with
+/// `cargo +nightly fuzz run <target>` from this crate's directory.
+#[cfg(fuzzing)]
+#[doc(hidden)]
+#[allow(clippy::missing_errors_doc)]
+pub mod fuzzing {
+    use rand::SeedableRng;
+    use rand_xoshiro::Xoshiro128Plus;
+
+    use crate::bit_set::BitSet;
+    use crate::{Node, compact, sample, serialize, structure};
+
+    pub fn from_compact(bytes: &[u8]) -> anyhow::Result<Vec<Node>> {
+        compact::from_compact(bytes)
+    }
+
+    #[must_use]
+    pub fn to_compact(nodes: &[Node]) -> Vec<u8> {
+        compact::to_compact(nodes)
+    }
+
+    /// The compiled buffer for `nodes` and its node count.
+    pub fn compile(nodes: &[Node]) -> anyhow::Result<(Vec<u8>, u8)> {
+        let serialized = serialize::serialize_network(nodes)?;
+        let num_nodes = serialized.num_nodes();
+        Ok((serialized.data, num_nodes))
+    }
+
+    /// Runs every sampler that parses a compiled buffer over `data`.
+    pub fn sample_buffer(data: &[u8], num_nodes: u8) -> anyhow::Result<()> {
+        let mut rng = Xoshiro128Plus::seed_from_u64(0);
+        let offsets = sample::record_offsets(data, num_nodes)?;
+        sample::sample(data, &offsets, &[], &mut rng)?;
+        sample::sample_weighted(data, &offsets, &[], &[], &mut rng)?;
+        sample::sample_twin(data, &offsets, [&[], &[]], &mut rng)?;
+        sample::log_joint(data, &offsets, &BitSet::new())?;
+        Ok(())
+    }
+
+    /// Resolves every node's CPT for every parent assignment.
+    pub fn describe(nodes: &[Node]) -> anyhow::Result<()> {
+        structure::get_conditional_marginals(nodes).map(drop)
+    }
+}
+
+/// Entry points for the timing harnesses in `benches/`, which drive the
+/// samplers on compiled networks without going through `JsValue`. Run them
+/// with `cargo bench --features bench`.
+#[cfg(feature = "bench")]
+#[doc(hidden)]
+#[allow(clippy::missing_errors_doc)]
+pub mod benchmarking {
+    use rand::SeedableRng;
+    use rand_xoshiro::Xoshiro128Plus;
+
+    use crate::serialize::{self, SerializedNetwork};
+    use crate::{Node, sample};
+
+    pub struct Compiled(SerializedNetwork);
+
+    pub fn compile(nodes: &[Node]) -> anyhow::Result<Compiled> {
+        serialize::serialize_network(nodes).map(Compiled)
+    }
+
+    /// Draws `num_samples` prior samples, returning how many nodes came out
+    /// true so the work can't be optimized away.
+    pub fn sample(compiled: &Compiled, num_samples: usize, seed: u64) -> anyhow::Result<usize> {
+        let mut rng = Xoshiro128Plus::seed_from_u64(seed);
+        let samples = sample::sample_all(
+            &compiled.0.data,
+            &compiled.0.offsets,
+            num_samples,
+            &[],
+            &mut rng,
+        )?;
+        Ok(samples
+            .iter()
+            .map(|sample| {
+                (0..compiled.0.num_nodes())
+                    .filter(|&node| sample.contains(node))
+                    .count()
+            })
+            .sum())
+    }
+
+    /// `CompiledNetwork::order_entries_by_frequency` with a seeded pilot,
+    /// returning the number of nodes reordered.
+    pub fn order_entries(
+        compiled: &mut Compiled,
+        pilot_samples: usize,
+        seed: u64,
+    ) -> anyhow::Result<usize> {
+        let mut rng = Xoshiro128Plus::seed_from_u64(seed);
+        let pilot = sample::sample_all(
+            &compiled.0.data,
+            &compiled.0.offsets,
+            pilot_samples,
+            &[],
+            &mut rng,
+        )?;
+        sample::order_entries_by_hits(&mut compiled.0.data, &compiled.0.offsets, &pilot)
+            .map(|reordered| reordered.len())
+    }
+}
 
 #[wasm_bindgen(start)]
 pub fn init_panic_hook() {
@@ -18,133 +170,2771 @@ pub fn init_panic_hook() {
 pub struct InterventionResult {
     pub true_case: HashMap<String, f64>,
     pub false_case: HashMap<String, f64>,
+    pub risk_ratio: HashMap<String, f64>,
+    pub odds_ratio: HashMap<String, f64>,
+    /// Marginals without the intervention, when the query computed them.
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub baseline: Option<HashMap<String, f64>>,
+    /// Nodes the intervention cannot move, sorted, when the query worked
+    /// them out.
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub unaffected_nodes: Option<Vec<String>>,
+    /// How the result was produced, when both arms ran on one network.
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub provenance: Option<Provenance>,
 }
 
-#[derive(Deserialize)]
+impl InterventionResult {
+    fn new(
+        true_case: HashMap<String, f64>,
+        false_case: HashMap<String, f64>,
+        baseline: Option<HashMap<String, f64>>,
+    ) -> Self {
+        let (risk_ratio, odds_ratio) = true_case
+            .iter()
+            .map(|(node_id, &p_do_true)| {
+                let p_do_false = false_case[node_id];
+                (
+                    (
+                        node_id.clone(),
+                        causal::causal_risk_ratio(p_do_true, p_do_false),
+                    ),
+                    (
+                        node_id.clone(),
+                        causal::causal_odds_ratio(p_do_true, p_do_false),
+                    ),
+                )
+            })
+            .unzip();
+        InterventionResult {
+            true_case,
+            false_case,
+            risk_ratio,
+            odds_ratio,
+            baseline,
+            unaffected_nodes: None,
+            provenance: None,
+        }
+    }
+}
+
+#[derive(Clone, Serialize, Deserialize)]
 #[serde(rename_all = "camelCase")]
 pub struct CptEntry {
     pub parent_states: HashMap<String, Option<bool>>,
-    pub probability: f32,
+    /// Kept at JS precision until serialization, where it is canonicalized to f32.
+    pub probability: f64,
+    /// Whether `probability` is `P(true | parents)` (the default) or
+    /// `P(false | parents)`. Read it through [`CptEntry::probability_of_true`].
+    #[serde(default = "default_true", skip_serializing_if = "Clone::clone")]
+    pub is_probability_of_true: bool,
+    /// When set, the probability is chosen by the sampled value of a
+    /// hyperparameter node, which becomes a parent of this node, and
+    /// `probability` is ignored.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub probability_params: Option<HierarchicalParam>,
 }
 
-#[derive(Deserialize)]
+/// A two-level parameter: `p_high` while the hyperparameter node is true and
+/// `p_low` while it is false. Both follow the entry's
+/// `is_probability_of_true` convention.
+#[derive(Clone, Serialize, Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub struct HierarchicalParam {
+    pub hyperparameter_id: String,
+    pub p_high: f64,
+    pub p_low: f64,
+}
+
+fn default_true() -> bool {
+    true
+}
+
+impl CptEntry {
+    /// An uninformative entry (probability 0.5) for the given parent states.
+    #[must_use]
+    pub fn uniform(parent_states: HashMap<String, Option<bool>>) -> Self {
+        Self {
+            parent_states,
+            probability: 0.5,
+            is_probability_of_true: true,
+            probability_params: None,
+        }
+    }
+
+    /// `P(true | parents)`, whichever convention `probability` is given in.
+    /// Ignores `probability_params`.
+    #[must_use]
+    pub fn probability_of_true(&self) -> f64 {
+        self.as_probability_of_true(self.probability)
+    }
+
+    /// `P(true | parents)` including a hierarchical parameter, which is
+    /// resolved with `parent_value`.
+    pub(crate) fn probability_of_true_given(&self, parent_value: impl Fn(&str) -> bool) -> f64 {
+        match &self.probability_params {
+            Some(params) if parent_value(&params.hyperparameter_id) => {
+                self.as_probability_of_true(params.p_high)
+            }
+            Some(params) => self.as_probability_of_true(params.p_low),
+            None => self.probability_of_true(),
+        }
+    }
+
+    /// Converts one of the entry's probabilities to `P(true)`.
+    pub(crate) fn as_probability_of_true(&self, probability: f64) -> f64 {
+        if self.is_probability_of_true {
+            probability
+        } else {
+            1.0 - probability
+        }
+    }
+
+    /// Stores `P(true | parents)`, switching the entry to that convention and
+    /// dropping any hierarchical parameter.
+    pub fn set_probability_of_true(&mut self, probability: f64) {
+        self.probability = probability;
+        self.is_probability_of_true = true;
+        self.probability_params = None;
+    }
+
+    /// Applies `f` to each of the entry's probabilities as `P(true)`, keeping
+    /// a hierarchical parameter hierarchical.
+    pub(crate) fn map_probabilities(&mut self, f: impl Fn(f64) -> f64) {
+        let to_true = |entry: &Self, probability| f(entry.as_probability_of_true(probability));
+        self.probability = to_true(self, self.probability);
+        if let Some(params) = &self.probability_params {
+            let (p_high, p_low) = (to_true(self, params.p_high), to_true(self, params.p_low));
+            self.probability_params = Some(HierarchicalParam {
+                p_high,
+                p_low,
+                ..params.clone()
+            });
+        }
+        self.is_probability_of_true = true;
+    }
+}
+
+#[derive(Clone, Serialize, Deserialize)]
 #[serde(rename_all = "camelCase")]
 pub struct Node {
     #[serde(rename = "_id")]
     pub id: String,
+    #[serde(default)]
     pub cpt_entries: Vec<CptEntry>,
+    /// The CPT as a complete truth table instead of entries. Expanded into
+    /// `cpt_entries` when nodes are read, so only one of the two may be set.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub cpt_table: Option<CptTable>,
+    /// Value the node was observed to take; queries treat it as evidence.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub observed: Option<bool>,
+    /// Lowest `P(true)` the node may take, whatever its CPT says. Applied
+    /// after CPT resolution; interventions and clamps are not bounded.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub probability_floor: Option<f64>,
+    /// Highest `P(true)` the node may take, whatever its CPT says.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub probability_ceiling: Option<f64>,
+    /// Chance that unmodeled causes make the node true on their own, OR-ed
+    /// with its CPT: `1 - (1 - p) * (1 - leak)`. Stands in for a root
+    /// "other causes" parent without adding one; applied before the floor
+    /// and ceiling.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub leak_probability: Option<f64>,
+    /// Unmeasured in the data the model stands for. Sampling ignores the
+    /// flag; identifiability checks treat the node as hidden.
+    #[serde(default, skip_serializing_if = "std::ops::Not::not")]
+    pub latent: bool,
+    /// Human-readable name shown by the editor. Exports carry it along, and
+    /// results can be keyed by it instead of `_id`.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub title: Option<String>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub description: Option<String>,
+    /// Arbitrary caller data, preserved but never interpreted.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub metadata: Option<serde_json::Value>,
+}
+
+/// Most parents [`Node::with_uniform_cpt`] takes: a full table over 8 would
+/// need 256 entries, one more than a node can hold.
+pub const MAX_UNIFORM_CPT_PARENTS: usize = 7;
+
+impl Node {
+    /// A root node with a single unconditional entry.
+    #[must_use]
+    pub fn with_prior(id: String, prior: f32) -> Self {
+        Self {
+            id,
+            cpt_entries: vec![CptEntry {
+                parent_states: HashMap::new(),
+                probability: f64::from(prior),
+                is_probability_of_true: true,
+                probability_params: None,
+            }],
+            cpt_table: None,
+            observed: None,
+            probability_floor: None,
+            probability_ceiling: None,
+            leak_probability: None,
+            latent: false,
+            title: None,
+            description: None,
+            metadata: None,
+        }
+    }
+
+    /// A node with a full `2^k` table over `parent_ids`, every entry uniform.
+    ///
+    /// Useful as a placeholder while exploring structure before eliciting
+    /// probabilities.
+    ///
+    /// # Errors
+    ///
+    /// When there are more than [`MAX_UNIFORM_CPT_PARENTS`] parents.
+    pub fn with_uniform_cpt(id: String, parent_ids: &[&str]) -> anyhow::Result<Self> {
+        if parent_ids.len() > MAX_UNIFORM_CPT_PARENTS {
+            anyhow::bail!(
+                "Node {id} has {count} parents; a full table supports at most \
+                 {MAX_UNIFORM_CPT_PARENTS}",
+                count = parent_ids.len()
+            );
+        }
+        let cpt_entries = (0..1usize << parent_ids.len())
+            .map(|assignment| {
+                let parent_states = parent_ids
+                    .iter()
+                    .enumerate()
+                    .map(|(i, &parent_id)| {
+                        (parent_id.to_string(), Some(assignment & (1 << i) != 0))
+                    })
+                    .collect();
+                CptEntry::uniform(parent_states)
+            })
+            .collect();
+        Ok(Self {
+            id,
+            cpt_entries,
+            cpt_table: None,
+            observed: None,
+            probability_floor: None,
+            probability_ceiling: None,
+            leak_probability: None,
+            latent: false,
+            title: None,
+            description: None,
+            metadata: None,
+        })
+    }
+
+    /// `P(true)` under the first CPT entry matching the given parent values,
+    /// with the node's leak and within its floor and ceiling, mirroring the
+    /// sampler. `None` if no entry matches.
+    pub(crate) fn probability_given(&self, parent_value: impl Fn(&str) -> bool) -> Option<f64> {
+        self.cpt_entries
+            .iter()
+            .find(|entry| {
+                entry.parent_states.iter().all(|(parent_id, state)| {
+                    state.is_none_or(|expected| parent_value(parent_id) == expected)
+                })
+            })
+            .map(|entry| self.effective_probability(entry.probability_of_true_given(&parent_value)))
+    }
+
+    /// An entry's `P(true)` as sampled: leaked, then bounded.
+    pub(crate) fn effective_probability(&self, probability: f64) -> f64 {
+        let probability = match self.leak_probability {
+            Some(leak) if leak > 0.0 => 1.0 - (1.0 - probability) * (1.0 - leak),
+            _ => probability,
+        };
+        self.bound_probability(probability)
+    }
+
+    pub(crate) fn bound_probability(&self, probability: f64) -> f64 {
+        let floor = self.probability_floor.unwrap_or(0.0);
+        let ceiling = self.probability_ceiling.unwrap_or(1.0);
+        probability.max(floor).min(ceiling)
+    }
 }
 
 #[wasm_bindgen]
 #[allow(clippy::missing_errors_doc)]
 pub fn compute_marginals(
     nodes: JsValue,
-    num_samples: usize,
+    num_samples: f64,
     intervention_node_id: Option<String>,
 ) -> Result<JsValue, JsValue> {
-    let nodes: Vec<Node> = serde_wasm_bindgen::from_value(nodes)
-        .map_err(|e| JsValue::from_str(&format!("Failed to deserialize nodes: {e}")))?;
+    let num_samples = checked_count("numSamples", num_samples, MAX_SAMPLES)?;
+    let nodes = deserialize_nodes(nodes)?;
 
     let serialized = serialize::serialize_network(&nodes)
         .map_err(|e| JsValue::from_str(&format!("Serialization failed: {e}")))?;
 
-    let mut seed = [0u8; 16];
-    getrandom::fill(&mut seed).map_err(|e| JsValue::from_str(&format!("RNG seed failed: {e}")))?;
-    let mut rng = Xoshiro128Plus::from_seed(seed);
+    let (seed, mut rng) = rng_from_seed(None)?;
 
-    let num_nodes = u8::try_from(serialized.topo_order.len())
-        .map_err(|_| JsValue::from_str("Too many nodes for u8"))?;
+    let Some(intervention_node_id) = intervention_node_id else {
+        let probabilities =
+            marginals::estimate_marginals(&serialized, num_samples, &[], &[], &mut rng)
+                .map_err(|e| JsValue::from_str(&e.to_string()))?;
+        return serde_wasm_bindgen::to_value(&probabilities)
+            .map_err(|e| JsValue::from_str(&format!("Failed to serialize result: {e}")));
+    };
 
-    // If no intervention, compute baseline marginals
-    if intervention_node_id.is_none() {
-        let mut node_true_counts = vec![0usize; usize::from(num_nodes)];
+    // Intervention case: compute both do(node=true) and do(node=false)
+    let intervention_idx = lookup::resolve(&serialized, "Intervention", &intervention_node_id)
+        .map_err(|e| not_found_error(&e))?;
 
-        for _ in 0..num_samples {
-            let sample_result = sample::sample(&serialized.data, num_nodes, None, &mut rng)
-                .map_err(|e| JsValue::from_str(&format!("Sampling failed: {e}")))?;
+    let num_nodes = serialized.num_nodes();
+    let mut estimate_with_intervention = |value: bool| {
+        marginals::estimate_marginals(
+            &serialized,
+            num_samples,
+            &marginals::intervention(num_nodes, intervention_idx, value),
+            &[],
+            &mut rng,
+        )
+        .map_err(|e| JsValue::from_str(&e.to_string()))
+    };
 
-            for node_idx in 0..num_nodes {
-                if sample_result.contains(node_idx) {
-                    node_true_counts[usize::from(node_idx)] += 1;
-                }
-            }
-        }
+    let true_case = estimate_with_intervention(true)?;
+    let false_case = estimate_with_intervention(false)?;
+    let mut result = InterventionResult::new(true_case, false_case, None);
+    result.provenance = Some(Provenance {
+        assumptions: assumptions::AssumptionSet::default(),
+        fingerprint: serialized.fingerprint(),
+        seed,
+    });
 
-        #[allow(clippy::cast_precision_loss)]
-        let probabilities: HashMap<String, f64> = serialized
-            .topo_order
-            .into_iter()
-            .zip(node_true_counts)
-            .map(|(node_id, count)| {
-                let probability = count as f64 / num_samples as f64;
-                (node_id, probability)
-            })
-            .collect();
+    serde_wasm_bindgen::to_value(&result)
+        .map_err(|e| JsValue::from_str(&format!("Failed to serialize result: {e}")))
+}
 
-        return serde_wasm_bindgen::to_value(&probabilities)
+/// Marginals averaged over up to 10 networks sharing node IDs, weighted by
+/// `weights` (which must sum to 1), as `{ ensemble, perNetwork }`. With an
+/// intervention node, each entry is an intervention result whose two arms
+/// are averaged separately.
+#[wasm_bindgen]
+// wasm-bindgen cannot take an `Option<&str>`.
+#[allow(clippy::missing_errors_doc, clippy::needless_pass_by_value)]
+pub fn compute_marginals_ensemble(
+    networks: Vec<JsValue>,
+    weights: &[f64],
+    num_samples_per_network: f64,
+    intervention_node_id: Option<String>,
+) -> Result<JsValue, JsValue> {
+    let num_samples = checked_count("numSamplesPerNetwork", num_samples_per_network, MAX_SAMPLES)?;
+    let networks = networks
+        .into_iter()
+        .map(deserialize_nodes)
+        .collect::<Result<Vec<_>, _>>()?;
+    ensemble::check(&networks, weights)
+        .map_err(|e| JsValue::from_str(&format!("Invalid ensemble: {e}")))?;
+
+    let mut rng = seeded_rng()?;
+    // Without an intervention only `true_cases` is filled, with the plain
+    // marginals of each network.
+    let mut true_cases = Vec::with_capacity(networks.len());
+    let mut false_cases = Vec::with_capacity(networks.len());
+    for (idx, nodes) in networks.iter().enumerate() {
+        let serialized = serialize::serialize_network(nodes).map_err(|e| {
+            JsValue::from_str(&format!("Serialization of network {idx} failed: {e}"))
+        })?;
+        let mut estimate = |overrides: &[Option<sample::Override>]| {
+            marginals::estimate_marginals(&serialized, num_samples, overrides, &[], &mut rng)
+                .map_err(|e| JsValue::from_str(&e.to_string()))
+        };
+        let Some(node_id) = &intervention_node_id else {
+            true_cases.push(estimate(&[])?);
+            continue;
+        };
+        let index = lookup::resolve(&serialized, "Intervention", node_id)
+            .map_err(|e| not_found_error(&e))?;
+        let num_nodes = serialized.num_nodes();
+        true_cases.push(estimate(&marginals::intervention(num_nodes, index, true))?);
+        false_cases.push(estimate(&marginals::intervention(num_nodes, index, false))?);
+    }
+
+    if intervention_node_id.is_none() {
+        let result = ensemble::Ensemble {
+            ensemble: ensemble::weighted_average(&true_cases, weights),
+            per_network: true_cases,
+        };
+        return serde_wasm_bindgen::to_value(&result)
             .map_err(|e| JsValue::from_str(&format!("Failed to serialize result: {e}")));
     }
+    let result = ensemble::Ensemble {
+        ensemble: InterventionResult::new(
+            ensemble::weighted_average(&true_cases, weights),
+            ensemble::weighted_average(&false_cases, weights),
+            None,
+        ),
+        per_network: true_cases
+            .into_iter()
+            .zip(false_cases)
+            .map(|(true_case, false_case)| InterventionResult::new(true_case, false_case, None))
+            .collect(),
+    };
+    serde_wasm_bindgen::to_value(&result)
+        .map_err(|e| JsValue::from_str(&format!("Failed to serialize result: {e}")))
+}
 
-    // Intervention case: compute both do(node=true) and do(node=false)
-    let intervention_node_id = intervention_node_id.unwrap();
-    let intervention_idx = u8::try_from(
-        serialized
-            .topo_order
-            .iter()
-            .position(|id| id == &intervention_node_id)
-            .ok_or_else(|| {
-                JsValue::from_str(&format!("Intervention node {intervention_node_id} not found"))
-            })?,
-    )
-    .map_err(|_| JsValue::from_str("Intervention index exceeds u8::MAX"))?;
-
-    let mut compute_marginals_with_intervention =
-        |intervention_value: bool| -> Result<HashMap<String, f64>, JsValue> {
-            let mut node_true_counts = vec![0usize; usize::from(num_nodes)];
-
-            for _ in 0..num_samples {
-                let sample_result = sample::sample(
-                    &serialized.data,
-                    num_nodes,
-                    Some(sample::Intervention {
-                        on_node: intervention_idx,
-                        value: intervention_value,
-                    }),
-                    &mut rng,
-                )
-                .map_err(|e| JsValue::from_str(&format!("Sampling failed: {e}")))?;
+/// Like `compute_marginals` without an intervention, but calls `on_progress`
+/// every `interval` samples (and after the last) with `{ samplesDrawn,
+/// marginals, topMovers }`. `topMovers` lists up to `top_k` nodes as
+/// `{ id, previous, current }`, those whose estimate moved most since the
+/// previous call first. A throwing callback stops the run with its error.
+#[wasm_bindgen]
+#[allow(clippy::missing_errors_doc)]
+pub fn compute_marginals_with_progress(
+    nodes: JsValue,
+    num_samples: f64,
+    interval: f64,
+    top_k: f64,
+    on_progress: &js_sys::Function,
+) -> Result<JsValue, JsValue> {
+    let num_samples = checked_count("numSamples", num_samples, MAX_SAMPLES)?;
+    let interval = checked_count("interval", interval, MAX_SAMPLES)?;
+    let top_k = checked_count("topK", top_k, usize::from(u8::MAX))?;
+    let nodes = deserialize_nodes(nodes)?;
+    let serialized = serialize::serialize_network(&nodes)
+        .map_err(|e| JsValue::from_str(&format!("Serialization failed: {e}")))?;
+    let mut rng = seeded_rng()?;
 
-                for node_idx in 0..num_nodes {
-                    if sample_result.contains(node_idx) {
-                        node_true_counts[usize::from(node_idx)] += 1;
-                    }
-                }
-            }
+    let mut callback_error = None;
+    let to_js = serde_wasm_bindgen::Serializer::new().serialize_maps_as_objects(true);
+    let result = progress::estimate_with_progress(
+        &serialized,
+        num_samples,
+        &[],
+        interval,
+        top_k,
+        &mut rng,
+        |snapshot| {
+            let snapshot = snapshot
+                .serialize(&to_js)
+                .map_err(|e| anyhow::anyhow!("Failed to serialize snapshot: {e}"))?;
+            on_progress.call1(&JsValue::NULL, &snapshot).map_err(|e| {
+                callback_error = Some(e);
+                anyhow::anyhow!("Progress callback threw")
+            })?;
+            Ok(())
+        },
+    );
+    if let Some(error) = callback_error {
+        return Err(error);
+    }
+    let marginals = result.map_err(|e| JsValue::from_str(&e.to_string()))?;
+    serde_wasm_bindgen::to_value(&marginals)
+        .map_err(|e| JsValue::from_str(&format!("Failed to serialize result: {e}")))
+}
 
-            #[allow(clippy::cast_precision_loss)]
-            let probabilities: HashMap<String, f64> = serialized
-                .topo_order
-                .iter()
-                .cloned()
-                .zip(node_true_counts)
-                .map(|(node_id, count)| {
-                    let probability = count as f64 / num_samples as f64;
-                    (node_id, probability)
-                })
-                .collect();
+#[derive(Serialize)]
+#[serde(rename_all = "camelCase")]
+pub struct BudgetResult {
+    pub marginals: HashMap<String, f64>,
+    pub meta: progress::BudgetMeta,
+}
 
-            Ok(probabilities)
-        };
+/// Best-effort marginals within `max_ms` milliseconds: sampling stops at the
+/// first clock check (every 1000 samples) past the budget, but never before
+/// `min_samples`. `meta.converged` reports whether every estimate moved less
+/// than 0.001 over the last 10% of samples.
+#[wasm_bindgen]
+#[allow(clippy::missing_errors_doc)]
+pub fn compute_marginals_with_budget(
+    nodes: JsValue,
+    max_ms: f64,
+    min_samples: f64,
+) -> Result<JsValue, JsValue> {
+    let max_ms = limits::non_negative("maxMs", max_ms).map_err(limit_error)?;
+    let min_samples = checked_count("minSamples", min_samples, MAX_SAMPLES)?;
+    let nodes = deserialize_nodes(nodes)?;
+    let serialized = serialize::serialize_network(&nodes)
+        .map_err(|e| JsValue::from_str(&format!("Serialization failed: {e}")))?;
+    let mut rng = seeded_rng()?;
+
+    let start = js_sys::Date::now();
+    let (marginals, meta) = progress::estimate_within_budget(
+        &serialized,
+        min_samples,
+        MAX_SAMPLES,
+        max_ms,
+        &mut rng,
+        || js_sys::Date::now() - start,
+    )
+    .map_err(|e| JsValue::from_str(&e.to_string()))?;
+
+    BudgetResult { marginals, meta }
+        .serialize(&serde_wasm_bindgen::Serializer::new().serialize_maps_as_objects(true))
+        .map_err(|e| JsValue::from_str(&format!("Failed to serialize result: {e}")))
+}
+
+#[derive(Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub struct HardEvidenceSpec {
+    pub node_id: String,
+    pub value: bool,
+}
+
+#[derive(Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub struct SoftEvidenceSpec {
+    pub node_id: String,
+    /// `P(observation | true) / P(observation | false)`.
+    pub likelihood_ratio: f64,
+}
+
+#[derive(Deserialize)]
+pub struct MixedEvidenceSpec {
+    #[serde(default)]
+    pub hard: Vec<HardEvidenceSpec>,
+    #[serde(default)]
+    pub soft: Vec<SoftEvidenceSpec>,
+}
+
+/// Posterior marginals under definite observations (`hard`) and uncertain
+/// ones given as likelihood ratios (`soft`), estimated by likelihood
+/// weighting. `evidence` is `{ hard: [{ nodeId, value }], soft: [{ nodeId,
+/// likelihoodRatio }] }`; a node may appear at most once across both lists.
+#[wasm_bindgen]
+#[allow(clippy::missing_errors_doc)]
+pub fn compute_posterior_mixed_evidence(
+    nodes: JsValue,
+    num_samples: f64,
+    evidence: JsValue,
+) -> Result<JsValue, JsValue> {
+    let num_samples = checked_count("numSamples", num_samples, MAX_SAMPLES)?;
+    let nodes = deserialize_nodes(nodes)?;
+    let spec: MixedEvidenceSpec = serde_wasm_bindgen::from_value(evidence)
+        .map_err(|e| JsValue::from_str(&format!("Failed to deserialize evidence: {e}")))?;
+    let serialized = serialize::serialize_network(&nodes)
+        .map_err(|e| JsValue::from_str(&format!("Serialization failed: {e}")))?;
+
+    let mut seen = std::collections::HashSet::new();
+    let mut index_of = |node_id: &str| {
+        let index =
+            lookup::resolve(&serialized, "Evidence", node_id).map_err(|e| not_found_error(&e))?;
+        if !seen.insert(index) {
+            return Err(JsValue::from_str(&format!(
+                "Node {node_id} has more than one piece of evidence"
+            )));
+        }
+        Ok(index)
+    };
+    let mut evidence = sample::MixedEvidence::default();
+    for HardEvidenceSpec { node_id, value } in &spec.hard {
+        evidence.hard.push((index_of(node_id)?, *value));
+    }
+    for SoftEvidenceSpec {
+        node_id,
+        likelihood_ratio,
+    } in &spec.soft
+    {
+        let ratio =
+            limits::non_negative("likelihoodRatio", *likelihood_ratio).map_err(limit_error)?;
+        evidence.soft.push((index_of(node_id)?, ratio));
+    }
+
+    let mut rng = seeded_rng()?;
+    let marginals =
+        marginals::estimate_marginals_mixed(&serialized, num_samples, &[], &evidence, &mut rng)
+            .map_err(|e| JsValue::from_str(&e.to_string()))?;
+    serde_wasm_bindgen::to_value(&marginals)
+        .map_err(|e| JsValue::from_str(&format!("Failed to serialize result: {e}")))
+}
+
+#[derive(Serialize)]
+#[serde(rename_all = "camelCase")]
+pub struct ConditionalMarginalsResult {
+    pub condition_probability: f64,
+    pub when_true: marginals::Branch,
+    pub when_false: marginals::Branch,
+    /// The observed (not `"?"`) evidence, the fingerprint and the seed.
+    pub provenance: Provenance,
+}
+
+/// Marginals of every node given `condition_node_id = true` and given
+/// `condition_node_id = false` (observational, not `do()`), as
+/// `{ conditionProbability, whenTrue, whenFalse, provenance }`. Each branch reports its
+/// sample count and standard errors, since one value may be rare.
+///
+/// `evidence` is optional, `{ [nodeId]: true | false | "?" }`; see
+/// [`compute_marginals_with_missing_values`] for what `"?"` means.
+#[wasm_bindgen]
+#[allow(clippy::missing_errors_doc)]
+pub fn compute_conditional_marginals(
+    nodes: JsValue,
+    num_samples: f64,
+    condition_node_id: &str,
+    evidence: JsValue,
+) -> Result<JsValue, JsValue> {
+    let num_samples = checked_count("numSamples", num_samples, MAX_SAMPLES)?;
+    let nodes = deserialize_nodes(nodes)?;
+    let serialized = serialize::serialize_network(&nodes)
+        .map_err(|e| JsValue::from_str(&format!("Serialization failed: {e}")))?;
+    let condition = lookup::resolve(&serialized, "Condition", condition_node_id)
+        .map_err(|e| not_found_error(&e))?;
+    let evidence = deserialize_partial_evidence(evidence, &serialized)?;
+
+    let (seed, mut rng) = rng_from_seed(None)?;
+    let split = marginals::split_by_node(&serialized, num_samples, condition, &evidence, &mut rng)
+        .map_err(|e| JsValue::from_str(&e.to_string()))?;
+    let assumptions = assumptions::AssumptionSet {
+        evidence: evidence
+            .iter()
+            .filter_map(|&(node, value)| {
+                value.map(|value| (serialized.topo_order[usize::from(node)].clone(), value))
+            })
+            .collect(),
+        ..Default::default()
+    };
+    let result = ConditionalMarginalsResult {
+        condition_probability: split.condition_probability,
+        when_true: split.when_true,
+        when_false: split.when_false,
+        provenance: Provenance {
+            assumptions,
+            fingerprint: serialized.fingerprint(),
+            seed,
+        },
+    };
+    serde_wasm_bindgen::to_value(&result)
+        .map_err(|e| JsValue::from_str(&format!("Failed to serialize result: {e}")))
+}
 
-    let true_case = compute_marginals_with_intervention(true)?;
-    let false_case = compute_marginals_with_intervention(false)?;
+#[derive(Serialize)]
+#[serde(rename_all = "camelCase")]
+pub struct ReweightedMarginalsResult {
+    pub marginals: HashMap<String, f64>,
+    /// Covariate shifts are not assumptions, so the set is empty; the
+    /// fingerprint and seed still identify the run.
+    pub provenance: Provenance,
+}
 
-    let result = InterventionResult {
-        true_case,
-        false_case,
+/// Marginals for a population whose covariates have `target_marginals`
+/// instead of the `training_marginals` the network was learned under, by
+/// importance-weighting forward samples (see
+/// `marginals::estimate_marginals_reweighted`). Both maps give `P(true)` for
+/// every ID in `covariate_ids`; training values must lie strictly inside
+/// `(0, 1)` so every sample has a finite weight. Returns `{ marginals,
+/// provenance }`.
+#[wasm_bindgen]
+#[allow(clippy::missing_errors_doc)]
+pub fn compute_marginals_reweighted(
+    nodes: JsValue,
+    num_samples: f64,
+    covariate_ids: Vec<String>,
+    training_marginals: JsValue,
+    target_marginals: JsValue,
+) -> Result<JsValue, JsValue> {
+    let num_samples = checked_count("numSamples", num_samples, MAX_SAMPLES)?;
+    let nodes = deserialize_nodes(nodes)?;
+    let read = |marginals: JsValue, name: &str| -> Result<HashMap<String, f64>, JsValue> {
+        serde_wasm_bindgen::from_value(marginals)
+            .map_err(|e| JsValue::from_str(&format!("Failed to deserialize {name}: {e}")))
     };
+    let training = read(training_marginals, "trainingMarginals")?;
+    let target = read(target_marginals, "targetMarginals")?;
+    let serialized = serialize::serialize_network(&nodes)
+        .map_err(|e| JsValue::from_str(&format!("Serialization failed: {e}")))?;
+
+    let covariates = covariate_ids
+        .into_iter()
+        .map(|node_id| {
+            let node = lookup::resolve(&serialized, "Covariate", &node_id)
+                .map_err(|e| not_found_error(&e))?;
+            let (Some(&training), Some(&target)) = (training.get(&node_id), target.get(&node_id))
+            else {
+                return Err(JsValue::from_str(&format!(
+                    "Covariate {node_id} needs both a training and a target marginal"
+                )));
+            };
+            if !(training > 0.0 && training < 1.0) {
+                return Err(JsValue::from_str(&format!(
+                    "Training marginal of {node_id} must be strictly between 0 and 1, got {training}"
+                )));
+            }
+            if !(0.0..=1.0).contains(&target) {
+                return Err(JsValue::from_str(&format!(
+                    "Target marginal of {node_id} must be in [0, 1], got {target}"
+                )));
+            }
+            Ok(marginals::CovariateShift {
+                node,
+                training,
+                target,
+            })
+        })
+        .collect::<Result<Vec<_>, JsValue>>()?;
 
+    let (seed, mut rng) = rng_from_seed(None)?;
+    let marginals =
+        marginals::estimate_marginals_reweighted(&serialized, num_samples, &covariates, &mut rng)
+            .map_err(|e| JsValue::from_str(&e.to_string()))?;
+    let result = ReweightedMarginalsResult {
+        marginals,
+        provenance: Provenance {
+            assumptions: assumptions::AssumptionSet::default(),
+            fingerprint: serialized.fingerprint(),
+            seed,
+        },
+    };
     serde_wasm_bindgen::to_value(&result)
         .map_err(|e| JsValue::from_str(&format!("Failed to serialize result: {e}")))
 }
 
+/// One value of an evidence object that may have gaps: an observation, or
+/// `"?"` for a node that was meant to be observed but wasn't recorded.
+#[derive(Deserialize)]
+#[serde(untagged)]
+pub enum PartialObservation {
+    Observed(bool),
+    Missing(String),
+}
+
+/// `{ [nodeId]: true | false | "?" }` by topological index, with `None` for
+/// `"?"`. Undefined or null means no evidence.
+fn deserialize_partial_evidence(
+    evidence: JsValue,
+    serialized: &serialize::SerializedNetwork,
+) -> Result<Vec<(u8, Option<bool>)>, JsValue> {
+    if evidence.is_undefined() || evidence.is_null() {
+        return Ok(Vec::new());
+    }
+    let evidence: BTreeMap<String, PartialObservation> =
+        serde_wasm_bindgen::from_value(evidence)
+            .map_err(|e| JsValue::from_str(&format!("Failed to deserialize evidence: {e}")))?;
+    evidence
+        .into_iter()
+        .map(|(node_id, observation)| {
+            let index = lookup::resolve(serialized, "Evidence", &node_id)
+                .map_err(|e| not_found_error(&e))?;
+            let value = match observation {
+                PartialObservation::Observed(value) => Some(value),
+                PartialObservation::Missing(marker) if marker == "?" => None,
+                PartialObservation::Missing(marker) => {
+                    return Err(JsValue::from_str(&format!(
+                        "Evidence for node {node_id} must be true, false or \"?\", got \"{marker}\""
+                    )));
+                }
+            };
+            Ok((index, value))
+        })
+        .collect()
+}
+
+/// Posterior marginals by likelihood weighting under `{ [nodeId]: true |
+/// false | "?" }`. A `"?"` node's value went unrecorded: it is sampled from
+/// its CPT exactly as if it had no evidence, and so enters the weight of the
+/// observed nodes only through their CPTs. Unlike soft evidence it carries
+/// no information of its own.
+#[wasm_bindgen]
+#[allow(clippy::missing_errors_doc)]
+pub fn compute_marginals_with_missing_values(
+    nodes: JsValue,
+    num_samples: f64,
+    evidence: JsValue,
+) -> Result<JsValue, JsValue> {
+    let num_samples = checked_count("numSamples", num_samples, MAX_SAMPLES)?;
+    let nodes = deserialize_nodes(nodes)?;
+    let serialized = serialize::serialize_network(&nodes)
+        .map_err(|e| JsValue::from_str(&format!("Serialization failed: {e}")))?;
+    let observed: Vec<(u8, bool)> = deserialize_partial_evidence(evidence, &serialized)?
+        .into_iter()
+        .filter_map(|(node, value)| value.map(|value| (node, value)))
+        .collect();
+
+    let mut rng = seeded_rng()?;
+    let marginals =
+        marginals::estimate_marginals_weighted(&serialized, num_samples, &[], &observed, &mut rng)
+            .map_err(|e| JsValue::from_str(&e.to_string()))?;
+    serde_wasm_bindgen::to_value(&marginals)
+        .map_err(|e| JsValue::from_str(&format!("Failed to serialize result: {e}")))
+}
+
+#[derive(Clone, Serialize, Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub struct InterventionSpec {
+    pub node_id: String,
+    pub value: bool,
+}
+
+/// The shapes `compute_marginals_v2` (and the queries taking interventions
+/// after it) accept for their interventions.
+#[derive(Serialize, Deserialize)]
+#[serde(untagged)]
+pub enum Interventions {
+    Single(InterventionSpec),
+    Batch(Vec<InterventionSpec>),
+}
+
+impl Interventions {
+    fn specs(&self) -> &[InterventionSpec] {
+        match self {
+            Interventions::Single(spec) => std::slice::from_ref(spec),
+            Interventions::Batch(specs) => specs,
+        }
+    }
+}
+
+fn deserialize_interventions(interventions: JsValue) -> Result<Option<Interventions>, JsValue> {
+    serde_wasm_bindgen::from_value(interventions).map_err(|e| {
+        JsValue::from_str(&format!(
+            "Failed to deserialize interventions (expected null, {{ nodeId, value }} or an array of them): {e}"
+        ))
+    })
+}
+
+fn intervention_overrides(
+    serialized: &serialize::SerializedNetwork,
+    interventions: Option<&Interventions>,
+) -> Result<Vec<Option<sample::Override>>, JsValue> {
+    let mut overrides = vec![None; usize::from(serialized.num_nodes())];
+    for spec in interventions
+        .iter()
+        .flat_map(|interventions| interventions.specs())
+    {
+        let index = lookup::resolve(serialized, "Intervention", &spec.node_id)
+            .map_err(|e| not_found_error(&e))?;
+        let slot = &mut overrides[usize::from(index)];
+        if let Some(sample::Override::Value(existing)) = *slot
+            && existing != spec.value
+        {
+            return Err(JsValue::from_str(&format!(
+                "Node {} is intervened on with conflicting values",
+                spec.node_id
+            )));
+        }
+        *slot = Some(sample::Override::Value(spec.value));
+    }
+    Ok(overrides)
+}
+
+#[derive(Serialize)]
+#[serde(rename_all = "camelCase")]
+pub struct MarginalsV2Result {
+    /// The interventions exactly as given, `null` for the baseline.
+    pub interventions: Option<Interventions>,
+    pub marginals: HashMap<String, f64>,
+    pub provenance: Provenance,
+}
+
+/// Marginals under `interventions`: `null` for the baseline, one
+/// `{ nodeId, value }` object, or an array of them applied together.
+#[wasm_bindgen]
+#[allow(clippy::missing_errors_doc)]
+pub fn compute_marginals_v2(
+    nodes: JsValue,
+    num_samples: f64,
+    interventions: JsValue,
+) -> Result<JsValue, JsValue> {
+    let num_samples = checked_count("numSamples", num_samples, MAX_SAMPLES)?;
+    let nodes = deserialize_nodes(nodes)?;
+    let interventions = deserialize_interventions(interventions)?;
+
+    let serialized = serialize::serialize_network(&nodes)
+        .map_err(|e| JsValue::from_str(&format!("Serialization failed: {e}")))?;
+    let overrides = intervention_overrides(&serialized, interventions.as_ref())?;
+
+    let (seed, mut rng) = rng_from_seed(None)?;
+    let marginals =
+        marginals::estimate_marginals(&serialized, num_samples, &overrides, &[], &mut rng)
+            .map_err(|e| JsValue::from_str(&e.to_string()))?;
+    let assumptions = assumptions::AssumptionSet {
+        interventions: interventions
+            .iter()
+            .flat_map(Interventions::specs)
+            .map(|spec| (spec.node_id.clone(), spec.value))
+            .collect(),
+        ..Default::default()
+    };
+
+    MarginalsV2Result {
+        interventions,
+        marginals,
+        provenance: Provenance {
+            assumptions,
+            fingerprint: serialized.fingerprint(),
+            seed,
+        },
+    }
+    .serialize(&serde_wasm_bindgen::Serializer::new().serialize_missing_as_null(true))
+    .map_err(|e| JsValue::from_str(&format!("Failed to serialize result: {e}")))
+}
+
+#[derive(Serialize)]
+#[serde(rename_all = "camelCase")]
+pub struct PartialCorrelations {
+    /// Row and column labels of `matrix`, in the order the nodes were given.
+    pub node_ids: Vec<String>,
+    pub matrix: Vec<Vec<f64>>,
+}
+
+/// Partial correlations of every pair of nodes given all the others, under
+/// `intervention` (as for `compute_marginals_v2`). `regularization` (default
+/// 1e-9) is added to the correlation matrix's diagonal before inversion, for
+/// nearly deterministic relationships; entries for nodes that never vary are
+/// NaN.
+#[wasm_bindgen]
+#[allow(clippy::missing_errors_doc)]
+pub fn compute_partial_correlations_wasm(
+    nodes: JsValue,
+    num_samples: f64,
+    intervention: JsValue,
+    regularization: Option<f64>,
+) -> Result<JsValue, JsValue> {
+    let num_samples = checked_count("numSamples", num_samples, MAX_STORED_SAMPLES)?;
+    let regularization = regularization
+        .map_or(Ok(statistics::DEFAULT_REGULARIZATION), |regularization| {
+            limits::non_negative("regularization", regularization)
+        })
+        .map_err(limit_error)?;
+    let nodes = deserialize_nodes(nodes)?;
+    let interventions = deserialize_interventions(intervention)?;
+    let serialized = serialize::serialize_network(&nodes)
+        .map_err(|e| JsValue::from_str(&format!("Serialization failed: {e}")))?;
+    let overrides = intervention_overrides(&serialized, interventions.as_ref())?;
+
+    let mut rng = seeded_rng()?;
+    let num_nodes = serialized.num_nodes();
+    let samples = sample::sample_all(
+        &serialized.data,
+        &serialized.offsets,
+        num_samples,
+        &overrides,
+        &mut rng,
+    )
+    .map_err(|e| JsValue::from_str(&e.to_string()))?;
+    let by_topo_index =
+        statistics::compute_partial_correlations(&samples, num_nodes, regularization)
+            .map_err(|e| JsValue::from_str(&format!("Partial correlations failed: {e}")))?;
+
+    let order: Vec<usize> = nodes
+        .iter()
+        .filter_map(|node| serialized.index_of(&node.id))
+        .map(usize::from)
+        .collect();
+    let result = PartialCorrelations {
+        node_ids: nodes.into_iter().map(|node| node.id).collect(),
+        matrix: order
+            .iter()
+            .map(|&i| order.iter().map(|&j| by_topo_index[i][j]).collect())
+            .collect(),
+    };
+    serde_wasm_bindgen::to_value(&result)
+        .map_err(|e| JsValue::from_str(&format!("Failed to serialize result: {e}")))
+}
+
+#[wasm_bindgen]
+#[allow(clippy::missing_errors_doc)]
+pub fn export_graphml(
+    nodes: JsValue,
+    marginals: JsValue,
+    strengths: JsValue,
+) -> Result<String, JsValue> {
+    let nodes = deserialize_nodes(nodes)?;
+    let marginals: Option<HashMap<String, f64>> = serde_wasm_bindgen::from_value(marginals)
+        .map_err(|e| JsValue::from_str(&format!("Failed to deserialize marginals: {e}")))?;
+    let strengths: Option<Vec<graphml::EdgeStrength>> =
+        serde_wasm_bindgen::from_value(strengths)
+            .map_err(|e| JsValue::from_str(&format!("Failed to deserialize strengths: {e}")))?;
+
+    graphml::export_graphml(&nodes, marginals.as_ref(), &strengths.unwrap_or_default())
+        .map_err(|e| JsValue::from_str(&format!("GraphML export failed: {e}")))
+}
+
+#[wasm_bindgen]
+#[allow(clippy::missing_errors_doc)]
+pub fn learn_structure(data: JsValue, node_ids: JsValue, alpha: f64) -> Result<JsValue, JsValue> {
+    let data: Vec<learning::DataRow> = serde_wasm_bindgen::from_value(data)
+        .map_err(|e| JsValue::from_str(&format!("Failed to deserialize data: {e}")))?;
+    let node_ids: Vec<String> = serde_wasm_bindgen::from_value(node_ids)
+        .map_err(|e| JsValue::from_str(&format!("Failed to deserialize node IDs: {e}")))?;
+
+    let nodes = learning::pc_algorithm(&data, &node_ids, alpha)
+        .map_err(|e| JsValue::from_str(&format!("Structure learning failed: {e}")))?;
+
+    serialize_nodes(&nodes)
+}
+
+/// Long-run behaviour of a dynamic network whose state variables are given
+/// as `X_prev` / `X_next` node pairs: `{ variables, transitionMatrix,
+/// stateDistribution, marginals, mixingTime }`. Bit `i` of a state index is
+/// `variables[i]`.
+#[wasm_bindgen]
+#[allow(clippy::missing_errors_doc)]
+pub fn compute_dbn_steady_state(nodes: JsValue) -> Result<JsValue, JsValue> {
+    let nodes = deserialize_nodes(nodes)?;
+    let steady_state = dbn::compute_dbn_steady_state(&nodes)
+        .map_err(|e| JsValue::from_str(&format!("Steady state failed: {e}")))?;
+    steady_state
+        .serialize(
+            &serde_wasm_bindgen::Serializer::new()
+                .serialize_maps_as_objects(true)
+                .serialize_missing_as_null(true),
+        )
+        .map_err(|e| JsValue::from_str(&format!("Failed to serialize result: {e}")))
+}
+
+/// Fewest steps after which a dynamic network is closer than total variation
+/// distance `epsilon` to its long-run distribution from any starting state,
+/// or `undefined` for periodic chains and beyond `2^40` steps.
+#[wasm_bindgen]
+#[allow(clippy::missing_errors_doc)]
+pub fn compute_dbn_mixing_time(nodes: JsValue, epsilon: f64) -> Result<Option<f64>, JsValue> {
+    let nodes = deserialize_nodes(nodes)?;
+    let matrix = dbn::compute_dbn_transition_matrix(&nodes)
+        .map_err(|e| JsValue::from_str(&format!("Transition matrix failed: {e}")))?;
+    let mixing_time = dbn::compute_mixing_time(&matrix, epsilon)
+        .map_err(|e| JsValue::from_str(&format!("Mixing time failed: {e}")))?;
+    #[allow(clippy::cast_precision_loss)]
+    Ok(mixing_time.map(|steps| steps as f64))
+}
+
+/// The `steps`-step transition matrix `M^steps` of a dynamic network, with
+/// states indexed as in `compute_dbn_steady_state`.
+#[wasm_bindgen]
+#[allow(clippy::missing_errors_doc)]
+pub fn compute_dbn_transition_power(nodes: JsValue, steps: f64) -> Result<JsValue, JsValue> {
+    let steps = checked_count("steps", steps, MAX_STEPS)?;
+    let nodes = deserialize_nodes(nodes)?;
+    let matrix = dbn::compute_dbn_transition_matrix(&nodes)
+        .map_err(|e| JsValue::from_str(&format!("Transition matrix failed: {e}")))?;
+    serde_wasm_bindgen::to_value(&dbn::matrix_power(&matrix, steps as u64))
+        .map_err(|e| JsValue::from_str(&format!("Failed to serialize result: {e}")))
+}
+
+/// Pairs where `data` and the graph disagree about conditional independence
+/// at level `alpha`, as `[{ x, y, z, pValue, dSeparated }]`.
+#[wasm_bindgen]
+#[allow(clippy::missing_errors_doc)]
+pub fn check_faithfulness(nodes: JsValue, data: JsValue, alpha: f64) -> Result<JsValue, JsValue> {
+    let nodes = deserialize_nodes(nodes)?;
+    let data: Vec<learning::DataRow> = serde_wasm_bindgen::from_value(data)
+        .map_err(|e| JsValue::from_str(&format!("Failed to deserialize data: {e}")))?;
+
+    let violations = learning::check_faithfulness(&nodes, &data, alpha)
+        .map_err(|e| JsValue::from_str(&format!("Faithfulness check failed: {e}")))?;
+    serde_wasm_bindgen::to_value(&violations)
+        .map_err(|e| JsValue::from_str(&format!("Failed to serialize result: {e}")))
+}
+
+/// Likelihood ratio test of null network `nodes_h0` against `nodes_h1` on
+/// complete rows of `data`, as `{ logLr, df, pValue }` (see
+/// [`learning::likelihood_ratio_test`]).
+#[wasm_bindgen]
+#[allow(clippy::missing_errors_doc)]
+pub fn likelihood_ratio_test_wasm(
+    nodes_h0: JsValue,
+    nodes_h1: JsValue,
+    data: JsValue,
+) -> Result<JsValue, JsValue> {
+    let nodes_h0 = deserialize_nodes(nodes_h0)?;
+    let nodes_h1 = deserialize_nodes(nodes_h1)?;
+    let data: Vec<learning::DataRow> = serde_wasm_bindgen::from_value(data)
+        .map_err(|e| JsValue::from_str(&format!("Failed to deserialize data: {e}")))?;
+
+    let test = learning::likelihood_ratio_test(&nodes_h0, &nodes_h1, &data)
+        .map_err(|e| JsValue::from_str(&format!("Likelihood ratio test failed: {e}")))?;
+    serde_wasm_bindgen::to_value(&test)
+        .map_err(|e| JsValue::from_str(&format!("Failed to serialize result: {e}")))
+}
+
+/// `n` rows from the observational distribution paired with `n` under
+/// `do(intervention_node_id = true)`, as CSV with columns `row`, `regime`,
+/// `diverged` and then one per node in the order given. Paired rows share
+/// their random numbers, so they differ only downstream of the intervention.
+#[wasm_bindgen]
+#[allow(clippy::missing_errors_doc)]
+pub fn generate_paired_dataset(
+    nodes: JsValue,
+    n: f64,
+    intervention_node_id: &str,
+    seed: Option<f64>,
+) -> Result<String, JsValue> {
+    let n = checked_count("n", n, MAX_ROWS)?;
+    let seed = seed
+        .map(|seed| limits::seed("seed", seed))
+        .transpose()
+        .map_err(limit_error)?;
+    let nodes = deserialize_nodes(nodes)?;
+    let serialized = serialize::serialize_network(&nodes)
+        .map_err(|e| JsValue::from_str(&format!("Serialization failed: {e}")))?;
+    let treatment = lookup::resolve(&serialized, "Intervention", intervention_node_id)
+        .map_err(|e| not_found_error(&e))?;
+    let columns: Vec<String> = nodes.into_iter().map(|node| node.id).collect();
+
+    let (_, mut rng) = rng_from_seed(seed)?;
+    dataset::paired_dataset_csv(&serialized, &columns, n, treatment, &mut rng)
+        .map_err(|e| JsValue::from_str(&format!("Dataset generation failed: {e}")))
+}
+
+/// `count` completions of `partial_assignment` (`{ nodeId: bool }`) as
+/// `{ topoOrder, layoutFingerprint, scenarios: [{ values, logProbability }],
+/// acceptanceRate? }`, each `values` a full assignment in `topoOrder`. With
+/// `mode` `"intervention"` (the default) the sketched nodes are imposed by
+/// `do()`; with `"evidence"` completions are rejection sampled so the rest of
+/// the world explains them. `logProbability` is under the unmodified network.
+#[wasm_bindgen]
+#[allow(clippy::missing_errors_doc)]
+pub fn complete_scenarios(
+    nodes: JsValue,
+    partial_assignment: JsValue,
+    count: f64,
+    seed: Option<f64>,
+    mode: JsValue,
+) -> Result<JsValue, JsValue> {
+    let count = checked_count("count", count, MAX_ROWS)?;
+    let seed = seed
+        .map(|seed| limits::seed("seed", seed))
+        .transpose()
+        .map_err(limit_error)?;
+    let partial: HashMap<String, bool> = serde_wasm_bindgen::from_value(partial_assignment)
+        .map_err(|e| JsValue::from_str(&format!("Failed to deserialize assignment: {e}")))?;
+    let mode: scenarios::ClampMode = if mode.is_undefined() || mode.is_null() {
+        scenarios::ClampMode::default()
+    } else {
+        serde_wasm_bindgen::from_value(mode)
+            .map_err(|e| JsValue::from_str(&format!("Invalid mode: {e}")))?
+    };
+    let nodes = deserialize_nodes(nodes)?;
+    let serialized = serialize::serialize_network(&nodes)
+        .map_err(|e| JsValue::from_str(&format!("Serialization failed: {e}")))?;
+
+    let (_, mut rng) = rng_from_seed(seed)?;
+    let completions =
+        scenarios::complete_scenarios(&nodes, &serialized, &partial, count, mode, &mut rng)
+            .map_err(|e| match e.downcast_ref::<lookup::NodeNotFound>() {
+                Some(not_found) => not_found_error(not_found),
+                None => JsValue::from_str(&format!("Scenario completion failed: {e}")),
+            })?;
+    serde_wasm_bindgen::to_value(&completions)
+        .map_err(|e| JsValue::from_str(&format!("Failed to serialize result: {e}")))
+}
+
+/// Fits the CPTs of `skeleton` to an uploaded CSV file (see
+/// [`learning::learn_parameters_from_csv`] for the format).
+#[wasm_bindgen]
+#[allow(clippy::missing_errors_doc)]
+pub fn learn_parameters_from_csv_string(
+    skeleton: JsValue,
+    csv_text: &JsValue,
+) -> Result<JsValue, JsValue> {
+    let skeleton = deserialize_nodes(skeleton)?;
+    let csv_text = csv_text
+        .as_string()
+        .ok_or_else(|| JsValue::from_str("CSV text must be a string"))?;
+
+    let nodes = learning::learn_parameters_from_csv(&skeleton, csv_text.as_bytes())
+        .map_err(|e| JsValue::from_str(&format!("Parameter learning failed: {e}")))?;
+
+    serialize_nodes(&nodes)
+}
+
+/// Replaces CPT entries from a spreadsheet exported as CSV, with columns
+/// `node, <parent>..., probability` (see [`cpt_import::import_cpts_csv`]).
+/// Returns `{ nodes, updatedNodes, errors }`, where each error is
+/// `{ line, nodeId?, message }` for a row that was skipped.
+#[wasm_bindgen]
+#[allow(clippy::missing_errors_doc)]
+pub fn import_cpts_csv(nodes: JsValue, csv_text: &str) -> Result<JsValue, JsValue> {
+    let nodes = deserialize_nodes(nodes)?;
+    let import = cpt_import::import_cpts_csv(&nodes, csv_text)
+        .map_err(|e| JsValue::from_str(&format!("CPT import failed: {e}")))?;
+    import
+        .serialize(
+            &serde_wasm_bindgen::Serializer::new()
+                .serialize_maps_as_objects(true)
+                .serialize_missing_as_null(true),
+        )
+        .map_err(|e| JsValue::from_str(&format!("Failed to serialize result: {e}")))
+}
+
+#[wasm_bindgen]
+#[allow(clippy::missing_errors_doc)]
+pub fn compute_log_evidence(
+    nodes: JsValue,
+    evidence: JsValue,
+    num_chains: f64,
+    num_steps: f64,
+) -> Result<JsValue, JsValue> {
+    let num_chains = checked_count("numChains", num_chains, MAX_STEPS)?;
+    let num_steps = checked_count("numSteps", num_steps, MAX_STEPS)?;
+    let nodes = deserialize_nodes(nodes)?;
+    let evidence: Vec<(String, bool)> =
+        serde_wasm_bindgen::from_value::<HashMap<String, bool>>(evidence)
+            .map_err(|e| JsValue::from_str(&format!("Failed to deserialize evidence: {e}")))?
+            .into_iter()
+            .collect();
+    let mut rng = seeded_rng()?;
+
+    let log_evidence = annealing::ais(&nodes, &evidence, num_chains, num_steps, &mut rng)
+        .map_err(|e| JsValue::from_str(&format!("Annealed importance sampling failed: {e}")))?;
+
+    Ok(JsValue::from_f64(log_evidence))
+}
+
+/// Encodes nodes in the compact storage format (see [`compact`]).
+#[wasm_bindgen]
+#[allow(clippy::missing_errors_doc)]
+pub fn to_compact(nodes: JsValue) -> Result<Vec<u8>, JsValue> {
+    let nodes = deserialize_nodes(nodes)?;
+    Ok(compact::to_compact(&nodes))
+}
+
+/// Summarizes how compact network `bytes_b` differs from `bytes_a` (see
+/// [`compact::diff_compact`]), as `{ addedNodes, removedNodes, addedEdges,
+/// removedEdges, changedNodes }`, edges as `{ parentId, childId }` and
+/// changed nodes as `{ nodeId, changedProbabilities, maxAbsChange }`.
+#[wasm_bindgen]
+#[allow(clippy::missing_errors_doc)]
+pub fn diff_compact(bytes_a: &[u8], bytes_b: &[u8]) -> Result<JsValue, JsValue> {
+    let diff = compact::diff_compact(bytes_a, bytes_b)
+        .map_err(|e| JsValue::from_str(&format!("Failed to diff compact networks: {e}")))?;
+    serde_wasm_bindgen::to_value(&diff)
+        .map_err(|e| JsValue::from_str(&format!("Failed to serialize result: {e}")))
+}
+
+/// The golden fixtures as `[{ name, nodes, options, expected }]`, for
+/// integrations that want to check them through their own call path.
+#[wasm_bindgen]
+#[allow(clippy::missing_errors_doc)]
+pub fn golden_fixtures() -> Result<JsValue, JsValue> {
+    let fixtures = fixtures::FIXTURES
+        .iter()
+        .map(|&(name, json)| {
+            let mut fixture: serde_json::Map<String, serde_json::Value> =
+                serde_json::from_str(json)
+                    .map_err(|e| JsValue::from_str(&format!("Fixture {name} is invalid: {e}")))?;
+            fixture.insert("name".to_string(), name.into());
+            Ok(fixture)
+        })
+        .collect::<Result<Vec<_>, JsValue>>()?;
+    fixtures
+        .serialize(&serde_wasm_bindgen::Serializer::new().serialize_maps_as_objects(true))
+        .map_err(|e| JsValue::from_str(&format!("Failed to serialize fixtures: {e}")))
+}
+
+/// Runs every golden fixture and returns
+/// `[{ name, passed, mismatches, actual, error? }]`.
+#[wasm_bindgen]
+#[allow(clippy::missing_errors_doc)]
+pub fn run_golden_checks() -> Result<JsValue, JsValue> {
+    fixtures::run_golden_checks()
+        .serialize(&serde_wasm_bindgen::Serializer::new().serialize_maps_as_objects(true))
+        .map_err(|e| JsValue::from_str(&format!("Failed to serialize result: {e}")))
+}
+
+/// The nodes with every CPT that can be written as a complete truth table
+/// given as `cptTable` over its parents in ID order. Nodes with hierarchical
+/// entries, more than 7 parents or unmatched parent assignments keep their
+/// `cptEntries`.
+#[wasm_bindgen]
+#[allow(clippy::missing_errors_doc)]
+pub fn to_cpt_tables(nodes: JsValue) -> Result<JsValue, JsValue> {
+    let mut nodes = deserialize_nodes(nodes)?;
+    for node in &mut nodes {
+        if let Some(table) = CptTable::from_node(node) {
+            node.cpt_entries = Vec::new();
+            node.cpt_table = Some(table);
+        }
+    }
+    serialize_nodes(&nodes)
+}
+
+/// Proposed entries for the parent states `node`'s CPT leaves uncovered,
+/// flagged `suggested: true`, or an `insufficientData` status when the
+/// specified rows can't support a fit.
+#[wasm_bindgen]
+#[allow(clippy::missing_errors_doc)]
+pub fn suggest_cpt_completion(node: JsValue) -> Result<JsValue, JsValue> {
+    let mut node: Node = serde_wasm_bindgen::from_value(node)
+        .map_err(|e| JsValue::from_str(&format!("Failed to deserialize node: {e}")))?;
+    cpt_table::expand_table(&mut node)
+        .map_err(|e| JsValue::from_str(&format!("Failed to deserialize node: {e}")))?;
+    let completion = completion::suggest_cpt_completion(&node)
+        .map_err(|e| JsValue::from_str(&format!("CPT completion failed: {e}")))?;
+    completion
+        .serialize(&serde_wasm_bindgen::Serializer::new().serialize_maps_as_objects(true))
+        .map_err(|e| JsValue::from_str(&format!("Failed to serialize result: {e}")))
+}
+
+#[wasm_bindgen]
+#[allow(clippy::missing_errors_doc)]
+pub fn from_compact(bytes: &[u8]) -> Result<JsValue, JsValue> {
+    let nodes = compact::from_compact(bytes)
+        .map_err(|e| JsValue::from_str(&format!("Failed to decode compact network: {e}")))?;
+    serialize_nodes(&nodes)
+}
+
+#[derive(Serialize)]
+#[serde(rename_all = "camelCase")]
+pub struct Provenance {
+    pub assumptions: assumptions::AssumptionSet,
+    pub fingerprint: String,
+    pub seed: u64,
+}
+
+#[derive(Serialize)]
+#[serde(rename_all = "camelCase")]
+pub struct MarginalsResult {
+    pub marginals: HashMap<String, f64>,
+    pub provenance: Provenance,
+    pub meta: marginals::QueryMeta,
+    /// Fraction of draws rejected for breaking `constraints`, when any were
+    /// given.
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub constraint_violation_rate: Option<f64>,
+}
+
+/// Computes marginals under the assumption set in `options`.
+///
+/// The result records the resolved assumptions (including payload `observed`
+/// flags), the network fingerprint, and the seed, so it can be reproduced.
+#[wasm_bindgen]
+#[allow(clippy::missing_errors_doc)]
+pub fn compute_marginals_with_options(
+    nodes: JsValue,
+    options: JsValue,
+) -> Result<JsValue, JsValue> {
+    let nodes = deserialize_nodes(nodes)?;
+    let options: options::QueryOptions = serde_wasm_bindgen::from_value(options)
+        .map_err(|e| JsValue::from_str(&format!("Failed to deserialize options: {e}")))?;
+
+    let serialized = serialize::serialize_network(&nodes)
+        .map_err(|e| JsValue::from_str(&format!("Serialization failed: {e}")))?;
+    let result = marginals_with_options(&nodes, &serialized, &options)?;
+    serde_wasm_bindgen::to_value(&result)
+        .map_err(|e| JsValue::from_str(&format!("Failed to serialize result: {e}")))
+}
+
+/// JSON-string variant of `compute_marginals_with_options`, for callers whose
+/// values don't survive structured deserialization (e.g. proxies). Parse
+/// errors name the path of the offending value.
+#[wasm_bindgen]
+#[allow(clippy::missing_errors_doc)]
+pub fn compute_marginals_json(nodes_json: &str, options_json: &str) -> Result<String, JsValue> {
+    let nodes = expand_tables(from_json(nodes_json, "nodes")?)?;
+    let options: options::QueryOptions = from_json(options_json, "options")?;
+
+    let serialized = serialize::serialize_network(&nodes)
+        .map_err(|e| JsValue::from_str(&format!("Serialization failed: {e}")))?;
+    let result = marginals_with_options(&nodes, &serialized, &options)?;
+    serde_json::to_string(&result)
+        .map_err(|e| JsValue::from_str(&format!("Failed to serialize result: {e}")))
+}
+
+fn marginals_with_options(
+    nodes: &[Node],
+    serialized: &serialize::SerializedNetwork,
+    options: &options::QueryOptions,
+) -> Result<MarginalsResult, JsValue> {
+    let num_samples = options.num_samples().map_err(limit_error)?;
+    let assumptions = options
+        .assumptions
+        .resolve(nodes)
+        .map_err(|e| invalid_assumptions(&e))?;
+    let overrides = assumptions
+        .overrides(serialized)
+        .map_err(|e| node_error(&e))?;
+    let evidence = assumptions
+        .evidence_indices(serialized)
+        .map_err(|e| node_error(&e))?;
+    let soft_evidence = options
+        .soft_evidence_indices(serialized)
+        .map_err(|e| JsValue::from_str(&format!("Invalid soft evidence: {e}")))?;
+
+    let correlated = options.root_correlations.is_some();
+    let constrained = !options.constraints.is_empty();
+    if options.zero_handling != marginals::ZeroHandling::Strict && (correlated || constrained) {
+        return Err(JsValue::from_str(
+            "rootCorrelations and constraints need rejection sampling, which cannot apply \
+             zeroHandling smoothing",
+        ));
+    }
+    if correlated && !soft_evidence.is_empty() {
+        return Err(JsValue::from_str(
+            "rootCorrelations need rejection sampling, which cannot apply soft evidence",
+        ));
+    }
+    if correlated && constrained {
+        return Err(JsValue::from_str(
+            "constraints cannot be enforced together with rootCorrelations",
+        ));
+    }
+    if constrained && !soft_evidence.is_empty() {
+        return Err(JsValue::from_str(
+            "Enforcing constraints needs rejection sampling, which cannot apply soft evidence",
+        ));
+    }
+
+    let (seed, mut rng) = rng_from_seed(options.seed().map_err(limit_error)?)?;
+    let mut constraint_violation_rate = None;
+    let estimate = match &options.root_correlations {
+        Some(correlations) => copula::Copula::new(correlations, serialized).and_then(|copula| {
+            copula.estimate_marginals(
+                options.algorithm,
+                serialized,
+                num_samples,
+                &overrides,
+                &evidence,
+                &mut rng,
+            )
+        }),
+        None if constrained => {
+            constraints::ResolvedConstraints::new(&options.constraints, serialized).and_then(
+                |constraints| {
+                    let (marginals, meta, violation_rate) = constraints.estimate_marginals(
+                        options.algorithm,
+                        serialized,
+                        num_samples,
+                        &overrides,
+                        &evidence,
+                        &mut rng,
+                    )?;
+                    constraint_violation_rate = Some(violation_rate);
+                    Ok((marginals, meta))
+                },
+            )
+        }
+        None => marginals::estimate_marginals_with_soft(
+            options.algorithm,
+            serialized,
+            num_samples,
+            &overrides,
+            &evidence,
+            &soft_evidence,
+            options.zero_handling,
+            &mut rng,
+        ),
+    };
+    let (marginals, meta) = estimate.map_err(|e| JsValue::from_str(&e.to_string()))?;
+    let marginals = options
+        .key_by
+        .apply(nodes, marginals)
+        .map_err(|e| JsValue::from_str(&e.to_string()))?;
+
+    Ok(MarginalsResult {
+        marginals,
+        provenance: Provenance {
+            assumptions,
+            fingerprint: serialized.fingerprint(),
+            seed,
+        },
+        meta,
+        constraint_violation_rate,
+    })
+}
+
+#[derive(Serialize)]
+#[serde(rename_all = "camelCase")]
+pub struct SelfCheckResult {
+    pub report: self_check::SelfCheckReport,
+    pub provenance: Provenance,
+}
+
+/// Diagnoses "the numbers look wrong" reports by comparing the sampler with
+/// exact enumeration on a small sub-network around `options.targets`.
+#[wasm_bindgen]
+#[allow(clippy::missing_errors_doc)]
+pub fn self_check(nodes: JsValue, options: JsValue) -> Result<JsValue, JsValue> {
+    let nodes = deserialize_nodes(nodes)?;
+    let options: options::QueryOptions = serde_wasm_bindgen::from_value(options)
+        .map_err(|e| JsValue::from_str(&format!("Failed to deserialize options: {e}")))?;
+    if options.root_correlations.is_some() {
+        return Err(JsValue::from_str(
+            "rootCorrelations are not supported by self_check; exact enumeration assumes independent roots",
+        ));
+    }
+    if !options.constraints.is_empty() {
+        return Err(JsValue::from_str(
+            "constraints are not supported by self_check; use check_constraints instead",
+        ));
+    }
+    if options.zero_handling != marginals::ZeroHandling::Strict {
+        return Err(JsValue::from_str(
+            "zeroHandling smoothing is not supported by self_check; exact enumeration keeps zeros",
+        ));
+    }
+    let num_samples = options.num_samples().map_err(limit_error)?;
+
+    let serialized = serialize::serialize_network(&nodes)
+        .map_err(|e| JsValue::from_str(&format!("Serialization failed: {e}")))?;
+    let assumptions = options
+        .assumptions
+        .resolve(&nodes)
+        .map_err(|e| invalid_assumptions(&e))?;
+
+    let (seed, mut rng) = rng_from_seed(options.seed().map_err(limit_error)?)?;
+    let report = self_check::self_check(&nodes, &options, num_samples, &assumptions, &mut rng)
+        .map_err(|e| JsValue::from_str(&format!("Self-check failed: {e}")))?;
+
+    let result = SelfCheckResult {
+        report,
+        provenance: Provenance {
+            assumptions,
+            fingerprint: serialized.fingerprint(),
+            seed,
+        },
+    };
+    serde_wasm_bindgen::to_value(&result)
+        .map_err(|e| JsValue::from_str(&format!("Failed to serialize result: {e}")))
+}
+
+#[derive(Serialize)]
+#[serde(rename_all = "camelCase")]
+pub struct RngTraceResult {
+    pub guarantee: &'static str,
+    pub fingerprint: String,
+    pub seed: u64,
+    pub samples: Vec<rng_trace::SampleTrace>,
+}
+
+/// Debug query for reproducibility audits: per sample, the number of random
+/// draws consumed and a hash of the sampled assignment.
+#[wasm_bindgen]
+#[allow(clippy::missing_errors_doc)]
+pub fn rng_trace(nodes: JsValue, seed: f64, num_samples: f64) -> Result<JsValue, JsValue> {
+    let seed = limits::seed("seed", seed).map_err(limit_error)?;
+    let num_samples = checked_count("numSamples", num_samples, MAX_SAMPLES)?;
+    let nodes = deserialize_nodes(nodes)?;
+    let serialized = serialize::serialize_network(&nodes)
+        .map_err(|e| JsValue::from_str(&format!("Serialization failed: {e}")))?;
+
+    let (seed, mut rng) = rng_from_seed(Some(seed))?;
+    let samples = rng_trace::rng_trace(&serialized, num_samples, &mut rng)
+        .map_err(|e| JsValue::from_str(&e.to_string()))?;
+
+    let result = RngTraceResult {
+        guarantee: rng_trace::REPRODUCIBILITY_GUARANTEE,
+        fingerprint: serialized.fingerprint(),
+        seed,
+        samples,
+    };
+    serde_wasm_bindgen::to_value(&result)
+        .map_err(|e| JsValue::from_str(&format!("Failed to serialize result: {e}")))
+}
+
+/// Structured description of how assumption set `b` differs from `a`.
+#[wasm_bindgen]
+#[allow(clippy::missing_errors_doc)]
+pub fn diff_assumptions(a: JsValue, b: JsValue) -> Result<JsValue, JsValue> {
+    let a: assumptions::AssumptionSet = serde_wasm_bindgen::from_value(a)
+        .map_err(|e| JsValue::from_str(&format!("Failed to deserialize assumptions: {e}")))?;
+    let b: assumptions::AssumptionSet = serde_wasm_bindgen::from_value(b)
+        .map_err(|e| JsValue::from_str(&format!("Failed to deserialize assumptions: {e}")))?;
+    serde_wasm_bindgen::to_value(&assumptions::diff_assumptions(&a, &b))
+        .map_err(|e| JsValue::from_str(&format!("Failed to serialize result: {e}")))
+}
+
+#[wasm_bindgen]
+#[allow(clippy::missing_errors_doc)]
+pub fn compute_causal_attribution(
+    nodes: JsValue,
+    num_samples: f64,
+    query_node_id: &str,
+    ancestor_ids: JsValue,
+) -> Result<JsValue, JsValue> {
+    let num_samples = checked_count("numSamples", num_samples, MAX_SAMPLES)?;
+    let nodes = deserialize_nodes(nodes)?;
+    let ancestor_ids: Vec<String> = serde_wasm_bindgen::from_value(ancestor_ids)
+        .map_err(|e| JsValue::from_str(&format!("Failed to deserialize ancestor IDs: {e}")))?;
+    let mut rng = seeded_rng()?;
+
+    let attributions = causal::shapley_causal_attribution(
+        &nodes,
+        num_samples,
+        query_node_id,
+        &ancestor_ids,
+        &mut rng,
+    )
+    .map_err(|e| match e.downcast_ref::<lookup::NodeNotFound>() {
+        Some(not_found) => not_found_error(not_found),
+        None => JsValue::from_str(&format!("Causal attribution failed: {e}")),
+    })?;
+
+    serde_wasm_bindgen::to_value(&attributions)
+        .map_err(|e| JsValue::from_str(&format!("Failed to serialize result: {e}")))
+}
+
+#[wasm_bindgen]
+#[allow(clippy::missing_errors_doc)]
+pub fn compare_networks(nodes_a: JsValue, nodes_b: JsValue) -> Result<JsValue, JsValue> {
+    let nodes_a = deserialize_nodes(nodes_a)?;
+    let nodes_b = deserialize_nodes(nodes_b)?;
+
+    let distance = structure::network_graph_distance(&nodes_a, &nodes_b)
+        .map_err(|e| JsValue::from_str(&format!("Comparison failed: {e}")))?;
+
+    serde_wasm_bindgen::to_value(&distance)
+        .map_err(|e| JsValue::from_str(&format!("Failed to serialize result: {e}")))
+}
+
+/// Where two parameterizations of the same structure disagree (see
+/// [`disagreement::compare_parameterizations`]): `{ marginalDifferences,
+/// entryDifferences, unmatchedEntries, targetImpacts? }`. Options are `{
+/// targetId?, threshold?, topK? }`; differing structures are an error listing
+/// every difference.
+#[wasm_bindgen]
+#[allow(clippy::missing_errors_doc)]
+pub fn compare_parameterizations(
+    nodes_a: JsValue,
+    nodes_b: JsValue,
+    num_samples: f64,
+    options: JsValue,
+) -> Result<JsValue, JsValue> {
+    let num_samples = checked_count("numSamples", num_samples, MAX_SAMPLES)?;
+    let nodes_a = deserialize_nodes(nodes_a)?;
+    let nodes_b = deserialize_nodes(nodes_b)?;
+    let options: disagreement::ComparisonOptions = if options.is_undefined() || options.is_null() {
+        disagreement::ComparisonOptions::default()
+    } else {
+        serde_wasm_bindgen::from_value(options)
+            .map_err(|e| JsValue::from_str(&format!("Failed to deserialize options: {e}")))?
+    };
+    let mut rng = seeded_rng()?;
+
+    let comparison = disagreement::compare_parameterizations(
+        &nodes_a,
+        &nodes_b,
+        num_samples,
+        &options,
+        &mut rng,
+    )
+    .map_err(|e| match e.downcast_ref::<lookup::NodeNotFound>() {
+        Some(not_found) => not_found_error(not_found),
+        None => JsValue::from_str(&format!("Comparison failed: {e}")),
+    })?;
+    comparison
+        .serialize(&serde_wasm_bindgen::Serializer::new().serialize_maps_as_objects(true))
+        .map_err(|e| JsValue::from_str(&format!("Failed to serialize result: {e}")))
+}
+
+#[wasm_bindgen]
+#[allow(clippy::missing_errors_doc)]
+pub fn compute_average_causal_effect_with_compliance(
+    nodes: JsValue,
+    num_samples: f64,
+    treatment_id: &str,
+    outcome_id: &str,
+    compliance_rate: f64,
+) -> Result<JsValue, JsValue> {
+    let num_samples = checked_count("numSamples", num_samples, MAX_SAMPLES)?;
+    let nodes = deserialize_nodes(nodes)?;
+    let mut rng = seeded_rng()?;
+
+    let effect = causal::average_causal_effect_with_compliance(
+        &nodes,
+        num_samples,
+        treatment_id,
+        outcome_id,
+        compliance_rate,
+        &mut rng,
+    )
+    .map_err(|e| match e.downcast_ref::<lookup::NodeNotFound>() {
+        Some(not_found) => not_found_error(not_found),
+        None => JsValue::from_str(&format!("Compliance analysis failed: {e}")),
+    })?;
+
+    serde_wasm_bindgen::to_value(&effect)
+        .map_err(|e| JsValue::from_str(&format!("Failed to serialize result: {e}")))
+}
+
+/// Local average treatment effect of `treatment_id` on `outcome_id` using
+/// `instrument_id` as a natural experiment (see [`causal::iv_effect`]).
+#[wasm_bindgen]
+#[allow(clippy::missing_errors_doc)]
+pub fn compute_iv_effect(
+    nodes: JsValue,
+    num_samples: f64,
+    instrument_id: &str,
+    treatment_id: &str,
+    outcome_id: &str,
+) -> Result<f64, JsValue> {
+    let num_samples = checked_count("numSamples", num_samples, MAX_SAMPLES)?;
+    let nodes = deserialize_nodes(nodes)?;
+    let mut rng = seeded_rng()?;
+
+    causal::iv_effect(
+        &nodes,
+        num_samples,
+        instrument_id,
+        treatment_id,
+        outcome_id,
+        &mut rng,
+    )
+    .map_err(|e| match e.downcast_ref::<lookup::NodeNotFound>() {
+        Some(missing) => not_found_error(missing),
+        None => JsValue::from_str(&format!("IV estimation failed: {e}")),
+    })
+}
+
+/// How likely the CPTs make it that each of `constraints` (as in
+/// `QueryOptions.constraints`) is broken, as `[{ nodes,
+/// violationProbability, method, example?, warning? }]`. `num_samples` is
+/// only used for constraints whose nodes have too many ancestors to
+/// enumerate.
+#[wasm_bindgen]
+#[allow(clippy::missing_errors_doc)]
+pub fn check_constraints(
+    nodes: JsValue,
+    constraints: JsValue,
+    num_samples: f64,
+) -> Result<JsValue, JsValue> {
+    let num_samples = checked_count("numSamples", num_samples, MAX_SAMPLES)?;
+    let nodes = deserialize_nodes(nodes)?;
+    let constraints: Vec<constraints::Constraint> = serde_wasm_bindgen::from_value(constraints)
+        .map_err(|e| JsValue::from_str(&format!("Failed to deserialize constraints: {e}")))?;
+    let mut rng = seeded_rng()?;
+
+    let checks = constraints::check_constraints(&nodes, &constraints, num_samples, &mut rng)
+        .map_err(|e| JsValue::from_str(&format!("Constraint check failed: {e}")))?;
+    checks
+        .serialize(&serde_wasm_bindgen::Serializer::new().serialize_maps_as_objects(true))
+        .map_err(|e| JsValue::from_str(&format!("Failed to serialize result: {e}")))
+}
+
+/// Runs the checks selected in `options` (all by default) and returns every
+/// issue found as `{ severity, nodeId, message }` objects. Fixed-parent
+/// warnings also carry `fixedParent: { parentId, value, entryIndices }`.
+#[wasm_bindgen]
+#[allow(clippy::missing_errors_doc)]
+pub fn validate_network_wasm(nodes: JsValue, options: JsValue) -> Result<JsValue, JsValue> {
+    let nodes = deserialize_nodes(nodes)?;
+    let options: validate::ValidationOptions = if options.is_undefined() || options.is_null() {
+        validate::ValidationOptions::default()
+    } else {
+        serde_wasm_bindgen::from_value(options)
+            .map_err(|e| JsValue::from_str(&format!("Failed to deserialize options: {e}")))?
+    };
+
+    let issues = validate::NetworkValidator::from(&options).validate(&nodes);
+    issues
+        .serialize(&serde_wasm_bindgen::Serializer::new().serialize_missing_as_null(true))
+        .map_err(|e| JsValue::from_str(&format!("Failed to serialize result: {e}")))
+}
+
+#[wasm_bindgen]
+#[allow(clippy::missing_errors_doc)]
+pub fn check_monotonicity(
+    nodes: JsValue,
+    num_samples: f64,
+    treatment_id: &str,
+    outcome_id: &str,
+) -> Result<JsValue, JsValue> {
+    let num_samples = checked_count("numSamples", num_samples, MAX_SAMPLES)?;
+    let nodes = deserialize_nodes(nodes)?;
+    let mut rng = seeded_rng()?;
+
+    let result =
+        causal::check_monotonicity(&nodes, num_samples, treatment_id, outcome_id, &mut rng)
+            .map_err(|e| match e.downcast_ref::<lookup::NodeNotFound>() {
+                Some(not_found) => not_found_error(not_found),
+                None => JsValue::from_str(&format!("Monotonicity check failed: {e}")),
+            })?;
+
+    result
+        .serialize(
+            &serde_wasm_bindgen::Serializer::new()
+                .serialize_maps_as_objects(true)
+                .serialize_missing_as_null(true),
+        )
+        .map_err(|e| JsValue::from_str(&format!("Failed to serialize result: {e}")))
+}
+
+/// Points parent references that only match a node ignoring case or Unicode
+/// normalization at that node's exact ID, as `{ nodes, resolved, conflicts }`.
+/// A reference is left alone, and listed in `conflicts`, when its entry
+/// already names the node it would be pointed at.
+#[wasm_bindgen]
+#[allow(clippy::missing_errors_doc)]
+pub fn resolve_relaxed_ids(nodes: JsValue) -> Result<JsValue, JsValue> {
+    let nodes = deserialize_nodes(nodes)?;
+    validate::resolve_relaxed_references(nodes)
+        .serialize(
+            &serde_wasm_bindgen::Serializer::new()
+                .serialize_maps_as_objects(true)
+                .serialize_missing_as_null(true),
+        )
+        .map_err(|e| JsValue::from_str(&format!("Failed to serialize result: {e}")))
+}
+
+#[wasm_bindgen]
+#[allow(clippy::missing_errors_doc)]
+pub fn compute_sensitivity_to_confounding(
+    nodes: JsValue,
+    num_samples: f64,
+    treatment_id: &str,
+    outcome_id: &str,
+    confounder_strength: f64,
+) -> Result<JsValue, JsValue> {
+    let num_samples = checked_count("numSamples", num_samples, MAX_SAMPLES)?;
+    let nodes = deserialize_nodes(nodes)?;
+    let mut rng = seeded_rng()?;
+
+    let bounds = causal::sensitivity_to_confounding(
+        &nodes,
+        num_samples,
+        treatment_id,
+        outcome_id,
+        confounder_strength,
+        &mut rng,
+    )
+    .map_err(|e| match e.downcast_ref::<lookup::NodeNotFound>() {
+        Some(not_found) => not_found_error(not_found),
+        None => JsValue::from_str(&format!("Sensitivity analysis failed: {e}")),
+    })?;
+
+    serde_wasm_bindgen::to_value(&bounds)
+        .map_err(|e| JsValue::from_str(&format!("Failed to serialize result: {e}")))
+}
+
+#[wasm_bindgen]
+#[allow(clippy::missing_errors_doc)]
+pub fn compute_interventional_heterogeneity(
+    nodes: JsValue,
+    num_samples: f64,
+    treatment_id: &str,
+    outcome_id: &str,
+    stratification_ids: JsValue,
+) -> Result<JsValue, JsValue> {
+    let num_samples = checked_count("numSamples", num_samples, MAX_SAMPLES)?;
+    let nodes = deserialize_nodes(nodes)?;
+    let stratification_ids: Vec<String> = serde_wasm_bindgen::from_value(stratification_ids)
+        .map_err(|e| {
+            JsValue::from_str(&format!("Failed to deserialize stratification IDs: {e}"))
+        })?;
+    let mut rng = seeded_rng()?;
+
+    let heterogeneity = causal::interventional_heterogeneity(
+        &nodes,
+        num_samples,
+        treatment_id,
+        outcome_id,
+        &stratification_ids,
+        &mut rng,
+    )
+    .map_err(|e| match e.downcast_ref::<lookup::NodeNotFound>() {
+        Some(not_found) => not_found_error(not_found),
+        None => JsValue::from_str(&format!("Heterogeneity analysis failed: {e}")),
+    })?;
+
+    heterogeneity
+        .serialize(&serde_wasm_bindgen::Serializer::new().serialize_maps_as_objects(true))
+        .map_err(|e| JsValue::from_str(&format!("Failed to serialize result: {e}")))
+}
+
+/// `{ distribution, averageMarginalEffect }`, where `distribution` is the
+/// array of `{ treatmentValue, outcomeProbability }` for every value of the
+/// treatment, sorted by value.
+#[wasm_bindgen]
+#[allow(clippy::missing_errors_doc)]
+pub fn compute_do_distribution(
+    nodes: JsValue,
+    num_samples: f64,
+    treatment_id: &str,
+    outcome_id: &str,
+) -> Result<JsValue, JsValue> {
+    let num_samples = checked_count("numSamples", num_samples, MAX_SAMPLES)?;
+    let nodes = deserialize_nodes(nodes)?;
+    let mut rng = seeded_rng()?;
+
+    let distribution =
+        causal::do_distribution(&nodes, num_samples, treatment_id, outcome_id, &mut rng).map_err(
+            |e| match e.downcast_ref::<lookup::NodeNotFound>() {
+                Some(not_found) => not_found_error(not_found),
+                None => JsValue::from_str(&format!("Do distribution failed: {e}")),
+            },
+        )?;
+    serde_wasm_bindgen::to_value(&distribution)
+        .map_err(|e| JsValue::from_str(&format!("Failed to serialize result: {e}")))
+}
+
+/// Replaces everything upstream of `boundary_node_ids` with priors on the
+/// boundary, returning `{ nodes, fidelity, warnings }`. `fidelity` compares
+/// the marginal of `target_node_id` before and after the reduction.
+#[wasm_bindgen]
+#[allow(clippy::missing_errors_doc)]
+pub fn freeze_upstream(
+    nodes: JsValue,
+    boundary_node_ids: JsValue,
+    num_samples: f64,
+    target_node_id: &str,
+) -> Result<JsValue, JsValue> {
+    let num_samples = checked_count("numSamples", num_samples, MAX_SAMPLES)?;
+    let nodes = deserialize_nodes(nodes)?;
+    let boundary_ids: Vec<String> = serde_wasm_bindgen::from_value(boundary_node_ids)
+        .map_err(|e| JsValue::from_str(&format!("Failed to deserialize boundary IDs: {e}")))?;
+    let mut rng = seeded_rng()?;
+
+    let frozen =
+        reduction::freeze_upstream(&nodes, &boundary_ids, num_samples, target_node_id, &mut rng)
+            .map_err(|e| JsValue::from_str(&format!("Freezing failed: {e}")))?;
+    frozen
+        .serialize(
+            &serde_wasm_bindgen::Serializer::new()
+                .serialize_maps_as_objects(true)
+                .serialize_missing_as_null(true),
+        )
+        .map_err(|e| JsValue::from_str(&format!("Failed to serialize result: {e}")))
+}
+
+#[derive(Serialize)]
+#[serde(rename_all = "camelCase")]
+struct FitResult<'a> {
+    nodes: &'a [Node],
+    iterations: usize,
+}
+
+/// Recalibrates CPTs so the network's marginals match `target_marginals`
+/// (an object of node ID to probability), keeping the structure.
+#[wasm_bindgen]
+#[allow(clippy::missing_errors_doc)]
+pub fn fit_marginals(
+    nodes: JsValue,
+    target_marginals: JsValue,
+    num_samples: f64,
+    max_iter: f64,
+    tol: f64,
+) -> Result<JsValue, JsValue> {
+    let num_samples = checked_count("numSamples", num_samples, MAX_SAMPLES)?;
+    let max_iter = checked_count("maxIter", max_iter, MAX_STEPS)?;
+    let mut nodes = deserialize_nodes(nodes)?;
+    let target_marginals: HashMap<String, f64> = serde_wasm_bindgen::from_value(target_marginals)
+        .map_err(|e| {
+        JsValue::from_str(&format!("Failed to deserialize target marginals: {e}"))
+    })?;
+    let mut rng = seeded_rng()?;
+
+    let iterations = learning::fit_marginals(
+        &mut nodes,
+        &target_marginals,
+        num_samples,
+        max_iter,
+        tol,
+        &mut rng,
+    )
+    .map_err(|e| JsValue::from_str(&format!("Fitting failed: {e}")))?;
+
+    FitResult {
+        nodes: &nodes,
+        iterations,
+    }
+    .serialize(
+        &serde_wasm_bindgen::Serializer::new()
+            .serialize_maps_as_objects(true)
+            .serialize_missing_as_null(true),
+    )
+    .map_err(|e| JsValue::from_str(&format!("Failed to serialize result: {e}")))
+}
+
+/// Baseline marginals against `calibration` (an object of node ID to observed
+/// frequency), with the per-node errors, their mean, and for each node the
+/// single CPT entries whose change would close its gap most cheaply.
+#[wasm_bindgen]
+#[allow(clippy::missing_errors_doc)]
+pub fn compute_calibration_report(
+    nodes: JsValue,
+    calibration: JsValue,
+    num_samples: f64,
+    seed: Option<f64>,
+) -> Result<JsValue, JsValue> {
+    let num_samples = checked_count("numSamples", num_samples, MAX_SAMPLES)?;
+    let seed = seed
+        .map(|seed| limits::seed("seed", seed))
+        .transpose()
+        .map_err(limit_error)?;
+    let nodes = deserialize_nodes(nodes)?;
+    let calibration: HashMap<String, f64> = serde_wasm_bindgen::from_value(calibration)
+        .map_err(|e| JsValue::from_str(&format!("Failed to deserialize calibration: {e}")))?;
+    let (_, mut rng) = rng_from_seed(seed)?;
+
+    let report = calibration::calibration_report(&nodes, &calibration, num_samples, &mut rng)
+        .map_err(|e| JsValue::from_str(&format!("Calibration failed: {e}")))?;
+    serde_wasm_bindgen::to_value(&report)
+        .map_err(|e| JsValue::from_str(&format!("Failed to serialize result: {e}")))
+}
+
+/// How well each node's CPT predicts `data_rows` (an array of objects of
+/// node ID to boolean; absent keys are missing), as a Map of node ID to
+/// `{ rows, predictedMean, observedMean, calibrationError }`.
+#[wasm_bindgen]
+#[allow(clippy::missing_errors_doc)]
+pub fn calibrate_network(nodes: JsValue, data_rows: JsValue) -> Result<JsValue, JsValue> {
+    let nodes = deserialize_nodes(nodes)?;
+    let data: Vec<learning::DataRow> = serde_wasm_bindgen::from_value(data_rows)
+        .map_err(|e| JsValue::from_str(&format!("Failed to deserialize data: {e}")))?;
+
+    let results = calibration::compute_calibration(&nodes, &data)
+        .map_err(|e| JsValue::from_str(&format!("Calibration failed: {e}")))?;
+    serde_wasm_bindgen::to_value(&results)
+        .map_err(|e| JsValue::from_str(&format!("Failed to serialize result: {e}")))
+}
+
+/// How well the CPT of `outcome_id` predicts it on `data_rows` (as for
+/// [`calibrate_network`]), as `{ rows, r2, brierScore, logLoss }`.
+#[wasm_bindgen]
+#[allow(clippy::missing_errors_doc)]
+pub fn score_predictions(
+    nodes: JsValue,
+    data_rows: JsValue,
+    outcome_id: &str,
+) -> Result<JsValue, JsValue> {
+    let nodes = deserialize_nodes(nodes)?;
+    let data: Vec<learning::DataRow> = serde_wasm_bindgen::from_value(data_rows)
+        .map_err(|e| JsValue::from_str(&format!("Failed to deserialize data: {e}")))?;
+
+    let scores = calibration::score_predictions(&nodes, &data, outcome_id)
+        .map_err(|e| JsValue::from_str(&format!("Scoring failed: {e}")))?;
+    serde_wasm_bindgen::to_value(&scores)
+        .map_err(|e| JsValue::from_str(&format!("Failed to serialize result: {e}")))
+}
+
+/// Points `{ p, targetMarginal, stdError }` for a slider over the clamp on
+/// `node_id`, computed with common random numbers across steps.
+#[wasm_bindgen]
+#[allow(clippy::missing_errors_doc)]
+pub fn sweep_soft_intervention(
+    nodes: JsValue,
+    node_id: &str,
+    target_node_id: &str,
+    steps: f64,
+    num_samples_per_step: f64,
+) -> Result<JsValue, JsValue> {
+    let steps = checked_count("steps", steps, MAX_STEPS)?;
+    let num_samples_per_step =
+        checked_count("numSamplesPerStep", num_samples_per_step, MAX_SAMPLES)?;
+    let nodes = deserialize_nodes(nodes)?;
+    let mut rng = seeded_rng()?;
+
+    let points = causal::sweep_soft_intervention(
+        &nodes,
+        node_id,
+        target_node_id,
+        steps,
+        num_samples_per_step,
+        &mut rng,
+    )
+    .map_err(|e| match e.downcast_ref::<lookup::NodeNotFound>() {
+        Some(not_found) => not_found_error(not_found),
+        None => JsValue::from_str(&format!("Sweep failed: {e}")),
+    })?;
+
+    serde_wasm_bindgen::to_value(&points)
+        .map_err(|e| JsValue::from_str(&format!("Failed to serialize result: {e}")))
+}
+
+/// `{ totalEffect, directEffect, indirectEffect, proportionMediated }` for
+/// the effect of `treatment_id` on `outcome_id` through `mediator_id`, from
+/// natural direct and indirect effects.
+#[wasm_bindgen]
+#[allow(clippy::missing_errors_doc)]
+pub fn compute_mediation_proportion(
+    nodes: JsValue,
+    num_samples: f64,
+    treatment_id: &str,
+    mediator_id: &str,
+    outcome_id: &str,
+) -> Result<JsValue, JsValue> {
+    let num_samples = checked_count("numSamples", num_samples, MAX_SAMPLES)?;
+    let nodes = deserialize_nodes(nodes)?;
+    let mut rng = seeded_rng()?;
+
+    let result = causal::mediation_proportion(
+        &nodes,
+        num_samples,
+        treatment_id,
+        mediator_id,
+        outcome_id,
+        &mut rng,
+    )
+    .map_err(|e| match e.downcast_ref::<lookup::NodeNotFound>() {
+        Some(not_found) => not_found_error(not_found),
+        None => JsValue::from_str(&format!("Mediation analysis failed: {e}")),
+    })?;
+    serde_wasm_bindgen::to_value(&result)
+        .map_err(|e| JsValue::from_str(&format!("Failed to serialize result: {e}")))
+}
+
+/// The dose-response curve of `outcome_id` in the treatment's probability,
+/// as an array of `[treatmentProbability, outcomeProbability]` pairs in the
+/// order of `treatment_probabilities` (each clamped to `[0.001, 0.999]`).
+#[wasm_bindgen]
+#[allow(clippy::missing_errors_doc)]
+pub fn compute_dose_response_wasm(
+    nodes: JsValue,
+    num_samples: f64,
+    treatment_id: &str,
+    outcome_id: &str,
+    treatment_probabilities: &[f64],
+) -> Result<JsValue, JsValue> {
+    let num_samples = checked_count("numSamples", num_samples, MAX_SAMPLES)?;
+    let nodes = deserialize_nodes(nodes)?;
+    let mut rng = seeded_rng()?;
+
+    let curve = causal::dose_response(
+        &nodes,
+        num_samples,
+        treatment_id,
+        outcome_id,
+        treatment_probabilities,
+        &mut rng,
+    )
+    .map_err(|e| match e.downcast_ref::<lookup::NodeNotFound>() {
+        Some(not_found) => not_found_error(not_found),
+        None => JsValue::from_str(&format!("Dose response failed: {e}")),
+    })?;
+    serde_wasm_bindgen::to_value(&curve)
+        .map_err(|e| JsValue::from_str(&format!("Failed to serialize result: {e}")))
+}
+
+/// What `outcome_id` would have been for the unit described by
+/// `observation` (`{ [nodeId]: boolean }`) had `treatment_id` been
+/// `counterfactual_treatment`, as a probability over the unit's abducted
+/// noise.
+#[wasm_bindgen]
+#[allow(clippy::missing_errors_doc)]
+pub fn compute_counterfactual_outcome(
+    nodes: JsValue,
+    observation: JsValue,
+    treatment_id: &str,
+    counterfactual_treatment: bool,
+    outcome_id: &str,
+    num_samples: f64,
+) -> Result<f64, JsValue> {
+    let num_samples = checked_count("numSamples", num_samples, MAX_SAMPLES)?;
+    let nodes = deserialize_nodes(nodes)?;
+    let observation: HashMap<String, bool> = serde_wasm_bindgen::from_value(observation)
+        .map_err(|e| JsValue::from_str(&format!("Failed to deserialize observation: {e}")))?;
+    let mut rng = seeded_rng()?;
+
+    causal::counterfactual_outcome(
+        &nodes,
+        &observation,
+        treatment_id,
+        counterfactual_treatment,
+        outcome_id,
+        num_samples,
+        &mut rng,
+    )
+    .map_err(|e| match e.downcast_ref::<lookup::NodeNotFound>() {
+        Some(not_found) => not_found_error(not_found),
+        None => JsValue::from_str(&format!("Counterfactual failed: {e}")),
+    })
+}
+
+/// Every other node ranked by `|P(outcome | do(true)) - P(outcome |
+/// do(false))|`, as `[{ nodeId, effect, outcomeIfTrue, outcomeIfFalse,
+/// isAncestor }]`. Non-ancestors of the outcome are not sampled.
+#[wasm_bindgen]
+#[allow(clippy::missing_errors_doc)]
+pub fn rank_outcome_impacts(
+    nodes: JsValue,
+    num_samples: f64,
+    outcome_id: &str,
+) -> Result<JsValue, JsValue> {
+    let num_samples = checked_count("numSamples", num_samples, MAX_SAMPLES)?;
+    let nodes = deserialize_nodes(nodes)?;
+    let mut rng = seeded_rng()?;
+
+    let impacts = causal::rank_outcome_impacts(&nodes, num_samples, outcome_id, &mut rng).map_err(
+        |e| match e.downcast_ref::<lookup::NodeNotFound>() {
+            Some(not_found) => not_found_error(not_found),
+            None => JsValue::from_str(&format!("Impact ranking failed: {e}")),
+        },
+    )?;
+    serde_wasm_bindgen::to_value(&impacts)
+        .map_err(|e| JsValue::from_str(&format!("Failed to serialize result: {e}")))
+}
+
+/// The intervention among `do(X=false)` and `do(X=true)` for each `X` in
+/// `intervention_candidates` that maximizes `P(utility_node_id)`, as
+/// `{ nodeId, value, expectedUtility }`.
+#[wasm_bindgen]
+// wasm-bindgen cannot take a `&[String]`.
+#[allow(clippy::missing_errors_doc, clippy::needless_pass_by_value)]
+pub fn compute_optimal_single_intervention(
+    nodes: JsValue,
+    num_samples: f64,
+    intervention_candidates: Vec<String>,
+    utility_node_id: &str,
+) -> Result<JsValue, JsValue> {
+    let num_samples = checked_count("numSamples", num_samples, MAX_SAMPLES)?;
+    let nodes = deserialize_nodes(nodes)?;
+    let mut rng = seeded_rng()?;
+
+    let optimal = causal::optimal_single_intervention(
+        &nodes,
+        num_samples,
+        &intervention_candidates,
+        utility_node_id,
+        &mut rng,
+    )
+    .map_err(|e| match e.downcast_ref::<lookup::NodeNotFound>() {
+        Some(missing) => not_found_error(missing),
+        None => JsValue::from_str(&format!("Intervention search failed: {e}")),
+    })?;
+    serde_wasm_bindgen::to_value(&optimal)
+        .map_err(|e| JsValue::from_str(&format!("Failed to serialize result: {e}")))
+}
+
+/// Covariate assignments under which `treatment_id` is nearly determined,
+/// as `[{ covariateAssignment, treatmentProbability }]` (see
+/// [`causal::check_positivity`]). Empty when positivity holds.
+#[wasm_bindgen]
+// wasm-bindgen cannot take a `&[String]`.
+#[allow(clippy::missing_errors_doc, clippy::needless_pass_by_value)]
+pub fn check_positivity(
+    nodes: JsValue,
+    treatment_id: &str,
+    covariate_ids: Vec<String>,
+    min_prob: f64,
+) -> Result<JsValue, JsValue> {
+    let nodes = deserialize_nodes(nodes)?;
+
+    let violations = causal::check_positivity(&nodes, treatment_id, &covariate_ids, min_prob)
+        .map_err(|e| match e.downcast_ref::<lookup::NodeNotFound>() {
+            Some(missing) => not_found_error(missing),
+            None => JsValue::from_str(&format!("Positivity check failed: {e}")),
+        })?;
+    violations
+        .serialize(&serde_wasm_bindgen::Serializer::new().serialize_maps_as_objects(true))
+        .map_err(|e| JsValue::from_str(&format!("Failed to serialize result: {e}")))
+}
+
+/// Percentiles (0 to 100) of unit-level treatment effects, as an object of
+/// outcome ID to values in the order requested.
+#[wasm_bindgen]
+#[allow(clippy::missing_errors_doc)]
+pub fn compute_effect_percentiles(
+    nodes: JsValue,
+    num_samples: f64,
+    treatment_id: &str,
+    outcome_ids: JsValue,
+    percentiles: JsValue,
+) -> Result<JsValue, JsValue> {
+    let num_samples = checked_count("numSamples", num_samples, MAX_SAMPLES)?;
+    let nodes = deserialize_nodes(nodes)?;
+    let outcome_ids: Vec<String> = serde_wasm_bindgen::from_value(outcome_ids)
+        .map_err(|e| JsValue::from_str(&format!("Failed to deserialize outcome IDs: {e}")))?;
+    let percentiles: Vec<f64> = serde_wasm_bindgen::from_value(percentiles)
+        .map_err(|e| JsValue::from_str(&format!("Failed to deserialize percentiles: {e}")))?;
+    let mut rng = seeded_rng()?;
+
+    let result = causal::effect_percentiles(
+        &nodes,
+        num_samples,
+        treatment_id,
+        &outcome_ids,
+        &percentiles,
+        &mut rng,
+    )
+    .map_err(|e| match e.downcast_ref::<lookup::NodeNotFound>() {
+        Some(not_found) => not_found_error(not_found),
+        None => JsValue::from_str(&format!("Effect percentiles failed: {e}")),
+    })?;
+
+    result
+        .serialize(&serde_wasm_bindgen::Serializer::new().serialize_maps_as_objects(true))
+        .map_err(|e| JsValue::from_str(&format!("Failed to serialize result: {e}")))
+}
+
+/// Doubly robust estimate of the average effect of `treatment_id` on
+/// `outcome_id` from `data_rows` (as for [`calibrate_network`]), adjusting
+/// for the treatment's parents in `nodes`. `propensity_model` and
+/// `outcome_model` are node arrays predicting the treatment and the outcome.
+#[wasm_bindgen]
+#[allow(clippy::missing_errors_doc)]
+pub fn compute_augmented_ipw_estimator(
+    nodes: JsValue,
+    data_rows: JsValue,
+    treatment_id: &str,
+    outcome_id: &str,
+    propensity_model: JsValue,
+    outcome_model: JsValue,
+) -> Result<f64, JsValue> {
+    let nodes = deserialize_nodes(nodes)?;
+    let data: Vec<learning::DataRow> = serde_wasm_bindgen::from_value(data_rows)
+        .map_err(|e| JsValue::from_str(&format!("Failed to deserialize data: {e}")))?;
+    let propensity_model = deserialize_nodes(propensity_model)?;
+    let outcome_model = deserialize_nodes(outcome_model)?;
+
+    causal::aipw_ate(
+        &nodes,
+        &data,
+        treatment_id,
+        outcome_id,
+        &propensity_model,
+        &outcome_model,
+    )
+    .map_err(|e| match e.downcast_ref::<lookup::NodeNotFound>() {
+        Some(not_found) => not_found_error(not_found),
+        None => JsValue::from_str(&format!("AIPW estimation failed: {e}")),
+    })
+}
+
+/// Units per arm a randomized study of `do(treatment_id)` needs to detect an
+/// effect of `expected_ate` on `outcome_id` with probability `power` at level
+/// `alpha`, searched over 100 to 1,000,000: `{ sampleSizePerArm,
+/// achievedPower, controlRate, treatedRate }`. The network gives the control
+/// rate; the study is then simulated.
+#[wasm_bindgen]
+#[allow(clippy::missing_errors_doc)]
+pub fn compute_required_sample_size(
+    nodes: JsValue,
+    treatment_id: &str,
+    outcome_id: &str,
+    expected_ate: f64,
+    power: f64,
+    alpha: f64,
+) -> Result<JsValue, JsValue> {
+    let nodes = deserialize_nodes(nodes)?;
+    let mut rng = seeded_rng()?;
+
+    let plan = power::power_analysis(
+        &nodes,
+        treatment_id,
+        outcome_id,
+        expected_ate,
+        power,
+        alpha,
+        &mut rng,
+    )
+    .map_err(|e| JsValue::from_str(&format!("Power analysis failed: {e}")))?;
+    serde_wasm_bindgen::to_value(&plan)
+        .map_err(|e| JsValue::from_str(&format!("Failed to serialize result: {e}")))
+}
+
+/// Samples needed for every marginal to be within `epsilon` of its true value
+/// with probability `confidence`, whatever the network: `{ hoeffding, clt,
+/// recommended }`. Hoeffding's bound always holds; the CLT bound is tighter
+/// but approximate, so the recommendation doubles it.
+#[wasm_bindgen]
+#[allow(clippy::missing_errors_doc)]
+pub fn recommend_sample_size(epsilon: f64, confidence: f64) -> Result<JsValue, JsValue> {
+    let bounds = statistics::sample_size_bounds(epsilon, confidence)
+        .map_err(|e| JsValue::from_str(&format!("Invalid bound: {e}")))?;
+    serde_wasm_bindgen::to_value(&bounds)
+        .map_err(|e| JsValue::from_str(&format!("Failed to serialize result: {e}")))
+}
+
+/// Quantile treatment effects of `treatment_id` on `outcome_id` at each of
+/// `quantiles` (within `[0, 1]`), as an object of the outcome ID to values in
+/// the order requested.
+#[wasm_bindgen]
+#[allow(clippy::missing_errors_doc)]
+pub fn compute_interventional_quantile_treatment_effect(
+    nodes: JsValue,
+    num_samples: f64,
+    treatment_id: &str,
+    outcome_id: &str,
+    quantiles: &[f64],
+) -> Result<JsValue, JsValue> {
+    let num_samples = checked_count("numSamples", num_samples, MAX_SAMPLES)?;
+    let nodes = deserialize_nodes(nodes)?;
+    let mut rng = seeded_rng()?;
+
+    let result = causal::compute_qte(
+        &nodes,
+        num_samples,
+        treatment_id,
+        outcome_id,
+        quantiles,
+        &mut rng,
+    )
+    .map_err(|e| match e.downcast_ref::<lookup::NodeNotFound>() {
+        Some(not_found) => not_found_error(not_found),
+        None => JsValue::from_str(&format!("Quantile treatment effect failed: {e}")),
+    })?;
+
+    result
+        .serialize(&serde_wasm_bindgen::Serializer::new().serialize_maps_as_objects(true))
+        .map_err(|e| JsValue::from_str(&format!("Failed to serialize result: {e}")))
+}
+
+/// Risk measure of `outcome_id` under `do(intervention_id = value)`: the
+/// mean outcome over the worst `alpha` fraction of worlds (see
+/// [`causal::expected_shortfall`]), as `{ expectedShortfall, probabilityTrue
+/// }`.
+#[wasm_bindgen]
+#[allow(clippy::missing_errors_doc)]
+pub fn compute_expected_shortfall(
+    nodes: JsValue,
+    num_samples: f64,
+    intervention_id: &str,
+    value: bool,
+    outcome_id: &str,
+    alpha: f64,
+) -> Result<JsValue, JsValue> {
+    let num_samples = checked_count("numSamples", num_samples, MAX_SAMPLES)?;
+    let nodes = deserialize_nodes(nodes)?;
+    let mut rng = seeded_rng()?;
+
+    let result = causal::expected_shortfall(
+        &nodes,
+        num_samples,
+        intervention_id,
+        value,
+        outcome_id,
+        alpha,
+        &mut rng,
+    )
+    .map_err(|e| match e.downcast_ref::<lookup::NodeNotFound>() {
+        Some(not_found) => not_found_error(not_found),
+        None => JsValue::from_str(&format!("Expected shortfall failed: {e}")),
+    })?;
+    serde_wasm_bindgen::to_value(&result)
+        .map_err(|e| JsValue::from_str(&format!("Failed to serialize result: {e}")))
+}
+
+/// How much `target_node_id`'s marginal depends on CPT entry order: for
+/// every node where an earlier wildcard entry shadows a more specific one,
+/// the marginal with that node's entries resolved most-specific-first.
+/// Returns `{ baseline, ambiguousNodes, impacts, allReordered }`, with
+/// `impacts` limited to shifts larger than `threshold`.
+#[wasm_bindgen]
+#[allow(clippy::missing_errors_doc)]
+pub fn ambiguity_impact(
+    nodes: JsValue,
+    target_node_id: &str,
+    num_samples: f64,
+    threshold: f64,
+) -> Result<JsValue, JsValue> {
+    let num_samples = checked_count("numSamples", num_samples, MAX_SAMPLES)?;
+    let threshold = limits::non_negative("threshold", threshold).map_err(limit_error)?;
+    let nodes = deserialize_nodes(nodes)?;
+    let mut rng = seeded_rng()?;
+
+    let impact =
+        ambiguity::ambiguity_impact(&nodes, target_node_id, num_samples, threshold, &mut rng)
+            .map_err(|e| JsValue::from_str(&format!("Ambiguity analysis failed: {e}")))?;
+    serde_wasm_bindgen::to_value(&impact)
+        .map_err(|e| JsValue::from_str(&format!("Failed to serialize result: {e}")))
+}
+
+/// Whether `P(outcomes | do(treatments))` can be computed from the nodes not
+/// listed in `hidden_ids` or marked `latent`, decided structurally without
+/// sampling. [`identify_effect`] also gives the estimand for a single
+/// treatment and outcome.
+#[wasm_bindgen]
+#[allow(clippy::missing_errors_doc)]
+pub fn check_identifiability(
+    nodes: JsValue,
+    treatment_ids: JsValue,
+    outcome_ids: JsValue,
+    hidden_ids: JsValue,
+) -> Result<JsValue, JsValue> {
+    let nodes = deserialize_nodes(nodes)?;
+    let ids = |ids: JsValue, role: &str| -> Result<Vec<String>, JsValue> {
+        serde_wasm_bindgen::from_value(ids)
+            .map_err(|e| JsValue::from_str(&format!("Failed to deserialize {role} IDs: {e}")))
+    };
+    let identifiable = identification::is_identifiable(
+        &nodes,
+        &ids(treatment_ids, "treatment")?,
+        &ids(outcome_ids, "outcome")?,
+        &ids(hidden_ids, "hidden")?,
+    )
+    .map_err(|e| JsValue::from_str(&format!("Identifiability check failed: {e}")))?;
+    Ok(JsValue::from_bool(identifiable))
+}
+
+/// Whether `P(outcome | do(treatment))` can be computed with the nodes marked
+/// `latent` unobserved, as `{ identifiable, adjustmentSet?, cComponents,
+/// formula? }`.
+#[wasm_bindgen]
+#[allow(clippy::missing_errors_doc)]
+pub fn identify_effect(nodes: JsValue, treatment: &str, outcome: &str) -> Result<JsValue, JsValue> {
+    let nodes = deserialize_nodes(nodes)?;
+    let identification = identification::identify(&nodes, treatment, outcome)
+        .map_err(|e| JsValue::from_str(&format!("Identifiability check failed: {e}")))?;
+    serde_wasm_bindgen::to_value(&identification)
+        .map_err(|e| JsValue::from_str(&format!("Failed to serialize result: {e}")))
+}
+
+/// Simplifies `{ outcomes, interventions?, observations? }`, read as
+/// `P(outcomes | do(interventions), observations)`, with the rules of the
+/// do-calculus, as `{ query, expression, steps, observational }`;
+/// `undefined` when no rule applies. Each step names the rule and the node it
+/// removed or turned from an action into an observation.
+#[wasm_bindgen]
+#[allow(clippy::missing_errors_doc)]
+pub fn compute_do_calculus_rules(nodes: JsValue, query: JsValue) -> Result<JsValue, JsValue> {
+    let nodes = deserialize_nodes(nodes)?;
+    let query: do_calculus::CausalQuery = serde_wasm_bindgen::from_value(query)
+        .map_err(|e| JsValue::from_str(&format!("Failed to deserialize query: {e}")))?;
+    let simplified = do_calculus::simplify_causal_query(&nodes, query)
+        .map_err(|e| JsValue::from_str(&format!("Do-calculus simplification failed: {e}")))?;
+    serde_wasm_bindgen::to_value(&simplified)
+        .map_err(|e| JsValue::from_str(&format!("Failed to serialize result: {e}")))
+}
+
+/// Each node's CPT resolved for every parent assignment, as an object of node
+/// ID to `[parentStates, probability]` pairs.
+#[wasm_bindgen]
+#[allow(clippy::missing_errors_doc)]
+pub fn get_conditional_marginals_wasm(nodes: JsValue) -> Result<JsValue, JsValue> {
+    let nodes = deserialize_nodes(nodes)?;
+    let conditionals = structure::get_conditional_marginals(&nodes)
+        .map_err(|e| JsValue::from_str(&e.to_string()))?;
+    conditionals
+        .serialize(&serde_wasm_bindgen::Serializer::new().serialize_maps_as_objects(true))
+        .map_err(|e| JsValue::from_str(&format!("Failed to serialize result: {e}")))
+}
+
+/// IDs of every node upstream of `node_id`, in topological order.
+#[wasm_bindgen]
+#[allow(clippy::missing_errors_doc)]
+pub fn ancestors(nodes: JsValue, node_id: &str) -> Result<Vec<String>, JsValue> {
+    let (serialized, node) = compile_with_node(nodes, node_id)?;
+    Ok(structure::ancestors(&serialized, node))
+}
+
+/// IDs of every node downstream of `node_id`, in topological order.
+#[wasm_bindgen]
+#[allow(clippy::missing_errors_doc)]
+pub fn descendants(nodes: JsValue, node_id: &str) -> Result<Vec<String>, JsValue> {
+    let (serialized, node) = compile_with_node(nodes, node_id)?;
+    Ok(structure::descendants(&serialized, node))
+}
+
+/// Number of directed paths between two nodes as `{ count, overflow }`.
+#[wasm_bindgen]
+#[allow(clippy::missing_errors_doc)]
+pub fn count_paths(nodes: JsValue, from: &str, to: &str) -> Result<JsValue, JsValue> {
+    let nodes = deserialize_nodes(nodes)?;
+    let serialized = serialize::serialize_network(&nodes)
+        .map_err(|e| JsValue::from_str(&format!("Serialization failed: {e}")))?;
+    let index_of =
+        |role, id| lookup::resolve(&serialized, role, id).map_err(|e| not_found_error(&e));
+    let paths = structure::count_paths(&serialized, index_of("From", from)?, index_of("To", to)?);
+    serde_wasm_bindgen::to_value(&paths)
+        .map_err(|e| JsValue::from_str(&format!("Failed to serialize result: {e}")))
+}
+
+/// Why `x` and `y` are or aren't d-separated by `evidence_ids`: `{
+/// dSeparated, trails: [{ path, blocked, blockingEvidence, closedColliders
+/// }], truncated }`, listing at most 1000 trails.
+#[wasm_bindgen]
+#[allow(clippy::missing_errors_doc, clippy::needless_pass_by_value)]
+pub fn explain_d_separation(
+    nodes: JsValue,
+    x: &str,
+    y: &str,
+    // wasm-bindgen cannot take a `&[String]`.
+    evidence_ids: Vec<String>,
+) -> Result<JsValue, JsValue> {
+    let nodes = deserialize_nodes(nodes)?;
+    let serialized = serialize::serialize_network(&nodes)
+        .map_err(|e| JsValue::from_str(&format!("Serialization failed: {e}")))?;
+    let resolve =
+        |role, id: &str| lookup::resolve(&serialized, role, id).map_err(|e| not_found_error(&e));
+    let evidence = evidence_ids
+        .iter()
+        .map(|id| resolve("Evidence", id))
+        .collect::<Result<Vec<_>, _>>()?;
+    let explanation =
+        structure::explain_d_separation(&serialized, resolve("X", x)?, resolve("Y", y)?, &evidence);
+    serde_wasm_bindgen::to_value(&explanation)
+        .map_err(|e| JsValue::from_str(&format!("Failed to serialize result: {e}")))
+}
+
+/// IDs of the Markov blanket of `node_id`, in topological order.
+#[wasm_bindgen]
+#[allow(clippy::missing_errors_doc)]
+pub fn markov_blanket(nodes: JsValue, node_id: &str) -> Result<Vec<String>, JsValue> {
+    let (serialized, node) = compile_with_node(nodes, node_id)?;
+    Ok(structure::markov_blanket(&serialized, node))
+}
+
+/// Why inference on `nodes` may be slow: `{ averageMarkovBlanketSize,
+/// maxMarkovBlanketSize, markovBlanketSizeDistribution, treewidthEstimate,
+/// totalCptEntries }`.
+#[wasm_bindgen]
+#[allow(clippy::missing_errors_doc)]
+pub fn get_network_complexity_metrics(nodes: JsValue) -> Result<JsValue, JsValue> {
+    let nodes = deserialize_nodes(nodes)?;
+    let serialized = serialize::serialize_network(&nodes)
+        .map_err(|e| JsValue::from_str(&format!("Serialization failed: {e}")))?;
+    let metrics = structure::complexity_metrics(&nodes, &serialized);
+    serde_wasm_bindgen::to_value(&metrics)
+        .map_err(|e| JsValue::from_str(&format!("Failed to serialize result: {e}")))
+}
+
+/// `{ topoOrder, idToIndex, fingerprint }`: the bit position of every node
+/// in compiled samples, and the layout fingerprint raw sample exports carry.
+/// The positions shift when an edit changes the topological order, and the
+/// fingerprint with them.
+#[wasm_bindgen]
+#[allow(clippy::missing_errors_doc)]
+pub fn index_map(nodes: JsValue) -> Result<JsValue, JsValue> {
+    let nodes = deserialize_nodes(nodes)?;
+    let serialized = serialize::serialize_network(&nodes)
+        .map_err(|e| JsValue::from_str(&format!("Serialization failed: {e}")))?;
+    serialized
+        .index_map()
+        .serialize(&serde_wasm_bindgen::Serializer::new().serialize_maps_as_objects(true))
+        .map_err(|e| JsValue::from_str(&format!("Failed to serialize result: {e}")))
+}
+
+fn compile_with_node(
+    nodes: JsValue,
+    node_id: &str,
+) -> Result<(serialize::SerializedNetwork, u8), JsValue> {
+    let nodes = deserialize_nodes(nodes)?;
+    let serialized = serialize::serialize_network(&nodes)
+        .map_err(|e| JsValue::from_str(&format!("Serialization failed: {e}")))?;
+    let node = lookup::resolve(&serialized, "Query", node_id).map_err(|e| not_found_error(&e))?;
+    Ok((serialized, node))
+}
+
+fn checked_count(field: &'static str, value: f64, max: usize) -> Result<usize, JsValue> {
+    limits::count(field, value, max).map_err(limit_error)
+}
+
+/// Rejected numeric arguments and exceeded budgets are thrown as `Error`s
+/// carrying `code` (see
+/// [`limits::ErrorCode`]) and `field`, so callers can react without parsing
+/// the message.
+fn limit_error(error: limits::LimitError) -> JsValue {
+    let limits::LimitError {
+        code,
+        field,
+        message,
+    } = error;
+    let js_error = js_sys::Error::new(&message);
+    for (key, value) in [("code", code.as_str()), ("field", field)] {
+        // Setting a property on a fresh `Error` cannot fail.
+        let _ = js_sys::Reflect::set(
+            &js_error,
+            &JsValue::from_str(key),
+            &JsValue::from_str(value),
+        );
+    }
+    js_error.into()
+}
+
+/// Unknown node IDs are thrown as `Error`s with `code` `"NODE_NOT_FOUND"`,
+/// `field` naming the role the node was given for, and
+/// `details: { nodeId, suggestions }` listing the IDs it may have meant.
+fn not_found_error(error: &lookup::NodeNotFound) -> JsValue {
+    let js_error = js_sys::Error::new(&error.to_string());
+    let details = js_sys::Object::new();
+    let suggestions: js_sys::Array = error
+        .suggestions
+        .iter()
+        .map(|suggestion| JsValue::from_str(suggestion))
+        .collect();
+    // Setting a property on a fresh object cannot fail.
+    let _ = js_sys::Reflect::set(&details, &"nodeId".into(), &error.node_id.as_str().into());
+    let _ = js_sys::Reflect::set(&details, &"suggestions".into(), &suggestions);
+    for (key, value) in [
+        ("code", JsValue::from_str("NODE_NOT_FOUND")),
+        ("field", JsValue::from_str(&error.role.to_lowercase())),
+        ("details", details.into()),
+    ] {
+        let _ = js_sys::Reflect::set(&js_error, &JsValue::from_str(key), &value);
+    }
+    js_error.into()
+}
+
+/// Raw samples drawn under another topological order are thrown as `Error`s
+/// with `code` `"LAYOUT_MISMATCH"` and `details: { expected, found }` giving
+/// both layout fingerprints.
+fn layout_mismatch_error(error: &raw_samples::LayoutMismatch) -> JsValue {
+    let js_error = js_sys::Error::new(&error.to_string());
+    let details = js_sys::Object::new();
+    // Setting a property on a fresh object cannot fail.
+    let _ = js_sys::Reflect::set(
+        &details,
+        &"expected".into(),
+        &error.expected.as_str().into(),
+    );
+    let _ = js_sys::Reflect::set(&details, &"found".into(), &error.found.as_str().into());
+    for (key, value) in [
+        ("code", JsValue::from_str("LAYOUT_MISMATCH")),
+        ("details", details.into()),
+    ] {
+        let _ = js_sys::Reflect::set(&js_error, &JsValue::from_str(key), &value);
+    }
+    js_error.into()
+}
+
+/// An assumption set rejected by `AssumptionSet::resolve`, structured like
+/// [`node_error`] when it names an unknown node.
+fn invalid_assumptions(error: &anyhow::Error) -> JsValue {
+    match error.downcast_ref::<lookup::NodeNotFound>() {
+        Some(not_found) => not_found_error(not_found),
+        None => JsValue::from_str(&format!("Invalid assumptions: {error}")),
+    }
+}
+
+/// An internal error as thrown to JS: structured when it is an unknown node
+/// ID, otherwise its message.
+fn node_error(error: &anyhow::Error) -> JsValue {
+    match error.downcast_ref::<lookup::NodeNotFound>() {
+        Some(not_found) => not_found_error(not_found),
+        None => JsValue::from_str(&error.to_string()),
+    }
+}
+
+fn rng_from_seed(seed: Option<u64>) -> Result<(u64, Xoshiro128Plus), JsValue> {
+    let seed = if let Some(seed) = seed {
+        seed
+    } else {
+        let mut bytes = [0u8; 8];
+        getrandom::fill(&mut bytes)
+            .map_err(|e| JsValue::from_str(&format!("RNG seed failed: {e}")))?;
+        u64::from_le_bytes(bytes) & MAX_SEED
+    };
+    Ok((seed, Xoshiro128Plus::seed_from_u64(seed)))
+}
+
+fn seeded_rng() -> Result<Xoshiro128Plus, JsValue> {
+    let mut seed = [0u8; 16];
+    getrandom::fill(&mut seed).map_err(|e| JsValue::from_str(&format!("RNG seed failed: {e}")))?;
+    Ok(Xoshiro128Plus::from_seed(seed))
+}
+
+fn deserialize_nodes(nodes: JsValue) -> Result<Vec<Node>, JsValue> {
+    let nodes = serde_wasm_bindgen::from_value(nodes)
+        .map_err(|e| JsValue::from_str(&format!("Failed to deserialize nodes: {e}")))?;
+    expand_tables(nodes)
+}
+
+fn expand_tables(mut nodes: Vec<Node>) -> Result<Vec<Node>, JsValue> {
+    for node in &mut nodes {
+        cpt_table::expand_table(node)
+            .map_err(|e| JsValue::from_str(&format!("Failed to deserialize nodes: {e}")))?;
+    }
+    Ok(nodes)
+}
+
+fn from_json<T: serde::de::DeserializeOwned>(json: &str, root: &str) -> Result<T, JsValue> {
+    let deserializer = &mut serde_json::Deserializer::from_str(json);
+    serde_path_to_error::deserialize(deserializer).map_err(|e| {
+        let path = e.path().to_string();
+        let location = match path.as_str() {
+            "." => root.to_string(),
+            path if path.starts_with('[') => format!("{root}{path}"),
+            path => format!("{root}.{path}"),
+        };
+        JsValue::from_str(&format!(
+            "Failed to parse {root} JSON at {location}: {error}",
+            error = e.inner()
+        ))
+    })
+}
+
+/// Nodes go back to JS with plain-object parent states and `null` wildcards,
+/// matching the shape they are accepted in.
+fn serialize_nodes(nodes: &[Node]) -> Result<JsValue, JsValue> {
+    nodes
+        .serialize(
+            &serde_wasm_bindgen::Serializer::new()
+                .serialize_maps_as_objects(true)
+                .serialize_missing_as_null(true),
+        )
+        .map_err(|e| JsValue::from_str(&format!("Failed to serialize nodes: {e}")))
+}
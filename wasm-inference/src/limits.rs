@@ -0,0 +1,144 @@
+//! Validation of numeric arguments arriving from JS, and memory budgets.
+//!
+//! JS numbers are doubles, so counts and seeds are taken as `f64` and checked
+//! here. Letting wasm-bindgen convert them to integers instead throws opaque
+//! errors for negatives and silently truncates fractions and huge values.
+
+/// Most samples a single query may draw.
+pub const MAX_SAMPLES: usize = 10_000_000;
+
+/// Most rows a generated dataset may have.
+pub const MAX_ROWS: usize = 1_000_000;
+
+/// Most samples a query may keep in memory at once, 32 bytes each.
+pub const MAX_STORED_SAMPLES: usize = 1_000_000;
+
+/// Most iterations, steps or chains a single query may run.
+pub const MAX_STEPS: usize = 100_000;
+
+/// Seeds are kept within `Number.MAX_SAFE_INTEGER` so they round-trip through JS.
+pub const MAX_SEED: u64 = (1 << 53) - 1;
+
+/// Largest memory budget accepted, in bytes: all of wasm32's address space.
+pub const MAX_MEMORY_BUDGET: usize = u32::MAX as usize;
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum ErrorCode {
+    /// NaN or an infinity.
+    NotANumber,
+    /// Has a fractional part.
+    NotAnInteger,
+    /// An integer outside the accepted range.
+    OutOfRange,
+    /// Storing more would exceed the caller's memory budget.
+    MemoryBudgetExceeded,
+}
+
+impl ErrorCode {
+    pub fn as_str(self) -> &'static str {
+        match self {
+            ErrorCode::NotANumber => "NOT_A_NUMBER",
+            ErrorCode::NotAnInteger => "NOT_AN_INTEGER",
+            ErrorCode::OutOfRange => "OUT_OF_RANGE",
+            ErrorCode::MemoryBudgetExceeded => "MEMORY_BUDGET_EXCEEDED",
+        }
+    }
+}
+
+#[derive(Debug)]
+pub struct LimitError {
+    pub code: ErrorCode,
+    /// Name of the argument or option at fault, as JS callers spell it.
+    pub field: &'static str,
+    pub message: String,
+}
+
+/// Checks that `value` is an integer in `[1, max]`.
+pub fn count(field: &'static str, value: f64, max: usize) -> Result<usize, LimitError> {
+    #[allow(clippy::cast_precision_loss)]
+    let value = integer(field, value, 1.0, max as f64)?;
+    #[allow(clippy::cast_possible_truncation, clippy::cast_sign_loss)]
+    Ok(value as usize)
+}
+
+/// Checks that `value` is an integer in `[0, MAX_SEED]`.
+pub fn seed(field: &'static str, value: f64) -> Result<u64, LimitError> {
+    #[allow(clippy::cast_precision_loss)]
+    let value = integer(field, value, 0.0, MAX_SEED as f64)?;
+    #[allow(clippy::cast_possible_truncation, clippy::cast_sign_loss)]
+    Ok(value as u64)
+}
+
+/// Checks that `value` is an integer position among `len` items, `[0, len)`.
+pub fn index(field: &'static str, value: f64, len: usize) -> Result<usize, LimitError> {
+    #[allow(clippy::cast_precision_loss)]
+    let value = integer(field, value, 0.0, len as f64 - 1.0)?;
+    #[allow(clippy::cast_possible_truncation, clippy::cast_sign_loss)]
+    Ok(value as usize)
+}
+
+/// Checks that `value` is a finite, non-negative amount such as a duration.
+pub fn non_negative(field: &'static str, value: f64) -> Result<f64, LimitError> {
+    if !value.is_finite() {
+        return Err(LimitError {
+            code: ErrorCode::NotANumber,
+            field,
+            message: format!("{field} must be a finite number, got {value}"),
+        });
+    }
+    if value < 0.0 {
+        return Err(LimitError {
+            code: ErrorCode::OutOfRange,
+            field,
+            message: format!("{field} must not be negative, got {value}"),
+        });
+    }
+    Ok(value)
+}
+
+fn integer(field: &'static str, value: f64, min: f64, max: f64) -> Result<f64, LimitError> {
+    let error = |code, message| LimitError {
+        code,
+        field,
+        message,
+    };
+    if !value.is_finite() {
+        return Err(error(
+            ErrorCode::NotANumber,
+            format!("{field} must be a finite number, got {value}"),
+        ));
+    }
+    if value.fract() != 0.0 {
+        return Err(error(
+            ErrorCode::NotAnInteger,
+            format!("{field} must be an integer, got {value}"),
+        ));
+    }
+    if !(min..=max).contains(&value) {
+        return Err(error(
+            ErrorCode::OutOfRange,
+            format!("{field} must be between {min} and {max}, got {value}"),
+        ));
+    }
+    Ok(value)
+}
+
+/// Checks that `additional` more bytes fit alongside `used` within `budget`.
+pub fn reserve(
+    field: &'static str,
+    used: usize,
+    additional: usize,
+    budget: usize,
+) -> Result<(), LimitError> {
+    if used.saturating_add(additional) > budget {
+        return Err(LimitError {
+            code: ErrorCode::MemoryBudgetExceeded,
+            field,
+            message: format!(
+                "Storing {additional} more bytes would exceed the {field} of {budget} bytes \
+                 ({used} in use)"
+            ),
+        });
+    }
+    Ok(())
+}
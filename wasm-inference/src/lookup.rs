@@ -0,0 +1,115 @@
+//! Node ID resolution with suggestions for near misses, which are nearly
+//! always a stray space, a case mismatch, or a typo between the editor and
+//! the payload.
+
+use std::fmt;
+
+use unicode_normalization::UnicodeNormalization;
+
+use crate::Node;
+use crate::serialize::SerializedNetwork;
+
+/// Most suggestions offered for one missing ID.
+const MAX_SUGGESTIONS: usize = 3;
+
+/// Key under which IDs differing only by case or Unicode normalization
+/// collide.
+pub(crate) fn relaxed_id(id: &str) -> String {
+    id.nfc().collect::<String>().to_lowercase().nfc().collect()
+}
+
+/// Candidates close to `id`, closest first: those equal once trimmed and
+/// compared by [`relaxed_id`], then those within a small edit distance of
+/// it, counted in characters.
+pub(crate) fn suggestions<'a>(
+    id: &str,
+    candidates: impl IntoIterator<Item = &'a str>,
+) -> Vec<String> {
+    let key: Vec<char> = relaxed_id(id.trim()).chars().collect();
+    let max_distance = (key.len() / 4).clamp(1, 3);
+    let mut close: Vec<(usize, &str)> = candidates
+        .into_iter()
+        .filter(|&candidate| candidate != id)
+        .filter_map(|candidate| {
+            let other: Vec<char> = relaxed_id(candidate.trim()).chars().collect();
+            let distance = edit_distance(&key, &other);
+            (distance <= max_distance).then_some((distance, candidate))
+        })
+        .collect();
+    close.sort_by_key(|&(distance, candidate)| (distance, candidate));
+    close
+        .into_iter()
+        .take(MAX_SUGGESTIONS)
+        .map(|(_, candidate)| candidate.to_string())
+        .collect()
+}
+
+/// Levenshtein distance over characters.
+fn edit_distance(a: &[char], b: &[char]) -> usize {
+    let mut previous: Vec<usize> = (0..=b.len()).collect();
+    for (i, &x) in a.iter().enumerate() {
+        let mut current = vec![i + 1; b.len() + 1];
+        for (j, &y) in b.iter().enumerate() {
+            current[j + 1] = (previous[j] + usize::from(x != y))
+                .min(previous[j + 1] + 1)
+                .min(current[j] + 1);
+        }
+        previous = current;
+    }
+    previous[b.len()]
+}
+
+/// A node ID that isn't in the network, with the IDs it may have meant.
+#[derive(Debug)]
+pub(crate) struct NodeNotFound {
+    /// What the node was for, as in "Intervention node X not found".
+    pub role: &'static str,
+    pub node_id: String,
+    pub suggestions: Vec<String>,
+}
+
+impl fmt::Display for NodeNotFound {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "{} node {} not found", self.role, self.node_id)?;
+        if !self.suggestions.is_empty() {
+            let quoted: Vec<String> = self
+                .suggestions
+                .iter()
+                .map(|suggestion| format!("'{suggestion}'"))
+                .collect();
+            write!(f, "; did you mean {}?", quoted.join(" or "))?;
+        }
+        Ok(())
+    }
+}
+
+impl std::error::Error for NodeNotFound {}
+
+/// The node with ID `node_id`, or the IDs it may have meant.
+pub(crate) fn find_node<'a>(
+    nodes: &'a [Node],
+    role: &'static str,
+    node_id: &str,
+) -> Result<&'a Node, NodeNotFound> {
+    nodes
+        .iter()
+        .find(|node| node.id == node_id)
+        .ok_or_else(|| NodeNotFound {
+            role,
+            node_id: node_id.to_string(),
+            suggestions: suggestions(node_id, nodes.iter().map(|node| node.id.as_str())),
+        })
+}
+
+/// The topological index of `node_id`, or the IDs it may have meant.
+pub(crate) fn resolve(
+    serialized: &SerializedNetwork,
+    role: &'static str,
+    node_id: &str,
+) -> Result<u8, NodeNotFound> {
+    serialized.index_of(node_id).ok_or_else(|| NodeNotFound {
+        role,
+        node_id: node_id.to_string(),
+        suggestions: suggestions(node_id, serialized.topo_order.iter().map(String::as_str)),
+    })
+}
@@ -0,0 +1,553 @@
+use anyhow::{Result, anyhow, bail};
+use rand_xoshiro::Xoshiro128Plus;
+use serde::{Deserialize, Serialize};
+use std::collections::HashMap;
+
+use crate::bit_set::BitSet;
+use crate::sample::{self, MixedEvidence, Override};
+use crate::serialize::SerializedNetwork;
+
+/// Monte Carlo estimate of `P(node = true)` for every node.
+///
+/// Samples that contradict `evidence` are rejected, so with evidence the
+/// result is the posterior marginal.
+pub(crate) fn estimate_marginals(
+    serialized: &SerializedNetwork,
+    num_samples: usize,
+    overrides: &[Option<Override>],
+    evidence: &[(u8, bool)],
+    rng: &mut Xoshiro128Plus,
+) -> Result<HashMap<String, f64>> {
+    estimate_marginals_by(serialized, num_samples, evidence, || {
+        sample::sample(&serialized.data, &serialized.offsets, overrides, rng)
+    })
+}
+
+/// [`estimate_marginals`] over samples from `draw`, for samplers that don't
+/// draw every node from its CPT.
+pub(crate) fn estimate_marginals_by(
+    serialized: &SerializedNetwork,
+    num_samples: usize,
+    evidence: &[(u8, bool)],
+    mut draw: impl FnMut() -> Result<BitSet>,
+) -> Result<HashMap<String, f64>> {
+    let num_nodes = serialized.num_nodes();
+    let mut node_true_counts = vec![0usize; usize::from(num_nodes)];
+    let mut accepted = 0usize;
+
+    for _ in 0..num_samples {
+        let sample_result = draw().map_err(|e| anyhow!("Sampling failed: {e}"))?;
+
+        if evidence
+            .iter()
+            .any(|&(node, value)| sample_result.contains(node) != value)
+        {
+            continue;
+        }
+        accepted += 1;
+
+        for node_idx in 0..num_nodes {
+            if sample_result.contains(node_idx) {
+                node_true_counts[usize::from(node_idx)] += 1;
+            }
+        }
+    }
+
+    if !evidence.is_empty() && accepted == 0 {
+        bail!("None of the {num_samples} samples were consistent with the evidence");
+    }
+
+    #[allow(clippy::cast_precision_loss)]
+    let probabilities = serialized
+        .topo_order
+        .iter()
+        .cloned()
+        .zip(node_true_counts)
+        .map(|(node_id, count)| {
+            let probability = count as f64 / accepted as f64;
+            (node_id, probability)
+        })
+        .collect();
+
+    Ok(probabilities)
+}
+
+/// Likelihood-weighting estimate of the posterior marginals: evidence nodes
+/// are fixed rather than rejected, so rare evidence costs no samples.
+pub(crate) fn estimate_marginals_weighted(
+    serialized: &SerializedNetwork,
+    num_samples: usize,
+    overrides: &[Option<Override>],
+    evidence: &[(u8, bool)],
+    rng: &mut Xoshiro128Plus,
+) -> Result<HashMap<String, f64>> {
+    let evidence = MixedEvidence {
+        hard: evidence.to_vec(),
+        soft: Vec::new(),
+    };
+    estimate_marginals_mixed(serialized, num_samples, overrides, &evidence, rng)
+}
+
+/// Likelihood weighting with hard and soft evidence together.
+pub(crate) fn estimate_marginals_mixed(
+    serialized: &SerializedNetwork,
+    num_samples: usize,
+    overrides: &[Option<Override>],
+    evidence: &MixedEvidence,
+    rng: &mut Xoshiro128Plus,
+) -> Result<HashMap<String, f64>> {
+    estimate_marginals_smoothed(
+        serialized,
+        num_samples,
+        overrides,
+        evidence,
+        ZeroHandling::Strict,
+        rng,
+    )
+    .map(|(marginals, _)| marginals)
+}
+
+/// [`estimate_marginals_mixed`] applying `zero_handling` to the weights,
+/// with the number of substitutions made.
+pub(crate) fn estimate_marginals_smoothed(
+    serialized: &SerializedNetwork,
+    num_samples: usize,
+    overrides: &[Option<Override>],
+    evidence: &MixedEvidence,
+    zero_handling: ZeroHandling,
+    rng: &mut Xoshiro128Plus,
+) -> Result<(HashMap<String, f64>, usize)> {
+    let num_nodes = serialized.num_nodes();
+    let observed = evidence.by_node(num_nodes);
+    let epsilon = match zero_handling {
+        ZeroHandling::Strict => None,
+        ZeroHandling::Smooth(epsilon) => Some(epsilon),
+    };
+    let mut node_true_weights = vec![0.0; usize::from(num_nodes)];
+    let mut total_weight = 0.0;
+    let mut substitutions = 0;
+
+    for _ in 0..num_samples {
+        let (sample_result, weight, substituted) = sample::sample_weighted_smoothed(
+            &serialized.data,
+            &serialized.offsets,
+            overrides,
+            &observed,
+            epsilon,
+            rng,
+        )
+        .map_err(|e| anyhow!("Sampling failed: {e}"))?;
+        total_weight += weight;
+        substitutions += substituted;
+        for node_idx in 0..num_nodes {
+            if sample_result.contains(node_idx) {
+                node_true_weights[usize::from(node_idx)] += weight;
+            }
+        }
+    }
+
+    if total_weight == 0.0 {
+        bail!(
+            "All {num_samples} samples had zero weight under the evidence; a CPT giving an \
+             observed value probability 0, or an intervention contradicting an observation, \
+             rules it out (zeroHandling \"smooth(epsilon)\" treats such zeros as unlikely)"
+        );
+    }
+
+    let marginals = serialized
+        .topo_order
+        .iter()
+        .cloned()
+        .zip(node_true_weights)
+        .map(|(node_id, weight)| (node_id, weight / total_weight))
+        .collect();
+    Ok((marginals, substitutions))
+}
+
+/// A covariate's `P(true)` where the network's parameters were learned and
+/// where it is applied.
+pub(crate) struct CovariateShift {
+    pub node: u8,
+    pub training: f64,
+    pub target: f64,
+}
+
+/// Marginals under a deployment population that differs from the training
+/// one only in the covariates' distribution. Forward samples are weighted by
+/// `prod_c P_target(x_c) / P_training(x_c)`, treating the covariates as
+/// independent Bernoullis under both, and the weighted average is taken.
+pub(crate) fn estimate_marginals_reweighted(
+    serialized: &SerializedNetwork,
+    num_samples: usize,
+    covariates: &[CovariateShift],
+    rng: &mut Xoshiro128Plus,
+) -> Result<HashMap<String, f64>> {
+    let num_nodes = serialized.num_nodes();
+    let mut node_true_weights = vec![0.0; usize::from(num_nodes)];
+    let mut total_weight = 0.0;
+    for _ in 0..num_samples {
+        let sample_result = sample::sample(&serialized.data, &serialized.offsets, &[], rng)
+            .map_err(|e| anyhow!("Sampling failed: {e}"))?;
+        let weight: f64 = covariates
+            .iter()
+            .map(|covariate| {
+                if sample_result.contains(covariate.node) {
+                    covariate.target / covariate.training
+                } else {
+                    (1.0 - covariate.target) / (1.0 - covariate.training)
+                }
+            })
+            .product();
+        total_weight += weight;
+        for node_idx in 0..num_nodes {
+            if sample_result.contains(node_idx) {
+                node_true_weights[usize::from(node_idx)] += weight;
+            }
+        }
+    }
+    if total_weight == 0.0 {
+        bail!("All {num_samples} samples had zero weight under the target covariates");
+    }
+    Ok(serialized
+        .topo_order
+        .iter()
+        .cloned()
+        .zip(node_true_weights)
+        .map(|(node_id, weight)| (node_id, weight / total_weight))
+        .collect())
+}
+
+#[derive(Serialize)]
+#[serde(rename_all = "camelCase")]
+pub struct Branch {
+    /// Samples in which the condition node took this branch's value.
+    pub samples: usize,
+    /// NaN for every node when the branch drew no samples.
+    pub marginals: HashMap<String, f64>,
+    /// Binomial standard error of each marginal, over the branch's effective
+    /// sample size when evidence weighs the samples.
+    pub standard_errors: HashMap<String, f64>,
+}
+
+#[derive(Serialize)]
+#[serde(rename_all = "camelCase")]
+pub struct ConditionalSplit {
+    pub condition_probability: f64,
+    pub when_true: Branch,
+    pub when_false: Branch,
+}
+
+/// Samples, weight, squared weight and per-node true weight of one branch.
+struct BranchTotals {
+    samples: usize,
+    weight: f64,
+    weight_squared: f64,
+    node_weights: Vec<f64>,
+}
+
+/// Marginals conditioned on each value of `condition`, from one pass of
+/// samples split by the value it drew. Rare branches show up as few
+/// `samples` and wide standard errors rather than being reweighted.
+///
+/// `evidence` is weighed in by likelihood weighting; its `None` values are
+/// nodes whose observation is missing, sampled freely. Without observed
+/// values every weight is 1 and this is plain forward sampling.
+pub(crate) fn split_by_node(
+    serialized: &SerializedNetwork,
+    num_samples: usize,
+    condition: u8,
+    evidence: &[(u8, Option<bool>)],
+    rng: &mut Xoshiro128Plus,
+) -> Result<ConditionalSplit> {
+    if evidence
+        .iter()
+        .any(|&(node, value)| node == condition && value.is_some())
+    {
+        bail!(
+            "Condition node {} is also observed, so one branch is empty",
+            serialized.topo_order[usize::from(condition)]
+        );
+    }
+    let num_nodes = serialized.num_nodes();
+    let totals = || BranchTotals {
+        samples: 0,
+        weight: 0.0,
+        weight_squared: 0.0,
+        node_weights: vec![0.0; usize::from(num_nodes)],
+    };
+    // Per branch (false, true).
+    let mut branches = [totals(), totals()];
+    for _ in 0..num_samples {
+        let (sample_result, weight) = sample::sample_with_partial_evidence(
+            &serialized.data,
+            &serialized.offsets,
+            evidence,
+            rng,
+        )
+        .map_err(|e| anyhow!("Sampling failed: {e}"))?;
+        let branch = &mut branches[usize::from(sample_result.contains(condition))];
+        branch.samples += 1;
+        branch.weight += weight;
+        branch.weight_squared += weight * weight;
+        for (node, node_weight) in (0..num_nodes).zip(branch.node_weights.iter_mut()) {
+            if sample_result.contains(node) {
+                *node_weight += weight;
+            }
+        }
+    }
+    let total_weight = branches[0].weight + branches[1].weight;
+    if total_weight == 0.0 {
+        bail!("All {num_samples} samples had zero weight under the evidence");
+    }
+
+    let branch = |totals: BranchTotals| {
+        let n = totals.weight * totals.weight / totals.weight_squared;
+        let probabilities: Vec<f64> = totals
+            .node_weights
+            .iter()
+            .map(|&weight| weight / totals.weight)
+            .collect();
+        let by_id = |values: &mut dyn Iterator<Item = f64>| -> HashMap<String, f64> {
+            serialized.topo_order.iter().cloned().zip(values).collect()
+        };
+        Branch {
+            samples: totals.samples,
+            standard_errors: by_id(&mut probabilities.iter().map(|p| (p * (1.0 - p) / n).sqrt())),
+            marginals: by_id(&mut probabilities.into_iter()),
+        }
+    };
+    let [when_false, when_true] = branches;
+    Ok(ConditionalSplit {
+        condition_probability: when_true.weight / total_weight,
+        when_true: branch(when_true),
+        when_false: branch(when_false),
+    })
+}
+
+/// Number of samples in the pilot run that estimates the acceptance rate.
+const PILOT_SAMPLES: usize = 2000;
+/// Below this estimated acceptance rate, `Auto` switches to likelihood
+/// weighting: rejection would keep fewer than 1 in 20 samples.
+pub(crate) const AUTO_ACCEPTANCE_THRESHOLD: f64 = 0.05;
+
+#[derive(Clone, Copy, Default, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub enum Algorithm {
+    /// Rejection sampling unless a pilot run finds the evidence too rare.
+    #[default]
+    Auto,
+    Rejection,
+    LikelihoodWeighting,
+}
+
+/// What likelihood weighting does with an evidence node whose CPT gives the
+/// observed value probability exactly 0. Written `"strict"` or
+/// `"smooth(epsilon)"`.
+#[derive(Clone, Copy, Debug, Default, PartialEq, Serialize, Deserialize)]
+#[serde(try_from = "String", into = "String")]
+pub enum ZeroHandling {
+    /// The sample gets weight 0.
+    #[default]
+    Strict,
+    /// The sample is weighted by `epsilon` instead, for the query only.
+    Smooth(f64),
+}
+
+impl TryFrom<String> for ZeroHandling {
+    type Error = String;
+
+    fn try_from(text: String) -> Result<Self, String> {
+        if text == "strict" {
+            return Ok(Self::Strict);
+        }
+        let epsilon = text
+            .strip_prefix("smooth(")
+            .and_then(|rest| rest.strip_suffix(')'))
+            .ok_or_else(|| {
+                format!("zeroHandling must be \"strict\" or \"smooth(epsilon)\", got {text:?}")
+            })?;
+        match epsilon.trim().parse::<f64>() {
+            Ok(epsilon) if epsilon > 0.0 && epsilon < 1.0 => Ok(Self::Smooth(epsilon)),
+            _ => Err(format!(
+                "zeroHandling epsilon must be a number in (0, 1), got {epsilon:?}"
+            )),
+        }
+    }
+}
+
+impl From<ZeroHandling> for String {
+    fn from(zero_handling: ZeroHandling) -> Self {
+        match zero_handling {
+            ZeroHandling::Strict => "strict".to_string(),
+            ZeroHandling::Smooth(epsilon) => format!("smooth({epsilon})"),
+        }
+    }
+}
+
+/// How a query was answered, so users can see why an algorithm was chosen.
+#[derive(Serialize)]
+#[serde(rename_all = "camelCase")]
+pub struct QueryMeta {
+    pub algorithm: Algorithm,
+    /// Fraction of pilot samples consistent with the evidence; only set when
+    /// `Auto` ran a pilot.
+    pub pilot_acceptance: Option<f64>,
+    /// Nodes conditioned on an observed value, in topological order.
+    pub hard_evidence: Vec<String>,
+    /// Nodes weighted by a likelihood ratio, in topological order.
+    pub soft_evidence: Vec<String>,
+    /// Zero likelihoods replaced by epsilon over all samples; only set under
+    /// `ZeroHandling::Smooth`.
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub zero_substitutions: Option<usize>,
+}
+
+/// Estimates marginals with `algorithm`, resolving `Auto` first.
+///
+/// The pilot runs on a copy of the generator, so when rejection sampling is
+/// chosen the estimate is identical to requesting it directly.
+pub(crate) fn estimate_marginals_with(
+    algorithm: Algorithm,
+    serialized: &SerializedNetwork,
+    num_samples: usize,
+    overrides: &[Option<Override>],
+    evidence: &[(u8, bool)],
+    rng: &mut Xoshiro128Plus,
+) -> Result<(HashMap<String, f64>, QueryMeta)> {
+    estimate_marginals_with_soft(
+        algorithm,
+        serialized,
+        num_samples,
+        overrides,
+        evidence,
+        &[],
+        ZeroHandling::Strict,
+        rng,
+    )
+}
+
+/// [`estimate_marginals_with`] plus soft evidence and smoothing of zero
+/// likelihoods, which only likelihood weighting can apply: `Auto` then skips
+/// the pilot, and `Rejection` is an error.
+#[allow(clippy::too_many_arguments)]
+pub(crate) fn estimate_marginals_with_soft(
+    algorithm: Algorithm,
+    serialized: &SerializedNetwork,
+    num_samples: usize,
+    overrides: &[Option<Override>],
+    evidence: &[(u8, bool)],
+    soft_evidence: &[(u8, f64)],
+    zero_handling: ZeroHandling,
+    rng: &mut Xoshiro128Plus,
+) -> Result<(HashMap<String, f64>, QueryMeta)> {
+    if let Some(&(node, _)) = soft_evidence
+        .iter()
+        .find(|&&(node, _)| evidence.iter().any(|&(observed, _)| observed == node))
+    {
+        bail!(
+            "Node {} has both hard and soft evidence",
+            serialized.topo_order[usize::from(node)]
+        );
+    }
+    let ids = |nodes: &mut dyn Iterator<Item = u8>| -> Vec<String> {
+        let mut nodes: Vec<u8> = nodes.collect();
+        nodes.sort_unstable();
+        nodes
+            .into_iter()
+            .map(|node| serialized.topo_order[usize::from(node)].clone())
+            .collect()
+    };
+    let hard_evidence = ids(&mut evidence.iter().map(|&(node, _)| node));
+    let smooth = zero_handling != ZeroHandling::Strict;
+    if !soft_evidence.is_empty() || smooth {
+        if algorithm == Algorithm::Rejection {
+            let what = if smooth {
+                "Smoothing zero probabilities"
+            } else {
+                "Soft evidence"
+            };
+            bail!("{what} needs likelihood weighting; rejection sampling cannot apply it");
+        }
+        let mixed = MixedEvidence {
+            hard: evidence.to_vec(),
+            soft: soft_evidence.to_vec(),
+        };
+        let (marginals, substitutions) = estimate_marginals_smoothed(
+            serialized,
+            num_samples,
+            overrides,
+            &mixed,
+            zero_handling,
+            rng,
+        )?;
+        return Ok((
+            marginals,
+            QueryMeta {
+                algorithm: Algorithm::LikelihoodWeighting,
+                pilot_acceptance: None,
+                hard_evidence,
+                soft_evidence: ids(&mut soft_evidence.iter().map(|&(node, _)| node)),
+                zero_substitutions: smooth.then_some(substitutions),
+            },
+        ));
+    }
+
+    let mut pilot_acceptance = None;
+    let algorithm = match algorithm {
+        Algorithm::Auto if evidence.is_empty() => Algorithm::Rejection,
+        Algorithm::Auto => {
+            let acceptance =
+                pilot_acceptance_rate(serialized, overrides, evidence, &mut rng.clone())?;
+            pilot_acceptance = Some(acceptance);
+            if acceptance < AUTO_ACCEPTANCE_THRESHOLD {
+                Algorithm::LikelihoodWeighting
+            } else {
+                Algorithm::Rejection
+            }
+        }
+        chosen => chosen,
+    };
+    let marginals = if algorithm == Algorithm::LikelihoodWeighting {
+        estimate_marginals_weighted(serialized, num_samples, overrides, evidence, rng)?
+    } else {
+        estimate_marginals(serialized, num_samples, overrides, evidence, rng)?
+    };
+    Ok((
+        marginals,
+        QueryMeta {
+            algorithm,
+            pilot_acceptance,
+            hard_evidence,
+            soft_evidence: Vec::new(),
+            zero_substitutions: None,
+        },
+    ))
+}
+
+fn pilot_acceptance_rate(
+    serialized: &SerializedNetwork,
+    overrides: &[Option<Override>],
+    evidence: &[(u8, bool)],
+    rng: &mut Xoshiro128Plus,
+) -> Result<f64> {
+    let mut accepted = 0usize;
+    for _ in 0..PILOT_SAMPLES {
+        let sample_result = sample::sample(&serialized.data, &serialized.offsets, overrides, rng)
+            .map_err(|e| anyhow!("Sampling failed: {e}"))?;
+        if evidence
+            .iter()
+            .all(|&(node, value)| sample_result.contains(node) == value)
+        {
+            accepted += 1;
+        }
+    }
+    #[allow(clippy::cast_precision_loss)]
+    Ok(accepted as f64 / PILOT_SAMPLES as f64)
+}
+
+/// Overrides that apply a single hard intervention.
+pub(crate) fn intervention(num_nodes: u8, on_node: u8, value: bool) -> Vec<Option<Override>> {
+    let mut overrides = vec![None; usize::from(num_nodes)];
+    overrides[usize::from(on_node)] = Some(Override::Value(value));
+    overrides
+}
@@ -0,0 +1,118 @@
+use anyhow::{Result, bail};
+use serde::Deserialize;
+use std::collections::{BTreeMap, HashMap};
+
+use crate::Node;
+use crate::assumptions::AssumptionSet;
+use crate::constraints::Constraint;
+use crate::copula::RootCorrelations;
+use crate::limits::{self, LimitError, MAX_SAMPLES};
+use crate::lookup::resolve;
+use crate::marginals::{Algorithm, ZeroHandling};
+use crate::serialize::SerializedNetwork;
+
+/// Options accepted by the options-based query entry points.
+#[derive(Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub struct QueryOptions {
+    /// Taken as a JS number and checked by [`QueryOptions::num_samples`].
+    pub num_samples: f64,
+    /// Seed for the sampler; a random one is chosen (and reported) when absent.
+    /// A seed reproduces results within a minor version; see `rng_trace`.
+    #[serde(default)]
+    pub seed: Option<f64>,
+    #[serde(default)]
+    pub assumptions: AssumptionSet,
+    /// Nodes the caller is asking about, used by diagnostics to decide which
+    /// part of the network to focus on. Empty means every node.
+    #[serde(default)]
+    pub targets: Vec<String>,
+    /// How evidence is handled; `auto` picks likelihood weighting when a
+    /// pilot run finds the evidence too rare for rejection sampling.
+    #[serde(default)]
+    pub algorithm: Algorithm,
+    #[serde(default)]
+    pub key_by: KeyBy,
+    /// Uncertain observations: node ID to the likelihood ratio
+    /// `P(observation | true) / P(observation | false)`. Answered by
+    /// likelihood weighting.
+    #[serde(default)]
+    pub soft_evidence: BTreeMap<String, f64>,
+    /// Draws these root nodes jointly through a Gaussian copula instead of
+    /// independently. Each keeps its own prior, but the network no longer
+    /// means what a Bayesian network strictly does: the roots are dependent
+    /// with no node explaining why. Needs rejection sampling.
+    #[serde(default)]
+    pub root_correlations: Option<RootCorrelations>,
+    /// Enforced by rejecting every sample that breaks one, with the fraction
+    /// rejected reported as `constraintViolationRate`. Needs rejection
+    /// sampling.
+    #[serde(default)]
+    pub constraints: Vec<Constraint>,
+    /// Whether a CPT zero on an observed value rules samples out or only
+    /// makes them unlikely. Applies to likelihood weights for this query;
+    /// the model and the drawn values are unchanged.
+    #[serde(default)]
+    pub zero_handling: ZeroHandling,
+}
+
+/// What result maps are keyed by.
+#[derive(Deserialize, Default, Clone, Copy)]
+#[serde(rename_all = "camelCase")]
+pub enum KeyBy {
+    #[default]
+    Id,
+    /// The node's `title`, falling back to `_id` for untitled nodes.
+    Title,
+}
+
+impl KeyBy {
+    /// Re-keys a map keyed by node ID. Fails when two nodes would share a key.
+    pub fn apply<T>(self, nodes: &[Node], by_id: HashMap<String, T>) -> Result<HashMap<String, T>> {
+        let KeyBy::Title = self else {
+            return Ok(by_id);
+        };
+        let titles: HashMap<&str, &str> = nodes
+            .iter()
+            .map(|node| (node.id.as_str(), node.title.as_deref().unwrap_or(&node.id)))
+            .collect();
+        let mut by_title = HashMap::with_capacity(by_id.len());
+        for (id, value) in by_id {
+            let title = titles.get(id.as_str()).copied().unwrap_or(&id).to_string();
+            if by_title.insert(title.clone(), value).is_some() {
+                bail!("More than one node has the title {title}; key results by id instead");
+            }
+        }
+        Ok(by_title)
+    }
+}
+
+impl QueryOptions {
+    pub fn num_samples(&self) -> Result<usize, LimitError> {
+        limits::count("numSamples", self.num_samples, MAX_SAMPLES)
+    }
+
+    pub fn seed(&self) -> Result<Option<u64>, LimitError> {
+        self.seed.map(|seed| limits::seed("seed", seed)).transpose()
+    }
+
+    /// `soft_evidence` by topological index, checking every ratio is positive
+    /// and finite.
+    pub(crate) fn soft_evidence_indices(
+        &self,
+        serialized: &SerializedNetwork,
+    ) -> Result<Vec<(u8, f64)>> {
+        self.soft_evidence
+            .iter()
+            .map(|(node_id, &ratio)| {
+                if !(ratio.is_finite() && ratio > 0.0) {
+                    bail!(
+                        "Likelihood ratio for node {node_id} must be positive and finite, got {ratio}"
+                    );
+                }
+                let index = resolve(serialized, "Soft evidence", node_id)?;
+                Ok((index, ratio))
+            })
+            .collect()
+    }
+}
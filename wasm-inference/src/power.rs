@@ -0,0 +1,174 @@
+//! Sample sizes for a two-arm interventional study planned on the network:
+//! how many units per arm a randomized `do(X)` experiment needs before a
+//! two-sided z-test is likely to detect a given effect on an outcome.
+
+use anyhow::{Result, anyhow, bail};
+use rand::{Rng, SeedableRng};
+use rand_xoshiro::Xoshiro128Plus;
+use serde::Serialize;
+
+use crate::Node;
+use crate::marginals::{estimate_marginals, intervention};
+use crate::serialize::serialize_network;
+use crate::statistics::{inverse_normal_cdf, standard_normal};
+
+const MIN_ARM_SIZE: usize = 100;
+const MAX_ARM_SIZE: usize = 1_000_000;
+/// Network samples behind the control arm's outcome rate.
+const RATE_SAMPLES: usize = 100_000;
+/// Simulated studies per candidate arm size.
+const REPLICATIONS: usize = 2000;
+/// Binomial draws with variance at least this use the normal approximation;
+/// smaller ones are drawn exactly.
+const NORMAL_APPROXIMATION_VARIANCE: f64 = 25.0;
+
+#[derive(Serialize)]
+#[serde(rename_all = "camelCase")]
+pub struct SampleSizePlan {
+    /// Fewest units per arm reaching the target power.
+    pub sample_size_per_arm: usize,
+    /// Simulated power at that size.
+    pub achieved_power: f64,
+    /// `P(outcome | do(treatment = false))` from the network.
+    pub control_rate: f64,
+    /// The control rate plus the expected effect.
+    pub treated_rate: f64,
+}
+
+/// Smallest arm size in `[MIN_ARM_SIZE, MAX_ARM_SIZE]` whose simulated
+/// power reaches `power`, found by bisection.
+///
+/// The network supplies the control arm's outcome rate; the treated arm's is
+/// that plus `expected_ate`. Each candidate size is scored on the same
+/// `REPLICATIONS` simulated studies (common random numbers), each testing the
+/// difference in outcome rates with a pooled two-proportion z-test at level
+/// `alpha`.
+pub fn power_analysis(
+    nodes: &[Node],
+    treatment_id: &str,
+    outcome_id: &str,
+    expected_ate: f64,
+    power: f64,
+    alpha: f64,
+    rng: &mut Xoshiro128Plus,
+) -> Result<SampleSizePlan> {
+    if !(power > 0.0 && power < 1.0) {
+        bail!("power must be within (0, 1), got {power}");
+    }
+    if !(alpha > 0.0 && alpha < 1.0) {
+        bail!("alpha must be within (0, 1), got {alpha}");
+    }
+    if expected_ate == 0.0 || !expected_ate.is_finite() {
+        bail!("The expected effect must be non-zero, got {expected_ate}");
+    }
+    let serialized = serialize_network(nodes)?;
+    let treatment = serialized
+        .index_of(treatment_id)
+        .ok_or_else(|| anyhow!("Treatment node {treatment_id} not found"))?;
+    if serialized.index_of(outcome_id).is_none() {
+        bail!("Outcome node {outcome_id} not found");
+    }
+    let overrides = intervention(serialized.num_nodes(), treatment, false);
+    let control_rate =
+        estimate_marginals(&serialized, RATE_SAMPLES, &overrides, &[], rng)?[outcome_id];
+    let treated_rate = control_rate + expected_ate;
+    if !(0.0..=1.0).contains(&treated_rate) {
+        bail!(
+            "The control rate {control_rate} plus the expected effect {expected_ate} is outside \
+             [0, 1]"
+        );
+    }
+
+    let critical = inverse_normal_cdf(1.0 - alpha / 2.0);
+    let common_seed: u64 = rng.random();
+    let simulated_power = |arm_size| {
+        let mut stream = Xoshiro128Plus::seed_from_u64(common_seed);
+        let rejections = (0..REPLICATIONS)
+            .filter(|_| {
+                let control = binomial(arm_size, control_rate, &mut stream);
+                let treated = binomial(arm_size, treated_rate, &mut stream);
+                z_statistic(control, treated, arm_size).abs() > critical
+            })
+            .count();
+        #[allow(clippy::cast_precision_loss)]
+        let rejection_rate = rejections as f64 / REPLICATIONS as f64;
+        rejection_rate
+    };
+
+    let achieved = simulated_power(MAX_ARM_SIZE);
+    if achieved < power {
+        bail!(
+            "Even {MAX_ARM_SIZE} units per arm only reach power {achieved}; the effect is too \
+             small to detect"
+        );
+    }
+    let (mut low, mut high, mut achieved_power) = (MIN_ARM_SIZE, MAX_ARM_SIZE, achieved);
+    let at_minimum = simulated_power(MIN_ARM_SIZE);
+    if at_minimum >= power {
+        high = MIN_ARM_SIZE;
+        achieved_power = at_minimum;
+    }
+    while high - low > 1 {
+        let mid = low + (high - low) / 2;
+        let mid_power = simulated_power(mid);
+        if mid_power >= power {
+            high = mid;
+            achieved_power = mid_power;
+        } else {
+            low = mid;
+        }
+    }
+    Ok(SampleSizePlan {
+        sample_size_per_arm: high,
+        achieved_power,
+        control_rate,
+        treated_rate,
+    })
+}
+
+/// Pooled two-proportion z-statistic; zero when every unit (or none) had the
+/// outcome, since the test can't reject then.
+#[allow(clippy::cast_precision_loss)]
+fn z_statistic(control: usize, treated: usize, arm_size: usize) -> f64 {
+    let n = arm_size as f64;
+    let pooled = (control + treated) as f64 / (2.0 * n);
+    let standard_error = (pooled * (1.0 - pooled) * 2.0 / n).sqrt();
+    if standard_error == 0.0 {
+        return 0.0;
+    }
+    (treated as f64 - control as f64) / n / standard_error
+}
+
+/// Successes in `n` trials of probability `p`. Small variances are drawn
+/// exactly by skipping geometrically distributed runs of failures, so the
+/// cost grows with the number of successes rather than `n`.
+#[allow(
+    clippy::cast_precision_loss,
+    clippy::cast_possible_truncation,
+    clippy::cast_sign_loss
+)]
+fn binomial(n: usize, p: f64, rng: &mut Xoshiro128Plus) -> usize {
+    if p > 0.5 {
+        return n - binomial(n, 1.0 - p, rng);
+    }
+    if p <= 0.0 {
+        return 0;
+    }
+    let mean = n as f64 * p;
+    let variance = mean * (1.0 - p);
+    if variance >= NORMAL_APPROXIMATION_VARIANCE {
+        let draw = (mean + variance.sqrt() * standard_normal(rng)).round();
+        return draw.clamp(0.0, n as f64) as usize;
+    }
+    let log_failure = (1.0 - p).ln();
+    let mut successes = 0;
+    let mut position = 0.0;
+    loop {
+        let u = 1.0 - rng.random::<f64>();
+        position += (u.ln() / log_failure).floor() + 1.0;
+        if position > n as f64 {
+            return successes;
+        }
+        successes += 1;
+    }
+}
@@ -0,0 +1,201 @@
+//! Periodic snapshots of a running estimate, so long runs can be shown as
+//! they converge.
+
+use anyhow::{Result, anyhow};
+use rand_xoshiro::Xoshiro128Plus;
+use serde::Serialize;
+use std::collections::{HashMap, VecDeque};
+
+use crate::bit_set::BitSet;
+use crate::sample::{self, Override};
+use crate::serialize::SerializedNetwork;
+
+/// True counts over the samples drawn so far, by topological index.
+pub(crate) struct RunningMarginals {
+    true_counts: Vec<usize>,
+    samples: usize,
+}
+
+impl RunningMarginals {
+    pub(crate) fn new(num_nodes: u8) -> Self {
+        RunningMarginals {
+            true_counts: vec![0; usize::from(num_nodes)],
+            samples: 0,
+        }
+    }
+
+    pub(crate) fn push(&mut self, sample: &BitSet) {
+        self.samples += 1;
+        for (node, count) in (0..=u8::MAX).zip(&mut self.true_counts) {
+            *count += usize::from(sample.contains(node));
+        }
+    }
+
+    pub(crate) fn estimates(&self) -> Vec<f64> {
+        #[allow(clippy::cast_precision_loss)]
+        self.true_counts
+            .iter()
+            .map(|&count| count as f64 / self.samples as f64)
+            .collect()
+    }
+}
+
+#[derive(Serialize)]
+pub struct Mover {
+    pub id: String,
+    pub previous: f64,
+    pub current: f64,
+}
+
+#[derive(Serialize)]
+#[serde(rename_all = "camelCase")]
+pub struct Snapshot {
+    pub samples_drawn: usize,
+    pub marginals: HashMap<String, f64>,
+    /// Nodes whose estimate moved most since the previous snapshot, largest
+    /// move first. Empty for the first snapshot.
+    pub top_movers: Vec<Mover>,
+}
+
+/// Indices of the `k` largest absolute changes, largest first. Selection is
+/// linear in the number of nodes; only the `k` winners are sorted.
+fn top_movers(previous: &[f64], current: &[f64], k: usize) -> Vec<usize> {
+    let delta = |i: usize| (current[i] - previous[i]).abs();
+    let by_delta = |&a: &usize, &b: &usize| delta(b).total_cmp(&delta(a));
+    let mut indices: Vec<usize> = (0..current.len()).collect();
+    let k = k.min(indices.len());
+    if k == 0 {
+        return Vec::new();
+    }
+    indices.select_nth_unstable_by(k - 1, by_delta);
+    indices.truncate(k);
+    indices.sort_unstable_by(by_delta);
+    indices
+}
+
+/// Samples the network like `estimate_marginals` without evidence, handing a
+/// [`Snapshot`] to `on_progress` every `interval` samples and after the last.
+/// An error from `on_progress` stops the run.
+pub(crate) fn estimate_with_progress(
+    serialized: &SerializedNetwork,
+    num_samples: usize,
+    overrides: &[Option<Override>],
+    interval: usize,
+    top_k: usize,
+    rng: &mut Xoshiro128Plus,
+    mut on_progress: impl FnMut(Snapshot) -> Result<()>,
+) -> Result<HashMap<String, f64>> {
+    let num_nodes = serialized.num_nodes();
+    let mut running = RunningMarginals::new(num_nodes);
+    let mut previous: Option<Vec<f64>> = None;
+    let snapshot_of = |estimates: &[f64]| -> HashMap<String, f64> {
+        serialized
+            .topo_order
+            .iter()
+            .cloned()
+            .zip(estimates.iter().copied())
+            .collect()
+    };
+
+    for drawn in 1..=num_samples {
+        let sample_result = sample::sample(&serialized.data, &serialized.offsets, overrides, rng)
+            .map_err(|e| anyhow!("Sampling failed: {e}"))?;
+        running.push(&sample_result);
+        if drawn % interval != 0 && drawn != num_samples {
+            continue;
+        }
+
+        let current = running.estimates();
+        let top_movers = previous.as_deref().map_or_else(Vec::new, |previous| {
+            top_movers(previous, &current, top_k)
+                .into_iter()
+                .map(|i| Mover {
+                    id: serialized.topo_order[i].clone(),
+                    previous: previous[i],
+                    current: current[i],
+                })
+                .collect()
+        });
+        on_progress(Snapshot {
+            samples_drawn: drawn,
+            marginals: snapshot_of(&current),
+            top_movers,
+        })?;
+        previous = Some(current);
+    }
+
+    Ok(snapshot_of(&previous.unwrap_or_default()))
+}
+
+/// Samples between clock checks in [`estimate_within_budget`].
+const BUDGET_CHECK_INTERVAL: usize = 1000;
+/// The run counts as converged when no estimate moved more than this over the
+/// last tenth of its samples.
+const CONVERGENCE_TOLERANCE: f64 = 0.001;
+
+#[derive(Serialize)]
+#[serde(rename_all = "camelCase")]
+pub struct BudgetMeta {
+    pub samples_used: usize,
+    pub elapsed_ms: f64,
+    pub converged: bool,
+}
+
+/// Samples until `elapsed_ms()` reaches `max_ms`, checking the clock every
+/// 1000 samples, but always draws at least `min_samples` and at most
+/// `max_samples`.
+pub(crate) fn estimate_within_budget(
+    serialized: &SerializedNetwork,
+    min_samples: usize,
+    max_samples: usize,
+    max_ms: f64,
+    rng: &mut Xoshiro128Plus,
+    mut elapsed_ms: impl FnMut() -> f64,
+) -> Result<(HashMap<String, f64>, BudgetMeta)> {
+    let num_nodes = serialized.num_nodes();
+    let mut running = RunningMarginals::new(num_nodes);
+    // Checkpoints from the last one at or before 90% of the samples onwards.
+    let mut checkpoints: VecDeque<(usize, Vec<f64>)> = VecDeque::new();
+    let mut elapsed = 0.0;
+    let mut drawn = 0;
+    while drawn < max_samples {
+        let sample_result = sample::sample(&serialized.data, &serialized.offsets, &[], rng)
+            .map_err(|e| anyhow!("Sampling failed: {e}"))?;
+        running.push(&sample_result);
+        drawn += 1;
+        if drawn % BUDGET_CHECK_INTERVAL != 0 {
+            continue;
+        }
+        checkpoints.push_back((drawn, running.estimates()));
+        while checkpoints.len() >= 2 && checkpoints[1].0 * 10 <= drawn * 9 {
+            checkpoints.pop_front();
+        }
+        elapsed = elapsed_ms();
+        if drawn >= min_samples && elapsed >= max_ms {
+            break;
+        }
+    }
+    if drawn % BUDGET_CHECK_INTERVAL != 0 {
+        elapsed = elapsed_ms();
+    }
+
+    let current = running.estimates();
+    let converged = checkpoints
+        .front()
+        .filter(|&&(samples, _)| samples < drawn)
+        .is_some_and(|(_, earlier)| {
+            earlier
+                .iter()
+                .zip(&current)
+                .all(|(a, b)| (a - b).abs() < CONVERGENCE_TOLERANCE)
+        });
+    let marginals = serialized.topo_order.iter().cloned().zip(current).collect();
+    Ok((
+        marginals,
+        BudgetMeta {
+            samples_used: drawn,
+            elapsed_ms: elapsed,
+            converged,
+        },
+    ))
+}
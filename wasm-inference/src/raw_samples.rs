@@ -0,0 +1,91 @@
+//! Packed export of raw samples, tagged with the bit layout they were drawn
+//! under.
+//!
+//! A blob is a magic header, the layout hash (see
+//! [`crate::serialize::layout_fingerprint`]) as a little-endian `u64` and the
+//! node count, followed by one row of `ceil(nodes / 8)` bytes per sample. Bit
+//! `i` of a row (least significant first) is the node at topological index
+//! `i`. Edits can move nodes to other indices, so a blob is only read back
+//! against a network with the same layout.
+
+use anyhow::{Result, bail};
+use std::fmt;
+
+use crate::bit_set::BitSet;
+use crate::serialize::layout_hash;
+
+const MAGIC: &[u8; 4] = b"DDRS";
+const HEADER_LEN: usize = MAGIC.len() + 8 + 1;
+
+/// Samples read against a network whose topological order differs from the
+/// one they were drawn under.
+#[derive(Debug)]
+pub(crate) struct LayoutMismatch {
+    pub expected: String,
+    pub found: String,
+}
+
+impl fmt::Display for LayoutMismatch {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(
+            f,
+            "Samples were drawn under layout {}, but the network's layout is {}",
+            self.found, self.expected
+        )
+    }
+}
+
+impl std::error::Error for LayoutMismatch {}
+
+fn row_len(num_nodes: u8) -> usize {
+    usize::from(num_nodes).div_ceil(8)
+}
+
+pub(crate) fn encode(topo_order: &[String], samples: &[BitSet]) -> Vec<u8> {
+    let num_nodes = u8::try_from(topo_order.len()).expect("networks have at most 255 nodes");
+    let row_len = row_len(num_nodes);
+    let mut bytes = Vec::with_capacity(HEADER_LEN + samples.len() * row_len);
+    bytes.extend(MAGIC);
+    bytes.extend(layout_hash(topo_order).to_le_bytes());
+    bytes.push(num_nodes);
+    for sample in samples {
+        bytes.extend(&sample.as_bytes()[..row_len]);
+    }
+    bytes
+}
+
+/// The samples in `bytes`, which must have been drawn under `topo_order`.
+/// A different layout fails with a [`LayoutMismatch`].
+pub(crate) fn decode(topo_order: &[String], bytes: &[u8]) -> Result<Vec<BitSet>> {
+    let Some((header, rows)) = bytes.split_at_checked(HEADER_LEN) else {
+        bail!("Raw samples are truncated: no header");
+    };
+    if &header[..MAGIC.len()] != MAGIC {
+        bail!("Not a raw sample export");
+    }
+    let hash = u64::from_le_bytes(header[MAGIC.len()..HEADER_LEN - 1].try_into()?);
+    let expected = layout_hash(topo_order);
+    if hash != expected {
+        return Err(LayoutMismatch {
+            expected: format!("{expected:016x}"),
+            found: format!("{hash:016x}"),
+        }
+        .into());
+    }
+    let row_len = row_len(header[HEADER_LEN - 1]);
+    if row_len == 0 || rows.len() % row_len != 0 {
+        bail!("Raw samples are truncated: {} bytes of rows", rows.len());
+    }
+    Ok(rows
+        .chunks_exact(row_len)
+        .map(|row| {
+            let mut sample = BitSet::new();
+            for index in 0..u8::try_from(topo_order.len()).unwrap_or(u8::MAX) {
+                if row[usize::from(index / 8)] >> (index % 8) & 1 == 1 {
+                    sample.insert(index);
+                }
+            }
+            sample
+        })
+        .collect())
+}
@@ -0,0 +1,87 @@
+//! Opt-in log of the queries a compiled network serves, replayable after
+//! the model is edited to see exactly which saved views would change.
+
+use serde::{Deserialize, Serialize};
+use std::collections::{HashMap, VecDeque};
+
+use crate::serialize::fnv1a;
+
+/// Most entries a log may be asked to hold.
+pub const MAX_LOG_CAPACITY: usize = 10_000;
+
+#[derive(Clone, Copy, Serialize, Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub enum QueryKind {
+    Marginals,
+    Intervention,
+}
+
+#[derive(Clone, Serialize, Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub struct LoggedQuery {
+    pub kind: QueryKind,
+    /// The intervened node, for intervention queries.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub node_id: Option<String>,
+    /// The query options as given, with `seed` set to the seed actually used
+    /// so a replay draws the same samples.
+    pub options: serde_json::Value,
+    pub seed: u64,
+    pub digest: String,
+}
+
+/// The most recent queries, oldest first; older ones are dropped to stay
+/// within `capacity`.
+pub(crate) struct QueryLog {
+    pub(crate) entries: VecDeque<LoggedQuery>,
+    capacity: usize,
+}
+
+impl QueryLog {
+    pub(crate) fn new(capacity: usize) -> Self {
+        QueryLog {
+            entries: VecDeque::with_capacity(capacity),
+            capacity,
+        }
+    }
+
+    pub(crate) fn push(&mut self, entry: LoggedQuery) {
+        if self.entries.len() == self.capacity {
+            self.entries.pop_front();
+        }
+        self.entries.push_back(entry);
+    }
+}
+
+#[derive(Serialize)]
+#[serde(rename_all = "camelCase")]
+pub struct ReplayOutcome {
+    pub kind: QueryKind,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub node_id: Option<String>,
+    pub previous_digest: String,
+    /// `None` when the query no longer runs; see `error`.
+    pub digest: Option<String>,
+    pub changed: bool,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub error: Option<String>,
+}
+
+/// Stable FNV-1a hash of result maps, each read in key order, so equal
+/// results give equal digests whatever order the maps iterate in.
+pub(crate) fn digest(maps: &[&HashMap<String, f64>]) -> String {
+    let bytes = maps.iter().flat_map(|map| {
+        let mut sorted: Vec<(&String, &f64)> = map.iter().collect();
+        sorted.sort_unstable_by_key(|&(id, _)| id);
+        sorted
+            .into_iter()
+            .flat_map(|(id, value)| {
+                id.bytes()
+                    .chain([0])
+                    .chain(value.to_bits().to_le_bytes())
+                    .collect::<Vec<u8>>()
+            })
+            .chain([0xff])
+    });
+    format!("{:016x}", fnv1a(bytes))
+}
@@ -0,0 +1,220 @@
+//! Partial evaluation: replacing the part of a network upstream of a cut with
+//! priors on the cut, so edits downstream of it are cheaper to iterate on.
+
+use anyhow::{Result, anyhow, bail};
+use rand_xoshiro::Xoshiro128Plus;
+use serde::Serialize;
+use std::collections::{BTreeSet, HashMap, HashSet};
+
+use crate::lookup::find_node;
+use crate::marginals::estimate_marginals;
+use crate::sample;
+use crate::serialize::{SerializedNetwork, get_node_parents, serialize_network};
+use crate::structure::ancestors;
+use crate::{CptEntry, Node};
+
+/// Largest boundary whose joint distribution is kept; a boundary of `k`
+/// nodes needs `2^k - 1` entries.
+const MAX_JOINT_BOUNDARY: usize = 8;
+
+#[derive(Serialize)]
+#[serde(rename_all = "camelCase")]
+pub struct Fidelity {
+    pub target_node_id: String,
+    /// Target marginal in the full network.
+    pub before: f64,
+    /// Target marginal in the reduced network.
+    pub after: f64,
+    pub difference: f64,
+}
+
+#[derive(Serialize)]
+#[serde(rename_all = "camelCase")]
+pub struct FrozenNetwork {
+    pub nodes: Vec<Node>,
+    pub fidelity: Fidelity,
+    pub warnings: Vec<String>,
+}
+
+/// Drops every ancestor of the boundary nodes and gives the boundary nodes
+/// CPTs over each other that reproduce their sampled joint distribution.
+///
+/// The boundary must cut the network: no remaining node may have a dropped
+/// parent. Boundaries of more than `MAX_JOINT_BOUNDARY` nodes are frozen as
+/// independent priors, which loses any correlation between them. The upstream
+/// part is sampled without evidence, so `observed` flags on dropped nodes no
+/// longer shape the boundary.
+pub fn freeze_upstream(
+    nodes: &[Node],
+    boundary_ids: &[String],
+    num_samples: usize,
+    target_node_id: &str,
+    rng: &mut Xoshiro128Plus,
+) -> Result<FrozenNetwork> {
+    let serialized = serialize_network(nodes)?;
+    let mut boundary = boundary_ids
+        .iter()
+        .map(|id| {
+            serialized
+                .index_of(id)
+                .ok_or_else(|| anyhow!("Boundary node {id} not found"))
+        })
+        .collect::<Result<Vec<u8>>>()?;
+    boundary.sort_unstable();
+    boundary.dedup();
+    if boundary.is_empty() {
+        bail!("At least one boundary node is required");
+    }
+    let boundary_set: HashSet<&str> = boundary_ids.iter().map(String::as_str).collect();
+    let upstream: BTreeSet<String> = boundary
+        .iter()
+        .flat_map(|&node| ancestors(&serialized, node))
+        .filter(|id| !boundary_set.contains(id.as_str()))
+        .collect();
+    if upstream.contains(target_node_id) {
+        bail!("Target node {target_node_id} is upstream of the boundary");
+    }
+
+    let mut warnings = Vec::new();
+    for node in nodes {
+        if upstream.contains(&node.id) {
+            if node.observed.is_some() {
+                warnings.push(format!(
+                    "Observation of {id} is dropped with the upstream nodes",
+                    id = node.id
+                ));
+            }
+            continue;
+        }
+        if boundary_set.contains(node.id.as_str()) {
+            continue;
+        }
+        if let Some(parent) = get_node_parents(node)
+            .into_iter()
+            .find(|&parent| upstream.contains(parent))
+        {
+            bail!(
+                "Node {id} has parent {parent} upstream of the boundary; add {parent} to the \
+                 boundary",
+                id = node.id
+            );
+        }
+    }
+
+    let joint = boundary.len() <= MAX_JOINT_BOUNDARY;
+    if !joint {
+        warnings.push(format!(
+            "Boundary has {} nodes, more than {MAX_JOINT_BOUNDARY}; its nodes are frozen as \
+             independent priors, losing correlations between them",
+            boundary.len()
+        ));
+    }
+    let counts = boundary_counts(&serialized, &boundary, joint, num_samples, rng)?;
+    let boundary_ids: Vec<&String> = boundary
+        .iter()
+        .map(|&node| &serialized.topo_order[usize::from(node)])
+        .collect();
+    let frozen = frozen_entries(&boundary_ids, &counts);
+
+    let reduced: Vec<Node> = nodes
+        .iter()
+        .filter(|node| !upstream.contains(&node.id))
+        .map(|node| {
+            let mut node = node.clone();
+            if let Some(entries) = frozen.get(node.id.as_str()) {
+                node.cpt_entries = entries.clone();
+                node.probability_floor = None;
+                node.probability_ceiling = None;
+                node.leak_probability = None;
+            }
+            node
+        })
+        .collect();
+
+    find_node(nodes, "Target", target_node_id)?;
+    let target_marginal = |nodes: &[Node], rng: &mut Xoshiro128Plus| -> Result<f64> {
+        let marginals = estimate_marginals(&serialize_network(nodes)?, num_samples, &[], &[], rng)?;
+        marginals
+            .get(target_node_id)
+            .copied()
+            .ok_or_else(|| anyhow!("Target node {target_node_id} not found"))
+    };
+    let before = target_marginal(nodes, rng)?;
+    let after = target_marginal(&reduced, rng)?;
+    Ok(FrozenNetwork {
+        nodes: reduced,
+        fidelity: Fidelity {
+            target_node_id: target_node_id.to_string(),
+            before,
+            after,
+            difference: after - before,
+        },
+        warnings,
+    })
+}
+
+/// Per boundary node (in topological order) and configuration of the
+/// boundary nodes before it, `[samples, true]`. Without `joint` each node has
+/// a single configuration.
+fn boundary_counts(
+    serialized: &SerializedNetwork,
+    boundary: &[u8],
+    joint: bool,
+    num_samples: usize,
+    rng: &mut Xoshiro128Plus,
+) -> Result<Vec<Vec<[usize; 2]>>> {
+    let mut counts: Vec<Vec<[usize; 2]>> = (0..boundary.len())
+        .map(|k| vec![[0; 2]; if joint { 1 << k } else { 1 }])
+        .collect();
+    for _ in 0..num_samples {
+        let sample_result = sample::sample(&serialized.data, &serialized.offsets, &[], rng)
+            .map_err(|e| anyhow!("Sampling failed: {e}"))?;
+        let mut configuration = 0;
+        for (k, &node) in boundary.iter().enumerate() {
+            let value = sample_result.contains(node);
+            let tally = &mut counts[k][if joint { configuration } else { 0 }];
+            tally[0] += 1;
+            tally[1] += usize::from(value);
+            configuration |= usize::from(value) << k;
+        }
+    }
+    Ok(counts)
+}
+
+/// CPT entries reproducing `counts`, each conditioned on every boundary node
+/// before it when the counts are split by configuration.
+fn frozen_entries<'a>(
+    boundary_ids: &[&'a String],
+    counts: &[Vec<[usize; 2]>],
+) -> HashMap<&'a str, Vec<CptEntry>> {
+    boundary_ids
+        .iter()
+        .zip(counts)
+        .map(|(&id, tallies)| {
+            let [total, total_true] = tallies.iter().fold([0, 0], |[n, t], [a, b]| [n + a, t + b]);
+            #[allow(clippy::cast_precision_loss)]
+            let marginal = total_true as f64 / total as f64;
+            let entries = (0..)
+                .zip(tallies)
+                .map(|(configuration, &[n, t])| CptEntry {
+                    parent_states: boundary_ids
+                        .iter()
+                        .take(tallies.len().trailing_zeros() as usize)
+                        .enumerate()
+                        .map(|(i, &parent)| (parent.clone(), Some(configuration & (1 << i) != 0)))
+                        .collect(),
+                    // Configurations never sampled fall back to the marginal.
+                    #[allow(clippy::cast_precision_loss)]
+                    probability: if n == 0 {
+                        marginal
+                    } else {
+                        t as f64 / n as f64
+                    },
+                    is_probability_of_true: true,
+                    probability_params: None,
+                })
+                .collect();
+            (id.as_str(), entries)
+        })
+        .collect()
+}
@@ -0,0 +1,75 @@
+//! Audit of how many random draws each sample consumes.
+//!
+//! Changes to the sampler that alter the number of draws per sample shift the
+//! random stream for every later sample, so "same seed, same results" breaks
+//! even when each individual draw is correct. Comparing traces across versions
+//! pinpoints the first sample where the streams diverge.
+//!
+//! The counter wraps the generator and the sampler is generic over it, so the
+//! ordinary sampling paths are monomorphized without any counting.
+
+use anyhow::{Result, anyhow};
+use rand::RngCore;
+use serde::Serialize;
+
+use crate::sample;
+use crate::serialize::{SerializedNetwork, fnv1a};
+
+/// The reproducibility promise made for seeded queries.
+pub const REPRODUCIBILITY_GUARANTEE: &str =
+    "Results are reproducible given the same network, options and seed within a minor version";
+
+#[derive(Serialize)]
+#[serde(rename_all = "camelCase")]
+pub struct SampleTrace {
+    /// Number of words drawn from the generator for this sample.
+    pub uniforms: usize,
+    /// FNV-1a hash of the sampled assignment, as 16 hex digits.
+    pub hash: String,
+}
+
+struct CountingRng<'a, R> {
+    inner: &'a mut R,
+    draws: usize,
+}
+
+impl<R: RngCore> RngCore for CountingRng<'_, R> {
+    fn next_u32(&mut self) -> u32 {
+        self.draws += 1;
+        self.inner.next_u32()
+    }
+
+    fn next_u64(&mut self) -> u64 {
+        self.draws += 1;
+        self.inner.next_u64()
+    }
+
+    fn fill_bytes(&mut self, dst: &mut [u8]) {
+        self.draws += dst.len().div_ceil(8);
+        self.inner.fill_bytes(dst);
+    }
+}
+
+/// Draws `num_samples` samples of the unmodified network, recording the draws
+/// each one consumed and a hash of its assignment.
+pub fn rng_trace(
+    serialized: &SerializedNetwork,
+    num_samples: usize,
+    rng: &mut impl RngCore,
+) -> Result<Vec<SampleTrace>> {
+    (0..num_samples)
+        .map(|_| {
+            let mut counting = CountingRng {
+                inner: &mut *rng,
+                draws: 0,
+            };
+            let assignment =
+                sample::sample(&serialized.data, &serialized.offsets, &[], &mut counting)
+                    .map_err(|e| anyhow!("Sampling failed: {e}"))?;
+            Ok(SampleTrace {
+                uniforms: counting.draws,
+                hash: format!("{:016x}", fnv1a(*assignment.as_bytes())),
+            })
+        })
+        .collect()
+}
@@ -1,67 +1,461 @@
 use anyhow::anyhow;
 use rand::Rng;
-use rand_xoshiro::Xoshiro128Plus;
 use winnow::{
     Parser,
     binary::{le_f32, le_u8, length_take},
-    combinator::seq,
+    combinator::{fail, seq},
     token::take,
 };
 
 use crate::bit_set::BitSet;
 
 pub(crate) fn sample(
-    mut serialized_network: &[u8],
-    num_nodes: u8,
-    intervention: Option<Intervention>,
-    rng: &mut Xoshiro128Plus,
+    serialized_network: &[u8],
+    offsets: &[usize],
+    overrides: &[Option<Override>],
+    rng: &mut impl Rng,
 ) -> anyhow::Result<BitSet> {
     let mut samples = BitSet::new();
-    if let Some(Intervention { value, on_node }) = intervention
-        && value
-    {
-        samples.insert(on_node);
+    for (node, record) in records(serialized_network, offsets)? {
+        let forced = overrides.get(usize::from(node)).copied().flatten();
+        let value = match node_draw(&samples, record, forced)? {
+            Draw::Fixed(value) => value,
+            Draw::Bernoulli(probability) => rng.random_bool(f64::from(probability)),
+        };
+        if value {
+            samples.insert(node);
+        }
     }
-    for node in 0..num_nodes {
-        let probability = process_node(&samples, &mut serialized_network)
-            .map_err(anyhow::Error::msg)?
-            .ok_or_else(|| anyhow!("Node without a matching CPT Entry"))?;
-        if let Some(Intervention { value: _, on_node }) = intervention
-            && on_node == node
-        {
-            continue;
+    Ok(samples)
+}
+
+/// `num_samples` independent samples, kept for statistics that need more than
+/// per-node counts.
+pub(crate) fn sample_all(
+    serialized_network: &[u8],
+    offsets: &[usize],
+    num_samples: usize,
+    overrides: &[Option<Override>],
+    rng: &mut impl Rng,
+) -> anyhow::Result<Vec<BitSet>> {
+    (0..num_samples)
+        .map(|_| sample(serialized_network, offsets, overrides, rng))
+        .collect()
+}
+
+/// What is known about a node when likelihood weighting.
+#[derive(Clone, Copy)]
+pub(crate) enum Evidence {
+    /// The node was observed to take this value.
+    Hard(bool),
+    /// An uncertain observation with likelihood ratio
+    /// `P(observation | true) / P(observation | false)`.
+    Soft(f64),
+}
+
+/// Hard and soft evidence by topological index.
+#[derive(Default)]
+pub(crate) struct MixedEvidence {
+    pub hard: Vec<(u8, bool)>,
+    pub soft: Vec<(u8, f64)>,
+}
+
+impl MixedEvidence {
+    /// The evidence indexed by node, as [`sample_weighted`] takes it.
+    pub(crate) fn by_node(&self, num_nodes: u8) -> Vec<Option<Evidence>> {
+        let mut by_node = vec![None; usize::from(num_nodes)];
+        for &(node, value) in &self.hard {
+            by_node[usize::from(node)] = Some(Evidence::Hard(value));
+        }
+        for &(node, ratio) in &self.soft {
+            by_node[usize::from(node)] = Some(Evidence::Soft(ratio));
         }
-        if rng.random_bool(f64::from(probability)) {
+        by_node
+    }
+}
+
+/// Likelihood-weighted sample. Nodes with hard evidence are fixed to their
+/// observed value instead of drawn, multiplying the weight by their
+/// probability of taking it (zero when an intervention contradicts it).
+/// Nodes with soft evidence are drawn as usual and multiply the weight by
+/// their likelihood ratio when true. Both slices are indexed by topological
+/// position and may be empty.
+pub(crate) fn sample_weighted(
+    serialized_network: &[u8],
+    offsets: &[usize],
+    overrides: &[Option<Override>],
+    evidence: &[Option<Evidence>],
+    rng: &mut impl Rng,
+) -> anyhow::Result<(BitSet, f64)> {
+    let (samples, weight, _) =
+        sample_weighted_smoothed(serialized_network, offsets, overrides, evidence, None, rng)?;
+    Ok((samples, weight))
+}
+
+/// [`sample_weighted`] where a CPT giving an observed value probability
+/// exactly 0 weighs the sample by `epsilon` instead, when set. Also returns
+/// how many such substitutions were made. Draws are unaffected, and so are
+/// observations contradicting an intervention or clamp.
+pub(crate) fn sample_weighted_smoothed(
+    serialized_network: &[u8],
+    offsets: &[usize],
+    overrides: &[Option<Override>],
+    evidence: &[Option<Evidence>],
+    epsilon: Option<f64>,
+    rng: &mut impl Rng,
+) -> anyhow::Result<(BitSet, f64, usize)> {
+    let mut samples = BitSet::new();
+    let mut weight = 1.0;
+    let mut substitutions = 0;
+    for (node, record) in records(serialized_network, offsets)? {
+        let forced = overrides.get(usize::from(node)).copied().flatten();
+        let node_draw = node_draw(&samples, record, forced)?;
+        let mut draw = || match node_draw {
+            Draw::Fixed(value) => value,
+            Draw::Bernoulli(probability) => rng.random_bool(f64::from(probability)),
+        };
+        let value = match evidence.get(usize::from(node)).copied().flatten() {
+            Some(Evidence::Hard(observed)) => {
+                weight *= match node_draw {
+                    Draw::Fixed(value) => f64::from(u8::from(value == observed)),
+                    Draw::Bernoulli(probability) => {
+                        let p_true = f64::from(probability);
+                        match (if observed { p_true } else { 1.0 - p_true }, epsilon) {
+                            (0.0, Some(epsilon)) => {
+                                substitutions += 1;
+                                epsilon
+                            }
+                            (likelihood, _) => likelihood,
+                        }
+                    }
+                };
+                observed
+            }
+            Some(Evidence::Soft(ratio)) => {
+                let value = draw();
+                if value {
+                    weight *= ratio;
+                }
+                value
+            }
+            None => draw(),
+        };
+        if value {
             samples.insert(node);
         }
     }
-    debug_assert!(serialized_network.is_empty());
-    Ok(samples)
+    Ok((samples, weight, substitutions))
+}
+
+/// Likelihood-weighted sample where some evidence nodes went unrecorded:
+/// `Some(value)` is observed and weighs the sample as in [`sample_weighted`],
+/// while `None` is drawn from the node's CPT like any unobserved node.
+pub(crate) fn sample_with_partial_evidence(
+    serialized_network: &[u8],
+    offsets: &[usize],
+    evidence: &[(u8, Option<bool>)],
+    rng: &mut impl Rng,
+) -> anyhow::Result<(BitSet, f64)> {
+    let mut by_node = vec![None; offsets.len().saturating_sub(1)];
+    for &(node, value) in evidence {
+        by_node[usize::from(node)] = value.map(Evidence::Hard);
+    }
+    sample_weighted(serialized_network, offsets, &[], &by_node, rng)
+}
+
+/// Samples both worlds of a twin network. Each node draws a single uniform
+/// shared by the two worlds and is true where it falls below that world's
+/// probability, so the worlds differ only downstream of where their
+/// `overrides` differ.
+pub(crate) fn sample_twin(
+    serialized_network: &[u8],
+    offsets: &[usize],
+    overrides: [&[Option<Override>]; 2],
+    rng: &mut impl Rng,
+) -> anyhow::Result<[BitSet; 2]> {
+    sample_nested(serialized_network, offsets, overrides, None, rng)
+}
+
+/// [`sample_twin`] where the second world's `carried` node takes whatever
+/// value it has in the first world, for nested counterfactuals such as
+/// `Y(x=1, M(x=0))`.
+pub(crate) fn sample_nested(
+    serialized_network: &[u8],
+    offsets: &[usize],
+    overrides: [&[Option<Override>]; 2],
+    carried: Option<u8>,
+    rng: &mut impl Rng,
+) -> anyhow::Result<[BitSet; 2]> {
+    let mut worlds = [BitSet::new(); 2];
+    for (node, record) in records(serialized_network, offsets)? {
+        let u: f64 = rng.random();
+        for (index, overrides) in overrides.into_iter().enumerate() {
+            let forced = overrides.get(usize::from(node)).copied().flatten();
+            let draw = node_draw(&worlds[index], record, forced)?;
+            let value = match draw {
+                _ if index == 1 && carried == Some(node) => worlds[0].contains(node),
+                Draw::Fixed(value) => value,
+                Draw::Bernoulli(probability) => u < f64::from(probability),
+            };
+            if value {
+                worlds[index].insert(node);
+            }
+        }
+    }
+    Ok(worlds)
+}
+
+/// Each node's record with its topological index, sliced out by `offsets`
+/// (each record's start, then the buffer's length). Errors when the offsets
+/// don't tile the buffer, which means they were built for another one.
+fn records<'a>(
+    serialized_network: &'a [u8],
+    offsets: &'a [usize],
+) -> anyhow::Result<impl Iterator<Item = (u8, &'a [u8])>> {
+    let tiles = offsets.first() == Some(&0)
+        && offsets.last() == Some(&serialized_network.len())
+        && offsets.len() <= 256
+        && offsets.is_sorted();
+    if !tiles {
+        return Err(anyhow!("Record offsets don't match the serialized network"));
+    }
+    Ok((0u8..).zip(
+        offsets
+            .windows(2)
+            .map(|bounds| &serialized_network[bounds[0]..bounds[1]]),
+    ))
 }
 
+/// The offset index of a buffer that came without one, found by reading
+/// every record in full. Errors on a corrupt record or on bytes left after
+/// the last node.
+#[cfg(fuzzing)]
+pub(crate) fn record_offsets(
+    serialized_network: &[u8],
+    num_nodes: u8,
+) -> anyhow::Result<Vec<usize>> {
+    let mut input = serialized_network;
+    let mut offsets = Vec::with_capacity(usize::from(num_nodes) + 1);
+    for _ in 0..num_nodes {
+        offsets.push(serialized_network.len() - input.len());
+        skip_node(&mut input).map_err(anyhow::Error::msg)?;
+    }
+    if !input.is_empty() {
+        return Err(anyhow!("{} bytes left after the last node", input.len()));
+    }
+    offsets.push(serialized_network.len());
+    Ok(offsets)
+}
+
+/// Log of the joint probability of a full assignment under the network.
+pub(crate) fn log_joint(
+    serialized_network: &[u8],
+    offsets: &[usize],
+    assignment: &BitSet,
+) -> anyhow::Result<f64> {
+    let mut log_probability = 0.0;
+    for (node, record) in records(serialized_network, offsets)? {
+        let probability = process_node(assignment, record)
+            .map_err(anyhow::Error::msg)?
+            .ok_or_else(|| anyhow!("Node without a matching CPT Entry"))?;
+        let probability = f64::from(probability);
+        log_probability += if assignment.contains(node) {
+            probability.ln()
+        } else {
+            (1.0 - probability).ln()
+        };
+    }
+    Ok(log_probability)
+}
+
+/// `P(true)` of a root node from its record.
+pub(crate) fn root_probability(record: &[u8]) -> anyhow::Result<f32> {
+    process_node(&BitSet::new(), record)
+        .map_err(anyhow::Error::msg)?
+        .ok_or_else(|| anyhow!("Node without a matching CPT Entry"))
+}
+
+/// Replaces a node's CPT during sampling. Overrides are indexed by topological
+/// position; an empty slice samples the unmodified network.
+#[derive(Clone, Copy)]
+pub(crate) enum Override {
+    /// A hard intervention, `do(node = value)`.
+    Value(bool),
+    /// A clamp: the node is drawn with this probability regardless of its parents.
+    Probability(f32),
+}
+
+/// How a node's value is drawn once its record has been read.
 #[derive(Clone, Copy)]
-pub(crate) struct Intervention {
-    pub(crate) value: bool,
-    pub(crate) on_node: u8,
+enum Draw {
+    Fixed(bool),
+    Bernoulli(f32),
+}
+
+/// Reads the node's record. An override replaces the CPT, so the record
+/// isn't read at all: an intervention never fails on the intervened node's
+/// own CPT, even one with no matching entry.
+fn node_draw(samples: &BitSet, record: &[u8], forced: Option<Override>) -> anyhow::Result<Draw> {
+    match forced {
+        Some(Override::Value(value)) => Ok(Draw::Fixed(value)),
+        Some(Override::Probability(probability)) => Ok(Draw::Bernoulli(probability)),
+        None => process_node(samples, record)
+            .map_err(anyhow::Error::msg)?
+            .map(Draw::Bernoulli)
+            .ok_or_else(|| anyhow!("Node without a matching CPT Entry")),
+    }
+}
+
+/// Advances past a record without resolving its CPT.
+#[cfg(fuzzing)]
+fn skip_node(input: &mut &[u8]) -> winnow::Result<()> {
+    let parents = length_take(le_u8).parse_next(input)?;
+    (unit_f32, unit_f32, unit_f32).parse_next(input)?;
+    let num_cpt_entries = le_u8.parse_next(input)?;
+    for _ in 0..num_cpt_entries {
+        cpt_entry(parents.len()).parse_next(input)?;
+    }
+    Ok(())
 }
 
-fn process_node(samples: &BitSet, input: &mut &[u8]) -> winnow::Result<Option<f32>> {
+/// Resolves a node's `P(true)` from its record, reading entries only up to
+/// the first match: the offset index, not this parse, finds the next record.
+fn process_node(samples: &BitSet, mut record: &[u8]) -> winnow::Result<Option<f32>> {
+    let input = &mut record;
     let parents = length_take(le_u8).parse_next(input)?;
     let parent_states = parents.iter().map(|&p| samples.contains(p));
+    let (floor, ceiling, leak) = (unit_f32, unit_f32, unit_f32).parse_next(input)?;
     let num_cpt_entries = le_u8.parse_next(input)?;
     let mut probability = None;
     for _ in 0..num_cpt_entries {
         let entry = cpt_entry(parents.len()).parse_next(input)?;
-        if probability.is_none() && entry.matches(parent_states.clone()) {
-            probability = Some(entry.probability);
+        if entry.matches(parent_states.clone()) {
+            probability = Some(match entry.probability {
+                EntryProbability::Fixed(probability) => probability,
+                EntryProbability::Hierarchical { parent, high, low } => {
+                    if samples.contains(parents[usize::from(parent)]) {
+                        high
+                    } else {
+                        low
+                    }
+                }
+            });
+            break;
         }
     }
-    Ok(probability)
+    Ok(probability.map(|probability| {
+        let probability = if leak > 0.0 {
+            1.0 - (1.0 - probability) * (1.0 - leak)
+        } else {
+            probability
+        };
+        probability.max(floor).min(ceiling)
+    }))
+}
+
+/// Reorders each record's entries so the ones `pilot` samples match first
+/// most often come first, letting [`process_node`] stop sooner. An entry only
+/// moves ahead of entries no parent assignment matches together with it, so
+/// every lookup still resolves to the same entry. Records keep their length
+/// and the offset index stays valid. Returns the topological indices of the
+/// nodes whose records changed.
+pub(crate) fn order_entries_by_hits(
+    serialized_network: &mut [u8],
+    offsets: &[usize],
+    pilot: &[BitSet],
+) -> anyhow::Result<Vec<u8>> {
+    let mut reordered = Vec::new();
+    let spans: Vec<(u8, usize, usize)> = records(serialized_network, offsets)?
+        .map(|(node, record)| (node, offsets[usize::from(node)], record.len()))
+        .collect();
+    for (node, start, len) in spans {
+        let record = &mut serialized_network[start..start + len];
+        if let Some(rewritten) = reorder_record(record, pilot).map_err(anyhow::Error::msg)? {
+            record.copy_from_slice(&rewritten);
+            reordered.push(node);
+        }
+    }
+    Ok(reordered)
+}
+
+/// The record with its entries in hit order, or `None` when that is the
+/// order they're already in.
+fn reorder_record(record: &[u8], pilot: &[BitSet]) -> winnow::Result<Option<Vec<u8>>> {
+    let input = &mut &record[..];
+    let parents = length_take(le_u8).parse_next(input)?;
+    (unit_f32, unit_f32, unit_f32).parse_next(input)?;
+    let num_cpt_entries = le_u8.parse_next(input)?;
+    let header = record.len() - input.len();
+    let mut entries = Vec::with_capacity(usize::from(num_cpt_entries));
+    for _ in 0..num_cpt_entries {
+        let start = record.len() - input.len();
+        let entry = cpt_entry(parents.len()).parse_next(input)?;
+        entries.push((start..record.len() - input.len(), entry));
+    }
+
+    let mut hits = vec![0usize; entries.len()];
+    for sample in pilot {
+        let parent_states = parents.iter().map(|&p| sample.contains(p));
+        let first_match = entries
+            .iter()
+            .position(|(_, entry)| entry.matches(parent_states.clone()));
+        if let Some(index) = first_match {
+            hits[index] += 1;
+        }
+    }
+
+    // Repeatedly place the most hit entry among those with no earlier,
+    // overlapping entry left to place.
+    let overlap = |a: &[u8], b: &[u8]| {
+        a.iter()
+            .zip(b)
+            .all(|(&a, &b)| (a ^ b) & (a >> 4) & (b >> 4) == 0)
+    };
+    let mut remaining: Vec<usize> = (0..entries.len()).collect();
+    let mut order = Vec::with_capacity(entries.len());
+    while !remaining.is_empty() {
+        let placeable = remaining.iter().enumerate().filter(|&(position, &entry)| {
+            remaining[..position].iter().all(|&earlier| {
+                !overlap(
+                    entries[earlier].1.parent_pattern,
+                    entries[entry].1.parent_pattern,
+                )
+            })
+        });
+        let (position, &entry) = placeable
+            .max_by_key(|&(_, &entry)| (hits[entry], std::cmp::Reverse(entry)))
+            .expect("the earliest remaining entry is always placeable");
+        order.push(entry);
+        remaining.remove(position);
+    }
+    if order.iter().enumerate().all(|(i, &entry)| i == entry) {
+        return Ok(None);
+    }
+    let mut rewritten = record[..header].to_vec();
+    for entry in order {
+        rewritten.extend_from_slice(&record[entries[entry].0.clone()]);
+    }
+    rewritten.extend_from_slice(&record[record.len() - input.len()..]);
+    Ok(Some(rewritten))
+}
+
+/// Tags for how an entry's probability is stored after its parent pattern.
+pub(crate) const ENTRY_FIXED: u8 = 0;
+pub(crate) const ENTRY_HIERARCHICAL: u8 = 1;
+
+pub(crate) enum EntryProbability {
+    Fixed(f32),
+    /// `high` while the parent at local position `parent` is true, else `low`.
+    Hierarchical {
+        parent: u8,
+        high: f32,
+        low: f32,
+    },
 }
 
 struct CPTEntry<'a> {
     parent_pattern: &'a [u8],
-    probability: f32,
+    probability: EntryProbability,
 }
 
 impl CPTEntry<'_> {
@@ -90,6 +484,30 @@ fn cpt_entry<'a>(
     let parent_pattern_bytes = num_parents.div_ceil(4);
     seq! { CPTEntry {
         parent_pattern: take(parent_pattern_bytes),
-        probability: le_f32
+        probability: entry_probability(num_parents)
     }}
 }
+
+/// An `f32` in `[0, 1]`. Anything else (including NaN) would make the
+/// sampler's Bernoulli draws panic.
+fn unit_f32(input: &mut &[u8]) -> winnow::Result<f32> {
+    le_f32
+        .verify(|probability| (0.0..=1.0).contains(probability))
+        .parse_next(input)
+}
+
+fn entry_probability<'a>(
+    num_parents: usize,
+) -> impl Parser<&'a [u8], EntryProbability, winnow::error::ContextError> {
+    move |input: &mut &'a [u8]| match le_u8.parse_next(input)? {
+        ENTRY_FIXED => unit_f32.map(EntryProbability::Fixed).parse_next(input),
+        ENTRY_HIERARCHICAL => (
+            le_u8.verify(|&parent| usize::from(parent) < num_parents),
+            unit_f32,
+            unit_f32,
+        )
+            .map(|(parent, high, low)| EntryProbability::Hierarchical { parent, high, low })
+            .parse_next(input),
+        _ => fail.parse_next(input),
+    }
+}
@@ -0,0 +1,128 @@
+//! Completions of a partial world the user sketches, for storytelling: the
+//! rest of the network filled in consistently, many times over.
+
+use anyhow::{Result, anyhow, bail};
+use rand::Rng;
+use serde::{Deserialize, Serialize};
+use std::collections::HashMap;
+
+use crate::sample::{self, Override};
+use crate::serialize::{SerializedNetwork, layout_fingerprint};
+use crate::{Node, limits, lookup};
+
+/// How the sketched nodes are held at their values.
+#[derive(Deserialize, Clone, Copy, Default)]
+#[serde(rename_all = "camelCase")]
+pub enum ClampMode {
+    /// As `do()`: the sketched values are imposed, so they say nothing about
+    /// their causes, which are sampled as usual.
+    #[default]
+    Intervention,
+    /// As evidence: only completions that happen to agree are kept, so the
+    /// causes shift to explain the sketch.
+    Evidence,
+}
+
+#[derive(Serialize)]
+#[serde(rename_all = "camelCase")]
+pub struct Scenario {
+    /// Every node's value, in `topo_order`.
+    pub values: Vec<bool>,
+    /// Natural log of the completion's probability under the unmodified
+    /// network, so `-Infinity` when an imposed value is impossible there.
+    pub log_probability: f64,
+}
+
+#[derive(Serialize)]
+#[serde(rename_all = "camelCase")]
+pub struct ScenarioCompletions {
+    pub topo_order: Vec<String>,
+    /// Fingerprint of `topo_order`, as in `index_map`.
+    pub layout_fingerprint: String,
+    pub scenarios: Vec<Scenario>,
+    /// Share of draws that agreed with the sketch, in evidence mode.
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub acceptance_rate: Option<f64>,
+}
+
+/// `count` full assignments of `serialized` agreeing with `partial`.
+///
+/// In evidence mode the draws are rejection sampled; if fewer than `count`
+/// agree within [`limits::MAX_SAMPLES`] draws the sketch is too unlikely and
+/// this fails rather than returning fewer scenarios.
+pub(crate) fn complete_scenarios(
+    nodes: &[Node],
+    serialized: &SerializedNetwork,
+    partial: &HashMap<String, bool>,
+    count: usize,
+    mode: ClampMode,
+    rng: &mut impl Rng,
+) -> Result<ScenarioCompletions> {
+    let num_nodes = usize::from(serialized.num_nodes());
+    let mut clamped = Vec::with_capacity(partial.len());
+    for (id, &value) in partial {
+        clamped.push((lookup::resolve(serialized, "Scenario", id)?, value));
+    }
+    let mut overrides = vec![None; num_nodes];
+    if let ClampMode::Intervention = mode {
+        for &(index, value) in &clamped {
+            overrides[usize::from(index)] = Some(Override::Value(value));
+        }
+    }
+
+    let mut samples = Vec::with_capacity(count);
+    let mut draws = 0usize;
+    while samples.len() < count {
+        if draws == limits::MAX_SAMPLES {
+            bail!(
+                "Only {} of {count} draws agreed with the scenario in {draws}; it is too unlikely to complete",
+                samples.len()
+            );
+        }
+        draws += 1;
+        let sample = sample::sample(&serialized.data, &serialized.offsets, &overrides, rng)?;
+        if clamped
+            .iter()
+            .all(|&(index, value)| sample.contains(index) == value)
+        {
+            samples.push(sample);
+        }
+    }
+
+    let by_id: HashMap<&str, &Node> = nodes.iter().map(|node| (node.id.as_str(), node)).collect();
+    let scenarios = samples
+        .iter()
+        .map(|sample| {
+            let value = |id: &str| {
+                serialized
+                    .index_of(id)
+                    .is_some_and(|index| sample.contains(index))
+            };
+            let mut log_probability = 0.0;
+            for id in &serialized.topo_order {
+                let p_true = by_id[id.as_str()]
+                    .probability_given(value)
+                    .ok_or_else(|| anyhow!("Node {id} has no CPT entry for a completion"))?;
+                log_probability += if value(id) { p_true } else { 1.0 - p_true }.ln();
+            }
+            Ok(Scenario {
+                values: (0..serialized.num_nodes())
+                    .map(|index| sample.contains(index))
+                    .collect(),
+                log_probability,
+            })
+        })
+        .collect::<Result<_>>()?;
+
+    #[allow(clippy::cast_precision_loss)]
+    let acceptance_rate = match mode {
+        ClampMode::Intervention => None,
+        ClampMode::Evidence => Some(count as f64 / draws as f64),
+    };
+    Ok(ScenarioCompletions {
+        topo_order: serialized.topo_order.clone(),
+        layout_fingerprint: layout_fingerprint(&serialized.topo_order),
+        scenarios,
+        acceptance_rate,
+    })
+}
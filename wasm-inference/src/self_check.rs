@@ -0,0 +1,214 @@
+//! One-call diagnostic comparing the sampler against exact enumeration.
+//!
+//! The network is reduced to a small ancestral sub-network around the query
+//! targets, which preserves their marginals, so both engines can run on it.
+
+use anyhow::{Result, anyhow, bail};
+use rand_xoshiro::Xoshiro128Plus;
+use serde::Serialize;
+use std::collections::{BTreeMap, BTreeSet, HashMap};
+
+use crate::Node;
+use crate::assumptions::AssumptionSet;
+use crate::exact::{MAX_EXACT_NODES, exact_marginals};
+use crate::lookup::find_node;
+use crate::marginals::{QueryMeta, ZeroHandling, estimate_marginals_with_soft};
+use crate::options::QueryOptions;
+use crate::serialize::{get_node_parents, serialize_network};
+
+/// Standard errors a discrepancy may reach before it counts as a failure.
+/// With at most 15 checked nodes this keeps false alarms below ~0.1%.
+const TOLERANCE_STANDARD_ERRORS: f64 = 4.0;
+/// Slack for the f32 rounding of probabilities in the sampler.
+const TOLERANCE_FLOOR: f64 = 1e-6;
+
+#[derive(Serialize)]
+#[serde(rename_all = "camelCase")]
+pub struct NodeCheck {
+    pub node_id: String,
+    pub exact: f64,
+    pub sampled: f64,
+    pub discrepancy: f64,
+    pub tolerance: f64,
+    pub passed: bool,
+}
+
+#[derive(Serialize)]
+#[serde(rename_all = "camelCase")]
+pub struct SelfCheckReport {
+    pub passed: bool,
+    /// Node IDs of the checked sub-network, in input order.
+    pub subnetwork: Vec<String>,
+    /// Targets left out because their ancestors did not fit.
+    pub skipped_targets: Vec<String>,
+    pub evidence_probability: f64,
+    pub nodes: Vec<NodeCheck>,
+    pub meta: QueryMeta,
+}
+
+/// Runs exact enumeration and the sampler on a reduced sub-network and
+/// compares every node's marginal against a sampling-noise tolerance.
+///
+/// `assumptions` must already be resolved against `nodes`. Evidence nodes
+/// (hard or soft) and their ancestors are always kept; targets (every node when none are given)
+/// are then added in order while the sub-network stays within 15 nodes.
+/// Ancestors are not followed past intervened or clamped nodes, since their
+/// CPTs are ignored.
+pub fn self_check(
+    nodes: &[Node],
+    options: &QueryOptions,
+    num_samples: usize,
+    assumptions: &AssumptionSet,
+    rng: &mut Xoshiro128Plus,
+) -> Result<SelfCheckReport> {
+    if num_samples == 0 {
+        bail!("Self-check needs at least one sample");
+    }
+    let nodes_by_id: HashMap<&str, &Node> = nodes.iter().map(|n| (n.id.as_str(), n)).collect();
+    let targets: Vec<&str> = if options.targets.is_empty() {
+        nodes.iter().map(|n| n.id.as_str()).collect()
+    } else {
+        options.targets.iter().map(String::as_str).collect()
+    };
+    for target in &targets {
+        find_node(nodes, "Target", target)?;
+    }
+
+    let is_overridden = |id: &str| {
+        assumptions.interventions.contains_key(id) || assumptions.clamps.contains_key(id)
+    };
+    let evidence_ids: Vec<&str> = assumptions
+        .evidence
+        .keys()
+        .chain(options.soft_evidence.keys())
+        .map(String::as_str)
+        .collect();
+    let mut included = ancestral_closure(
+        &nodes_by_id,
+        &evidence_ids,
+        &BTreeSet::new(),
+        &is_overridden,
+    )?;
+    if included.len() > MAX_EXACT_NODES {
+        bail!(
+            "The evidence and its ancestors span {} nodes, more than the {MAX_EXACT_NODES} that can be checked exactly",
+            included.len()
+        );
+    }
+    let mut skipped_targets = Vec::new();
+    for &target in &targets {
+        let extended = ancestral_closure(&nodes_by_id, &[target], &included, &is_overridden)?;
+        if extended.len() <= MAX_EXACT_NODES {
+            included = extended;
+        } else {
+            skipped_targets.push(target.to_string());
+        }
+    }
+
+    let subnetwork = subnetwork(nodes, &included, &is_overridden);
+    let sub_assumptions = AssumptionSet {
+        name: assumptions.name.clone(),
+        interventions: restrict(&assumptions.interventions, &included),
+        evidence: assumptions.evidence.clone(),
+        clamps: restrict(&assumptions.clamps, &included),
+    };
+
+    let exact = exact_marginals(&subnetwork, &sub_assumptions, &options.soft_evidence)?;
+    let serialized = serialize_network(&subnetwork)?;
+    let overrides = sub_assumptions.overrides(&serialized)?;
+    let evidence = sub_assumptions.evidence_indices(&serialized)?;
+    let soft_evidence = options.soft_evidence_indices(&serialized)?;
+    let (sampled, meta) = estimate_marginals_with_soft(
+        options.algorithm,
+        &serialized,
+        num_samples,
+        &overrides,
+        &evidence,
+        &soft_evidence,
+        ZeroHandling::Strict,
+        rng,
+    )?;
+
+    #[allow(clippy::cast_precision_loss)]
+    let effective_samples = num_samples as f64 * exact.evidence_probability;
+    let checks: Vec<NodeCheck> = subnetwork
+        .iter()
+        .map(|node| {
+            let exact = exact.marginals[&node.id];
+            let sampled = sampled[&node.id];
+            let discrepancy = (sampled - exact).abs();
+            let standard_error = (exact * (1.0 - exact) / effective_samples).sqrt();
+            let tolerance = TOLERANCE_STANDARD_ERRORS * standard_error + TOLERANCE_FLOOR;
+            NodeCheck {
+                node_id: node.id.clone(),
+                exact,
+                sampled,
+                discrepancy,
+                tolerance,
+                passed: discrepancy <= tolerance,
+            }
+        })
+        .collect();
+
+    Ok(SelfCheckReport {
+        passed: checks.iter().all(|check| check.passed),
+        subnetwork: subnetwork.into_iter().map(|n| n.id).collect(),
+        skipped_targets,
+        evidence_probability: exact.evidence_probability,
+        nodes: checks,
+        meta,
+    })
+}
+
+/// `included` extended by `start` and its ancestors, stopping at overridden
+/// nodes.
+fn ancestral_closure<'a>(
+    nodes_by_id: &HashMap<&'a str, &'a Node>,
+    start: &[&'a str],
+    included: &BTreeSet<&'a str>,
+    is_overridden: &impl Fn(&str) -> bool,
+) -> Result<BTreeSet<&'a str>> {
+    let mut closure = included.clone();
+    let mut stack = start.to_vec();
+    while let Some(id) = stack.pop() {
+        if !closure.insert(id) || is_overridden(id) {
+            continue;
+        }
+        let node = nodes_by_id
+            .get(id)
+            .ok_or_else(|| anyhow!("Node {id} not found"))?;
+        stack.extend(get_node_parents(node));
+    }
+    Ok(closure)
+}
+
+/// The `included` nodes, with observations dropped (the assumptions carry
+/// them). Overridden nodes may have lost their parents; their CPTs are never
+/// consulted, so a placeholder prior keeps the sub-network valid.
+fn subnetwork(
+    nodes: &[Node],
+    included: &BTreeSet<&str>,
+    is_overridden: &impl Fn(&str) -> bool,
+) -> Vec<Node> {
+    nodes
+        .iter()
+        .filter(|n| included.contains(n.id.as_str()))
+        .map(|n| {
+            if is_overridden(&n.id) {
+                Node::with_prior(n.id.clone(), 0.5)
+            } else {
+                Node {
+                    observed: None,
+                    ..n.clone()
+                }
+            }
+        })
+        .collect()
+}
+
+fn restrict<T: Copy>(map: &BTreeMap<String, T>, included: &BTreeSet<&str>) -> BTreeMap<String, T> {
+    map.iter()
+        .filter(|(id, _)| included.contains(id.as_str()))
+        .map(|(id, &value)| (id.clone(), value))
+        .collect()
+}
@@ -1,14 +1,245 @@
 use anyhow::{Result, anyhow, bail};
+use serde::Serialize;
 use std::collections::{HashMap, HashSet, VecDeque};
+use std::io::{self, Write};
 
+use crate::cpt_table::expand_table;
+use crate::sample::{ENTRY_FIXED, ENTRY_HIERARCHICAL, EntryProbability};
 use crate::{CptEntry, Node};
 
 pub struct SerializedNetwork {
     pub data: Vec<u8>,
     pub topo_order: Vec<String>,
+    /// Parents of each node by topological index, ascending.
+    pub parents: Vec<Vec<u8>>,
+    /// Start of each node's record in `data`, followed by `data.len()`.
+    pub offsets: Vec<usize>,
 }
 
+impl SerializedNetwork {
+    pub fn num_nodes(&self) -> u8 {
+        u8::try_from(self.topo_order.len()).expect("serialize_network caps networks at 255 nodes")
+    }
+
+    /// Children of each node by topological index, ascending.
+    pub fn children(&self) -> Vec<Vec<u8>> {
+        let mut children = vec![Vec::new(); self.parents.len()];
+        for (child, parents) in self.parents.iter().enumerate() {
+            let child = u8::try_from(child).expect("serialize_network caps networks at 255 nodes");
+            for &parent in parents {
+                children[usize::from(parent)].push(child);
+            }
+        }
+        children
+    }
+
+    pub fn index_of(&self, node_id: &str) -> Option<u8> {
+        self.topo_order
+            .iter()
+            .position(|id| id == node_id)
+            .map(|idx| u8::try_from(idx).expect("serialize_network caps networks at 255 nodes"))
+    }
+
+    /// Serializes a new version of an existing node on its own, or returns
+    /// `None` when the node is unknown or its parent set changed, in which
+    /// case the topological order may no longer hold.
+    pub(crate) fn reserialize_node(&self, node: &Node) -> Result<Option<(u8, Vec<u8>)>> {
+        let Some(index) = self.index_of(&node.id) else {
+            return Ok(None);
+        };
+        if node.cpt_entries.is_empty() {
+            bail!("{}", empty_cpt_message(&node.id));
+        }
+        let parent_ids = get_node_parents(node);
+        let Some(id_to_topo_index) = parent_ids
+            .iter()
+            .map(|&id| self.index_of(id).map(|idx| (id, idx)))
+            .collect::<Option<HashMap<&str, u8>>>()
+        else {
+            return Ok(None);
+        };
+        let mut parents: Vec<u8> = id_to_topo_index.values().copied().collect();
+        parents.sort_unstable();
+        if parents != self.parents[usize::from(index)] {
+            return Ok(None);
+        }
+        let mut record = Vec::new();
+        serialize_node(node, &parent_ids, &id_to_topo_index, &mut record)?;
+        Ok(Some((index, record)))
+    }
+
+    /// Swaps in a record from [`Self::reserialize_node`], shifting the offsets
+    /// of the nodes after it.
+    pub(crate) fn replace_record(&mut self, index: u8, record: Vec<u8>) {
+        let index = usize::from(index);
+        let (start, end) = (self.offsets[index], self.offsets[index + 1]);
+        let new_end = start + record.len();
+        self.data.splice(start..end, record);
+        for offset in &mut self.offsets[index + 1..] {
+            *offset = *offset - end + new_end;
+        }
+    }
+}
+
+/// Gives the node `node_id` of `nodes` (the array `serialized` was compiled
+/// from) the CPT `new_entries`. With the same parents and entry count, the
+/// record keeps its length and is overwritten in place; otherwise every
+/// record is compiled again. Returns whether the whole network was.
+///
+/// Either way the result is byte for byte what [`serialize_network`] makes of
+/// the updated array. On error `serialized` is left as it was.
+pub(crate) fn update_node_cpt(
+    serialized: &mut SerializedNetwork,
+    node_id: &str,
+    new_entries: &[CptEntry],
+    nodes: &[Node],
+) -> Result<bool> {
+    let node = nodes
+        .iter()
+        .find(|node| node.id == node_id)
+        .ok_or_else(|| anyhow!("Node {node_id} not found"))?;
+    let updated = Node {
+        cpt_entries: new_entries.to_vec(),
+        cpt_table: None,
+        ..node.clone()
+    };
+    if new_entries.len() == node.cpt_entries.len()
+        && let Some((index, record)) = serialized.reserialize_node(&updated)?
+    {
+        let index = usize::from(index);
+        let (start, end) = (serialized.offsets[index], serialized.offsets[index + 1]);
+        if record.len() == end - start {
+            serialized.data[start..end].copy_from_slice(&record);
+            return Ok(false);
+        }
+    }
+    let nodes: Vec<Node> = nodes
+        .iter()
+        .map(|node| {
+            if node.id == node_id {
+                updated.clone()
+            } else {
+                node.clone()
+            }
+        })
+        .collect();
+    *serialized = serialize_network(&nodes)?;
+    Ok(true)
+}
+
+/// Where each node sits in compiled samples, for data that refers to nodes
+/// by bit position.
+#[derive(Serialize)]
+#[serde(rename_all = "camelCase")]
+pub struct IndexMap {
+    pub topo_order: Vec<String>,
+    pub id_to_index: HashMap<String, u8>,
+    /// [`layout_fingerprint`] of `topo_order`.
+    pub fingerprint: String,
+}
+
+impl SerializedNetwork {
+    pub(crate) fn index_map(&self) -> IndexMap {
+        IndexMap {
+            topo_order: self.topo_order.clone(),
+            id_to_index: self.topo_order.iter().cloned().zip(0..).collect(),
+            fingerprint: layout_fingerprint(&self.topo_order),
+        }
+    }
+
+    /// Stable 64-bit FNV-1a hash of the topological order and compiled bytes,
+    /// identifying exactly which model produced a result.
+    pub fn fingerprint(&self) -> String {
+        let bytes = self
+            .topo_order
+            .iter()
+            .flat_map(|id| id.bytes().chain([0]))
+            .chain(self.data.iter().copied());
+        format!("{:016x}", fnv1a(bytes))
+    }
+}
+
+/// Hash of a topological order, which fixes what each bit of a compiled
+/// sample means. Unlike [`SerializedNetwork::fingerprint`] it survives edits
+/// that leave every node at its index, such as new probabilities.
+#[must_use]
+pub fn layout_fingerprint(topo_order: &[String]) -> String {
+    format!("{:016x}", layout_hash(topo_order))
+}
+
+pub(crate) fn layout_hash(topo_order: &[String]) -> u64 {
+    fnv1a(topo_order.iter().flat_map(|id| id.bytes().chain([0])))
+}
+
+pub(crate) fn fnv1a(bytes: impl IntoIterator<Item = u8>) -> u64 {
+    const OFFSET_BASIS: u64 = 0xcbf2_9ce4_8422_2325;
+    const PRIME: u64 = 0x0100_0000_01b3;
+    bytes.into_iter().fold(OFFSET_BASIS, |hash, byte| {
+        (hash ^ u64::from(byte)).wrapping_mul(PRIME)
+    })
+}
+
+/// Compiles `nodes`, expanding any `cpt_table` into entries first.
 pub fn serialize_network(nodes: &[Node]) -> Result<SerializedNetwork> {
+    let mut data = Vec::new();
+    let Layout {
+        topo_order,
+        parents,
+        offsets,
+    } = write_network(nodes, &mut data)?;
+    Ok(SerializedNetwork {
+        data,
+        topo_order,
+        parents,
+        offsets,
+    })
+}
+
+/// Writes the compiled records of `nodes` to `writer` as they are produced,
+/// without holding the whole buffer, and returns the topological order. The
+/// bytes are the `data` of [`serialize_network`].
+#[allow(clippy::missing_errors_doc)]
+pub fn serialize_network_to_writer<W: Write>(
+    nodes: &[Node],
+    writer: &mut W,
+) -> Result<Vec<String>> {
+    write_network(nodes, writer).map(|layout| layout.topo_order)
+}
+
+/// Everything [`SerializedNetwork`] holds besides the records.
+struct Layout {
+    topo_order: Vec<String>,
+    parents: Vec<Vec<u8>>,
+    offsets: Vec<usize>,
+}
+
+/// Counts the bytes passing through, so record offsets are known without
+/// reading them back.
+struct Counting<W> {
+    inner: W,
+    written: usize,
+}
+
+impl<W: Write> Write for Counting<W> {
+    fn write(&mut self, buf: &[u8]) -> io::Result<usize> {
+        let written = self.inner.write(buf)?;
+        self.written += written;
+        Ok(written)
+    }
+
+    fn flush(&mut self) -> io::Result<()> {
+        self.inner.flush()
+    }
+}
+
+fn write_network(nodes: &[Node], writer: &mut impl Write) -> Result<Layout> {
+    if nodes.iter().any(|node| node.cpt_table.is_some()) {
+        let mut expanded = nodes.to_vec();
+        for node in &mut expanded {
+            expand_table(node)?;
+        }
+        return write_network(&expanded, writer);
+    }
     if nodes.len() > 255 {
         bail!(
             "Network has {len} nodes, maximum 255 supported",
@@ -28,6 +259,11 @@ pub fn serialize_network(nodes: &[Node]) -> Result<SerializedNetwork> {
         .collect();
 
     for node in nodes {
+        // Every draw from such a node fails, so refuse it here rather than at
+        // sample time.
+        if node.cpt_entries.is_empty() {
+            bail!("{}", empty_cpt_message(&node.id));
+        }
         let parents = parents_cache
             .get(node.id.as_str())
             .ok_or_else(|| anyhow!("Parents for node {} not found in cache", node.id))?;
@@ -54,31 +290,47 @@ pub fn serialize_network(nodes: &[Node]) -> Result<SerializedNetwork> {
         })
         .collect();
 
-    let mut buffer = Vec::new();
+    let mut writer = Counting {
+        inner: writer,
+        written: 0,
+    };
+    let mut parents = Vec::with_capacity(topo_order.len());
+    let mut offsets = Vec::with_capacity(topo_order.len() + 1);
 
     for node_id in &topo_order {
         let node = nodes_by_id
             .get(node_id.as_str())
             .ok_or_else(|| anyhow!("Node {node_id} not found"))?;
 
-        let parents = parents_cache
+        let node_parents = parents_cache
             .get(node_id.as_str())
             .ok_or_else(|| anyhow!("Parents for node {node_id} not found in cache"))?;
 
-        serialize_node(node, parents, &id_to_topo_index, &mut buffer)?;
+        offsets.push(writer.written);
+        parents.push(serialize_node(
+            node,
+            node_parents,
+            &id_to_topo_index,
+            &mut writer,
+        )?);
     }
 
-    Ok(SerializedNetwork {
-        data: buffer,
+    offsets.push(writer.written);
+
+    Ok(Layout {
         topo_order,
+        parents,
+        offsets,
     })
 }
 
+/// Kahn's algorithm, breaking ties by input order so the same node array
+/// always yields the same order (and so the same random stream per seed).
 fn topological_sort(
     nodes: &[Node],
     parents_cache: &HashMap<&str, Vec<&str>>,
 ) -> Result<Vec<String>> {
-    let mut graph: HashMap<&str, HashSet<&str>> = HashMap::new();
+    let mut children: HashMap<&str, Vec<&str>> = HashMap::new();
     let mut in_degree: HashMap<&str, usize> = HashMap::new();
 
     for node in nodes {
@@ -89,16 +341,19 @@ fn topological_sort(
             .ok_or_else(|| anyhow!("Parents for node {id} not found in cache", id = node.id))?;
 
         for &parent_id in parents {
-            graph.entry(parent_id).or_default().insert(node.id.as_str());
+            children
+                .entry(parent_id)
+                .or_default()
+                .push(node.id.as_str());
             *in_degree.entry(node.id.as_str()).or_insert(0) += 1;
             in_degree.entry(parent_id).or_insert(0);
         }
     }
 
-    let mut queue: VecDeque<&str> = in_degree
+    let mut queue: VecDeque<&str> = nodes
         .iter()
-        .filter(|&(_, &deg)| deg == 0)
-        .map(|(&id, _)| id)
+        .map(|node| node.id.as_str())
+        .filter(|id| in_degree.get(id) == Some(&0))
         .collect();
 
     let mut result = Vec::new();
@@ -106,7 +361,7 @@ fn topological_sort(
     while let Some(node_id) = queue.pop_front() {
         result.push(node_id.to_string());
 
-        if let Some(children) = graph.get(node_id) {
+        if let Some(children) = children.get(node_id) {
             for &child_id in children {
                 let deg = in_degree.get_mut(child_id).unwrap();
                 *deg -= 1;
@@ -128,24 +383,36 @@ fn topological_sort(
     Ok(result)
 }
 
-fn get_node_parents(node: &Node) -> Vec<&str> {
+/// Shared with the validator so the lint and the compile error read alike.
+pub(crate) fn empty_cpt_message(node_id: &str) -> String {
+    format!(
+        "Node {node_id} has no CPT entries; add a default probability, an entry with no \
+         parent states"
+    )
+}
+
+pub(crate) fn get_node_parents(node: &Node) -> Vec<&str> {
     let mut all_parents = HashSet::new();
 
     for entry in &node.cpt_entries {
         for parent_id in entry.parent_states.keys() {
             all_parents.insert(parent_id.as_str());
         }
+        if let Some(params) = &entry.probability_params {
+            all_parents.insert(params.hyperparameter_id.as_str());
+        }
     }
 
     all_parents.into_iter().collect()
 }
 
+/// Writes the node's record to `writer`, returning its parents' indices.
 fn serialize_node(
     node: &Node,
     parent_ids: &[&str],
     id_to_topo_index: &HashMap<&str, u8>,
-    buffer: &mut Vec<u8>,
-) -> Result<()> {
+    writer: &mut impl Write,
+) -> Result<Vec<u8>> {
     let parent_index_pairs: Vec<(&str, u8)> = parent_ids
         .iter()
         .map(|&id| {
@@ -165,21 +432,118 @@ fn serialize_node(
 
     let num_parents = u8::try_from(parent_indices.len())
         .map_err(|_| anyhow!("Number of parents exceeds u8::MAX"))?;
-    buffer.push(num_parents);
-    buffer.extend_from_slice(&parent_indices);
+    writer.write_all(&[num_parents])?;
+    writer.write_all(&parent_indices)?;
+
+    let (floor, ceiling) = probability_bounds(node)?;
+    writer.write_all(&floor.to_le_bytes())?;
+    writer.write_all(&ceiling.to_le_bytes())?;
+    writer.write_all(&leak_probability(node)?.to_le_bytes())?;
 
     let num_cpt_entries = u8::try_from(node.cpt_entries.len())
         .map_err(|_| anyhow!("Number of CPT entries exceeds u8::MAX"))?;
-    buffer.push(num_cpt_entries);
+    writer.write_all(&[num_cpt_entries])?;
+
+    for (entry_idx, entry) in node.cpt_entries.iter().enumerate() {
+        // Checked as given first, so a bad `P(false)` is reported as entered
+        // rather than flipped.
+        let canonical = |probability| {
+            canonical_probability(&node.id, entry_idx, probability)?;
+            canonical_probability(
+                &node.id,
+                entry_idx,
+                entry.as_probability_of_true(probability),
+            )
+        };
+        let probability = match &entry.probability_params {
+            None => EntryProbability::Fixed(canonical(entry.probability)?),
+            Some(params) => EntryProbability::Hierarchical {
+                parent: sorted_parent_ids
+                    .iter()
+                    .position(|&id| id == params.hyperparameter_id)
+                    .and_then(|position| u8::try_from(position).ok())
+                    .expect("hyperparameters are among the node's parents"),
+                high: canonical(params.p_high)?,
+                low: canonical(params.p_low)?,
+            },
+        };
+        serialize_cpt_entry(entry, &probability, &sorted_parent_ids, writer)?;
+    }
 
-    for entry in &node.cpt_entries {
-        serialize_cpt_entry(entry, &sorted_parent_ids, buffer);
+    Ok(parent_indices)
+}
+
+/// The node's floor and ceiling, defaulting to `[0, 1]`.
+pub(crate) fn probability_bounds(node: &Node) -> Result<(f32, f32)> {
+    let floor = node.probability_floor.unwrap_or(0.0);
+    let ceiling = node.probability_ceiling.unwrap_or(1.0);
+    for (name, bound) in [("floor", floor), ("ceiling", ceiling)] {
+        if !(0.0..=1.0).contains(&bound) {
+            bail!(
+                "Node {id} has probability {name} {bound}, outside [0, 1]",
+                id = node.id
+            );
+        }
+    }
+    if floor > ceiling {
+        bail!(
+            "Node {id} has probability floor {floor} above its ceiling {ceiling}",
+            id = node.id
+        );
     }
+    #[allow(clippy::cast_possible_truncation)]
+    Ok((floor as f32, ceiling as f32))
+}
 
-    Ok(())
+/// The node's leak, defaulting to 0.
+pub(crate) fn leak_probability(node: &Node) -> Result<f32> {
+    let leak = node.leak_probability.unwrap_or(0.0);
+    if !(0.0..=1.0).contains(&leak) {
+        bail!(
+            "Node {id} has leak probability {leak}, outside [0, 1]",
+            id = node.id
+        );
+    }
+    #[allow(clippy::cast_possible_truncation)]
+    Ok(leak as f32)
 }
 
-fn serialize_cpt_entry(entry: &CptEntry, parent_ids: &[&str], buffer: &mut Vec<u8>) {
+/// Converts a JS-provided probability to the f32 stored in the network.
+///
+/// Negative zero becomes 0.0. Non-zero values too small for a normal f32 are
+/// rejected rather than clamped, since sampling would treat them as 0 while the
+/// editor still displays a non-zero value. NaN, infinities and values outside
+/// `[0, 1]` are rejected.
+pub(crate) fn canonical_probability(
+    node_id: &str,
+    entry_idx: usize,
+    probability: f64,
+) -> Result<f32> {
+    if !probability.is_finite() {
+        bail!("Node {node_id} CPT entry {entry_idx} has non-finite probability {probability}");
+    }
+    if !(0.0..=1.0).contains(&probability) {
+        bail!("Node {node_id} CPT entry {entry_idx} has probability {probability}, outside [0, 1]");
+    }
+    if probability == 0.0 {
+        return Ok(0.0);
+    }
+    if probability.abs() < f64::from(f32::MIN_POSITIVE) {
+        bail!(
+            "Node {node_id} CPT entry {entry_idx} has probability {probability:e}, \
+             which is too small to represent; use 0 instead"
+        );
+    }
+    #[allow(clippy::cast_possible_truncation)]
+    Ok(probability as f32)
+}
+
+fn serialize_cpt_entry(
+    entry: &CptEntry,
+    probability: &EntryProbability,
+    parent_ids: &[&str],
+    writer: &mut impl Write,
+) -> io::Result<()> {
     let num_pattern_bytes = parent_ids.len().div_ceil(4);
     let mut pattern_bytes = vec![0u8; num_pattern_bytes];
 
@@ -200,6 +564,16 @@ fn serialize_cpt_entry(entry: &CptEntry, parent_ids: &[&str], buffer: &mut Vec<u
         }
     }
 
-    buffer.extend_from_slice(&pattern_bytes);
-    buffer.extend_from_slice(&entry.probability.to_le_bytes());
+    writer.write_all(&pattern_bytes)?;
+    match *probability {
+        EntryProbability::Fixed(probability) => {
+            writer.write_all(&[ENTRY_FIXED])?;
+            writer.write_all(&probability.to_le_bytes())
+        }
+        EntryProbability::Hierarchical { parent, high, low } => {
+            writer.write_all(&[ENTRY_HIERARCHICAL, parent])?;
+            writer.write_all(&high.to_le_bytes())?;
+            writer.write_all(&low.to_le_bytes())
+        }
+    }
 }
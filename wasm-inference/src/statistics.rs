@@ -0,0 +1,333 @@
+use anyhow::{Result, bail};
+use rand::Rng;
+use rand_xoshiro::Xoshiro128Plus;
+use serde::Serialize;
+use std::f64::consts::PI;
+
+use crate::bit_set::BitSet;
+
+/// Upper tail probability `P(X > x)` for a chi-squared variable with `df`
+/// degrees of freedom.
+pub(crate) fn chi_squared_sf(x: f64, df: f64) -> f64 {
+    if x <= 0.0 {
+        return 1.0;
+    }
+    regularized_gamma_q(df / 2.0, x / 2.0)
+}
+
+/// Natural log of the gamma function (Lanczos approximation).
+pub(crate) fn ln_gamma(x: f64) -> f64 {
+    const COEFFICIENTS: [f64; 6] = [
+        76.180_091_729_471_46,
+        -86.505_320_329_416_77,
+        24.014_098_240_830_91,
+        -1.231_739_572_450_155,
+        0.001_208_650_973_866_179,
+        -0.000_005_395_239_384_953,
+    ];
+    let tmp = x + 5.5;
+    let tmp = tmp - (x + 0.5) * tmp.ln();
+    let mut series = 1.000_000_000_190_015;
+    let mut y = x;
+    for coefficient in COEFFICIENTS {
+        y += 1.0;
+        series += coefficient / y;
+    }
+    -tmp + (2.506_628_274_631_000_5 * series / x).ln()
+}
+
+/// Regularized upper incomplete gamma function `Q(a, x)`.
+fn regularized_gamma_q(a: f64, x: f64) -> f64 {
+    if x < a + 1.0 {
+        1.0 - gamma_p_series(a, x)
+    } else {
+        gamma_q_continued_fraction(a, x)
+    }
+}
+
+const MAX_ITERATIONS: usize = 500;
+const EPSILON: f64 = 1e-14;
+
+#[allow(clippy::many_single_char_names)]
+fn gamma_p_series(a: f64, x: f64) -> f64 {
+    let mut term = 1.0 / a;
+    let mut sum = term;
+    let mut denominator = a;
+    for _ in 0..MAX_ITERATIONS {
+        denominator += 1.0;
+        term *= x / denominator;
+        sum += term;
+        if term.abs() < sum.abs() * EPSILON {
+            break;
+        }
+    }
+    sum * (-x + a * x.ln() - ln_gamma(a)).exp()
+}
+
+#[allow(clippy::many_single_char_names)]
+fn gamma_q_continued_fraction(a: f64, x: f64) -> f64 {
+    const TINY: f64 = 1e-300;
+    let mut b = x + 1.0 - a;
+    let mut c = 1.0 / TINY;
+    let mut d = 1.0 / b;
+    let mut h = d;
+    for i in 1..=MAX_ITERATIONS {
+        #[allow(clippy::cast_precision_loss)]
+        let i = i as f64;
+        let an = -i * (i - a);
+        b += 2.0;
+        d = an * d + b;
+        if d.abs() < TINY {
+            d = TINY;
+        }
+        c = b + an / c;
+        if c.abs() < TINY {
+            c = TINY;
+        }
+        d = 1.0 / d;
+        let delta = d * c;
+        h *= delta;
+        if (delta - 1.0).abs() < EPSILON {
+            break;
+        }
+    }
+    (-x + a * x.ln() - ln_gamma(a)).exp() * h
+}
+
+/// Added to the diagonal of a correlation matrix unless the caller says
+/// otherwise.
+pub(crate) const DEFAULT_REGULARIZATION: f64 = 1e-9;
+/// Pivots smaller than this make a matrix count as singular.
+const SINGULAR_PIVOT: f64 = 1e-12;
+
+/// Partial correlation of every pair of nodes given all the others, indexed
+/// by topological index.
+///
+/// The correlation matrix `R` of the non-constant nodes, with
+/// `regularization` added to its diagonal, is inverted to the precision
+/// matrix `P`, and `pcor_ij = -P_ij / sqrt(P_ii * P_jj)`. Entries involving a
+/// node that never varies are NaN; the diagonal is 1 otherwise.
+pub(crate) fn compute_partial_correlations(
+    samples: &[BitSet],
+    num_nodes: u8,
+    regularization: f64,
+) -> Result<Vec<Vec<f64>>> {
+    let n = usize::from(num_nodes);
+    if samples.is_empty() {
+        bail!("Partial correlations need at least one sample");
+    }
+    // co_true[i][j] counts samples with both i and j true (i <= j).
+    let mut co_true = vec![vec![0usize; n]; n];
+    let mut true_nodes = Vec::with_capacity(n);
+    for sample in samples {
+        true_nodes.clear();
+        true_nodes.extend((0..num_nodes).filter(|&node| sample.contains(node)));
+        for (k, &i) in true_nodes.iter().enumerate() {
+            for &j in &true_nodes[k..] {
+                co_true[usize::from(i)][usize::from(j)] += 1;
+            }
+        }
+    }
+
+    #[allow(clippy::cast_precision_loss)]
+    let total = samples.len() as f64;
+    #[allow(clippy::cast_precision_loss)]
+    let mean: Vec<f64> = (0..n).map(|i| co_true[i][i] as f64 / total).collect();
+    let varying: Vec<usize> = (0..n).filter(|&i| mean[i] > 0.0 && mean[i] < 1.0).collect();
+    let correlation = |i: usize, j: usize| {
+        let (low, high) = (i.min(j), i.max(j));
+        #[allow(clippy::cast_precision_loss)]
+        let covariance = co_true[low][high] as f64 / total - mean[i] * mean[j];
+        covariance / (mean[i] * (1.0 - mean[i]) * mean[j] * (1.0 - mean[j])).sqrt()
+    };
+    let regularized: Vec<Vec<f64>> = varying
+        .iter()
+        .map(|&i| {
+            varying
+                .iter()
+                .map(|&j| {
+                    if i == j {
+                        1.0 + regularization
+                    } else {
+                        correlation(i, j)
+                    }
+                })
+                .collect()
+        })
+        .collect();
+    let Some(precision) = invert(regularized) else {
+        bail!("The correlation matrix is singular; increase the regularization");
+    };
+
+    let mut partial = vec![vec![f64::NAN; n]; n];
+    for (a, &i) in varying.iter().enumerate() {
+        for (b, &j) in varying.iter().enumerate() {
+            partial[i][j] = if a == b {
+                1.0
+            } else {
+                -precision[a][b] / (precision[a][a] * precision[b][b]).sqrt()
+            };
+        }
+    }
+    Ok(partial)
+}
+
+/// Gauss-Jordan inversion with partial pivoting; `None` when singular.
+pub(crate) fn invert(mut matrix: Vec<Vec<f64>>) -> Option<Vec<Vec<f64>>> {
+    let n = matrix.len();
+    let mut inverse: Vec<Vec<f64>> = (0..n)
+        .map(|i| (0..n).map(|j| f64::from(u8::from(i == j))).collect())
+        .collect();
+    for column in 0..n {
+        let pivot = (column..n)
+            .max_by(|&a, &b| matrix[a][column].abs().total_cmp(&matrix[b][column].abs()))?;
+        if matrix[pivot][column].abs() < SINGULAR_PIVOT {
+            return None;
+        }
+        matrix.swap(column, pivot);
+        inverse.swap(column, pivot);
+        let scale = matrix[column][column];
+        for k in 0..n {
+            matrix[column][k] /= scale;
+            inverse[column][k] /= scale;
+        }
+        for row in 0..n {
+            if row == column {
+                continue;
+            }
+            let factor = matrix[row][column];
+            if factor == 0.0 {
+                continue;
+            }
+            for k in 0..n {
+                matrix[row][k] -= factor * matrix[column][k];
+                inverse[row][k] -= factor * inverse[column][k];
+            }
+        }
+    }
+    Some(inverse)
+}
+
+/// Box-Muller; `1 - u` keeps the logarithm's argument in `(0, 1]`.
+pub(crate) fn standard_normal(rng: &mut Xoshiro128Plus) -> f64 {
+    let u = 1.0 - rng.random::<f64>();
+    let v: f64 = rng.random();
+    (-2.0 * u.ln()).sqrt() * (2.0 * PI * v).cos()
+}
+
+/// Acklam's rational approximation, accurate to about `1e-9`.
+#[allow(clippy::excessive_precision, clippy::unreadable_literal)]
+pub(crate) fn inverse_normal_cdf(p: f64) -> f64 {
+    const A: [f64; 6] = [
+        -3.969683028665376e+01,
+        2.209460984245205e+02,
+        -2.759285104469687e+02,
+        1.383577518672690e+02,
+        -3.066479806614716e+01,
+        2.506628277459239e+00,
+    ];
+    const B: [f64; 5] = [
+        -5.447609879822406e+01,
+        1.615858368580409e+02,
+        -1.556989798598866e+02,
+        6.680131188771972e+01,
+        -1.328068155288572e+01,
+    ];
+    const C: [f64; 6] = [
+        -7.784894002430293e-03,
+        -3.223964580411365e-01,
+        -2.400758277161838e+00,
+        -2.549732539343734e+00,
+        4.374664141464968e+00,
+        2.938163982698783e+00,
+    ];
+    const D: [f64; 4] = [
+        7.784695709041462e-03,
+        3.224671290700398e-01,
+        2.445134137142996e+00,
+        3.754408661907416e+00,
+    ];
+    const LOW: f64 = 0.02425;
+
+    if p <= 0.0 {
+        return f64::NEG_INFINITY;
+    }
+    if p >= 1.0 {
+        return f64::INFINITY;
+    }
+    let tail = |q: f64| {
+        (((((C[0] * q + C[1]) * q + C[2]) * q + C[3]) * q + C[4]) * q + C[5])
+            / ((((D[0] * q + D[1]) * q + D[2]) * q + D[3]) * q + 1.0)
+    };
+    if p < LOW {
+        tail((-2.0 * p.ln()).sqrt())
+    } else if p > 1.0 - LOW {
+        -tail((-2.0 * (1.0 - p).ln()).sqrt())
+    } else {
+        let q = p - 0.5;
+        let r = q * q;
+        (((((A[0] * r + A[1]) * r + A[2]) * r + A[3]) * r + A[4]) * r + A[5]) * q
+            / (((((B[0] * r + B[1]) * r + B[2]) * r + B[3]) * r + B[4]) * r + 1.0)
+    }
+}
+
+#[derive(Clone, Copy)]
+pub(crate) enum BoundMethod {
+    Hoeffding,
+    Clt,
+}
+
+/// Samples after which an estimated probability is within `epsilon` of the
+/// truth with probability at least `1 - delta`, whatever the probability:
+/// Hoeffding's `ln(2 / delta) / (2 epsilon^2)`.
+pub(crate) fn hoeffding_sample_size(epsilon: f64, delta: f64) -> usize {
+    ceil_count((2.0 / delta).ln() / (2.0 * epsilon * epsilon))
+}
+
+/// The normal approximation's `(z_{alpha/2} / (2 epsilon))^2`, for the worst
+/// case of a probability of one half. Tighter than
+/// [`hoeffding_sample_size`], but only asymptotically guaranteed.
+pub(crate) fn clt_sample_size(epsilon: f64, alpha: f64) -> usize {
+    let z = inverse_normal_cdf(1.0 - alpha / 2.0);
+    ceil_count((z / (2.0 * epsilon)).powi(2))
+}
+
+pub(crate) fn recommend_samples(epsilon: f64, alpha: f64, method: BoundMethod) -> usize {
+    match method {
+        BoundMethod::Hoeffding => hoeffding_sample_size(epsilon, alpha),
+        BoundMethod::Clt => clt_sample_size(epsilon, alpha),
+    }
+}
+
+#[allow(clippy::cast_possible_truncation, clippy::cast_sign_loss)]
+fn ceil_count(n: f64) -> usize {
+    // Saturates for bounds beyond `usize`.
+    n.ceil().max(1.0) as usize
+}
+
+#[derive(Serialize)]
+#[serde(rename_all = "camelCase")]
+pub struct SampleSizeBounds {
+    pub hoeffding: usize,
+    pub clt: usize,
+    /// Twice the CLT bound, as a margin for its approximation.
+    pub recommended: usize,
+}
+
+/// Both bounds for estimating every marginal to within `epsilon` with
+/// probability `confidence`.
+pub(crate) fn sample_size_bounds(epsilon: f64, confidence: f64) -> Result<SampleSizeBounds> {
+    if !(epsilon > 0.0 && epsilon < 1.0) {
+        bail!("epsilon must be in (0, 1), got {epsilon}");
+    }
+    if !(confidence > 0.0 && confidence < 1.0) {
+        bail!("confidence must be in (0, 1), got {confidence}");
+    }
+    let alpha = 1.0 - confidence;
+    let clt = recommend_samples(epsilon, alpha, BoundMethod::Clt);
+    Ok(SampleSizeBounds {
+        hoeffding: recommend_samples(epsilon, alpha, BoundMethod::Hoeffding),
+        clt,
+        recommended: clt.saturating_mul(2),
+    })
+}
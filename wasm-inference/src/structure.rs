@@ -0,0 +1,524 @@
+use anyhow::{Result, bail};
+use serde::Serialize;
+use std::collections::{BTreeSet, HashMap};
+
+use crate::Node;
+use crate::serialize::{SerializedNetwork, get_node_parents};
+
+/// Parent sets larger than this are not compared parametrically, since every
+/// parent assignment is enumerated.
+const MAX_COMPARED_PARENTS: usize = 16;
+
+#[derive(Serialize)]
+pub struct NetworkDistance {
+    /// Number of edges present in exactly one of the networks.
+    pub structural: f64,
+    /// Mean absolute difference of `P(true)` over every parent assignment of
+    /// nodes whose parent sets agree. Zero when no node is comparable.
+    pub parametric: f64,
+}
+
+/// Distance between two versions of a network over the same node IDs.
+pub fn network_graph_distance(a: &[Node], b: &[Node]) -> Result<NetworkDistance> {
+    let by_id_a: HashMap<&str, &Node> = a.iter().map(|n| (n.id.as_str(), n)).collect();
+    let by_id_b: HashMap<&str, &Node> = b.iter().map(|n| (n.id.as_str(), n)).collect();
+    if by_id_a.len() != a.len() || by_id_b.len() != b.len() {
+        bail!("Duplicate node IDs detected");
+    }
+    let ids_a: BTreeSet<&str> = by_id_a.keys().copied().collect();
+    let ids_b: BTreeSet<&str> = by_id_b.keys().copied().collect();
+    if ids_a != ids_b {
+        let only_a: Vec<&str> = ids_a.difference(&ids_b).copied().collect();
+        let only_b: Vec<&str> = ids_b.difference(&ids_a).copied().collect();
+        bail!(
+            "Networks have different node IDs (only in first: {only_a:?}, only in second: {only_b:?})"
+        );
+    }
+
+    let mut structural = 0usize;
+    let mut total_difference = 0.0;
+    let mut num_compared = 0usize;
+    for id in ids_a {
+        let node_a = by_id_a[id];
+        let node_b = by_id_b[id];
+        let parents_a: BTreeSet<&str> = get_node_parents(node_a).into_iter().collect();
+        let parents_b: BTreeSet<&str> = get_node_parents(node_b).into_iter().collect();
+        structural += parents_a.symmetric_difference(&parents_b).count();
+
+        if parents_a != parents_b || parents_a.len() > MAX_COMPARED_PARENTS {
+            continue;
+        }
+        let parents: Vec<&str> = parents_a.into_iter().collect();
+        for assignment in 0..1usize << parents.len() {
+            let parent_value = |parent_id: &str| {
+                parents
+                    .iter()
+                    .position(|&p| p == parent_id)
+                    .is_some_and(|i| assignment & (1 << i) != 0)
+            };
+            if let (Some(p_a), Some(p_b)) = (
+                node_a.probability_given(parent_value),
+                node_b.probability_given(parent_value),
+            ) {
+                total_difference += (p_a - p_b).abs();
+                num_compared += 1;
+            }
+        }
+    }
+
+    #[allow(clippy::cast_precision_loss)]
+    Ok(NetworkDistance {
+        structural: structural as f64,
+        parametric: if num_compared == 0 {
+            0.0
+        } else {
+            total_difference / num_compared as f64
+        },
+    })
+}
+
+pub type ConditionalMarginals = HashMap<String, Vec<(HashMap<String, bool>, f64)>>;
+
+/// `P(node = true | parents = pa)` for every full assignment `pa` of each
+/// node's parents, read straight from the CPTs (first matching entry, within
+/// the node's bounds). Assignments no entry covers are left out.
+pub fn get_conditional_marginals(nodes: &[Node]) -> Result<ConditionalMarginals> {
+    nodes
+        .iter()
+        .map(|node| {
+            let mut parents = get_node_parents(node);
+            parents.sort_unstable();
+            if parents.len() > MAX_COMPARED_PARENTS {
+                bail!(
+                    "Node {id} has {count} parents; at most {MAX_COMPARED_PARENTS} can be enumerated",
+                    id = node.id,
+                    count = parents.len()
+                );
+            }
+            let rows = (0..1usize << parents.len())
+                .filter_map(|assignment| {
+                    let parent_value = |parent_id: &str| {
+                        parents
+                            .iter()
+                            .position(|&p| p == parent_id)
+                            .is_some_and(|i| assignment & (1 << i) != 0)
+                    };
+                    let probability = node.probability_given(parent_value)?;
+                    let states = parents
+                        .iter()
+                        .enumerate()
+                        .map(|(i, &id)| (id.to_string(), assignment & (1 << i) != 0))
+                        .collect();
+                    Some((states, probability))
+                })
+                .collect();
+            Ok((node.id.clone(), rows))
+        })
+        .collect()
+}
+
+/// Path counts saturate here, which keeps them exact as JS numbers.
+const MAX_PATH_COUNT: u64 = (1 << 53) - 1;
+
+#[derive(Serialize)]
+pub struct PathCount {
+    pub count: u64,
+    /// Set when the true count exceeds `count`, which is then the cap.
+    pub overflow: bool,
+}
+
+/// Every node upstream of `node`, in topological order.
+pub fn ancestors(serialized: &SerializedNetwork, node: u8) -> Vec<String> {
+    closure(serialized, node, &serialized.parents)
+}
+
+/// Every node downstream of `node`, in topological order.
+pub fn descendants(serialized: &SerializedNetwork, node: u8) -> Vec<String> {
+    closure(serialized, node, &serialized.children())
+}
+
+fn closure(serialized: &SerializedNetwork, node: u8, edges: &[Vec<u8>]) -> Vec<String> {
+    let mut reached = vec![false; edges.len()];
+    let mut stack = edges[usize::from(node)].clone();
+    while let Some(next) = stack.pop() {
+        if !std::mem::replace(&mut reached[usize::from(next)], true) {
+            stack.extend(&edges[usize::from(next)]);
+        }
+    }
+    serialized
+        .topo_order
+        .iter()
+        .zip(reached)
+        .filter(|&(_, reached)| reached)
+        .map(|(id, _)| id.clone())
+        .collect()
+}
+
+/// Number of distinct directed paths from `from` to `to`.
+///
+/// Counted by dynamic programming over the topological order, so shared
+/// sub-paths (as in a diamond) are counted once per route rather than
+/// re-walked. A node has a single, empty path to itself.
+pub fn count_paths(serialized: &SerializedNetwork, from: u8, to: u8) -> PathCount {
+    let mut counts = vec![0u64; serialized.parents.len()];
+    let mut overflowed = vec![false; serialized.parents.len()];
+    counts[usize::from(from)] = 1;
+    for node in usize::from(from) + 1..=usize::from(to) {
+        let mut total = 0u64;
+        for &parent in &serialized.parents[node] {
+            let parent = usize::from(parent);
+            total = total.saturating_add(counts[parent]);
+            overflowed[node] |= overflowed[parent];
+        }
+        if total > MAX_PATH_COUNT {
+            overflowed[node] = true;
+            total = MAX_PATH_COUNT;
+        }
+        counts[node] = total;
+    }
+    PathCount {
+        count: counts[usize::from(to)],
+        overflow: overflowed[usize::from(to)],
+    }
+}
+
+/// Whether `x` and `y` are d-separated by `given`.
+///
+/// Searches for an active trail from `x` with the reachability procedure
+/// of Koller and Friedman: a trail passes a non-collider outside `given`, and
+/// a collider only when it or one of its descendants is in `given`.
+pub fn d_separated(serialized: &SerializedNetwork, x: u8, y: u8, given: &[u8]) -> bool {
+    d_separated_in(&serialized.parents, x, y, given)
+}
+
+/// [`d_separated`] over an arbitrary DAG given by each node's parents, such
+/// as a network with some edges cut.
+pub(crate) fn d_separated_in(parents: &[Vec<u8>], x: u8, y: u8, given: &[u8]) -> bool {
+    let num_nodes = parents.len();
+    let mut children = vec![Vec::new(); num_nodes];
+    for (child, node_parents) in (0u8..).zip(parents) {
+        for &parent in node_parents {
+            children[usize::from(parent)].push(child);
+        }
+    }
+    let mut observed = vec![false; num_nodes];
+    for &node in given {
+        observed[usize::from(node)] = true;
+    }
+    // Colliders in here are opened by the evidence: `given` and its ancestors.
+    let mut opens_collider = observed.clone();
+    let mut stack = given.to_vec();
+    while let Some(node) = stack.pop() {
+        for &parent in &parents[usize::from(node)] {
+            if !std::mem::replace(&mut opens_collider[usize::from(parent)], true) {
+                stack.push(parent);
+            }
+        }
+    }
+
+    // Visits are `(node, arrived from a child)`.
+    let mut visited = vec![[false; 2]; num_nodes];
+    let mut queue = vec![(x, true)];
+    while let Some((node, from_child)) = queue.pop() {
+        let index = usize::from(node);
+        if std::mem::replace(&mut visited[index][usize::from(from_child)], true) {
+            continue;
+        }
+        if node == y && !observed[index] {
+            return false;
+        }
+        if !observed[index] {
+            queue.extend(children[index].iter().map(|&child| (child, false)));
+            if from_child {
+                queue.extend(parents[index].iter().map(|&parent| (parent, true)));
+            }
+        }
+        if !from_child && opens_collider[index] {
+            queue.extend(parents[index].iter().map(|&parent| (parent, true)));
+        }
+    }
+    true
+}
+
+/// Trails listed by [`explain_d_separation`] before it stops looking.
+const MAX_EXPLAINED_TRAILS: usize = 1000;
+
+#[derive(Serialize)]
+#[serde(rename_all = "camelCase")]
+pub struct TrailExplanation {
+    /// Node IDs from `x` to `y`.
+    pub path: Vec<String>,
+    pub blocked: bool,
+    /// Evidence nodes the trail passes through without colliding.
+    pub blocking_evidence: Vec<String>,
+    /// Colliders on the trail with neither themselves nor a descendant in
+    /// the evidence.
+    pub closed_colliders: Vec<String>,
+}
+
+#[derive(Serialize)]
+#[serde(rename_all = "camelCase")]
+pub struct DSeparationExplanation {
+    pub d_separated: bool,
+    pub trails: Vec<TrailExplanation>,
+    /// Set when there were more than `trails` lists.
+    pub truncated: bool,
+}
+
+/// Every simple trail between `x` and `y`, ignoring edge directions, up to
+/// `limit`; the flag is set when there were more.
+fn trails(serialized: &SerializedNetwork, x: u8, y: u8, limit: usize) -> (Vec<Vec<u8>>, bool) {
+    let children = serialized.children();
+    let neighbours = |node: u8| {
+        let node = usize::from(node);
+        serialized.parents[node]
+            .iter()
+            .chain(&children[node])
+            .copied()
+    };
+    let mut found = Vec::new();
+    let mut path = vec![x];
+    let mut on_path = vec![false; children.len()];
+    on_path[usize::from(x)] = true;
+    // Each frame is the untried neighbours of the node at that depth.
+    let mut stack: Vec<Vec<u8>> = vec![neighbours(x).collect()];
+    while let Some(untried) = stack.last_mut() {
+        let Some(next) = untried.pop() else {
+            stack.pop();
+            if let Some(node) = path.pop() {
+                on_path[usize::from(node)] = false;
+            }
+            continue;
+        };
+        if on_path[usize::from(next)] {
+            continue;
+        }
+        if next == y {
+            if found.len() == limit {
+                return (found, true);
+            }
+            found.push(path.iter().copied().chain([y]).collect());
+            continue;
+        }
+        path.push(next);
+        on_path[usize::from(next)] = true;
+        stack.push(neighbours(next).collect());
+    }
+    (found, false)
+}
+
+/// Which evidence blocks `trail`. A non-collider blocks when it is observed,
+/// and a collider when neither it nor any descendant is, which
+/// `opens_collider` (the evidence and its ancestors) records.
+fn find_blocking_evidence(
+    serialized: &SerializedNetwork,
+    trail: &[u8],
+    observed: &[bool],
+    opens_collider: &[bool],
+) -> TrailExplanation {
+    let id = |node: u8| serialized.topo_order[usize::from(node)].clone();
+    let mut blocking_evidence = Vec::new();
+    let mut closed_colliders = Vec::new();
+    for window in trail.windows(3) {
+        let [before, node, after] = [window[0], window[1], window[2]];
+        let parents = &serialized.parents[usize::from(node)];
+        if parents.contains(&before) && parents.contains(&after) {
+            if !opens_collider[usize::from(node)] {
+                closed_colliders.push(id(node));
+            }
+        } else if observed[usize::from(node)] {
+            blocking_evidence.push(id(node));
+        }
+    }
+    TrailExplanation {
+        path: trail.iter().map(|&node| id(node)).collect(),
+        blocked: !blocking_evidence.is_empty() || !closed_colliders.is_empty(),
+        blocking_evidence,
+        closed_colliders,
+    }
+}
+
+/// Every trail between `x` and `y` with what blocks it given `evidence`.
+/// `x` and `y` are d-separated exactly when every trail is blocked.
+pub fn explain_d_separation(
+    serialized: &SerializedNetwork,
+    x: u8,
+    y: u8,
+    evidence: &[u8],
+) -> DSeparationExplanation {
+    let mut observed = vec![false; serialized.parents.len()];
+    for &node in evidence {
+        observed[usize::from(node)] = true;
+    }
+    let mut opens_collider = observed.clone();
+    for node in (0..serialized.num_nodes()).rev() {
+        if opens_collider[usize::from(node)] {
+            for &parent in &serialized.parents[usize::from(node)] {
+                opens_collider[usize::from(parent)] = true;
+            }
+        }
+    }
+    let (found, truncated) = trails(serialized, x, y, MAX_EXPLAINED_TRAILS);
+    DSeparationExplanation {
+        d_separated: d_separated(serialized, x, y, evidence),
+        trails: found
+            .iter()
+            .map(|trail| find_blocking_evidence(serialized, trail, &observed, &opens_collider))
+            .collect(),
+        truncated,
+    }
+}
+
+#[derive(Serialize)]
+#[serde(rename_all = "camelCase")]
+pub struct ComplexityMetrics {
+    pub average_markov_blanket_size: f64,
+    pub max_markov_blanket_size: usize,
+    /// Number of nodes with each blanket size, indexed by size.
+    pub markov_blanket_size_distribution: Vec<usize>,
+    /// Width of a min-degree elimination order of the moral graph: an upper
+    /// bound on the treewidth, which exact inference is exponential in.
+    pub treewidth_estimate: usize,
+    pub total_cpt_entries: usize,
+}
+
+/// Each node's neighbours once parents are married and directions dropped.
+/// A node's neighbours here are its Markov blanket.
+fn moral_graph(parents: &[Vec<u8>]) -> Vec<BTreeSet<u8>> {
+    let mut neighbours = vec![BTreeSet::new(); parents.len()];
+    for (child, node_parents) in (0u8..).zip(parents) {
+        for (i, &parent) in node_parents.iter().enumerate() {
+            neighbours[usize::from(child)].insert(parent);
+            neighbours[usize::from(parent)].insert(child);
+            for &other in &node_parents[i + 1..] {
+                neighbours[usize::from(parent)].insert(other);
+                neighbours[usize::from(other)].insert(parent);
+            }
+        }
+    }
+    neighbours
+}
+
+/// Parents, children and the children's other parents of `node`, in
+/// topological order.
+pub fn markov_blanket(serialized: &SerializedNetwork, node: u8) -> Vec<String> {
+    moral_graph(&serialized.parents)[usize::from(node)]
+        .iter()
+        .map(|&index| serialized.topo_order[usize::from(index)].clone())
+        .collect()
+}
+
+/// Structural measures of how hard `nodes` are to do inference on.
+pub fn complexity_metrics(nodes: &[Node], serialized: &SerializedNetwork) -> ComplexityMetrics {
+    let mut neighbours = moral_graph(&serialized.parents);
+    let sizes: Vec<usize> = neighbours.iter().map(BTreeSet::len).collect();
+    let max_markov_blanket_size = sizes.iter().copied().max().unwrap_or(0);
+    let mut markov_blanket_size_distribution = vec![0; max_markov_blanket_size + 1];
+    for &size in &sizes {
+        markov_blanket_size_distribution[size] += 1;
+    }
+    #[allow(clippy::cast_precision_loss)]
+    let average_markov_blanket_size = if sizes.is_empty() {
+        0.0
+    } else {
+        sizes.iter().sum::<usize>() as f64 / sizes.len() as f64
+    };
+
+    // Eliminate the node with the fewest neighbours (the earliest on ties),
+    // connecting its neighbours; the width is the largest such clique less one.
+    let mut remaining: BTreeSet<u8> = (0..serialized.num_nodes()).collect();
+    let mut treewidth_estimate = 0;
+    while let Some(&next) = remaining
+        .iter()
+        .min_by_key(|&&node| neighbours[usize::from(node)].len())
+    {
+        remaining.remove(&next);
+        let clique = std::mem::take(&mut neighbours[usize::from(next)]);
+        treewidth_estimate = treewidth_estimate.max(clique.len());
+        for &a in &clique {
+            let adjacent = &mut neighbours[usize::from(a)];
+            adjacent.remove(&next);
+            adjacent.extend(clique.iter().filter(|&&b| b != a));
+        }
+    }
+
+    ComplexityMetrics {
+        average_markov_blanket_size,
+        max_markov_blanket_size,
+        markov_blanket_size_distribution,
+        treewidth_estimate,
+        total_cpt_entries: nodes.iter().map(|node| node.cpt_entries.len()).sum(),
+    }
+}
+
+#[derive(Serialize)]
+#[serde(rename_all = "camelCase")]
+pub struct QueryValidation {
+    pub all_ids_exist: bool,
+    /// Requested IDs not in the network, in the order given.
+    pub missing_ids: Vec<String>,
+    /// Whether intervening can change some query node's marginal given the
+    /// evidence. False without an intervention.
+    pub intervention_affects_query: bool,
+    /// For each known evidence node, whether it is d-connected to some query
+    /// node given the rest of the evidence. Evidence mapped to false leaves
+    /// every query marginal as it would be without it.
+    pub evidence_informativeness: HashMap<String, bool>,
+}
+
+/// Pre-flight check of a query, from the graph alone.
+///
+/// An intervention is judged in the network with its incoming edges cut, as
+/// the intervened query is sampled: it can affect a query node only along a
+/// trail leaving it that the evidence leaves open. Unknown IDs are reported
+/// in `missing_ids` and otherwise ignored.
+pub fn validate_query(
+    serialized: &SerializedNetwork,
+    intervention_id: Option<&str>,
+    evidence_ids: &[&str],
+    query_ids: &[&str],
+) -> QueryValidation {
+    let mut missing_ids = Vec::new();
+    let mut resolve = |ids: &[&str]| -> Vec<(String, u8)> {
+        ids.iter()
+            .filter_map(|&id| {
+                let index = serialized.index_of(id);
+                if index.is_none() {
+                    missing_ids.push(id.to_owned());
+                }
+                Some((id.to_owned(), index?))
+            })
+            .collect()
+    };
+    let intervention = resolve(&intervention_id.into_iter().collect::<Vec<_>>());
+    let evidence = resolve(evidence_ids);
+    let queries = resolve(query_ids);
+
+    let mut parents = serialized.parents.clone();
+    for (_, index) in &intervention {
+        parents[usize::from(*index)].clear();
+    }
+    let given: Vec<u8> = evidence.iter().map(|&(_, index)| index).collect();
+    let intervention_affects_query = intervention.iter().any(|&(_, x)| {
+        queries
+            .iter()
+            .any(|&(_, q)| q == x || !d_separated_in(&parents, x, q, &given))
+    });
+    let evidence_informativeness = evidence
+        .iter()
+        .map(|(id, e)| {
+            let rest: Vec<u8> = given.iter().copied().filter(|g| g != e).collect();
+            let informative = queries
+                .iter()
+                .any(|&(_, q)| q == *e || !d_separated_in(&parents, *e, q, &rest));
+            (id.clone(), informative)
+        })
+        .collect();
+
+    QueryValidation {
+        all_ids_exist: missing_ids.is_empty(),
+        missing_ids,
+        intervention_affects_query,
+        evidence_informativeness,
+    }
+}
@@ -0,0 +1,616 @@
+//! Composable validation of a node array.
+//!
+//! `serialize_network` stops at the first structural problem; the validator
+//! instead collects every issue it can find so editors can show them together.
+
+use serde::{Deserialize, Serialize};
+use std::collections::{BTreeSet, HashMap, HashSet, VecDeque};
+
+use crate::lookup::relaxed_id;
+use crate::serialize::{
+    canonical_probability, empty_cpt_message, get_node_parents, leak_probability,
+    probability_bounds,
+};
+use crate::{CptEntry, Node};
+
+/// Nodes with more parents than this are not enumerated for completeness.
+pub(crate) const MAX_COMPLETENESS_PARENTS: usize = 16;
+const MAX_NODES: usize = 255;
+
+#[derive(Clone, Copy, Debug, PartialEq, Eq, Serialize)]
+#[serde(rename_all = "camelCase")]
+pub enum Severity {
+    /// The network cannot be sampled as is.
+    Error,
+    /// The network can be sampled but probably doesn't mean what was intended.
+    Warning,
+}
+
+#[derive(Serialize)]
+#[serde(rename_all = "camelCase")]
+pub struct ValidationIssue {
+    pub severity: Severity,
+    pub node_id: Option<String>,
+    pub message: String,
+    /// Set on the fixed-parent lint, so an editor can let intentional uses
+    /// be acknowledged.
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub fixed_parent: Option<FixedParent>,
+}
+
+/// A parent that every reachable entry mentioning it fixes to one value.
+#[derive(Serialize)]
+#[serde(rename_all = "camelCase")]
+pub struct FixedParent {
+    pub parent_id: String,
+    pub value: bool,
+    /// The reachable entries fixing the parent, by index in `cptEntries`.
+    pub entry_indices: Vec<usize>,
+}
+
+/// A `(node, parent)` pair whose fixed-parent warning was reviewed and is
+/// intended.
+#[derive(Clone, Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub struct AcknowledgedParent {
+    pub node_id: String,
+    pub parent_id: String,
+}
+
+impl ValidationIssue {
+    fn error(node_id: Option<&str>, message: String) -> Self {
+        Self {
+            severity: Severity::Error,
+            node_id: node_id.map(str::to_string),
+            message,
+            fixed_parent: None,
+        }
+    }
+
+    fn warning(node_id: Option<&str>, message: String) -> Self {
+        Self {
+            severity: Severity::Warning,
+            node_id: node_id.map(str::to_string),
+            message,
+            fixed_parent: None,
+        }
+    }
+}
+
+/// Builder selecting which checks to run. Nothing is checked by default.
+#[derive(Clone, Default)]
+#[allow(clippy::struct_excessive_bools)]
+pub struct NetworkValidator {
+    references: bool,
+    probabilities: bool,
+    cpt_completeness: bool,
+    cycle: bool,
+    strict_ids: bool,
+    fixed_parents: bool,
+    acknowledged: Vec<AcknowledgedParent>,
+}
+
+impl NetworkValidator {
+    #[must_use]
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Duplicate IDs, parents missing from the array, and the node limit.
+    #[must_use]
+    pub fn check_references(mut self, enabled: bool) -> Self {
+        self.references = enabled;
+        self
+    }
+
+    /// Every CPT probability is finite, within `[0, 1]`, and representable.
+    #[must_use]
+    pub fn check_probabilities(mut self, enabled: bool) -> Self {
+        self.probabilities = enabled;
+        self
+    }
+
+    /// Every node has CPT entries and every parent assignment matches one.
+    #[must_use]
+    pub fn check_cpt_completeness(mut self, enabled: bool) -> Self {
+        self.cpt_completeness = enabled;
+        self
+    }
+
+    #[must_use]
+    pub fn check_cycle(mut self) -> Self {
+        self.cycle = true;
+        self
+    }
+
+    /// IDs that collide ignoring case and Unicode normalization, and parent
+    /// references that only match a node under those relaxations.
+    #[must_use]
+    pub fn check_strict_ids(mut self, enabled: bool) -> Self {
+        self.strict_ids = enabled;
+        self
+    }
+
+    /// Parents that every entry able to fire fixes to the same value, with
+    /// other entries leaving them free: usually forgotten complementary
+    /// rows. Reported as warnings, except for acknowledged pairs.
+    #[must_use]
+    pub fn check_fixed_parents(
+        mut self,
+        enabled: bool,
+        acknowledged: Vec<AcknowledgedParent>,
+    ) -> Self {
+        self.fixed_parents = enabled;
+        self.acknowledged = acknowledged;
+        self
+    }
+
+    #[must_use]
+    pub fn validate(&self, nodes: &[Node]) -> Vec<ValidationIssue> {
+        let mut issues = Vec::new();
+        if self.references {
+            references(nodes, &mut issues);
+        }
+        if self.probabilities {
+            probabilities(nodes, &mut issues);
+        }
+        if self.cpt_completeness {
+            cpt_completeness(nodes, &mut issues);
+        }
+        if self.cycle {
+            cycle(nodes, &mut issues);
+        }
+        if self.strict_ids {
+            strict_ids(nodes, &mut issues);
+        }
+        if self.fixed_parents {
+            fixed_parents(nodes, &self.acknowledged, &mut issues);
+        }
+        issues
+    }
+}
+
+/// Checks requested from JS; every check except `strictIds` runs unless
+/// turned off. `acknowledgedParents` silences the fixed-parent lint for the
+/// pairs listed.
+#[derive(Deserialize)]
+#[serde(rename_all = "camelCase", default)]
+#[allow(clippy::struct_excessive_bools)]
+pub struct ValidationOptions {
+    pub references: bool,
+    pub probabilities: bool,
+    pub cpt_completeness: bool,
+    pub cycle: bool,
+    pub strict_ids: bool,
+    pub fixed_parents: bool,
+    pub acknowledged_parents: Vec<AcknowledgedParent>,
+}
+
+impl Default for ValidationOptions {
+    fn default() -> Self {
+        Self {
+            references: true,
+            probabilities: true,
+            cpt_completeness: true,
+            cycle: true,
+            strict_ids: false,
+            fixed_parents: true,
+            acknowledged_parents: Vec::new(),
+        }
+    }
+}
+
+impl From<&ValidationOptions> for NetworkValidator {
+    fn from(options: &ValidationOptions) -> Self {
+        let validator = NetworkValidator::new()
+            .check_references(options.references)
+            .check_probabilities(options.probabilities)
+            .check_cpt_completeness(options.cpt_completeness)
+            .check_strict_ids(options.strict_ids)
+            .check_fixed_parents(options.fixed_parents, options.acknowledged_parents.clone());
+        if options.cycle {
+            validator.check_cycle()
+        } else {
+            validator
+        }
+    }
+}
+
+fn references(nodes: &[Node], issues: &mut Vec<ValidationIssue>) {
+    if nodes.len() > MAX_NODES {
+        issues.push(ValidationIssue::error(
+            None,
+            format!(
+                "Network has {len} nodes, maximum {MAX_NODES} supported",
+                len = nodes.len()
+            ),
+        ));
+    }
+
+    let mut seen = HashSet::new();
+    let mut reported = HashSet::new();
+    for node in nodes {
+        if !seen.insert(node.id.as_str()) && reported.insert(node.id.as_str()) {
+            issues.push(ValidationIssue::error(
+                Some(&node.id),
+                format!("Duplicate node ID {id}", id = node.id),
+            ));
+        }
+    }
+
+    for node in nodes {
+        let mut parents = get_node_parents(node);
+        parents.sort_unstable();
+        for parent_id in parents {
+            if !seen.contains(parent_id) {
+                issues.push(ValidationIssue::error(
+                    Some(&node.id),
+                    format!(
+                        "Node {child} references parent {parent_id} which is not in the node array",
+                        child = node.id
+                    ),
+                ));
+            }
+        }
+    }
+}
+
+fn probabilities(nodes: &[Node], issues: &mut Vec<ValidationIssue>) {
+    for node in nodes {
+        let bounds_valid = match probability_bounds(node) {
+            Ok(_) => true,
+            Err(e) => {
+                issues.push(ValidationIssue::error(Some(&node.id), e.to_string()));
+                false
+            }
+        };
+        if let Err(e) = leak_probability(node) {
+            issues.push(ValidationIssue::error(Some(&node.id), e.to_string()));
+        }
+        for (entry_idx, entry) in node.cpt_entries.iter().enumerate() {
+            let used = match &entry.probability_params {
+                Some(params) => vec![params.p_high, params.p_low],
+                None => vec![entry.probability],
+            };
+            for probability in used {
+                entry_probability(node, entry, entry_idx, probability, bounds_valid, issues);
+            }
+        }
+    }
+}
+
+fn entry_probability(
+    node: &Node,
+    entry: &CptEntry,
+    entry_idx: usize,
+    probability: f64,
+    bounds_valid: bool,
+    issues: &mut Vec<ValidationIssue>,
+) {
+    if let Err(e) = canonical_probability(&node.id, entry_idx, probability) {
+        issues.push(ValidationIssue::error(Some(&node.id), e.to_string()));
+        return;
+    }
+    let p_true = entry.as_probability_of_true(probability);
+    if bounds_valid
+        && (node.probability_floor.is_some_and(|floor| p_true < floor)
+            || node
+                .probability_ceiling
+                .is_some_and(|ceiling| p_true > ceiling))
+    {
+        issues.push(ValidationIssue::warning(
+            Some(&node.id),
+            format!(
+                "Node {id} CPT entry {entry_idx} has P(true) {p_true}, outside the node's own \
+                 floor and ceiling; it will be sampled as {bounded}",
+                id = node.id,
+                bounded = node.effective_probability(p_true)
+            ),
+        ));
+    }
+}
+
+fn cpt_completeness(nodes: &[Node], issues: &mut Vec<ValidationIssue>) {
+    for node in nodes {
+        if node.cpt_entries.is_empty() {
+            issues.push(ValidationIssue::error(
+                Some(&node.id),
+                empty_cpt_message(&node.id),
+            ));
+            continue;
+        }
+        let mut parents = get_node_parents(node);
+        parents.sort_unstable();
+        if parents.len() > MAX_COMPLETENESS_PARENTS {
+            issues.push(ValidationIssue::warning(
+                Some(&node.id),
+                format!(
+                    "Node {id} has {count} parents; CPT completeness was not checked",
+                    id = node.id,
+                    count = parents.len()
+                ),
+            ));
+            continue;
+        }
+        let uncovered = (0..1usize << parents.len()).find(|&assignment| {
+            let parent_value = |parent_id: &str| {
+                parents
+                    .iter()
+                    .position(|&p| p == parent_id)
+                    .is_some_and(|i| assignment & (1 << i) != 0)
+            };
+            node.probability_given(parent_value).is_none()
+        });
+        if let Some(assignment) = uncovered {
+            let example: Vec<String> = parents
+                .iter()
+                .enumerate()
+                .map(|(i, parent_id)| format!("{parent_id}={}", assignment & (1 << i) != 0))
+                .collect();
+            issues.push(ValidationIssue::error(
+                Some(&node.id),
+                format!(
+                    "Node {id} has no CPT entry for some parent states, e.g. {{{example}}}; suggest_cpt_completion can propose them",
+                    id = node.id,
+                    example = example.join(", ")
+                ),
+            ));
+        }
+    }
+}
+
+fn cycle(nodes: &[Node], issues: &mut Vec<ValidationIssue>) {
+    let ids: HashSet<&str> = nodes.iter().map(|n| n.id.as_str()).collect();
+    let mut children: HashMap<&str, Vec<&str>> = HashMap::new();
+    let mut in_degree: HashMap<&str, usize> = ids.iter().map(|&id| (id, 0)).collect();
+    for node in nodes {
+        for parent_id in get_node_parents(node) {
+            if ids.contains(parent_id) {
+                children
+                    .entry(parent_id)
+                    .or_default()
+                    .push(node.id.as_str());
+                *in_degree.entry(node.id.as_str()).or_default() += 1;
+            }
+        }
+    }
+
+    let mut queue: VecDeque<&str> = in_degree
+        .iter()
+        .filter(|&(_, &degree)| degree == 0)
+        .map(|(&id, _)| id)
+        .collect();
+    while let Some(id) = queue.pop_front() {
+        in_degree.remove(id);
+        for &child in children.get(id).into_iter().flatten() {
+            if let Some(degree) = in_degree.get_mut(child) {
+                *degree -= 1;
+                if *degree == 0 {
+                    queue.push_back(child);
+                }
+            }
+        }
+    }
+
+    if !in_degree.is_empty() {
+        let remaining: BTreeSet<&str> = in_degree.into_keys().collect();
+        issues.push(ValidationIssue::error(
+            None,
+            format!(
+                "Cycle detected in Bayesian network; nodes on or downstream of it: {{{}}}",
+                remaining.into_iter().collect::<Vec<_>>().join(", ")
+            ),
+        ));
+    }
+}
+
+fn strict_ids(nodes: &[Node], issues: &mut Vec<ValidationIssue>) {
+    let mut by_relaxed_id: HashMap<String, Vec<&str>> = HashMap::new();
+    for node in nodes {
+        let ids = by_relaxed_id.entry(relaxed_id(&node.id)).or_default();
+        if !ids.contains(&node.id.as_str()) {
+            ids.push(&node.id);
+        }
+    }
+    for node in nodes {
+        let ids = &by_relaxed_id[&relaxed_id(&node.id)];
+        if let Some(&first) = ids.first().filter(|&&first| first != node.id) {
+            issues.push(ValidationIssue::warning(
+                Some(&node.id),
+                format!(
+                    "Node IDs {first:?} and {id:?} differ only by case or Unicode normalization",
+                    id = node.id
+                ),
+            ));
+        }
+    }
+
+    let exact_ids: HashSet<&str> = nodes.iter().map(|n| n.id.as_str()).collect();
+    for node in nodes {
+        let mut parents = get_node_parents(node);
+        parents.sort_unstable();
+        for parent_id in parents {
+            if exact_ids.contains(parent_id) {
+                continue;
+            }
+            if let Some(candidates) = by_relaxed_id.get(&relaxed_id(parent_id)) {
+                issues.push(ValidationIssue::error(
+                    Some(&node.id),
+                    format!(
+                        "Node {id} references parent {parent_id:?}, which only matches {candidates} \
+                         ignoring case or Unicode normalization",
+                        id = node.id,
+                        candidates = candidates
+                            .iter()
+                            .map(|candidate| format!("{candidate:?}"))
+                            .collect::<Vec<_>>()
+                            .join(" or ")
+                    ),
+                ));
+            }
+        }
+    }
+}
+
+/// Which of `node`'s entries are the first match for some assignment of
+/// `parents`, its parents sorted by ID.
+pub(crate) fn reachable_entries(node: &Node, parents: &[&str]) -> Vec<bool> {
+    let mut fires = vec![false; node.cpt_entries.len()];
+    for assignment in 0..1usize << parents.len() {
+        let first = node.cpt_entries.iter().position(|entry| {
+            entry.parent_states.iter().all(|(parent_id, state)| {
+                state.is_none_or(|expected| {
+                    parents
+                        .binary_search(&parent_id.as_str())
+                        .is_ok_and(|i| assignment & (1 << i) != 0)
+                        == expected
+                })
+            })
+        });
+        if let Some(first) = first {
+            fires[first] = true;
+        }
+    }
+    fires
+}
+
+fn fixed_parents(
+    nodes: &[Node],
+    acknowledged: &[AcknowledgedParent],
+    issues: &mut Vec<ValidationIssue>,
+) {
+    for node in nodes {
+        let mut parents = get_node_parents(node);
+        parents.sort_unstable();
+        if parents.len() > MAX_COMPLETENESS_PARENTS {
+            continue;
+        }
+        let fires = reachable_entries(node, &parents);
+        for &parent_id in &parents {
+            if acknowledged
+                .iter()
+                .any(|pair| pair.node_id == node.id && pair.parent_id == parent_id)
+            {
+                continue;
+            }
+            let state = |entry: &CptEntry| entry.parent_states.get(parent_id).copied().flatten();
+            let reachable = || {
+                node.cpt_entries
+                    .iter()
+                    .enumerate()
+                    .filter(|&(i, _)| fires[i])
+            };
+            let fixed: Vec<(usize, bool)> = reachable()
+                .filter_map(|(i, entry)| state(entry).map(|value| (i, value)))
+                .collect();
+            let Some(&(_, value)) = fixed.first() else {
+                continue;
+            };
+            if fixed.iter().any(|&(_, other)| other != value)
+                || reachable().all(|(_, entry)| state(entry).is_some())
+            {
+                continue;
+            }
+            let entry_indices: Vec<usize> = fixed.iter().map(|&(i, _)| i).collect();
+            issues.push(ValidationIssue {
+                fixed_parent: Some(FixedParent {
+                    parent_id: parent_id.to_string(),
+                    value,
+                    entry_indices: entry_indices.clone(),
+                }),
+                ..ValidationIssue::warning(
+                    Some(&node.id),
+                    format!(
+                        "Node {id} only distinguishes {parent_id}={value} (entries {entries}); \
+                         {parent_id}={other} falls through to entries that ignore it. Add the \
+                         complementary rows, or acknowledge this if intended",
+                        id = node.id,
+                        other = !value,
+                        entries = entry_indices
+                            .iter()
+                            .map(ToString::to_string)
+                            .collect::<Vec<_>>()
+                            .join(", ")
+                    ),
+                )
+            });
+        }
+    }
+}
+
+#[derive(Serialize)]
+#[serde(rename_all = "camelCase")]
+pub struct ResolvedReferences {
+    pub nodes: Vec<Node>,
+    /// How many references were rewritten.
+    pub resolved: usize,
+    /// References left alone because their entry already names the node
+    /// they would be rewritten to.
+    pub conflicts: Vec<ValidationIssue>,
+}
+
+/// Rewrites parent references that match no node exactly but match exactly
+/// one node ignoring case and Unicode normalization. Ambiguous references are
+/// left for the lint, and so are references whose canonical ID their entry
+/// already uses, since renaming them would overwrite that state.
+pub fn resolve_relaxed_references(mut nodes: Vec<Node>) -> ResolvedReferences {
+    let exact_ids: HashSet<String> = nodes.iter().map(|n| n.id.clone()).collect();
+    let mut by_relaxed_id: HashMap<String, Vec<String>> = HashMap::new();
+    for id in &exact_ids {
+        by_relaxed_id
+            .entry(relaxed_id(id))
+            .or_default()
+            .push(id.clone());
+    }
+
+    let mut resolved = 0;
+    let mut conflicts = Vec::new();
+    for node in &mut nodes {
+        for (entry_index, entry) in node.cpt_entries.iter_mut().enumerate() {
+            let mut renames: Vec<(String, String)> = entry
+                .parent_states
+                .keys()
+                .filter(|parent_id| !exact_ids.contains(*parent_id))
+                .filter_map(
+                    |parent_id| match by_relaxed_id.get(&relaxed_id(parent_id)) {
+                        Some(candidates) if candidates.len() == 1 => {
+                            Some((parent_id.clone(), candidates[0].clone()))
+                        }
+                        _ => None,
+                    },
+                )
+                .collect();
+            renames.sort_unstable();
+            for (from, to) in renames {
+                if entry.parent_states.contains_key(&to) {
+                    conflicts.push(ValidationIssue::error(
+                        Some(&node.id),
+                        format!(
+                            "Node {id} entry {entry_index} references both {from:?} and {to:?}, \
+                             which differ only by case or Unicode normalization; {from:?} was \
+                             left unresolved",
+                            id = node.id
+                        ),
+                    ));
+                } else if let Some(state) = entry.parent_states.remove(&from) {
+                    entry.parent_states.insert(to, state);
+                    resolved += 1;
+                }
+            }
+            if let Some(params) = &mut entry.probability_params
+                && !exact_ids.contains(&params.hyperparameter_id)
+                && let Some([candidate]) = by_relaxed_id
+                    .get(&relaxed_id(&params.hyperparameter_id))
+                    .map(Vec::as_slice)
+            {
+                params.hyperparameter_id.clone_from(candidate);
+                resolved += 1;
+            }
+        }
+    }
+    ResolvedReferences {
+        nodes,
+        resolved,
+        conflicts,
+    }
+}
@@ -0,0 +1,108 @@
+//! Several compiled networks (e.g. a fork, its baseline and the published
+//! version) held under string keys, sharing one memory budget.
+
+use std::collections::HashMap;
+use wasm_bindgen::prelude::*;
+
+use crate::compiled::CompiledNetwork;
+use crate::limits::{self, MAX_MEMORY_BUDGET};
+use crate::{deserialize_nodes, limit_error, structure};
+
+#[wasm_bindgen]
+pub struct Workspace {
+    networks: HashMap<String, CompiledNetwork>,
+    memory_budget: usize,
+}
+
+impl Workspace {
+    fn get(&self, key: &str) -> Result<&CompiledNetwork, JsValue> {
+        self.networks
+            .get(key)
+            .ok_or_else(|| JsValue::from_str(&format!("No network registered under key {key}")))
+    }
+}
+
+#[wasm_bindgen]
+impl Workspace {
+    /// `memory_budget` caps the bytes held across every registered network;
+    /// without one, only wasm's address space limits the workspace.
+    #[wasm_bindgen(constructor)]
+    #[allow(clippy::missing_errors_doc)]
+    pub fn new(memory_budget: Option<f64>) -> Result<Workspace, JsValue> {
+        let memory_budget = match memory_budget {
+            Some(budget) => {
+                limits::count("memoryBudget", budget, MAX_MEMORY_BUDGET).map_err(limit_error)?
+            }
+            None => MAX_MEMORY_BUDGET,
+        };
+        Ok(Workspace {
+            networks: HashMap::new(),
+            memory_budget,
+        })
+    }
+
+    /// Compiles `nodes` and stores them under `key`, replacing any network
+    /// already there.
+    #[allow(clippy::missing_errors_doc)]
+    pub fn register(&mut self, key: String, nodes: JsValue) -> Result<(), JsValue> {
+        let network = CompiledNetwork::compile(deserialize_nodes(nodes)?)?;
+        self.insert(key, network)
+    }
+
+    /// Takes ownership of an already compiled network; the JS handle passed
+    /// in can no longer be used.
+    #[allow(clippy::missing_errors_doc)]
+    pub fn insert(&mut self, key: String, network: CompiledNetwork) -> Result<(), JsValue> {
+        let replaced = self
+            .networks
+            .get(&key)
+            .map_or(0, CompiledNetwork::heap_bytes);
+        limits::reserve(
+            "memoryBudget",
+            self.memory_bytes() - replaced,
+            network.heap_bytes(),
+            self.memory_budget,
+        )
+        .map_err(limit_error)?;
+        self.networks.insert(key, network);
+        Ok(())
+    }
+
+    /// Drops the network under `key`, returning whether there was one.
+    pub fn evict(&mut self, key: &str) -> bool {
+        self.networks.remove(key).is_some()
+    }
+
+    /// Registered keys, sorted.
+    #[must_use]
+    pub fn keys(&self) -> Vec<String> {
+        let mut keys: Vec<String> = self.networks.keys().cloned().collect();
+        keys.sort_unstable();
+        keys
+    }
+
+    #[wasm_bindgen(getter)]
+    #[must_use]
+    pub fn memory_bytes(&self) -> usize {
+        self.networks
+            .values()
+            .map(CompiledNetwork::heap_bytes)
+            .sum()
+    }
+
+    /// `CompiledNetwork::compute_marginals` on the network under `key`.
+    #[allow(clippy::missing_errors_doc)]
+    pub fn compute_marginals(&self, key: &str, options: JsValue) -> Result<JsValue, JsValue> {
+        self.get(key)?.compute_marginals(options)
+    }
+
+    /// `compare_networks` on the networks under `key_a` and `key_b`.
+    #[allow(clippy::missing_errors_doc)]
+    pub fn compare(&self, key_a: &str, key_b: &str) -> Result<JsValue, JsValue> {
+        let distance =
+            structure::network_graph_distance(&self.get(key_a)?.nodes, &self.get(key_b)?.nodes)
+                .map_err(|e| JsValue::from_str(&format!("Comparison failed: {e}")))?;
+        serde_wasm_bindgen::to_value(&distance)
+            .map_err(|e| JsValue::from_str(&format!("Failed to serialize result: {e}")))
+    }
+}
@@ -0,0 +1,4673 @@
+//! Tests that go through the JS-visible entry points, so inputs are
+//! deserialized from real JS values rather than constructed in Rust.
+//! Renames, optional arguments and map-to-object conversions only break at
+//! this boundary, so every new entry point gets a test here.
+//!
+//! Run with `cargo test --target wasm32-unknown-unknown`.
+#![cfg(target_arch = "wasm32")]
+
+use js_sys::{Array, JSON, Map, Object, Reflect};
+use wasm_bindgen::{JsCast, JsValue};
+use wasm_bindgen_test::wasm_bindgen_test;
+use wasm_inference::{
+    CompiledNetwork, MAX_UNIFORM_CPT_PARENTS, Node, Workspace, ambiguity_impact, ancestors,
+    calibrate_network, check_constraints, check_faithfulness, check_identifiability,
+    check_monotonicity, check_positivity, compare_parameterizations, complete_scenarios,
+    compute_augmented_ipw_estimator, compute_calibration_report, compute_causal_attribution,
+    compute_conditional_marginals, compute_counterfactual_outcome, compute_dbn_mixing_time,
+    compute_dbn_steady_state, compute_dbn_transition_power, compute_do_calculus_rules,
+    compute_do_distribution, compute_dose_response_wasm, compute_effect_percentiles,
+    compute_expected_shortfall, compute_interventional_quantile_treatment_effect,
+    compute_iv_effect, compute_log_evidence, compute_marginals, compute_marginals_ensemble,
+    compute_marginals_json, compute_marginals_reweighted, compute_marginals_v2,
+    compute_marginals_with_budget, compute_marginals_with_missing_values,
+    compute_marginals_with_options, compute_marginals_with_progress, compute_mediation_proportion,
+    compute_mixing_time, compute_optimal_single_intervention, compute_partial_correlations_wasm,
+    compute_posterior_mixed_evidence, compute_required_sample_size,
+    compute_sensitivity_to_confounding, count_paths, descendants, diff_assumptions, diff_compact,
+    explain_d_separation, export_graphml, fit_marginals, freeze_upstream, from_compact,
+    generate_paired_dataset, get_network_complexity_metrics, get_network_summary, get_node_info,
+    golden_fixtures, identify_effect, import_cpts_csv, index_map, layout_fingerprint,
+    learn_parameters_from_csv_string, learn_structure, likelihood_ratio_test_wasm, markov_blanket,
+    rank_outcome_impacts, recommend_sample_size, resolve_relaxed_ids, rng_trace, run_golden_checks,
+    score_predictions, self_check, serialize_network_to_writer, suggest_cpt_completion, to_compact,
+    to_cpt_tables, validate_network_wasm, validate_query,
+};
+
+fn set(target: &Object, key: &str, value: &JsValue) {
+    Reflect::set(target, &JsValue::from_str(key), value).unwrap();
+}
+
+fn entry(parent_states_json: &str, probability: f64) -> JsValue {
+    let entry = Object::new();
+    set(
+        &entry,
+        "parentStates",
+        &JSON::parse(parent_states_json).unwrap(),
+    );
+    set(&entry, "probability", &JsValue::from_f64(probability));
+    entry.into()
+}
+
+fn node(id: &str, entries: Vec<JsValue>) -> JsValue {
+    let node = Object::new();
+    set(&node, "_id", &JsValue::from_str(id));
+    set(&node, "cptEntries", &entries.into_iter().collect::<Array>());
+    node.into()
+}
+
+fn nodes(nodes: Vec<JsValue>) -> JsValue {
+    nodes.into_iter().collect::<Array>().into()
+}
+
+fn marginal(result: &JsValue, id: &str) -> f64 {
+    result
+        .dyn_ref::<Map>()
+        .expect("marginals are returned as a Map")
+        .get(&JsValue::from_str(id))
+        .as_f64()
+        .expect("marginal is a number")
+}
+
+fn error_message<T: std::fmt::Debug>(result: Result<T, JsValue>) -> String {
+    let error = result.expect_err("expected an error");
+    error
+        .as_string()
+        .or_else(|| get(&error, "message").as_string())
+        .expect("errors are strings or Errors")
+}
+
+#[wasm_bindgen_test]
+fn negative_zero_probability_is_zero() {
+    let network = nodes(vec![node("A", vec![entry("{}", -0.0)])]);
+
+    let result = compute_marginals(network, 1000.0, None).unwrap();
+
+    assert!(marginal(&result, "A").abs() < f64::EPSILON);
+}
+
+#[wasm_bindgen_test]
+fn denormal_probability_is_rejected() {
+    let network = nodes(vec![node("A", vec![entry("{}", 0.5), entry("{}", 1e-320)])]);
+
+    let message = error_message(compute_marginals(network, 10.0, None));
+
+    assert!(
+        message.contains("Node A CPT entry 1 has probability 1e-320"),
+        "{message}"
+    );
+}
+
+#[wasm_bindgen_test]
+fn f32_subnormal_probability_is_rejected() {
+    let network = nodes(vec![node("A", vec![entry("{}", 1e-40)])]);
+
+    let message = error_message(compute_marginals(network, 10.0, None));
+
+    assert!(message.contains("Node A CPT entry 0"), "{message}");
+    assert!(message.contains("use 0 instead"), "{message}");
+}
+
+#[wasm_bindgen_test]
+fn nan_probability_names_node_and_entry() {
+    let network = nodes(vec![node("A", vec![entry("{}", f64::NAN)])]);
+
+    let message = error_message(compute_marginals(network, 10.0, None));
+
+    assert!(
+        message.contains("Node A CPT entry 0 has non-finite probability NaN"),
+        "{message}"
+    );
+}
+
+#[wasm_bindgen_test]
+fn out_of_range_probability_names_node_and_entry() {
+    let network = nodes(vec![node("A", vec![entry("{}", 0.5), entry("{}", 1.3)])]);
+
+    let message = error_message(compute_marginals(network, 10.0, None));
+
+    assert!(
+        message.contains("Node A CPT entry 1 has probability 1.3, outside [0, 1]"),
+        "{message}"
+    );
+
+    // -0.1 as P(false) would be stored as P(true) 1.1.
+    let of_false = entry("{}", -0.1);
+    set(
+        of_false.unchecked_ref(),
+        "isProbabilityOfTrue",
+        &JsValue::FALSE,
+    );
+    let network = nodes(vec![node("B", vec![of_false])]);
+
+    let message = error_message(compute_marginals(network, 10.0, None));
+
+    assert!(
+        message.contains("Node B CPT entry 0 has probability -0.1, outside [0, 1]"),
+        "{message}"
+    );
+}
+
+#[wasm_bindgen_test]
+fn infinite_probability_names_node_and_entry() {
+    let network = nodes(vec![
+        node("A", vec![entry("{}", 0.5)]),
+        node(
+            "B",
+            vec![
+                entry(r#"{"A": true}"#, 0.8),
+                entry(r#"{"A": false}"#, f64::NEG_INFINITY),
+            ],
+        ),
+    ]);
+
+    let message = error_message(compute_marginals(network, 10.0, None));
+
+    assert!(
+        message.contains("Node B CPT entry 1 has non-finite probability -inf"),
+        "{message}"
+    );
+}
+
+#[wasm_bindgen_test]
+fn graphml_export_parses_and_round_trips_counts() {
+    let network = nodes(vec![
+        node("A & <B>", vec![entry("{}", 0.5)]),
+        node("C", vec![entry("{}", 0.5)]),
+        node(
+            "D \"quoted\"",
+            vec![
+                entry(r#"{"A & <B>": true, "C": null}"#, 0.9),
+                entry("{}", 0.1),
+            ],
+        ),
+    ]);
+    let marginals = Map::new();
+    marginals.set(&"C".into(), &JsValue::from_f64(0.25));
+
+    let xml = export_graphml(network, marginals.into(), JsValue::UNDEFINED).unwrap();
+
+    let document = roxmltree::Document::parse(&xml).unwrap();
+    let graph_nodes: Vec<_> = document
+        .descendants()
+        .filter(|n| n.has_tag_name("node"))
+        .collect();
+    let graph_edges: Vec<_> = document
+        .descendants()
+        .filter(|n| n.has_tag_name("edge"))
+        .collect();
+    assert_eq!(graph_nodes.len(), 3);
+    assert_eq!(graph_edges.len(), 2);
+    assert_eq!(graph_nodes[0].attribute("id"), Some("A & <B>"));
+    assert!(
+        graph_edges
+            .iter()
+            .any(|e| e.attribute("source") == Some("A & <B>")
+                && e.attribute("target") == Some("D \"quoted\""))
+    );
+    let marginal_data: Vec<_> = document
+        .descendants()
+        .filter(|n| n.attribute("key") == Some("marginal"))
+        .collect();
+    assert_eq!(marginal_data.len(), 1);
+    assert_eq!(marginal_data[0].text(), Some("0.25"));
+}
+
+#[wasm_bindgen_test]
+fn graphml_export_writes_groups_and_edge_strengths() {
+    let network = || {
+        let a = node("A", vec![entry("{}", 0.5)]);
+        set(
+            a.unchecked_ref(),
+            "metadata",
+            &JSON::parse(r#"{"group": "takeoff"}"#).unwrap(),
+        );
+        let b = node("B", vec![entry("{}", 0.5)]);
+        set(
+            b.unchecked_ref(),
+            "metadata",
+            &JSON::parse(r#"{"tags": ["risk", "policy"]}"#).unwrap(),
+        );
+        let c = node(
+            "C",
+            vec![entry(r#"{"A": true, "B": null}"#, 0.9), entry("{}", 0.1)],
+        );
+        nodes(vec![a, b, c])
+    };
+    let strengths = JSON::parse(r#"[{"parentId": "A", "childId": "C", "strength": 0.8}]"#).unwrap();
+
+    let xml = export_graphml(network(), JsValue::UNDEFINED, strengths).unwrap();
+
+    let document = roxmltree::Document::parse(&xml).unwrap();
+    let key = |id: &str| {
+        document
+            .descendants()
+            .find(|n| n.has_tag_name("key") && n.attribute("id") == Some(id))
+            .and_then(|n| n.attribute("for"))
+    };
+    assert_eq!(key("group"), Some("node"));
+    assert_eq!(key("strength"), Some("edge"));
+    let data = |parent: roxmltree::Node, key: &str| {
+        parent
+            .children()
+            .find(|n| n.attribute("key") == Some(key))
+            .and_then(|n| n.text())
+            .map(str::to_string)
+    };
+    let groups: Vec<_> = document
+        .descendants()
+        .filter(|n| n.has_tag_name("node"))
+        .map(|n| data(n, "group"))
+        .collect();
+    assert_eq!(
+        groups,
+        [
+            Some("takeoff".to_string()),
+            Some("risk,policy".to_string()),
+            None
+        ]
+    );
+    let strengths: Vec<_> = document
+        .descendants()
+        .filter(|n| n.has_tag_name("edge"))
+        .map(|n| (n.attribute("source").unwrap(), data(n, "strength")))
+        .collect();
+    assert_eq!(strengths, [("A", Some("0.8".to_string())), ("B", None)]);
+
+    let unknown = JSON::parse(r#"[{"parentId": "C", "childId": "A", "strength": 0.8}]"#).unwrap();
+    let error = export_graphml(network(), JsValue::UNDEFINED, unknown).unwrap_err();
+    assert!(
+        error.as_string().unwrap().contains("not an edge"),
+        "{error:?}"
+    );
+
+    let plain = export_graphml(
+        nodes(vec![node("A", vec![entry("{}", 0.5)])]),
+        JsValue::UNDEFINED,
+        JsValue::UNDEFINED,
+    )
+    .unwrap();
+    assert!(!plain.contains(r#"id="group""#), "{plain}");
+    assert!(!plain.contains(r#"id="strength""#), "{plain}");
+}
+
+fn compact_field(tag: u8, payload: &[u8]) -> Vec<u8> {
+    let mut field = vec![tag, u8::try_from(payload.len()).unwrap()];
+    field.extend_from_slice(payload);
+    field
+}
+
+#[wasm_bindgen_test]
+fn compact_format_round_trips_nodes() {
+    let network = nodes(vec![
+        node("A", vec![entry("{}", 0.3)]),
+        node(
+            "B",
+            vec![entry(r#"{"A": true}"#, 0.8), entry(r#"{"A": null}"#, 0.2)],
+        ),
+    ]);
+
+    let bytes = to_compact(network).unwrap();
+    let decoded = from_compact(&bytes).unwrap();
+
+    assert_eq!(to_compact(decoded.clone()).unwrap(), bytes);
+    let decoded = Array::from(&decoded);
+    assert_eq!(decoded.length(), 2);
+    let b_entries = Array::from(&Reflect::get(&decoded.get(1), &"cptEntries".into()).unwrap());
+    let states = Reflect::get(&b_entries.get(1), &"parentStates".into()).unwrap();
+    assert!(Reflect::get(&states, &"A".into()).unwrap().is_null());
+}
+
+#[wasm_bindgen_test]
+fn compact_format_ignores_unknown_fields() {
+    let mut parent = compact_field(1, b"A");
+    parent.extend(compact_field(2, &[1]));
+    parent.extend(compact_field(42, b"future parent field"));
+    let mut entry = compact_field(1, &parent);
+    entry.extend(compact_field(2, &0.75f64.to_le_bytes()));
+    entry.extend(compact_field(42, b"future entry field"));
+    let mut child = compact_field(1, b"B");
+    child.extend(compact_field(42, b"future node field"));
+    child.extend(compact_field(2, &entry));
+    child.extend(compact_field(2, &compact_field(2, &0.1f64.to_le_bytes())));
+    let mut root_entry = compact_field(2, &0.5f64.to_le_bytes());
+    root_entry.extend(compact_field(42, &[]));
+    let mut root = compact_field(1, b"A");
+    root.extend(compact_field(2, &root_entry));
+
+    let mut bytes = b"DDCN\x01".to_vec();
+    bytes.extend(compact_field(1, &root));
+    bytes.extend(compact_field(42, b"future top-level record"));
+    bytes.extend(compact_field(1, &child));
+
+    let decoded = from_compact(&bytes).unwrap();
+
+    assert_eq!(Array::from(&decoded).length(), 2);
+    let result = compute_marginals(decoded, 1000.0, Some("A".to_string())).unwrap();
+    let true_case = Reflect::get(&result, &"trueCase".into()).unwrap();
+    assert!((marginal(&true_case, "B") - 0.75).abs() < 0.1);
+}
+
+#[wasm_bindgen_test]
+fn compact_format_rejects_truncated_input() {
+    let network = nodes(vec![node("A", vec![entry("{}", 0.3)])]);
+    let bytes = to_compact(network).unwrap();
+
+    let message = error_message(from_compact(&bytes[..bytes.len() - 3]));
+
+    assert!(message.contains("truncated"), "{message}");
+}
+
+fn options(json: &str) -> JsValue {
+    JSON::parse(json).unwrap()
+}
+
+fn get(target: &JsValue, key: &str) -> JsValue {
+    if let Some(map) = target.dyn_ref::<Map>() {
+        return map.get(&JsValue::from_str(key));
+    }
+    Reflect::get(target, &JsValue::from_str(key)).unwrap()
+}
+
+#[wasm_bindgen_test]
+fn options_result_embeds_resolved_assumptions_and_is_reproducible() {
+    let network = || {
+        let observed = node("A", vec![entry("{}", 0.3)]);
+        set(observed.unchecked_ref(), "observed", &JsValue::TRUE);
+        nodes(vec![
+            observed,
+            node(
+                "B",
+                vec![entry(r#"{"A": true}"#, 0.8), entry(r#"{"A": false}"#, 0.2)],
+            ),
+            node("C", vec![entry("{}", 0.5)]),
+        ])
+    };
+    let query = r#"{
+        "numSamples": 2000,
+        "seed": 7,
+        "assumptions": { "name": "scenario", "clamps": { "C": 0.9 } }
+    }"#;
+
+    let first = compute_marginals_with_options(network(), options(query)).unwrap();
+    let second = compute_marginals_with_options(network(), options(query)).unwrap();
+
+    let marginals = get(&first, "marginals");
+    assert!((marginal(&marginals, "A") - 1.0).abs() < f64::EPSILON);
+    assert!((marginal(&marginals, "B") - 0.8).abs() < 0.05);
+    assert!((marginal(&marginals, "C") - 0.9).abs() < 0.05);
+    assert!(
+        (marginal(&marginals, "B") - marginal(&get(&second, "marginals"), "B")).abs()
+            < f64::EPSILON
+    );
+    let provenance = get(&first, "provenance");
+    assert_eq!(get(&provenance, "seed").as_f64(), Some(7.0));
+    assert_eq!(
+        get(&provenance, "fingerprint").as_string().unwrap().len(),
+        16
+    );
+    let assumptions = get(&provenance, "assumptions");
+    assert_eq!(
+        get(&assumptions, "name").as_string().as_deref(),
+        Some("scenario")
+    );
+    assert_eq!(
+        get(&get(&assumptions, "evidence"), "A").as_bool(),
+        Some(true)
+    );
+}
+
+#[wasm_bindgen_test]
+fn query_results_embed_their_provenance() {
+    let network = || nodes(chain(0.9));
+    let fingerprint = get(
+        &get(
+            &compute_marginals_with_options(network(), options(r#"{"numSamples": 10}"#)).unwrap(),
+            "provenance",
+        ),
+        "fingerprint",
+    );
+    // The assumptions as `kind:nodeId=value` strings, in kind order.
+    let assumptions = |result: &JsValue| -> Vec<String> {
+        let provenance = get(result, "provenance");
+        assert_eq!(get(&provenance, "fingerprint"), fingerprint);
+        assert!(get(&provenance, "seed").as_f64().is_some());
+        let assumptions = get(&provenance, "assumptions");
+        ["interventions", "evidence", "clamps"]
+            .into_iter()
+            .flat_map(|kind| {
+                let assumed = get(&assumptions, kind).dyn_into::<Map>().unwrap();
+                js_sys::try_iter(&assumed.entries())
+                    .unwrap()
+                    .unwrap()
+                    .map(move |pair| {
+                        let pair = Array::from(&pair.unwrap());
+                        let value = JSON::stringify(&pair.get(1)).unwrap();
+                        format!("{kind}:{}={value}", pair.get(0).as_string().unwrap())
+                    })
+            })
+            .collect()
+    };
+
+    let intervention = compute_marginals(network(), 10.0, Some("A".to_string())).unwrap();
+    assert!(assumptions(&intervention).is_empty());
+
+    let v2 = compute_marginals_v2(
+        network(),
+        10.0,
+        options(r#"{"nodeId": "B", "value": true}"#),
+    )
+    .unwrap();
+    assert_eq!(assumptions(&v2), ["interventions:B=true"]);
+
+    let split =
+        compute_conditional_marginals(network(), 10.0, "A", options(r#"{"B": true, "C": "?"}"#))
+            .unwrap();
+    assert_eq!(assumptions(&split), ["evidence:B=true"]);
+
+    let reweighted = compute_marginals_reweighted(
+        network(),
+        10.0,
+        vec!["A".to_string()],
+        options(r#"{"A": 0.3}"#),
+        options(r#"{"A": 0.6}"#),
+    )
+    .unwrap();
+    assert!(assumptions(&reweighted).is_empty());
+
+    let mut compiled = CompiledNetwork::new(network()).unwrap();
+    let query = r#"{"numSamples": 10, "seed": 4, "assumptions": {"clamps": {"C": 0.5}}}"#;
+    let result = compiled.compute_intervention(options(query), "A").unwrap();
+    assert_eq!(assumptions(&result), ["clamps:C=0.5"]);
+    assert_eq!(get(&get(&result, "provenance"), "seed").as_f64(), Some(4.0));
+}
+
+#[wasm_bindgen_test]
+fn diff_assumptions_reports_each_change() {
+    let a =
+        options(r#"{ "name": "a", "interventions": { "X": true }, "evidence": { "E": false } }"#);
+    let b = options(r#"{ "name": "a", "interventions": { "X": false }, "clamps": { "C": 0.5 } }"#);
+
+    let diff = diff_assumptions(a, b).unwrap();
+
+    assert!(get(&diff, "name").is_undefined() || get(&diff, "name").is_null());
+    let interventions = Array::from(&get(&diff, "interventions"));
+    assert_eq!(interventions.length(), 1);
+    assert_eq!(get(&interventions.get(0), "before").as_bool(), Some(true));
+    assert_eq!(get(&interventions.get(0), "after").as_bool(), Some(false));
+    let evidence = Array::from(&get(&diff, "evidence"));
+    assert!(
+        get(&evidence.get(0), "after").is_undefined() || get(&evidence.get(0), "after").is_null()
+    );
+    assert_eq!(Array::from(&get(&diff, "clamps")).length(), 1);
+}
+
+fn rare_evidence_network(prior: f64) -> JsValue {
+    nodes(vec![
+        node("Rare", vec![entry("{}", prior)]),
+        node(
+            "Effect",
+            vec![
+                entry(r#"{"Rare": true}"#, 0.9),
+                entry(r#"{"Rare": false}"#, 0.1),
+            ],
+        ),
+    ])
+}
+
+#[wasm_bindgen_test]
+fn auto_keeps_rejection_sampling_for_common_evidence() {
+    let query = r#"{
+        "numSamples": 5000,
+        "seed": 3,
+        "assumptions": { "evidence": { "Rare": true } }
+    }"#;
+
+    let result =
+        compute_marginals_with_options(rare_evidence_network(0.5), options(query)).unwrap();
+
+    let meta = get(&result, "meta");
+    assert_eq!(
+        get(&meta, "algorithm").as_string().as_deref(),
+        Some("rejection")
+    );
+    let acceptance = get(&meta, "pilotAcceptance").as_f64().unwrap();
+    assert!((acceptance - 0.5).abs() < 0.05);
+    assert!((marginal(&get(&result, "marginals"), "Effect") - 0.9).abs() < 0.03);
+}
+
+#[wasm_bindgen_test]
+fn auto_switches_to_likelihood_weighting_for_rare_evidence() {
+    let query = r#"{
+        "numSamples": 5000,
+        "seed": 3,
+        "assumptions": { "evidence": { "Rare": true } }
+    }"#;
+
+    let result =
+        compute_marginals_with_options(rare_evidence_network(0.001), options(query)).unwrap();
+
+    let meta = get(&result, "meta");
+    assert_eq!(
+        get(&meta, "algorithm").as_string().as_deref(),
+        Some("likelihoodWeighting")
+    );
+    assert!(get(&meta, "pilotAcceptance").as_f64().unwrap() < 0.05);
+    let marginals = get(&result, "marginals");
+    assert!((marginal(&marginals, "Rare") - 1.0).abs() < f64::EPSILON);
+    assert!((marginal(&marginals, "Effect") - 0.9).abs() < 0.03);
+}
+
+#[wasm_bindgen_test]
+fn explicit_algorithm_skips_the_pilot() {
+    let query = r#"{
+        "numSamples": 1000,
+        "seed": 3,
+        "algorithm": "likelihoodWeighting",
+        "assumptions": { "evidence": { "Rare": true } }
+    }"#;
+
+    let result =
+        compute_marginals_with_options(rare_evidence_network(0.5), options(query)).unwrap();
+
+    let meta = get(&result, "meta");
+    assert_eq!(
+        get(&meta, "algorithm").as_string().as_deref(),
+        Some("likelihoodWeighting")
+    );
+    assert!(get(&meta, "pilotAcceptance").is_undefined());
+}
+
+#[wasm_bindgen_test]
+fn json_entry_point_matches_structured_entry_point() {
+    let nodes_json = r#"[
+        { "_id": "A", "cptEntries": [{ "parentStates": {}, "probability": 0.3 }] },
+        { "_id": "B", "cptEntries": [
+            { "parentStates": { "A": true }, "probability": 0.8 },
+            { "parentStates": { "A": null }, "probability": 0.2 }
+        ] }
+    ]"#;
+    let query = r#"{ "numSamples": 2000, "seed": 11 }"#;
+
+    let from_json = JSON::parse(&compute_marginals_json(nodes_json, query).unwrap()).unwrap();
+    let structured =
+        compute_marginals_with_options(JSON::parse(nodes_json).unwrap(), options(query)).unwrap();
+
+    let json_b = get(&get(&from_json, "marginals"), "B").as_f64().unwrap();
+    assert!((json_b - marginal(&get(&structured, "marginals"), "B")).abs() < f64::EPSILON);
+}
+
+#[wasm_bindgen_test]
+fn json_entry_point_reports_the_path_of_bad_values() {
+    let nodes_json = r#"[
+        { "_id": "A", "cptEntries": [{ "parentStates": {}, "probability": 0.3 }] },
+        { "_id": "B", "cptEntries": [{ "parentStates": {}, "probability": "high" }] }
+    ]"#;
+
+    let message = error_message(
+        compute_marginals_json(nodes_json, r#"{ "numSamples": 10 }"#).map(JsValue::from),
+    );
+
+    assert!(
+        message.contains("nodes[1].cptEntries[0].probability"),
+        "{message}"
+    );
+}
+
+/// `A -> {B, C} -> D -> {E, F} -> G`: two stacked diamonds.
+fn stacked_diamonds() -> JsValue {
+    let child = |id: &str, parents: &[&str]| {
+        let states: Vec<String> = parents.iter().map(|p| format!(r#""{p}": null"#)).collect();
+        node(id, vec![entry(&format!("{{{}}}", states.join(", ")), 0.5)])
+    };
+    nodes(vec![
+        child("G", &["E", "F"]),
+        child("E", &["D"]),
+        child("F", &["D"]),
+        child("D", &["B", "C"]),
+        child("B", &["A"]),
+        child("C", &["A"]),
+        child("A", &[]),
+    ])
+}
+
+#[wasm_bindgen_test]
+fn closures_list_each_node_once_in_topological_order() {
+    let upstream = ancestors(stacked_diamonds(), "G").unwrap();
+    assert_eq!(upstream.len(), 6);
+    assert_eq!(upstream.first().map(String::as_str), Some("A"));
+    let position = |id: &str| upstream.iter().position(|u| u == id).unwrap();
+    assert!(position("D") > position("B") && position("D") > position("C"));
+    assert!(position("E") > position("D") && position("F") > position("D"));
+
+    let downstream = descendants(stacked_diamonds(), "B").unwrap();
+    assert_eq!(downstream.len(), 4);
+    assert_eq!(downstream.first().map(String::as_str), Some("D"));
+    assert_eq!(downstream.last().map(String::as_str), Some("G"));
+
+    assert!(ancestors(stacked_diamonds(), "A").unwrap().is_empty());
+}
+
+#[wasm_bindgen_test]
+fn path_counts_multiply_through_diamonds() {
+    let paths = count_paths(stacked_diamonds(), "A", "G").unwrap();
+    assert_eq!(get(&paths, "count").as_f64(), Some(4.0));
+    assert_eq!(get(&paths, "overflow").as_bool(), Some(false));
+
+    let paths = count_paths(stacked_diamonds(), "B", "C").unwrap();
+    assert_eq!(get(&paths, "count").as_f64(), Some(0.0));
+    let paths = count_paths(stacked_diamonds(), "G", "A").unwrap();
+    assert_eq!(get(&paths, "count").as_f64(), Some(0.0));
+}
+
+#[wasm_bindgen_test]
+fn path_counts_saturate_and_report_overflow() {
+    // A ladder of 60 diamonds has 2^60 paths end to end.
+    let mut network = vec![node("L0", vec![entry("{}", 0.5)])];
+    for i in 0..60 {
+        let parent = format!("L{i}");
+        for side in ["a", "b"] {
+            network.push(node(
+                &format!("L{i}{side}"),
+                vec![entry(&format!(r#"{{"{parent}": null}}"#), 0.5)],
+            ));
+        }
+        network.push(node(
+            &format!("L{}", i + 1),
+            vec![entry(&format!(r#"{{"L{i}a": null, "L{i}b": null}}"#), 0.5)],
+        ));
+    }
+
+    let paths = count_paths(nodes(network), "L0", "L60").unwrap();
+
+    assert_eq!(get(&paths, "overflow").as_bool(), Some(true));
+    assert_eq!(get(&paths, "count").as_f64(), Some(9_007_199_254_740_991.0));
+}
+
+fn error_code(error: &JsValue) -> (String, String) {
+    assert!(error.is_instance_of::<js_sys::Error>(), "{error:?}");
+    let field = |key| {
+        get(error, key)
+            .as_string()
+            .expect("error field is a string")
+    };
+    (field("code"), field("field"))
+}
+
+#[wasm_bindgen_test]
+fn unknown_node_ids_suggest_near_matches() {
+    let network = || {
+        nodes(vec![
+            node("Regulation ", vec![entry("{}", 0.5)]),
+            node("Économie", vec![entry("{}", 0.5)]),
+        ])
+    };
+    let suggestions = |error: &JsValue| {
+        JSON::stringify(&get(&get(error, "details"), "suggestions"))
+            .unwrap()
+            .as_string()
+            .unwrap()
+    };
+
+    let error = compute_marginals(network(), 10.0, Some("Regulation".to_string())).unwrap_err();
+    assert_eq!(
+        error_code(&error),
+        ("NODE_NOT_FOUND".to_string(), "intervention".to_string())
+    );
+    assert_eq!(
+        get(&get(&error, "details"), "nodeId")
+            .as_string()
+            .as_deref(),
+        Some("Regulation")
+    );
+    assert_eq!(suggestions(&error), r#"["Regulation "]"#);
+    assert!(error_message(Err::<(), _>(error)).contains("did you mean 'Regulation '?"));
+
+    // Case and Unicode normalization are ignored, and a typo is tolerated.
+    let mut compiled = CompiledNetwork::new(network()).unwrap();
+    for spelled in ["économie", "E\u{301}conomie", "Economie"] {
+        let query = format!(
+            r#"{{"numSamples": 10, "assumptions": {{"evidence": {{"{spelled}": true}}}}}}"#
+        );
+        let error = compiled.compute_marginals(options(&query)).unwrap_err();
+        assert_eq!(error_code(&error).1, "evidence", "{spelled}");
+        assert_eq!(suggestions(&error), r#"["Économie"]"#, "{spelled}");
+    }
+
+    let error = compiled
+        .compute_intervention(options(r#"{"numSamples": 10}"#), "Inflation")
+        .unwrap_err();
+    assert_eq!(suggestions(&error), "[]");
+    assert_eq!(
+        error_message(Err::<(), _>(error)),
+        "Intervention node Inflation not found"
+    );
+}
+
+#[wasm_bindgen_test]
+fn lookups_report_missing_nodes_with_their_role() {
+    let network = || nodes(chain(0.9));
+    let cases = [
+        (
+            compute_conditional_marginals(network(), 10.0, "Z", JsValue::UNDEFINED).map(drop),
+            "condition",
+        ),
+        (
+            compute_marginals_reweighted(
+                network(),
+                10.0,
+                vec!["Z".to_string()],
+                options("{}"),
+                options("{}"),
+            )
+            .map(drop),
+            "covariate",
+        ),
+        (ancestors(network(), "Z").map(drop), "query"),
+        (count_paths(network(), "A", "Z").map(drop), "to"),
+        (
+            compute_do_distribution(network(), 10.0, "A", "Z").map(drop),
+            "outcome",
+        ),
+        (
+            compute_mediation_proportion(network(), 10.0, "A", "Z", "C").map(drop),
+            "mediator",
+        ),
+        (
+            compute_causal_attribution(network(), 10.0, "C", options(r#"["Z"]"#)).map(drop),
+            "ancestor",
+        ),
+    ];
+
+    for (result, field) in cases {
+        let error = result.unwrap_err();
+        assert_eq!(
+            error_code(&error),
+            ("NODE_NOT_FOUND".to_string(), field.to_string())
+        );
+        assert_eq!(
+            get(&get(&error, "details"), "nodeId")
+                .as_string()
+                .as_deref(),
+            Some("Z")
+        );
+    }
+}
+
+#[wasm_bindgen_test]
+fn bad_sample_counts_are_rejected_with_codes() {
+    let network = || nodes(vec![node("A", vec![entry("{}", 0.5)])]);
+    let cases = [
+        (-5.0, "OUT_OF_RANGE"),
+        (0.0, "OUT_OF_RANGE"),
+        (1.5e7, "OUT_OF_RANGE"),
+        (10.5, "NOT_AN_INTEGER"),
+        (f64::NAN, "NOT_A_NUMBER"),
+        (f64::INFINITY, "NOT_A_NUMBER"),
+    ];
+
+    for (num_samples, expected) in cases {
+        let error = compute_marginals(network(), num_samples, None).unwrap_err();
+        assert_eq!(
+            error_code(&error),
+            (expected.to_string(), "numSamples".to_string()),
+            "numSamples = {num_samples}"
+        );
+    }
+}
+
+#[wasm_bindgen_test]
+fn bad_numeric_options_are_rejected_with_codes() {
+    let network = || nodes(vec![node("A", vec![entry("{}", 0.5)])]);
+    let cases = [
+        (r#"{"numSamples": -1}"#, "OUT_OF_RANGE", "numSamples"),
+        (r#"{"numSamples": 2.5}"#, "NOT_AN_INTEGER", "numSamples"),
+        (r#"{"numSamples": 10, "seed": -3}"#, "OUT_OF_RANGE", "seed"),
+        (
+            r#"{"numSamples": 10, "seed": 0.5}"#,
+            "NOT_AN_INTEGER",
+            "seed",
+        ),
+        (
+            r#"{"numSamples": 10, "seed": 1e16}"#,
+            "OUT_OF_RANGE",
+            "seed",
+        ),
+    ];
+
+    for (json, code, field) in cases {
+        let error = compute_marginals_with_options(network(), options(json)).unwrap_err();
+        assert_eq!(
+            error_code(&error),
+            (code.to_string(), field.to_string()),
+            "{json}"
+        );
+        let error = compute_marginals_json("[]", json).unwrap_err();
+        assert_eq!(
+            error_code(&error),
+            (code.to_string(), field.to_string()),
+            "{json}"
+        );
+    }
+}
+
+#[wasm_bindgen_test]
+fn largest_safe_seed_is_accepted() {
+    let network = nodes(vec![node("A", vec![entry("{}", 0.5)])]);
+
+    let trace = rng_trace(network, 9_007_199_254_740_991.0, 3.0).unwrap();
+
+    assert_eq!(get(&trace, "seed").as_f64(), Some(9_007_199_254_740_991.0));
+    let error = rng_trace(nodes(vec![]), -1.0, 3.0).unwrap_err();
+    assert_eq!(
+        error_code(&error),
+        ("OUT_OF_RANGE".to_string(), "seed".to_string())
+    );
+}
+
+#[wasm_bindgen_test]
+fn workspace_queries_compares_and_budgets_networks() {
+    let variant = |prior| nodes(vec![node("A", vec![entry("{}", prior)])]);
+    let mut workspace = Workspace::new(None).unwrap();
+    workspace.register("fork".into(), variant(0.2)).unwrap();
+    workspace.register("baseline".into(), variant(0.6)).unwrap();
+
+    assert_eq!(workspace.keys(), vec!["baseline", "fork"]);
+    let distance = workspace.compare("fork", "baseline").unwrap();
+    assert!((get(&distance, "parametric").as_f64().unwrap() - 0.4).abs() < 1e-6);
+    let result = workspace
+        .compute_marginals("fork", options(r#"{"numSamples": 1000, "seed": 1}"#))
+        .unwrap();
+    assert!(get(&result, "marginals").is_object());
+
+    let per_network = u32::try_from(workspace.memory_bytes() / 2).unwrap();
+    let mut tight = Workspace::new(Some(f64::from(per_network + per_network / 2))).unwrap();
+    tight.register("fork".into(), variant(0.2)).unwrap();
+    let error = tight.register("baseline".into(), variant(0.6)).unwrap_err();
+    assert_eq!(
+        error_code(&error),
+        (
+            "MEMORY_BUDGET_EXCEEDED".to_string(),
+            "memoryBudget".to_string()
+        )
+    );
+    // Replacing a network only counts the difference.
+    tight.register("fork".into(), variant(0.6)).unwrap();
+    assert!(tight.evict("fork"));
+    tight.register("baseline".into(), variant(0.6)).unwrap();
+    assert!(workspace.compare("fork", "published").is_err());
+}
+
+fn chain(b_given_a: f64) -> Vec<JsValue> {
+    vec![
+        node("A", vec![entry("{}", 0.3)]),
+        node(
+            "B",
+            vec![
+                entry(r#"{"A": true}"#, b_given_a),
+                entry(r#"{"A": false}"#, 0.1),
+            ],
+        ),
+        node("C", vec![entry(r#"{"B": null}"#, 0.5)]),
+    ]
+}
+
+#[wasm_bindgen_test]
+fn cpt_edits_rewrite_only_the_changed_records() {
+    let mut network = CompiledNetwork::new(nodes(chain(0.9))).unwrap();
+    let edited = nodes(vec![node(
+        "B",
+        vec![
+            entry(r#"{"A": true}"#, 0.4),
+            entry(r#"{"A": null}"#, 0.2),
+            entry(r#"{"A": false}"#, 0.1),
+        ],
+    )]);
+
+    let result = network.update_nodes(edited).unwrap();
+
+    assert_eq!(get(&result, "fullRecompile").as_bool(), Some(false));
+    let mut expected = chain(0.4);
+    expected[1] = node(
+        "B",
+        vec![
+            entry(r#"{"A": true}"#, 0.4),
+            entry(r#"{"A": null}"#, 0.2),
+            entry(r#"{"A": false}"#, 0.1),
+        ],
+    );
+    let fresh = CompiledNetwork::new(nodes(expected)).unwrap();
+    assert_eq!(network.fingerprint(), fresh.fingerprint());
+    let query = || options(r#"{"numSamples": 2000, "seed": 3}"#);
+    let updated = get(&network.compute_marginals(query()).unwrap(), "marginals");
+    let recompiled = get(&fresh.compute_marginals(query()).unwrap(), "marginals");
+    for id in ["A", "B", "C"] {
+        assert!((marginal(&updated, id) - marginal(&recompiled, id)).abs() < f64::EPSILON);
+    }
+}
+
+#[wasm_bindgen_test]
+fn entry_reordering_moves_common_entries_ahead_without_changing_results() {
+    let network = || {
+        nodes(vec![
+            node("A", vec![entry("{}", 0.1)]),
+            // A is rarely true, so the second entry matches most samples.
+            node(
+                "B",
+                vec![entry(r#"{"A": true}"#, 0.9), entry(r#"{"A": false}"#, 0.2)],
+            ),
+            // The catch-all overlaps the first entry, which must stay ahead.
+            node(
+                "C",
+                vec![entry(r#"{"A": true}"#, 0.7), entry(r#"{"A": null}"#, 0.4)],
+            ),
+        ])
+    };
+    let mut reordered = CompiledNetwork::new(network()).unwrap();
+    let original = CompiledNetwork::new(network()).unwrap();
+
+    let moved = reordered.order_entries_by_frequency(1000.0).unwrap();
+
+    assert_eq!(moved, vec!["B".to_string()]);
+    assert_ne!(reordered.fingerprint(), original.fingerprint());
+    let query = || options(r#"{"numSamples": 2000, "seed": 5}"#);
+    let after = get(&reordered.compute_marginals(query()).unwrap(), "marginals");
+    let before = get(&original.compute_marginals(query()).unwrap(), "marginals");
+    for id in ["A", "B", "C"] {
+        assert!((marginal(&after, id) - marginal(&before, id)).abs() < f64::EPSILON);
+    }
+    assert!(
+        reordered
+            .order_entries_by_frequency(1000.0)
+            .unwrap()
+            .is_empty()
+    );
+}
+
+#[wasm_bindgen_test]
+fn parent_set_changes_fall_back_to_a_full_recompile() {
+    let mut network = CompiledNetwork::new(nodes(chain(0.9))).unwrap();
+    // C now depends on A instead of B.
+    let rewired = node("C", vec![entry(r#"{"A": null}"#, 0.5)]);
+
+    let result = network.update_nodes(nodes(vec![rewired.clone()])).unwrap();
+
+    assert_eq!(get(&result, "fullRecompile").as_bool(), Some(true));
+    let mut expected = chain(0.9);
+    expected[2] = rewired;
+    let fresh = CompiledNetwork::new(nodes(expected)).unwrap();
+    assert_eq!(network.fingerprint(), fresh.fingerprint());
+}
+
+#[wasm_bindgen_test]
+fn invalid_updates_leave_the_handle_unchanged() {
+    let mut network = CompiledNetwork::new(nodes(chain(0.9))).unwrap();
+    let before = network.fingerprint();
+
+    let bad_cpt = nodes(vec![node("A", vec![entry("{}", f64::NAN)])]);
+    assert!(network.update_nodes(bad_cpt).is_err());
+    let cycle = nodes(vec![node("A", vec![entry(r#"{"C": null}"#, 0.5)])]);
+    assert!(network.update_nodes(cycle).is_err());
+
+    assert_eq!(network.fingerprint(), before);
+}
+
+#[wasm_bindgen_test]
+fn v2_interventions_accept_null_object_or_array_and_are_echoed() {
+    let network = || {
+        nodes(vec![
+            node("A", vec![entry("{}", 0.5)]),
+            node("B", vec![entry("{}", 0.5)]),
+            node(
+                "C",
+                vec![entry(r#"{"A": true, "B": true}"#, 1.0), entry("{}", 0.0)],
+            ),
+        ])
+    };
+
+    let baseline = compute_marginals_v2(network(), 100.0, JsValue::NULL).unwrap();
+    assert!(get(&baseline, "interventions").is_null());
+
+    let single = options(r#"{"nodeId": "A", "value": false}"#);
+    let result = compute_marginals_v2(network(), 100.0, single).unwrap();
+    assert_eq!(
+        get(&get(&result, "interventions"), "nodeId")
+            .as_string()
+            .as_deref(),
+        Some("A")
+    );
+    assert!(marginal(&get(&result, "marginals"), "C").abs() < f64::EPSILON);
+
+    let batch = options(r#"[{"nodeId": "A", "value": true}, {"nodeId": "B", "value": true}]"#);
+    let result = compute_marginals_v2(network(), 100.0, batch).unwrap();
+    assert!(Array::is_array(&get(&result, "interventions")));
+    assert!((marginal(&get(&result, "marginals"), "C") - 1.0).abs() < f64::EPSILON);
+
+    let conflicting =
+        options(r#"[{"nodeId": "A", "value": true}, {"nodeId": "A", "value": false}]"#);
+    let message = error_message(compute_marginals_v2(network(), 100.0, conflicting));
+    assert!(message.contains("conflicting values"), "{message}");
+}
+
+#[wasm_bindgen_test]
+fn handle_reuses_baseline_until_the_network_changes() {
+    let mut network = CompiledNetwork::new(nodes(chain(0.9))).unwrap();
+    let query = || options(r#"{"numSamples": 2000, "seed": 5}"#);
+
+    let first = network.compute_intervention(query(), "B").unwrap();
+    assert!(network.has_cached_baseline());
+    let cached = network.compute_intervention(query(), "C").unwrap();
+    network.invalidate_cache();
+    assert!(!network.has_cached_baseline());
+    let recomputed = network.compute_intervention(query(), "C").unwrap();
+    for id in ["A", "B", "C"] {
+        let baseline = |result: &JsValue| marginal(&get(result, "baseline"), id);
+        assert!((baseline(&first) - baseline(&cached)).abs() < f64::EPSILON);
+        assert!((baseline(&cached) - baseline(&recomputed)).abs() < f64::EPSILON);
+    }
+
+    network
+        .update_nodes(nodes(vec![node("A", vec![entry("{}", 1.0)])]))
+        .unwrap();
+    assert!(!network.has_cached_baseline());
+    let updated = network.compute_intervention(query(), "B").unwrap();
+    assert!((marginal(&get(&updated, "baseline"), "A") - 1.0).abs() < f64::EPSILON);
+}
+
+#[wasm_bindgen_test]
+fn progress_snapshots_carry_sorted_top_movers() {
+    let network = nodes(vec![
+        node("A", vec![entry("{}", 0.5)]),
+        node("B", vec![entry(r#"{"A": true}"#, 0.9), entry("{}", 0.2)]),
+        node("C", vec![entry("{}", 0.1)]),
+    ]);
+    let record = js_sys::Function::new_with_args(
+        "snapshot",
+        "(globalThis.progressSnapshots ??= []).push(snapshot)",
+    );
+
+    compute_marginals_with_progress(network, 1000.0, 300.0, 2.0, &record).unwrap();
+
+    let snapshots: Array = Reflect::get(&js_sys::global(), &"progressSnapshots".into())
+        .unwrap()
+        .into();
+    let drawn: Vec<f64> = snapshots
+        .iter()
+        .map(|s| get(&s, "samplesDrawn").as_f64().unwrap())
+        .collect();
+    assert_eq!(drawn, vec![300.0, 600.0, 900.0, 1000.0]);
+    assert_eq!(
+        Array::from(&get(&snapshots.get(0), "topMovers")).length(),
+        0
+    );
+    for snapshot in snapshots.iter().skip(1) {
+        let movers = Array::from(&get(&snapshot, "topMovers"));
+        assert_eq!(movers.length(), 2);
+        let moved = |i| {
+            let mover = movers.get(i);
+            let current = get(&mover, "current").as_f64().unwrap();
+            assert_eq!(
+                get(
+                    &get(&snapshot, "marginals"),
+                    &get(&mover, "id").as_string().unwrap()
+                )
+                .as_f64(),
+                Some(current)
+            );
+            (current - get(&mover, "previous").as_f64().unwrap()).abs()
+        };
+        assert!(moved(0) >= moved(1));
+    }
+
+    let throwing = js_sys::Function::new_with_args("snapshot", "throw new Error('stop')");
+    let network = nodes(vec![node("A", vec![entry("{}", 0.5)])]);
+    let error = compute_marginals_with_progress(network, 100.0, 10.0, 1.0, &throwing).unwrap_err();
+    assert_eq!(
+        Reflect::get(&error, &"message".into())
+            .unwrap()
+            .as_string()
+            .as_deref(),
+        Some("stop")
+    );
+}
+
+#[wasm_bindgen_test]
+fn exhausted_budget_still_draws_min_samples() {
+    let network = || nodes(vec![node("A", vec![entry("{}", 0.5)])]);
+
+    let result = compute_marginals_with_budget(network(), 0.0, 2500.0).unwrap();
+
+    let meta = get(&result, "meta");
+    // The clock is checked every 1000 samples.
+    assert_eq!(get(&meta, "samplesUsed").as_f64(), Some(3000.0));
+    assert!(get(&meta, "elapsedMs").as_f64().unwrap() >= 0.0);
+    assert!(get(&meta, "converged").as_bool().is_some());
+    assert!(get(&get(&result, "marginals"), "A").as_f64().is_some());
+
+    let error = compute_marginals_with_budget(network(), -1.0, 10.0).unwrap_err();
+    assert_eq!(
+        error_code(&error),
+        ("OUT_OF_RANGE".to_string(), "maxMs".to_string())
+    );
+}
+
+fn latent(node: JsValue) -> JsValue {
+    set(node.unchecked_ref(), "latent", &JsValue::TRUE);
+    node
+}
+
+#[wasm_bindgen_test]
+fn identifiability_reports_adjustment_or_factorization() {
+    let u = latent(node("U", vec![entry("{}", 0.5)]));
+    let x = node(
+        "X",
+        vec![entry(r#"{"U":true}"#, 0.8), entry(r#"{"U":false}"#, 0.2)],
+    );
+    let m = node(
+        "M",
+        vec![entry(r#"{"X":true}"#, 0.7), entry(r#"{"X":false}"#, 0.3)],
+    );
+    let y_entries = |via: &str| {
+        vec![
+            entry(&format!(r#"{{"{via}":true,"U":true}}"#), 0.9),
+            entry(&format!(r#"{{"{via}":true,"U":false}}"#), 0.6),
+            entry(&format!(r#"{{"{via}":false,"U":true}}"#), 0.4),
+            entry(&format!(r#"{{"{via}":false,"U":false}}"#), 0.1),
+        ]
+    };
+
+    let bow = nodes(vec![u.clone(), x.clone(), node("Y", y_entries("X"))]);
+    let result = identify_effect(bow, "X", "Y").unwrap();
+    assert_eq!(get(&result, "identifiable"), JsValue::FALSE);
+    assert!(get(&result, "formula").is_undefined());
+
+    let frontdoor = nodes(vec![u.clone(), x.clone(), m, node("Y", y_entries("M"))]);
+    let result = identify_effect(frontdoor, "X", "Y").unwrap();
+    assert_eq!(get(&result, "identifiable"), JsValue::TRUE);
+    assert!(get(&result, "adjustmentSet").is_undefined());
+    assert_eq!(
+        JSON::stringify(&get(&result, "cComponents")).unwrap(),
+        r#"[["M"],["Y"]]"#
+    );
+    assert_eq!(
+        get(&result, "formula").as_string().unwrap(),
+        "P(Y | do(X)) = Σ_{M} Q[{M}] Q[{Y}]"
+    );
+
+    // With U observed, adjusting for it closes the backdoor path.
+    let observed_u = node("U", vec![entry("{}", 0.5)]);
+    let backdoor = nodes(vec![observed_u, x, node("Y", y_entries("X"))]);
+    let result = identify_effect(backdoor, "X", "Y").unwrap();
+    assert_eq!(
+        JSON::stringify(&get(&result, "adjustmentSet")).unwrap(),
+        r#"["U"]"#
+    );
+    assert_eq!(
+        get(&result, "formula").as_string().unwrap(),
+        "P(Y | do(X)) = Σ_{U} P(Y | X, U) P(U)"
+    );
+}
+
+/// A network over `edges` with one wildcard entry per node, for structural
+/// queries.
+fn dag(edges: &[(&str, &str)]) -> JsValue {
+    let mut ids: Vec<&str> = edges.iter().flat_map(|&(a, b)| [a, b]).collect();
+    ids.sort_unstable();
+    ids.dedup();
+    nodes(
+        ids.iter()
+            .map(|&id| {
+                let parents: serde_json::Map<String, serde_json::Value> = edges
+                    .iter()
+                    .filter(|&&(_, child)| child == id)
+                    .map(|&(parent, _)| (parent.to_string(), serde_json::Value::Null))
+                    .collect();
+                node(
+                    id,
+                    vec![entry(&serde_json::Value::Object(parents).to_string(), 0.5)],
+                )
+            })
+            .collect(),
+    )
+}
+
+#[wasm_bindgen_test]
+fn identifiability_projects_hidden_nodes_before_checking_for_hedges() {
+    let identifiable = |edges: &[(&str, &str)], hidden: &[&str]| {
+        let ids = |ids: &[&str]| {
+            ids.iter()
+                .map(|&id| JsValue::from_str(id))
+                .collect::<Array>()
+        };
+        check_identifiability(
+            dag(edges),
+            ids(&["X"]).into(),
+            ids(&["Y"]).into(),
+            ids(hidden).into(),
+        )
+        .unwrap()
+        .as_bool()
+        .unwrap()
+    };
+
+    let bow = [("U", "X"), ("U", "Y"), ("X", "Y")];
+    assert!(!identifiable(&bow, &["U"]));
+    assert!(identifiable(&bow, &[]));
+    let front_door = [("U", "X"), ("U", "Y"), ("X", "M"), ("M", "Y")];
+    assert!(identifiable(&front_door, &["U"]));
+    let back_door = [("Z", "X"), ("Z", "Y"), ("X", "Y"), ("U", "Z"), ("U", "Y")];
+    assert!(identifiable(&back_door, &["U"]));
+    // The napkin graph needs Tian's recursion to get past W.
+    let napkin = [
+        ("W", "Z"),
+        ("Z", "X"),
+        ("X", "Y"),
+        ("U1", "W"),
+        ("U1", "X"),
+        ("U2", "W"),
+        ("U2", "Y"),
+    ];
+    assert!(identifiable(&napkin, &["U1", "U2"]));
+    // Confounding the treatment with its mediator is a hedge too.
+    let confounded_mediator = [("X", "M"), ("M", "Y"), ("U", "X"), ("U", "M")];
+    assert!(!identifiable(&confounded_mediator, &["U"]));
+
+    // A hidden chain L1 -> L2 still confounds X and Y: the projection joins
+    // them with a bidirected edge.
+    let hidden_chain = [("L1", "L2"), ("L1", "X"), ("L2", "Y"), ("X", "Y")];
+    assert!(!identifiable(&hidden_chain, &["L1", "L2"]));
+    // With L1 observed, L1 -> L2 -> Y projects to a directed L1 -> Y and no
+    // bidirected edge is left.
+    assert!(identifiable(&hidden_chain, &["L2"]));
+    // A hidden mediator projects to a directed edge.
+    assert!(identifiable(&[("X", "L"), ("L", "Y")], &["L"]));
+}
+
+#[wasm_bindgen_test]
+fn do_calculus_rules_remove_actions_when_the_graph_allows() {
+    let network = |u: JsValue| {
+        nodes(vec![
+            u,
+            node(
+                "X",
+                vec![entry(r#"{"U":true}"#, 0.8), entry(r#"{"U":false}"#, 0.2)],
+            ),
+            node(
+                "Y",
+                vec![
+                    entry(r#"{"X":true,"U":true}"#, 0.9),
+                    entry(r#"{"X":true,"U":false}"#, 0.6),
+                    entry(r#"{"X":false,"U":true}"#, 0.4),
+                    entry(r#"{"X":false,"U":false}"#, 0.1),
+                ],
+            ),
+        ])
+    };
+    let u = || node("U", vec![entry("{}", 0.5)]);
+    let query = |json: &str| JSON::parse(json).unwrap();
+
+    // Conditioning on U closes the backdoor path, so do(X) becomes X (rule 2).
+    let result = compute_do_calculus_rules(
+        network(u()),
+        query(r#"{"outcomes":["Y"],"interventions":["X"],"observations":["U"]}"#),
+    )
+    .unwrap();
+    assert_eq!(
+        get(&result, "expression").as_string().unwrap(),
+        "P(Y | U, X)"
+    );
+    assert_eq!(get(&result, "observational"), JsValue::TRUE);
+    let steps = get(&result, "steps");
+    assert_eq!(
+        JSON::stringify(&steps).unwrap().as_string().unwrap(),
+        r#"[{"rule":2,"nodeId":"X","expression":"P(Y | U, X)"}]"#
+    );
+
+    // X doesn't cause U, so the action is deleted outright (rule 3).
+    let result = compute_do_calculus_rules(
+        network(u()),
+        query(r#"{"outcomes":["U"],"interventions":["X"]}"#),
+    )
+    .unwrap();
+    assert_eq!(get(&result, "expression").as_string().unwrap(), "P(U)");
+
+    // With U latent and unobserved, no rule touches the confounded effect.
+    let result = compute_do_calculus_rules(
+        network(latent(u())),
+        query(r#"{"outcomes":["Y"],"interventions":["X"]}"#),
+    )
+    .unwrap();
+    assert!(result.is_undefined());
+
+    let error = error_message(compute_do_calculus_rules(
+        network(u()),
+        query(r#"{"outcomes":["Y"],"interventions":["Y"]}"#),
+    ));
+    assert!(error.contains("more than once"));
+}
+
+#[wasm_bindgen_test]
+fn probability_of_false_entries_match_probability_of_true_entries() {
+    let of_false = |parent_states_json: &str, probability: f64| {
+        let entry = entry(parent_states_json, probability);
+        set(
+            entry.unchecked_ref(),
+            "isProbabilityOfTrue",
+            &JsValue::FALSE,
+        );
+        entry
+    };
+    let as_true = nodes(chain(0.8));
+    let as_false = nodes(vec![
+        node("A", vec![of_false("{}", 0.7)]),
+        node(
+            "B",
+            vec![
+                of_false(r#"{"A": true}"#, 0.2),
+                entry(r#"{"A": false}"#, 0.1),
+            ],
+        ),
+        node("C", vec![of_false(r#"{"B": null}"#, 0.5)]),
+    ]);
+
+    let query = r#"{ "numSamples": 2000, "seed": 7 }"#;
+    let expected = compute_marginals_with_options(as_true, options(query)).unwrap();
+    let actual = compute_marginals_with_options(as_false, options(query)).unwrap();
+
+    // Both conventions compile to the same buffer.
+    let fingerprint = |result: &JsValue| get(&get(result, "provenance"), "fingerprint");
+    assert_eq!(fingerprint(&expected), fingerprint(&actual));
+    for id in ["A", "B", "C"] {
+        let (expected, actual) = (get(&expected, "marginals"), get(&actual, "marginals"));
+        assert!(
+            (marginal(&expected, id) - marginal(&actual, id)).abs() < f64::EPSILON,
+            "{id}"
+        );
+    }
+}
+
+#[wasm_bindgen_test]
+fn ensemble_averages_per_network_marginals_by_weight() {
+    let networks = || vec![nodes(chain(0.9)), nodes(chain(0.2))];
+    let result = compute_marginals_ensemble(networks(), &[0.25, 0.75], 2000.0, None).unwrap();
+
+    let per_network = Array::from(&get(&result, "perNetwork"));
+    assert_eq!(per_network.length(), 2);
+    let expected =
+        0.25 * marginal(&per_network.get(0), "B") + 0.75 * marginal(&per_network.get(1), "B");
+    assert!((marginal(&get(&result, "ensemble"), "B") - expected).abs() < 1e-12);
+
+    let result =
+        compute_marginals_ensemble(networks(), &[0.5, 0.5], 2000.0, Some("A".into())).unwrap();
+    let ensemble = get(&result, "ensemble");
+    assert!((marginal(&get(&ensemble, "trueCase"), "A") - 1.0).abs() < f64::EPSILON);
+    assert!(get(&get(&result, "perNetwork"), "0").is_object());
+
+    let message = error_message(compute_marginals_ensemble(
+        networks(),
+        &[0.5, 0.6],
+        10.0,
+        None,
+    ));
+    assert!(message.contains("Weights sum to"), "{message}");
+    let message = error_message(compute_marginals_ensemble(
+        vec![
+            nodes(chain(0.9)),
+            nodes(vec![node("A", vec![entry("{}", 0.5)])]),
+        ],
+        &[0.5, 0.5],
+        10.0,
+        None,
+    ));
+    assert!(message.contains("same node IDs"), "{message}");
+    let message = error_message(compute_marginals_ensemble(
+        (0..11).map(|_| nodes(chain(0.9))).collect(),
+        &[1.0 / 11.0; 11],
+        10.0,
+        None,
+    ));
+    assert!(message.contains("at most 10 networks"), "{message}");
+}
+
+#[wasm_bindgen_test]
+fn paired_rows_differ_only_downstream_of_the_intervention() {
+    let csv = generate_paired_dataset(nodes(chain(0.8)), 200.0, "B", Some(3.0)).unwrap();
+    assert_eq!(
+        csv,
+        generate_paired_dataset(nodes(chain(0.8)), 200.0, "B", Some(3.0)).unwrap()
+    );
+
+    let mut lines = csv.lines();
+    assert_eq!(lines.next(), Some("row,regime,diverged,A,B,C"));
+    let rows: Vec<Vec<&str>> = lines.map(|line| line.split(',').collect()).collect();
+    assert_eq!(rows.len(), 400);
+    let mut diverged = 0;
+    for pair in rows.chunks(2) {
+        let [observational, interventional] = pair else {
+            unreachable!()
+        };
+        assert_eq!(observational[1], "observational");
+        assert_eq!(interventional[1], "interventional");
+        assert_eq!(interventional[4], "true");
+        assert_eq!(observational[3], interventional[3], "A is upstream");
+        assert_eq!(observational[5], interventional[5], "C ignores B");
+        let differs = observational[4] != interventional[4];
+        assert_eq!(observational[2], differs.to_string());
+        diverged += usize::from(differs);
+    }
+    assert!(diverged > 0);
+}
+
+#[wasm_bindgen_test]
+fn hierarchical_entries_follow_their_hyperparameter() {
+    let network = || {
+        let local = entry("{}", 0.0);
+        set(
+            local.unchecked_ref(),
+            "probabilityParams",
+            &JSON::parse(r#"{ "hyperparameterId": "H", "pHigh": 0.9, "pLow": 0.1 }"#).unwrap(),
+        );
+        nodes(vec![
+            node("H", vec![entry("{}", 0.5)]),
+            node("X", vec![local]),
+        ])
+    };
+    let under = |value: bool| {
+        let query = format!(
+            r#"{{ "numSamples": 20000, "seed": 1, "assumptions": {{ "interventions": {{ "H": {value} }} }} }}"#
+        );
+        let result = compute_marginals_with_options(network(), options(&query)).unwrap();
+        marginal(&get(&result, "marginals"), "X")
+    };
+
+    assert!((under(true) - 0.9).abs() < 0.02);
+    assert!((under(false) - 0.1).abs() < 0.02);
+
+    let bytes = to_compact(network()).unwrap();
+    assert_eq!(to_compact(from_compact(&bytes).unwrap()).unwrap(), bytes);
+    let decoded = Array::from(&from_compact(&bytes).unwrap());
+    let entries = Array::from(&get(&decoded.get(1), "cptEntries"));
+    let params = get(&entries.get(0), "probabilityParams");
+    assert_eq!(get(&params, "pHigh").as_f64(), Some(0.9));
+}
+
+#[wasm_bindgen_test]
+fn annotations_survive_exports_and_can_key_results() {
+    let network = || {
+        let a = node("A", vec![entry("{}", 0.3)]);
+        set(a.unchecked_ref(), "title", &"Takeoff is fast".into());
+        set(a.unchecked_ref(), "description", &"<b>fast</b>".into());
+        set(
+            a.unchecked_ref(),
+            "metadata",
+            &JSON::parse(r#"{ "color": "red", "tags": [1, 2] }"#).unwrap(),
+        );
+        nodes(vec![
+            a,
+            node(
+                "B",
+                vec![entry(r#"{"A": true}"#, 0.8), entry(r#"{"A": false}"#, 0.1)],
+            ),
+        ])
+    };
+
+    let bytes = to_compact(network()).unwrap();
+    let decoded = Array::from(&from_compact(&bytes).unwrap()).get(0);
+    assert_eq!(
+        get(&decoded, "title").as_string().unwrap(),
+        "Takeoff is fast"
+    );
+    assert_eq!(
+        JSON::stringify(&get(&decoded, "metadata")).unwrap(),
+        r#"{"color":"red","tags":[1,2]}"#
+    );
+    let handle = CompiledNetwork::new(network()).unwrap();
+    let round_tripped = Array::from(&handle.nodes().unwrap()).get(0);
+    assert_eq!(
+        get(&round_tripped, "description").as_string().unwrap(),
+        "<b>fast</b>"
+    );
+
+    let xml = export_graphml(network(), JsValue::UNDEFINED, JsValue::UNDEFINED).unwrap();
+    assert!(
+        xml.contains(r#"<data key="title">Takeoff is fast</data>"#),
+        "{xml}"
+    );
+    assert!(xml.contains("&lt;b&gt;fast&lt;/b&gt;"), "{xml}");
+
+    let result = compute_marginals_with_options(
+        network(),
+        options(r#"{ "numSamples": 100, "keyBy": "title" }"#),
+    )
+    .unwrap();
+    let marginals = get(&result, "marginals").dyn_into::<Map>().unwrap();
+    assert!(marginals.has(&"Takeoff is fast".into()));
+    assert!(marginals.has(&"B".into()));
+    assert!(!marginals.has(&"A".into()));
+}
+
+#[wasm_bindgen_test]
+fn mixed_evidence_combines_hard_and_soft_observations() {
+    let network = || {
+        nodes(vec![
+            node("A", vec![entry("{}", 0.5)]),
+            node("B", vec![entry("{}", 0.5)]),
+            node(
+                "C",
+                vec![entry(r#"{"A": true}"#, 0.9), entry(r#"{"A": false}"#, 0.2)],
+            ),
+        ])
+    };
+    let evidence = |json: &str| JSON::parse(json).unwrap();
+
+    // A ratio of 3 on a fair coin gives a posterior of 3 / (3 + 1).
+    let result = compute_posterior_mixed_evidence(
+        network(),
+        40_000.0,
+        evidence(
+            r#"{ "hard": [{ "nodeId": "C", "value": true }],
+                 "soft": [{ "nodeId": "B", "likelihoodRatio": 3 }] }"#,
+        ),
+    )
+    .unwrap();
+    assert!((marginal(&result, "B") - 0.75).abs() < 0.02);
+    assert!((marginal(&result, "C") - 1.0).abs() < f64::EPSILON);
+    // P(A | C) = 0.45 / (0.45 + 0.1).
+    assert!((marginal(&result, "A") - 0.45 / 0.55).abs() < 0.02);
+
+    let message = error_message(compute_posterior_mixed_evidence(
+        network(),
+        100.0,
+        evidence(
+            r#"{ "hard": [{ "nodeId": "B", "value": true }],
+                 "soft": [{ "nodeId": "B", "likelihoodRatio": 2 }] }"#,
+        ),
+    ));
+    assert!(
+        message.contains("more than one piece of evidence"),
+        "{message}"
+    );
+    let error = compute_posterior_mixed_evidence(
+        network(),
+        100.0,
+        evidence(r#"{ "soft": [{ "nodeId": "B", "likelihoodRatio": -1 }] }"#),
+    )
+    .unwrap_err();
+    assert_eq!(
+        error_code(&error),
+        ("OUT_OF_RANGE".to_string(), "likelihoodRatio".to_string())
+    );
+}
+
+#[wasm_bindgen_test]
+fn calibration_report_suggests_entries_that_close_the_gap() {
+    let network = nodes(vec![
+        node("A", vec![entry("{}", 0.3)]),
+        node(
+            "B",
+            vec![entry(r#"{"A": true}"#, 0.9), entry(r#"{"A": false}"#, 0.2)],
+        ),
+    ]);
+    let calibration = JSON::parse(r#"{ "B": 0.5 }"#).unwrap();
+
+    let report = compute_calibration_report(network, calibration, 50_000.0, Some(3.0)).unwrap();
+
+    let by_node: Array = get(&report, "nodes").into();
+    assert_eq!(by_node.length(), 1);
+    let b = by_node.get(0);
+    // Baseline P(B) = 0.3 * 0.9 + 0.7 * 0.2 = 0.41.
+    let error = get(&b, "absoluteError").as_f64().unwrap();
+    assert!((error - 0.09).abs() < 0.01, "{error}");
+    assert!((get(&report, "meanAbsoluteError").as_f64().unwrap() - error).abs() < 1e-12);
+
+    // Closing the gap through B's `A = true` entry would need P(true) > 1, so
+    // only A's prior (slope 0.7) and B's `A = false` entry (slope 0.7) remain.
+    let adjustments: Array = get(&b, "adjustments").into();
+    assert_eq!(adjustments.length(), 2);
+    for adjustment in adjustments.iter() {
+        let sensitivity = get(&adjustment, "sensitivity").as_f64().unwrap();
+        let change = get(&adjustment, "suggested").as_f64().unwrap()
+            - get(&adjustment, "current").as_f64().unwrap();
+        assert!((sensitivity - 0.7).abs() < 0.05, "{sensitivity}");
+        assert!((sensitivity * change - error).abs() < 1e-9);
+        assert!(
+            get(&adjustment, "predictedMeanAbsoluteError")
+                .as_f64()
+                .unwrap()
+                < 1e-9
+        );
+    }
+
+    let message = error_message(compute_calibration_report(
+        nodes(vec![node("A", vec![entry("{}", 0.3)])]),
+        JSON::parse(r#"{ "A": 1.5 }"#).unwrap(),
+        100.0,
+        None,
+    ));
+    assert!(message.contains("must be within [0, 1]"), "{message}");
+}
+
+#[wasm_bindgen_test]
+fn calibration_bins_cpt_predictions_against_rows() {
+    let network = nodes(vec![
+        node("A", vec![entry("{}", 0.3)]),
+        node(
+            "B",
+            vec![entry(r#"{"A": true}"#, 0.9), entry(r#"{"A": false}"#, 0.2)],
+        ),
+    ]);
+    let mut rows = vec![r#"{ "A": true, "B": true }"#; 3];
+    rows.extend([r#"{ "A": false, "B": false }"#; 7]);
+    rows.push(r#"{ "B": true }"#);
+    let data = JSON::parse(&format!("[{}]", rows.join(","))).unwrap();
+
+    let results = calibrate_network(network, data).unwrap();
+
+    let b = get(&results, "B");
+    assert_eq!(get(&b, "rows").as_f64(), Some(10.0));
+    assert!((get(&b, "predictedMean").as_f64().unwrap() - 0.41).abs() < 1e-9);
+    assert!((get(&b, "observedMean").as_f64().unwrap() - 0.3).abs() < 1e-9);
+    // Deciles 9 and 2: (|2.7 - 3| + |1.4 - 0|) / 10.
+    assert!((get(&b, "calibrationError").as_f64().unwrap() - 0.17).abs() < 1e-9);
+    let a = get(&results, "A");
+    assert_eq!(get(&a, "rows").as_f64(), Some(10.0));
+    assert!(get(&a, "calibrationError").as_f64().unwrap() < 1e-9);
+}
+
+#[wasm_bindgen_test]
+fn do_distribution_lists_each_treatment_level_in_order() {
+    let network = nodes(vec![
+        node("A", vec![entry("{}", 0.3)]),
+        node(
+            "B",
+            vec![entry(r#"{"A": true}"#, 0.9), entry(r#"{"A": false}"#, 0.2)],
+        ),
+    ]);
+
+    let result = compute_do_distribution(network, 20_000.0, "A", "B").unwrap();
+
+    let levels: Array = get(&result, "distribution").into();
+    assert_eq!(levels.length(), 2);
+    assert_eq!(
+        get(&levels.get(0), "treatmentValue").as_string().unwrap(),
+        "false"
+    );
+    assert_eq!(
+        get(&levels.get(1), "treatmentValue").as_string().unwrap(),
+        "true"
+    );
+    let probability = |i| get(&levels.get(i), "outcomeProbability").as_f64().unwrap();
+    assert!((probability(0) - 0.2).abs() < 0.02);
+    assert!((probability(1) - 0.9).abs() < 0.02);
+    let effect = get(&result, "averageMarginalEffect").as_f64().unwrap();
+    assert!((effect - (probability(1) - probability(0))).abs() < 1e-12);
+}
+
+#[wasm_bindgen_test]
+fn frozen_boundary_keeps_its_joint_distribution() {
+    let network = || {
+        nodes(vec![
+            node("U", vec![entry("{}", 0.5)]),
+            node(
+                "A",
+                vec![entry(r#"{"U": true}"#, 0.9), entry(r#"{"U": false}"#, 0.1)],
+            ),
+            node(
+                "B",
+                vec![entry(r#"{"U": true}"#, 0.8), entry(r#"{"U": false}"#, 0.2)],
+            ),
+            node(
+                "C",
+                vec![entry(r#"{"A": true, "B": true}"#, 0.9), entry("{}", 0.1)],
+            ),
+        ])
+    };
+
+    let frozen = freeze_upstream(
+        network(),
+        JSON::parse(r#"["A", "B"]"#).unwrap(),
+        40_000.0,
+        "C",
+    )
+    .unwrap();
+
+    let ids: Vec<String> = Array::from(&get(&frozen, "nodes"))
+        .iter()
+        .map(|node| get(&node, "_id").as_string().unwrap())
+        .collect();
+    assert_eq!(ids, ["A", "B", "C"]);
+    // A and B share U, so P(A, B) = 0.37 and P(C) = 0.37 * 0.9 + 0.63 * 0.1;
+    // as independent priors it would be 0.3.
+    let fidelity = get(&frozen, "fidelity");
+    let after = get(&fidelity, "after").as_f64().unwrap();
+    assert!((after - 0.396).abs() < 0.02, "{after}");
+    assert!(get(&fidelity, "difference").as_f64().unwrap().abs() < 0.02);
+    assert_eq!(Array::from(&get(&frozen, "warnings")).length(), 0);
+
+    let message = error_message(freeze_upstream(
+        network(),
+        JSON::parse(r#"["A"]"#).unwrap(),
+        100.0,
+        "C",
+    ));
+    assert!(
+        message.contains("Node B has parent U upstream"),
+        "{message}"
+    );
+}
+
+#[wasm_bindgen_test]
+fn partial_correlations_vanish_given_the_mediator() {
+    // A -> B -> C: A and C are correlated, but independent given B.
+    let network = nodes(vec![
+        node(
+            "C",
+            vec![entry(r#"{"B": true}"#, 0.8), entry(r#"{"B": false}"#, 0.1)],
+        ),
+        node("A", vec![entry("{}", 0.5)]),
+        node(
+            "B",
+            vec![entry(r#"{"A": true}"#, 0.9), entry(r#"{"A": false}"#, 0.2)],
+        ),
+    ]);
+
+    let result =
+        compute_partial_correlations_wasm(network, 100_000.0, JsValue::NULL, None).unwrap();
+
+    let ids: Vec<String> = Array::from(&get(&result, "nodeIds"))
+        .iter()
+        .map(|id| id.as_string().unwrap())
+        .collect();
+    assert_eq!(ids, ["C", "A", "B"]);
+    let matrix: Vec<Vec<f64>> = Array::from(&get(&result, "matrix"))
+        .iter()
+        .map(|row| {
+            Array::from(&row)
+                .iter()
+                .map(|x| x.as_f64().unwrap())
+                .collect()
+        })
+        .collect();
+    assert!((matrix[0][0] - 1.0).abs() < 1e-12);
+    assert!(matrix[0][1].abs() < 0.02, "{}", matrix[0][1]);
+    assert!((matrix[0][1] - matrix[1][0]).abs() < 1e-12);
+    assert!(matrix[1][2] > 0.5 && matrix[0][2] > 0.4, "{matrix:?}");
+
+    // do(B = true) makes B constant, so its row is NaN.
+    let intervened = compute_partial_correlations_wasm(
+        nodes(vec![
+            node("A", vec![entry("{}", 0.5)]),
+            node(
+                "B",
+                vec![entry(r#"{"A": true}"#, 0.9), entry(r#"{"A": false}"#, 0.2)],
+            ),
+        ]),
+        1000.0,
+        JSON::parse(r#"{ "nodeId": "B", "value": true }"#).unwrap(),
+        None,
+    )
+    .unwrap();
+    let rows = Array::from(&get(&intervened, "matrix"));
+    assert!(Array::from(&rows.get(1)).get(0).as_f64().unwrap().is_nan());
+}
+
+#[wasm_bindgen_test]
+fn soft_evidence_approaches_hard_evidence_as_the_ratio_grows() {
+    let network = || {
+        nodes(vec![
+            node("A", vec![entry("{}", 0.3)]),
+            node(
+                "B",
+                vec![entry(r#"{"A": true}"#, 0.9), entry(r#"{"A": false}"#, 0.2)],
+            ),
+        ])
+    };
+    let posterior = |options_json: &str| {
+        let result = compute_marginals_with_options(network(), options(options_json)).unwrap();
+        (get(&result, "marginals"), get(&result, "meta"))
+    };
+
+    let (hard, hard_meta) = posterior(
+        r#"{ "numSamples": 50000, "seed": 5, "algorithm": "likelihoodWeighting",
+             "assumptions": { "evidence": { "B": true } } }"#,
+    );
+    let (soft, soft_meta) =
+        posterior(r#"{ "numSamples": 50000, "seed": 5, "softEvidence": { "B": 1e9 } }"#);
+    // P(A | B) = 0.27 / 0.41.
+    assert!((marginal(&hard, "A") - 0.27 / 0.41).abs() < 0.02);
+    assert!((marginal(&soft, "A") - marginal(&hard, "A")).abs() < 0.02);
+    assert!(marginal(&soft, "B") > 0.999);
+    let ids = |meta: &JsValue, key| -> Vec<String> {
+        Array::from(&get(meta, key))
+            .iter()
+            .map(|id| id.as_string().unwrap())
+            .collect()
+    };
+    assert_eq!(ids(&hard_meta, "hardEvidence"), ["B"]);
+    assert!(ids(&hard_meta, "softEvidence").is_empty());
+    assert!(ids(&soft_meta, "hardEvidence").is_empty());
+    assert_eq!(ids(&soft_meta, "softEvidence"), ["B"]);
+    assert_eq!(
+        get(&soft_meta, "algorithm").as_string().as_deref(),
+        Some("likelihoodWeighting")
+    );
+
+    // A ratio of 2 on A: 0.6 / (0.6 + 0.7).
+    let (weak, _) = posterior(r#"{ "numSamples": 50000, "seed": 5, "softEvidence": { "A": 2 } }"#);
+    assert!((marginal(&weak, "A") - 0.6 / 1.3).abs() < 0.02);
+
+    for (options_json, expected) in [
+        (
+            r#"{ "numSamples": 10, "softEvidence": { "A": 0 } }"#,
+            "must be positive and finite",
+        ),
+        (
+            r#"{ "numSamples": 10, "algorithm": "rejection", "softEvidence": { "A": 2 } }"#,
+            "needs likelihood weighting",
+        ),
+    ] {
+        let message = error_message(compute_marginals_with_options(
+            network(),
+            options(options_json),
+        ));
+        assert!(message.contains(expected), "{message}");
+    }
+}
+
+#[wasm_bindgen_test]
+fn conditional_marginals_split_one_pass_by_the_condition() {
+    let network = nodes(vec![
+        node("A", vec![entry("{}", 0.3)]),
+        node(
+            "B",
+            vec![entry(r#"{"A": true}"#, 0.9), entry(r#"{"A": false}"#, 0.2)],
+        ),
+    ]);
+
+    let split = compute_conditional_marginals(network, 50_000.0, "B", JsValue::UNDEFINED).unwrap();
+
+    // P(B) = 0.41, P(A | B) = 0.27 / 0.41 and P(A | not B) = 0.03 / 0.59.
+    assert!((get(&split, "conditionProbability").as_f64().unwrap() - 0.41).abs() < 0.02);
+    let when_true = get(&split, "whenTrue");
+    let when_false = get(&split, "whenFalse");
+    let samples = |branch: &JsValue| get(branch, "samples").as_f64().unwrap();
+    assert!((samples(&when_true) + samples(&when_false) - 50_000.0).abs() < f64::EPSILON);
+    assert!((marginal(&get(&when_true, "marginals"), "A") - 0.27 / 0.41).abs() < 0.02);
+    assert!((marginal(&get(&when_false, "marginals"), "A") - 0.03 / 0.59).abs() < 0.02);
+    assert!((marginal(&get(&when_true, "marginals"), "B") - 1.0).abs() < f64::EPSILON);
+    assert!(marginal(&get(&when_true, "standardErrors"), "B").abs() < f64::EPSILON);
+    let error = marginal(&get(&when_true, "standardErrors"), "A");
+    assert!(error > 0.0 && error < 0.01, "{error}");
+}
+
+#[wasm_bindgen_test]
+fn dose_response_traces_the_outcome_along_clamped_doses() {
+    let network = nodes(vec![
+        node("A", vec![entry("{}", 0.3)]),
+        node(
+            "B",
+            vec![entry(r#"{"A": true}"#, 0.9), entry(r#"{"A": false}"#, 0.2)],
+        ),
+    ]);
+
+    let curve = compute_dose_response_wasm(network, 20_000.0, "A", "B", &[0.0, 0.5, 1.0]).unwrap();
+
+    let pairs: Vec<(f64, f64)> = Array::from(&curve)
+        .iter()
+        .map(|pair| {
+            let pair = Array::from(&pair);
+            (pair.get(0).as_f64().unwrap(), pair.get(1).as_f64().unwrap())
+        })
+        .collect();
+    assert_eq!(
+        pairs.iter().map(|&(t, _)| t).collect::<Vec<_>>(),
+        [0.001, 0.5, 0.999]
+    );
+    // P(B) = 0.2 + 0.7 t, and shared random numbers keep the curve monotone.
+    for &(t, p) in &pairs {
+        assert!((p - (0.2 + 0.7 * t)).abs() < 0.02, "{t}: {p}");
+    }
+    assert!(pairs.windows(2).all(|pair| pair[0].1 <= pair[1].1));
+}
+
+#[wasm_bindgen_test]
+fn mediation_splits_the_total_effect_through_the_mediator() {
+    let network = |y_entries| {
+        nodes(vec![
+            node("X", vec![entry("{}", 0.5)]),
+            node(
+                "M",
+                vec![entry(r#"{"X": true}"#, 0.8), entry(r#"{"X": false}"#, 0.2)],
+            ),
+            node("Y", y_entries),
+        ])
+    };
+    let mediated = network(vec![
+        entry(r#"{"X": true, "M": true}"#, 0.9),
+        entry(r#"{"X": true, "M": false}"#, 0.5),
+        entry(r#"{"X": false, "M": true}"#, 0.6),
+        entry(r#"{"X": false, "M": false}"#, 0.2),
+    ]);
+
+    let result = compute_mediation_proportion(mediated, 50_000.0, "X", "M", "Y").unwrap();
+
+    // E[Y(0)] = 0.28, E[Y(1)] = 0.82 and E[Y(1, M(0))] = 0.58.
+    let value = |key| get(&result, key).as_f64().unwrap();
+    assert!((value("totalEffect") - 0.54).abs() < 0.02);
+    assert!((value("directEffect") - 0.30).abs() < 0.02);
+    assert!((value("indirectEffect") - 0.24).abs() < 0.02);
+    assert!((value("proportionMediated") - 0.24 / 0.54).abs() < 0.04);
+
+    let unaffected =
+        compute_mediation_proportion(network(vec![entry("{}", 0.5)]), 1000.0, "X", "M", "Y")
+            .unwrap();
+    assert!(get(&unaffected, "totalEffect").as_f64().unwrap().abs() < f64::EPSILON);
+    assert!(
+        get(&unaffected, "proportionMediated")
+            .as_f64()
+            .unwrap()
+            .is_nan()
+    );
+}
+
+#[wasm_bindgen_test]
+fn ambiguity_impact_reports_shadowed_specific_entries() {
+    let network = nodes(vec![
+        node("A", vec![entry("{}", 0.5)]),
+        // The wildcard entry shadows the `A = true` entry entirely.
+        node("B", vec![entry("{}", 0.1), entry(r#"{"A": true}"#, 0.9)]),
+        // Same order, but most-specific-first changes nothing.
+        node("C", vec![entry(r#"{"B": true}"#, 0.7), entry("{}", 0.2)]),
+    ]);
+
+    let impact = ambiguity_impact(network, "B", 20_000.0, 0.05).unwrap();
+
+    let baseline = get(&impact, "baseline").as_f64().unwrap();
+    assert!((baseline - 0.1).abs() < 0.02);
+    let ambiguous: Vec<String> = Array::from(&get(&impact, "ambiguousNodes"))
+        .iter()
+        .map(|id| id.as_string().unwrap())
+        .collect();
+    assert_eq!(ambiguous, ["B"]);
+    let impacts = Array::from(&get(&impact, "impacts"));
+    assert_eq!(impacts.length(), 1);
+    let shift = get(&impacts.get(0), "shift").as_f64().unwrap();
+    assert!((shift - 0.4).abs() < 0.03, "{shift}");
+    assert!((get(&impact, "allReordered").as_f64().unwrap() - baseline - shift).abs() < 1e-12);
+}
+
+#[wasm_bindgen_test]
+fn quantile_treatment_effect_steps_between_the_arms_quantiles() {
+    let network = nodes(vec![
+        node("A", vec![entry("{}", 0.5)]),
+        node(
+            "B",
+            vec![entry(r#"{"A": true}"#, 0.9), entry(r#"{"A": false}"#, 0.2)],
+        ),
+    ]);
+
+    let result = compute_interventional_quantile_treatment_effect(
+        network.clone(),
+        20_000.0,
+        "A",
+        "B",
+        &[0.05, 0.5, 0.95],
+    )
+    .unwrap();
+
+    // Treated quantiles turn to 1 above 0.1, control ones above 0.8.
+    let effects: Vec<f64> = Array::from(&get(&result, "B"))
+        .iter()
+        .map(|value| value.as_f64().unwrap())
+        .collect();
+    assert_eq!(effects, [0.0, 1.0, 0.0]);
+
+    let error = error_message(compute_interventional_quantile_treatment_effect(
+        network,
+        100.0,
+        "A",
+        "B",
+        &[1.5],
+    ));
+    assert!(error.contains("outside [0, 1]"));
+}
+
+#[wasm_bindgen_test]
+fn marginals_and_intervention_results_have_the_documented_shapes() {
+    let network = || {
+        nodes(vec![
+            node("A", vec![entry("{}", 0.5)]),
+            node(
+                "B",
+                vec![entry(r#"{"A": true}"#, 0.9), entry(r#"{"A": false}"#, 0.2)],
+            ),
+        ])
+    };
+
+    let plain = compute_marginals(network(), 1000.0, None).unwrap();
+    let plain = plain.dyn_ref::<Map>().expect("marginals are a Map");
+    assert_eq!(plain.size(), 2);
+
+    let result = compute_marginals(network(), 1000.0, Some("A".to_string())).unwrap();
+    assert!(result.is_object() && !result.is_instance_of::<Map>());
+    for key in ["trueCase", "falseCase", "riskRatio", "oddsRatio"] {
+        let value = get(&result, key);
+        assert_eq!(value.dyn_ref::<Map>().map(Map::size), Some(2), "{key}");
+    }
+    // Plain queries compute no baseline, and the key is left out entirely.
+    assert!(!Reflect::has(&result, &JsValue::from_str("baseline")).unwrap());
+    assert!((marginal(&get(&result, "trueCase"), "A") - 1.0).abs() < f64::EPSILON);
+    assert!(marginal(&get(&result, "falseCase"), "A").abs() < f64::EPSILON);
+
+    let message = error_message(compute_marginals(network(), 10.0, Some("Z".to_string())));
+    assert!(
+        message.contains("Intervention node Z not found"),
+        "{message}"
+    );
+}
+
+#[wasm_bindgen_test]
+fn unicode_empty_and_long_ids_round_trip() {
+    let long = "n".repeat(10_000);
+    for id in ["风险 🚀", "", "e\u{301}", long.as_str()] {
+        let parent = serde_json::to_string(&serde_json::json!({ id: true })).unwrap();
+        let network = nodes(vec![
+            node(id, vec![entry("{}", 1.0)]),
+            node("child", vec![entry(&parent, 1.0), entry("{}", 0.0)]),
+        ]);
+
+        let result = compute_marginals(network, 100.0, Some(id.to_string())).unwrap();
+
+        assert!((marginal(&get(&result, "trueCase"), id) - 1.0).abs() < f64::EPSILON);
+        assert!((marginal(&get(&result, "trueCase"), "child") - 1.0).abs() < f64::EPSILON);
+        assert!(marginal(&get(&result, "falseCase"), "child").abs() < f64::EPSILON);
+    }
+}
+
+#[wasm_bindgen_test]
+fn malformed_networks_are_rejected_with_messages() {
+    let bad_entries = Object::new();
+    set(&bad_entries, "_id", &JsValue::from_str("A"));
+    set(&bad_entries, "cptEntries", &JsValue::from_str("0.5"));
+    let cases = [
+        (
+            JsValue::from_str("not nodes"),
+            "Failed to deserialize nodes",
+        ),
+        (
+            nodes(vec![bad_entries.into()]),
+            "Failed to deserialize nodes",
+        ),
+        (
+            nodes(vec![node("A", vec![entry(r#"{"Z": true}"#, 0.5)])]),
+            "references parent Z which is not in the node array",
+        ),
+        (
+            nodes(vec![
+                node("A", vec![entry("{}", 0.5)]),
+                node("A", vec![entry("{}", 0.5)]),
+            ]),
+            "Duplicate node IDs",
+        ),
+        (
+            nodes(vec![
+                node("A", vec![entry(r#"{"B": true}"#, 0.5)]),
+                node("B", vec![entry(r#"{"A": true}"#, 0.5)]),
+            ]),
+            "Cycle detected",
+        ),
+    ];
+
+    for (network, expected) in cases {
+        let message = error_message(compute_marginals(network, 10.0, None));
+        assert!(message.contains(expected), "{expected}: {message}");
+    }
+}
+
+fn table_node(id: &str, parent_order: &[&str], probabilities: &[f64]) -> JsValue {
+    let table = Object::new();
+    set(
+        &table,
+        "parentOrder",
+        &parent_order
+            .iter()
+            .map(|&id| JsValue::from_str(id))
+            .collect::<Array>(),
+    );
+    set(
+        &table,
+        "probabilities",
+        &probabilities
+            .iter()
+            .map(|&p| JsValue::from_f64(p))
+            .collect::<Array>(),
+    );
+    let node = Object::new();
+    set(&node, "_id", &JsValue::from_str(id));
+    set(&node, "cptTable", &table);
+    node.into()
+}
+
+#[wasm_bindgen_test]
+fn truth_tables_match_entries_and_are_exported() {
+    let entries = || {
+        nodes(vec![
+            node("A", vec![entry("{}", 1.0)]),
+            node("B", vec![entry("{}", 0.0)]),
+            node(
+                "C",
+                vec![entry(r#"{"A": true, "B": false}"#, 0.75), entry("{}", 0.25)],
+            ),
+        ])
+    };
+    // The first parent alternates fastest: rows are FF, TF, FT, TT.
+    let table = nodes(vec![
+        node("A", vec![entry("{}", 1.0)]),
+        node("B", vec![entry("{}", 0.0)]),
+        table_node("C", &["A", "B"], &[0.25, 0.75, 0.25, 0.25]),
+    ]);
+
+    let query = options(r#"{"numSamples": 20000, "seed": 3}"#);
+    let from_entries = compute_marginals_with_options(entries(), query.clone()).unwrap();
+    let from_table = compute_marginals_with_options(table, query).unwrap();
+    let c = |result: &JsValue| marginal(&get(result, "marginals"), "C");
+    assert!((c(&from_table) - 0.75).abs() < 0.02);
+    assert!((c(&from_table) - c(&from_entries)).abs() < f64::EPSILON);
+
+    let exported = Array::from(&to_cpt_tables(entries()).unwrap());
+    let table = get(&exported.get(2), "cptTable");
+    let probabilities: Vec<f64> = Array::from(&get(&table, "probabilities"))
+        .iter()
+        .map(|p| p.as_f64().unwrap())
+        .collect();
+    assert_eq!(probabilities, [0.25, 0.75, 0.25, 0.25]);
+    assert_eq!(
+        Array::from(&get(&exported.get(2), "cptEntries")).length(),
+        0
+    );
+
+    let short = nodes(vec![
+        node("A", vec![entry("{}", 0.5)]),
+        table_node("C", &["A"], &[0.5]),
+    ]);
+    let message = error_message(compute_marginals(short, 10.0, None));
+    assert!(message.contains("1 parents need 2"), "{message}");
+
+    let both = table_node("C", &[], &[0.5]);
+    set(
+        both.unchecked_ref(),
+        "cptEntries",
+        &Array::of1(&entry("{}", 0.5)),
+    );
+    let message = error_message(compute_marginals(nodes(vec![both]), 10.0, None));
+    assert!(
+        message.contains("both cptEntries and cptTable"),
+        "{message}"
+    );
+}
+
+struct FullDisk;
+
+impl std::io::Write for FullDisk {
+    fn write(&mut self, _: &[u8]) -> std::io::Result<usize> {
+        Err(std::io::ErrorKind::StorageFull.into())
+    }
+
+    fn flush(&mut self) -> std::io::Result<()> {
+        Ok(())
+    }
+}
+
+#[wasm_bindgen_test]
+fn network_serializes_to_any_writer() {
+    let network = [
+        Node::with_uniform_cpt("B".to_string(), &["A"]).unwrap(),
+        Node::with_prior("A".to_string(), 0.3),
+    ];
+
+    let mut direct = Vec::new();
+    let topo_order = serialize_network_to_writer(&network, &mut direct).unwrap();
+    let mut buffered = std::io::BufWriter::new(Vec::new());
+    serialize_network_to_writer(&network, &mut buffered).unwrap();
+
+    assert_eq!(topo_order, ["A", "B"]);
+    assert!(!direct.is_empty());
+    assert_eq!(buffered.into_inner().unwrap(), direct);
+    assert!(serialize_network_to_writer(&network, &mut FullDisk).is_err());
+
+    let parents: Vec<String> = (0..=MAX_UNIFORM_CPT_PARENTS)
+        .map(|i| format!("P{i}"))
+        .collect();
+    let parents: Vec<&str> = parents.iter().map(String::as_str).collect();
+    let widest = Node::with_uniform_cpt("C".to_string(), &parents[1..]).unwrap();
+    assert_eq!(widest.cpt_entries.len(), 1 << MAX_UNIFORM_CPT_PARENTS);
+    let message = Node::with_uniform_cpt("C".to_string(), &parents)
+        .err()
+        .unwrap()
+        .to_string();
+    assert!(message.contains("Node C has 8 parents"), "{message}");
+}
+
+#[wasm_bindgen_test]
+fn golden_fixtures_reproduce_exactly() {
+    let checks = Array::from(&run_golden_checks().unwrap());
+    assert_eq!(checks.length(), 5);
+    for check in checks.iter() {
+        // On failure the message carries `actual`, to paste into the fixture
+        // if the change was intended.
+        assert!(get(&check, "passed").as_bool().unwrap(), "{check:?}");
+    }
+
+    let fixtures = Array::from(&golden_fixtures().unwrap());
+    let names: Vec<String> = fixtures
+        .iter()
+        .map(|fixture| get(&fixture, "name").as_string().unwrap())
+        .collect();
+    assert_eq!(
+        names,
+        ["chain", "collider", "wildcards", "intervention", "evidence"]
+    );
+}
+
+/// Rows from `A -> B -> C`, each copying its parent 90% of the time.
+fn chain_rows(num_rows: usize) -> JsValue {
+    let mut state: u32 = 12_345;
+    let mut coin = |p: f64| {
+        state = state.wrapping_mul(1_664_525).wrapping_add(1_013_904_223);
+        f64::from(state >> 8) / f64::from(1u32 << 24) < p
+    };
+    (0..num_rows)
+        .map(|_| {
+            let a = coin(0.5);
+            let b = if coin(0.9) { a } else { !a };
+            let c = if coin(0.9) { b } else { !b };
+            let row = Object::new();
+            set(&row, "A", &JsValue::from_bool(a));
+            set(&row, "B", &JsValue::from_bool(b));
+            set(&row, "C", &JsValue::from_bool(c));
+            JsValue::from(row)
+        })
+        .collect::<Array>()
+        .into()
+}
+
+#[wasm_bindgen_test]
+fn faithfulness_flags_edges_the_data_contradicts() {
+    let data = chain_rows(2000);
+    let faithful = nodes(chain(0.9));
+    assert_eq!(
+        Array::from(&check_faithfulness(faithful, data.clone(), 0.01).unwrap()).length(),
+        0
+    );
+
+    // Without the B -> C edge, B and C are d-separated but clearly dependent.
+    let missing_edge = nodes(vec![
+        node("A", vec![entry("{}", 0.5)]),
+        node(
+            "B",
+            vec![entry(r#"{"A": true}"#, 0.9), entry(r#"{"A": false}"#, 0.1)],
+        ),
+        node("C", vec![entry("{}", 0.5)]),
+    ]);
+    let violations = Array::from(&check_faithfulness(missing_edge, data, 0.01).unwrap());
+    let mut pairs: Vec<(String, bool)> = violations
+        .iter()
+        .map(|violation| {
+            let mut pair =
+                [get(&violation, "x"), get(&violation, "y")].map(|id| id.as_string().unwrap());
+            pair.sort();
+            (
+                pair.concat(),
+                get(&violation, "dSeparated").as_bool().unwrap(),
+            )
+        })
+        .collect();
+    pairs.sort();
+    // C is also marginally dependent on A, which the graph separates.
+    assert_eq!(pairs, [("AC".to_string(), true), ("BC".to_string(), true)]);
+}
+
+#[wasm_bindgen_test]
+fn dbn_steady_state_follows_the_transition_matrix() {
+    let network = || {
+        nodes(vec![
+            node("X_prev", vec![entry("{}", 0.5)]),
+            node(
+                "X_next",
+                vec![
+                    entry(r#"{"X_prev": true}"#, 0.9),
+                    entry(r#"{"X_prev": false}"#, 0.2),
+                ],
+            ),
+        ])
+    };
+    let rows = |matrix: &JsValue| -> Vec<Vec<f64>> {
+        Array::from(matrix)
+            .iter()
+            .map(|row| {
+                Array::from(&row)
+                    .iter()
+                    .map(|p| p.as_f64().unwrap())
+                    .collect()
+            })
+            .collect()
+    };
+    let close = |a: f64, b: f64| (a - b).abs() < 1e-9;
+
+    let result = compute_dbn_steady_state(network()).unwrap();
+    let matrix = rows(&get(&result, "transitionMatrix"));
+    assert!(
+        close(matrix[0][1], 0.2) && close(matrix[1][1], 0.9),
+        "{matrix:?}"
+    );
+    // Stationary P(X) = 0.2 / (0.2 + 0.1); the gap to it shrinks by 0.7 per
+    // step, from 2/3 at worst, so it is within 1/4 after 3 steps.
+    assert!(close(
+        get(&get(&result, "marginals"), "X").as_f64().unwrap(),
+        2.0 / 3.0
+    ));
+    assert_eq!(get(&result, "mixingTime").as_f64(), Some(3.0));
+    // Within 0.1 once (2/3) 0.7^t <= 0.1.
+    assert_eq!(compute_dbn_mixing_time(network(), 0.1).unwrap(), Some(6.0));
+    let message = error_message(compute_dbn_mixing_time(network(), 0.0));
+    assert!(
+        message.contains("epsilon must be within (0, 1)"),
+        "{message}"
+    );
+    // Mixed means strictly below epsilon: these chains start 1/2 away and
+    // reach 0 and 1/4 after one step.
+    let flip_half = vec![vec![0.5, 0.5], vec![0.5, 0.5]];
+    assert_eq!(compute_mixing_time(&flip_half, 0.5).unwrap(), Some(1));
+    let stay_or_flip = vec![vec![0.75, 0.25], vec![0.25, 0.75]];
+    assert_eq!(compute_mixing_time(&stay_or_flip, 0.25).unwrap(), Some(2));
+    for (matrix, expected) in [
+        (vec![], "has no states"),
+        (vec![vec![1.0, 0.0]], "must be square"),
+        (
+            vec![vec![1.5, -0.5], vec![0.0, 1.0]],
+            "Row 0 of the transition matrix has entry -0.5",
+        ),
+        (
+            vec![vec![1.0, 0.0], vec![0.5, 0.4]],
+            "Row 1 of the transition matrix sums to 0.9",
+        ),
+    ] {
+        let message = compute_mixing_time(&matrix, 0.1).unwrap_err().to_string();
+        assert!(message.contains(expected), "{message}");
+    }
+
+    let squared = rows(&compute_dbn_transition_power(network(), 2.0).unwrap());
+    assert!(
+        close(squared[0][0], 0.66) && close(squared[0][1], 0.34),
+        "{squared:?}"
+    );
+
+    let unpaired = nodes(vec![node("Y_prev", vec![entry("{}", 0.5)])]);
+    let message = error_message(compute_dbn_steady_state(unpaired));
+    assert!(message.contains("no Y_next counterpart"), "{message}");
+}
+
+#[wasm_bindgen_test]
+fn root_correlations_couple_roots_but_keep_their_priors() {
+    let network = || {
+        nodes(vec![
+            node("A", vec![entry("{}", 0.5)]),
+            node("B", vec![entry("{}", 0.5)]),
+            node(
+                "C",
+                vec![entry(r#"{"A": true, "B": true}"#, 1.0), entry("{}", 0.0)],
+            ),
+        ])
+    };
+    let query = |matrix: &str| {
+        options(&format!(
+            r#"{{"numSamples": 40000, "seed": 7,
+                "rootCorrelations": {{"nodeIds": ["A", "B"], "matrix": {matrix}}}}}"#
+        ))
+    };
+
+    let result = compute_marginals_with_options(network(), query("[[1, 0.9], [0.9, 1]]")).unwrap();
+    let marginals = get(&result, "marginals");
+    assert!((marginal(&marginals, "A") - 0.5).abs() < 0.02);
+    assert!((marginal(&marginals, "B") - 0.5).abs() < 0.02);
+    // Independent roots would give 0.25; a latent correlation of 0.9 gives
+    // 1/4 + asin(0.9) / (2 pi), about 0.43.
+    assert!((marginal(&marginals, "C") - 0.43).abs() < 0.02);
+
+    let three = options(
+        r#"{"numSamples": 100, "rootCorrelations": {"nodeIds": ["A", "B", "C"],
+            "matrix": [[1, -0.9, -0.9], [-0.9, 1, -0.9], [-0.9, -0.9, 1]]}}"#,
+    );
+    let message = error_message(compute_marginals_with_options(network(), three));
+    assert!(message.contains("has parents"), "{message}");
+
+    let not_psd = nodes(vec![
+        node("A", vec![entry("{}", 0.5)]),
+        node("B", vec![entry("{}", 0.5)]),
+        node("D", vec![entry("{}", 0.5)]),
+    ]);
+    let query = options(
+        r#"{"numSamples": 100, "rootCorrelations": {"nodeIds": ["A", "B", "D"],
+            "matrix": [[1, -0.9, -0.9], [-0.9, 1, -0.9], [-0.9, -0.9, 1]]}}"#,
+    );
+    let message = error_message(compute_marginals_with_options(not_psd, query));
+    assert!(message.contains("not positive semi-definite"), "{message}");
+}
+
+#[wasm_bindgen_test]
+fn node_info_and_summary_read_the_compiled_structure() {
+    let network = CompiledNetwork::new(nodes(chain(0.9))).unwrap();
+
+    let info = get_node_info(&network, "B").unwrap();
+    assert_eq!(get(&info, "numParents").as_f64(), Some(1.0));
+    assert_eq!(
+        Array::from(&get(&info, "parentIds")).get(0).as_string(),
+        Some("A".to_string())
+    );
+    assert_eq!(get(&info, "numCptEntries").as_f64(), Some(2.0));
+    assert_eq!(get(&info, "isRoot"), JsValue::FALSE);
+    assert_eq!(get(&info, "isLeaf"), JsValue::FALSE);
+    assert_eq!(get(&info, "topoIndex").as_f64(), Some(1.0));
+    assert_eq!(
+        get(&get_node_info(&network, "A").unwrap(), "isRoot"),
+        JsValue::TRUE
+    );
+    assert_eq!(
+        get(&get_node_info(&network, "C").unwrap(), "isLeaf"),
+        JsValue::TRUE
+    );
+    let message = error_message(get_node_info(&network, "Z"));
+    assert!(message.contains("Node Z not found"), "{message}");
+
+    let summary = get_network_summary(&network).unwrap();
+    assert_eq!(get(&summary, "numNodes").as_f64(), Some(3.0));
+    assert_eq!(get(&summary, "numEdges").as_f64(), Some(2.0));
+    assert_eq!(get(&summary, "maxParents").as_f64(), Some(1.0));
+    assert_eq!(get(&summary, "hasCycles"), JsValue::FALSE);
+}
+
+#[wasm_bindgen_test]
+fn cpt_completion_extrapolates_logistically_or_declines() {
+    // Each parent alone moves the log-odds from logit(0.1) to 0, so both
+    // together reach logit(0.9).
+    let partial = node(
+        "C",
+        vec![
+            entry(r#"{"A": false, "B": false}"#, 0.1),
+            entry(r#"{"A": true, "B": false}"#, 0.5),
+            entry(r#"{"A": false, "B": true}"#, 0.5),
+        ],
+    );
+    let result = suggest_cpt_completion(partial).unwrap();
+    assert_eq!(get(&result, "status").as_string().unwrap(), "suggested");
+    let suggestions = Array::from(&get(&result, "suggestions"));
+    assert_eq!(suggestions.length(), 1);
+    let suggestion = suggestions.get(0);
+    assert_eq!(get(&suggestion, "suggested"), JsValue::TRUE);
+    assert_eq!(get(&get(&suggestion, "parentStates"), "A"), JsValue::TRUE);
+    assert!((get(&suggestion, "probability").as_f64().unwrap() - 0.9).abs() < 1e-6);
+
+    let sparse = node("C", vec![entry(r#"{"A": true, "B": false}"#, 0.5)]);
+    let result = suggest_cpt_completion(sparse).unwrap();
+    assert_eq!(
+        get(&result, "status").as_string().unwrap(),
+        "insufficientData"
+    );
+    assert_eq!(Array::from(&get(&result, "suggestions")).length(), 0);
+
+    let complete = node("A", vec![entry("{}", 0.3)]);
+    let result = suggest_cpt_completion(complete).unwrap();
+    assert_eq!(get(&result, "status").as_string().unwrap(), "complete");
+}
+
+#[wasm_bindgen_test]
+fn perturbing_an_entry_reweights_retained_samples() {
+    let mut network = CompiledNetwork::new(nodes(chain(0.9))).unwrap();
+    let message = error_message(network.perturb_entry("A", 0.0, 0.6));
+    assert!(message.contains("No retained samples"), "{message}");
+
+    let marginals = network
+        .retain_samples(options(r#"{"numSamples": 20000, "seed": 3}"#))
+        .unwrap();
+    assert!((marginal(&marginals, "A") - 0.3).abs() < 0.02);
+
+    let result = network.perturb_entry("A", 0.0, 0.6).unwrap();
+    let marginals = get(&result, "marginals");
+    assert!((marginal(&marginals, "A") - 0.6).abs() < 0.02);
+    // 0.6 * 0.9 + 0.4 * 0.1
+    assert!((marginal(&marginals, "B") - 0.58).abs() < 0.02);
+    let ess = get(&result, "effectiveSampleSize").as_f64().unwrap();
+    assert!(ess > 1000.0 && ess < 20000.0, "{ess}");
+
+    let unchanged = network.perturb_entry("A", 0.0, 0.3).unwrap();
+    assert!((get(&unchanged, "effectiveSampleSize").as_f64().unwrap() - 20000.0).abs() < 1e-6);
+    for (entry_index, code) in [
+        (f64::NAN, "NOT_A_NUMBER"),
+        (0.5, "NOT_AN_INTEGER"),
+        (-1.0, "OUT_OF_RANGE"),
+        (1.0, "OUT_OF_RANGE"),
+    ] {
+        let error = network.perturb_entry("A", entry_index, 0.6).unwrap_err();
+        assert_eq!(
+            error_code(&error),
+            (code.to_string(), "entryIndex".to_string())
+        );
+    }
+
+    network.invalidate_cache();
+    assert!(network.perturb_entry("A", 0.0, 0.6).is_err());
+}
+
+#[wasm_bindgen_test]
+fn missing_evidence_values_are_sampled_freely() {
+    let network = || {
+        nodes(vec![
+            node("A", vec![entry("{}", 0.3)]),
+            node(
+                "B",
+                vec![entry(r#"{"A": true}"#, 0.9), entry(r#"{"A": false}"#, 0.1)],
+            ),
+            node(
+                "C",
+                vec![entry(r#"{"A": true}"#, 0.8), entry(r#"{"A": false}"#, 0.2)],
+            ),
+        ])
+    };
+
+    // C is unrecorded, so only B informs A: 0.27 / (0.27 + 0.07).
+    let marginals = compute_marginals_with_missing_values(
+        network(),
+        40_000.0,
+        options(r#"{"B": true, "C": "?"}"#),
+    )
+    .unwrap();
+    assert!((marginal(&marginals, "A") - 0.27 / 0.34).abs() < 0.02);
+    assert!((marginal(&marginals, "B") - 1.0).abs() < 1e-12);
+
+    let split = compute_conditional_marginals(
+        network(),
+        40_000.0,
+        "A",
+        options(r#"{"B": true, "C": "?"}"#),
+    )
+    .unwrap();
+    assert!((get(&split, "conditionProbability").as_f64().unwrap() - 0.27 / 0.34).abs() < 0.02);
+
+    let message = error_message(compute_marginals_with_missing_values(
+        network(),
+        100.0,
+        options(r#"{"C": "unknown"}"#),
+    ));
+    assert!(
+        message.contains(r#"must be true, false or "?""#),
+        "{message}"
+    );
+}
+
+#[wasm_bindgen_test]
+fn leak_probability_is_or_ed_into_sampled_and_exact_answers() {
+    let network = |leak: f64| {
+        let child = node(
+            "B",
+            vec![entry(r#"{"A": true}"#, 0.5), entry(r#"{"A": false}"#, 0.0)],
+        );
+        set(
+            child.unchecked_ref(),
+            "leakProbability",
+            &JsValue::from_f64(leak),
+        );
+        nodes(vec![node("A", vec![entry("{}", 0.2)]), child])
+    };
+
+    let result = self_check(network(0.3), options(r#"{"numSamples": 20000, "seed": 5}"#)).unwrap();
+    let report = get(&result, "report");
+    assert_eq!(get(&report, "passed"), JsValue::TRUE);
+    let checks = Array::from(&get(&report, "nodes"));
+    let b = checks
+        .iter()
+        .find(|check| get(check, "nodeId").as_string().unwrap() == "B")
+        .unwrap();
+    // 0.2 * (1 - 0.5 * 0.7) + 0.8 * 0.3
+    assert!((get(&b, "exact").as_f64().unwrap() - 0.37).abs() < 1e-6);
+
+    let compiled = CompiledNetwork::new(network(0.3)).unwrap();
+    let info = get_node_info(&compiled, "B").unwrap();
+    assert_eq!(get(&info, "numParents").as_f64(), Some(1.0));
+    assert!((get(&info, "leakProbability").as_f64().unwrap() - 0.3).abs() < 1e-12);
+
+    let issues = Array::from(&validate_network_wasm(network(1.5), JsValue::UNDEFINED).unwrap());
+    assert!(issues.iter().any(|issue| {
+        get(&issue, "message")
+            .as_string()
+            .unwrap()
+            .contains("leak probability 1.5, outside [0, 1]")
+    }));
+}
+
+#[wasm_bindgen_test]
+fn reweighting_moves_marginals_to_the_target_covariates() {
+    let result = compute_marginals_reweighted(
+        nodes(chain(0.9)),
+        40_000.0,
+        vec!["A".to_string()],
+        options(r#"{"A": 0.3}"#),
+        options(r#"{"A": 0.6}"#),
+    )
+    .unwrap();
+    let marginals = get(&result, "marginals");
+    assert!((marginal(&marginals, "A") - 0.6).abs() < 0.02);
+    // 0.6 * 0.9 + 0.4 * 0.1
+    assert!((marginal(&marginals, "B") - 0.58).abs() < 0.02);
+
+    let message = error_message(compute_marginals_reweighted(
+        nodes(chain(0.9)),
+        100.0,
+        vec!["A".to_string()],
+        options(r#"{"A": 1}"#),
+        options(r#"{"A": 0.6}"#),
+    ));
+    assert!(message.contains("strictly between 0 and 1"), "{message}");
+}
+
+#[wasm_bindgen_test]
+fn outcome_impacts_rank_ancestors_and_zero_the_rest() {
+    let mut network = chain(0.9);
+    network.push(node("D", vec![entry("{}", 0.5)]));
+    let impacts = Array::from(&rank_outcome_impacts(nodes(network), 20_000.0, "B").unwrap());
+    assert_eq!(impacts.length(), 3);
+
+    let first = impacts.get(0);
+    assert_eq!(get(&first, "nodeId").as_string().unwrap(), "A");
+    assert_eq!(get(&first, "isAncestor"), JsValue::TRUE);
+    assert!((get(&first, "effect").as_f64().unwrap() - 0.8).abs() < 0.02);
+
+    for impact in impacts.iter().skip(1) {
+        assert_eq!(get(&impact, "isAncestor"), JsValue::FALSE);
+        assert_eq!(get(&impact, "effect").as_f64(), Some(0.0));
+        assert_eq!(
+            get(&impact, "outcomeIfTrue").as_f64(),
+            get(&impact, "outcomeIfFalse").as_f64()
+        );
+    }
+}
+
+#[wasm_bindgen_test]
+fn counterfactual_outcome_abducts_the_unit_noise() {
+    let network = || {
+        nodes(vec![
+            node("A", vec![entry("{}", 0.5)]),
+            node(
+                "B",
+                vec![entry(r#"{"A": true}"#, 0.9), entry(r#"{"A": false}"#, 0.1)],
+            ),
+        ])
+    };
+    // B's noise was below 0.9; it would also have been below 0.1 with
+    // probability 1/9.
+    let probability = compute_counterfactual_outcome(
+        network(),
+        options(r#"{"A": true, "B": true}"#),
+        "A",
+        false,
+        "B",
+        40_000.0,
+    )
+    .unwrap();
+    assert!((probability - 1.0 / 9.0).abs() < 0.02, "{probability}");
+
+    let factual = compute_counterfactual_outcome(
+        network(),
+        options(r#"{"A": true, "B": true}"#),
+        "A",
+        true,
+        "B",
+        1000.0,
+    )
+    .unwrap();
+    assert!((factual - 1.0).abs() < 1e-12);
+}
+
+#[wasm_bindgen_test]
+fn nodes_without_cpt_entries_are_rejected_before_sampling() {
+    let network = || {
+        nodes(vec![
+            node("A", vec![]),
+            node(
+                "B",
+                vec![entry(r#"{"A": true}"#, 0.9), entry(r#"{"A": false}"#, 0.1)],
+            ),
+        ])
+    };
+
+    let message = error_message(compute_marginals(network(), 100.0, None));
+    assert!(
+        message.contains("Node A has no CPT entries; add a default probability"),
+        "{message}"
+    );
+    // Intervening would sidestep the CPT, but the network is still refused.
+    let query = options(r#"{"numSamples": 100, "assumptions": {"interventions": {"A": false}}}"#);
+    let message = error_message(compute_marginals_with_options(network(), query));
+    assert!(message.contains("Node A has no CPT entries"), "{message}");
+
+    let issues = Array::from(&validate_network_wasm(network(), JsValue::UNDEFINED).unwrap());
+    assert!(issues.iter().any(|issue| {
+        get(&issue, "nodeId").as_string().as_deref() == Some("A")
+            && get(&issue, "message")
+                .as_string()
+                .unwrap()
+                .contains("no CPT entries")
+    }));
+}
+
+#[wasm_bindgen_test]
+fn prediction_scores_compare_cpt_lookups_with_outcomes() {
+    let row = |a: bool, b: bool| {
+        let row = Object::new();
+        set(&row, "A", &JsValue::from_bool(a));
+        set(&row, "B", &JsValue::from_bool(b));
+        JsValue::from(row)
+    };
+    // Predictions 0.9, 0.9, 0.1, 0.1 against outcomes 1, 0, 0, 0.
+    let data: Array = [
+        row(true, true),
+        row(true, false),
+        row(false, false),
+        row(false, false),
+    ]
+    .into_iter()
+    .collect();
+    let scores = score_predictions(nodes(chain(0.9)), data.into(), "B").unwrap();
+    let brier = (0.01 + 0.81 + 0.01 + 0.01) / 4.0;
+    assert_eq!(get(&scores, "rows").as_f64(), Some(4.0));
+    assert!((get(&scores, "brierScore").as_f64().unwrap() - brier).abs() < 1e-9);
+    // SS_tot = 0.75 around the mean outcome 0.25.
+    assert!((get(&scores, "r2").as_f64().unwrap() - (1.0 - 4.0 * brier / 0.75)).abs() < 1e-9);
+    let log_loss = -(0.9f64.ln() + 0.1f64.ln() + 2.0 * 0.9f64.ln()) / 4.0;
+    assert!((get(&scores, "logLoss").as_f64().unwrap() - log_loss).abs() < 1e-9);
+}
+
+#[wasm_bindgen_test]
+fn aipw_recovers_the_effect_when_either_model_is_right() {
+    let confounded = || {
+        nodes(vec![
+            node("Z", vec![entry("{}", 0.5)]),
+            node(
+                "X",
+                vec![entry(r#"{"Z": true}"#, 0.8), entry(r#"{"Z": false}"#, 0.2)],
+            ),
+            node(
+                "Y",
+                vec![
+                    entry(r#"{"X": true, "Z": true}"#, 0.9),
+                    entry(r#"{"X": false, "Z": true}"#, 0.6),
+                    entry(r#"{"X": true, "Z": false}"#, 0.5),
+                    entry(r#"{"X": false, "Z": false}"#, 0.1),
+                ],
+            ),
+        ])
+    };
+    // Frequencies match the CPTs exactly, so the estimate is exact too.
+    let data = Array::new();
+    for (z, x, rows, y_true) in [
+        (true, true, 40, 36),
+        (true, false, 10, 6),
+        (false, true, 10, 5),
+        (false, false, 40, 4),
+    ] {
+        for i in 0..rows {
+            let row = Object::new();
+            set(&row, "Z", &JsValue::from_bool(z));
+            set(&row, "X", &JsValue::from_bool(x));
+            set(&row, "Y", &JsValue::from_bool(i < y_true));
+            data.push(&row);
+        }
+    }
+    let ate = 0.5 * (0.9 - 0.6) + 0.5 * (0.5 - 0.1);
+
+    let estimate = |outcome_model| {
+        compute_augmented_ipw_estimator(
+            confounded(),
+            data.clone().into(),
+            "X",
+            "Y",
+            confounded(),
+            outcome_model,
+        )
+        .unwrap()
+    };
+    assert!((estimate(confounded()) - ate).abs() < 1e-9);
+    // An outcome model blind to Z is rescued by the right propensities.
+    let blind = nodes(vec![
+        node("X", vec![entry("{}", 0.5)]),
+        node(
+            "Y",
+            vec![entry(r#"{"X": true}"#, 0.5), entry(r#"{"X": false}"#, 0.5)],
+        ),
+    ]);
+    assert!((estimate(blind) - ate).abs() < 1e-9);
+}
+
+#[wasm_bindgen_test]
+fn interventions_report_the_baseline_for_nodes_they_cannot_reach() {
+    let mut network = CompiledNetwork::new(nodes(chain(0.9))).unwrap();
+    let unaffected = |result: &JsValue| -> Vec<String> {
+        Array::from(&get(result, "unaffectedNodes"))
+            .iter()
+            .map(|id| id.as_string().unwrap())
+            .collect()
+    };
+
+    let result = network
+        .compute_intervention(options(r#"{"numSamples": 500, "seed": 3}"#), "B")
+        .unwrap();
+    assert_eq!(unaffected(&result), ["A"]);
+    let baseline = marginal(&get(&result, "baseline"), "A");
+    for arm in ["trueCase", "falseCase"] {
+        assert!((marginal(&get(&result, arm), "A") - baseline).abs() < f64::EPSILON);
+    }
+    assert!((marginal(&get(&result, "riskRatio"), "A") - 1.0).abs() < f64::EPSILON);
+
+    // C's own intervention cuts it off from B.
+    let query =
+        options(r#"{"numSamples": 500, "seed": 3, "assumptions": {"interventions": {"C": true}}}"#);
+    let result = network.compute_intervention(query, "B").unwrap();
+    assert_eq!(unaffected(&result), ["A", "C"]);
+
+    // Evidence on a reached node withholds every claim, even where one would
+    // hold (A is cut off from C under do(B)).
+    let query =
+        options(r#"{"numSamples": 500, "seed": 3, "assumptions": {"evidence": {"C": true}}}"#);
+    let result = network.compute_intervention(query, "B").unwrap();
+    assert!(unaffected(&result).is_empty());
+}
+
+#[wasm_bindgen_test]
+fn required_sample_size_matches_the_two_proportion_formula() {
+    // P(B | do(A = false)) = 0.1, so the study compares 0.1 against 0.2,
+    // which the normal approximation puts at about 199 units per arm.
+    let plan = compute_required_sample_size(nodes(chain(0.9)), "A", "B", 0.1, 0.8, 0.05).unwrap();
+    let size = get(&plan, "sampleSizePerArm").as_f64().unwrap();
+    assert!((170.0..230.0).contains(&size), "{size}");
+    assert!(get(&plan, "achievedPower").as_f64().unwrap() >= 0.8);
+    assert!((get(&plan, "controlRate").as_f64().unwrap() - 0.1).abs() < 0.01);
+
+    let message = error_message(compute_required_sample_size(
+        nodes(chain(0.9)),
+        "A",
+        "B",
+        1e-4,
+        0.8,
+        0.05,
+    ));
+    assert!(
+        message.contains("effect is too small to detect"),
+        "{message}"
+    );
+}
+
+#[wasm_bindgen_test]
+fn mutual_exclusivity_is_checked_exactly_and_enforced_by_rejection() {
+    let network = || {
+        nodes(vec![
+            node("A", vec![entry("{}", 0.5)]),
+            node(
+                "B",
+                vec![entry(r#"{"A": true}"#, 0.0), entry(r#"{"A": false}"#, 0.6)],
+            ),
+            node("C", vec![entry("{}", 0.1)]),
+        ])
+    };
+    let constraints = options(
+        r#"[{"type": "mutuallyExclusive", "nodes": ["A", "B"]},
+            {"type": "mutuallyExclusive", "nodes": ["A", "C"]}]"#,
+    );
+    let checks = Array::from(&check_constraints(network(), constraints, 1000.0).unwrap());
+    let (held, broken) = (checks.get(0), checks.get(1));
+    assert_eq!(get(&held, "method").as_string().as_deref(), Some("exact"));
+    assert!(get(&held, "violationProbability").as_f64().unwrap().abs() < 1e-12);
+    assert!(get(&held, "warning").is_undefined());
+    assert!((get(&broken, "violationProbability").as_f64().unwrap() - 0.05).abs() < 1e-12);
+    assert_eq!(get(&get(&broken, "example"), "C").as_bool(), Some(true));
+    let warning = get(&broken, "warning").as_string().unwrap();
+    assert!(warning.contains("{A=true, C=true}"), "{warning}");
+
+    let query = options(
+        r#"{"numSamples": 20000, "seed": 1,
+            "constraints": [{"type": "mutuallyExclusive", "nodes": ["A", "C"]}]}"#,
+    );
+    let result = compute_marginals_with_options(network(), query).unwrap();
+    let rate = get(&result, "constraintViolationRate").as_f64().unwrap();
+    assert!((rate - 0.05).abs() < 0.01, "{rate}");
+    // Conditioned on not both: P(C) = 0.05 / 0.95.
+    assert!((marginal(&get(&result, "marginals"), "C") - 0.05 / 0.95).abs() < 0.01);
+}
+
+#[wasm_bindgen_test]
+fn a_large_exclusive_group_is_checked_by_sampling_without_enumerating_it() {
+    let ids: Vec<String> = (0..40).map(|i| format!("N{i}")).collect();
+    let network = nodes(
+        ids.iter()
+            .map(|id| node(id, vec![entry("{}", 0.01)]))
+            .collect(),
+    );
+    let constraints =
+        options(&serde_json::json!([{"type": "mutuallyExclusive", "nodes": ids}]).to_string());
+    let checks = Array::from(&check_constraints(network, constraints, 20000.0).unwrap());
+    let check = checks.get(0);
+    assert_eq!(
+        get(&check, "method").as_string().as_deref(),
+        Some("sampled")
+    );
+    // At least two of forty independent 1% nodes.
+    let expected = 1.0 - 0.99_f64.powi(40) - 40.0 * 0.01 * 0.99_f64.powi(39);
+    let probability = get(&check, "violationProbability").as_f64().unwrap();
+    assert!((probability - expected).abs() < 0.01, "{probability}");
+    let example = get(&check, "example");
+    let set_count = ids
+        .iter()
+        .filter(|id| get(&example, id).as_bool() == Some(true))
+        .count();
+    assert!(set_count >= 2);
+}
+
+#[wasm_bindgen_test]
+fn in_place_cpt_updates_match_a_full_recompile_byte_for_byte() {
+    let mut network = CompiledNetwork::new(nodes(chain(0.9))).unwrap();
+    let entries = |json: &[(&str, f64)]| -> JsValue {
+        json.iter()
+            .map(|&(states, p)| entry(states, p))
+            .collect::<Array>()
+            .into()
+    };
+
+    let result = network
+        .update_node_cpt(
+            "B",
+            entries(&[(r#"{"A": true}"#, 0.7), (r#"{"A": false}"#, 0.1)]),
+        )
+        .unwrap();
+    assert_eq!(get(&result, "fullRecompile").as_bool(), Some(false));
+    let fresh = CompiledNetwork::new(nodes(chain(0.7))).unwrap();
+    assert_eq!(network.fingerprint(), fresh.fingerprint());
+
+    // Dropping B's parent changes the record's shape.
+    let result = network
+        .update_node_cpt("B", entries(&[("{}", 0.5)]))
+        .unwrap();
+    assert_eq!(get(&result, "fullRecompile").as_bool(), Some(true));
+    let mut expected = chain(0.7);
+    expected[1] = node("B", vec![entry("{}", 0.5)]);
+    let fresh = CompiledNetwork::new(nodes(expected)).unwrap();
+    assert_eq!(network.fingerprint(), fresh.fingerprint());
+
+    let message = error_message(network.update_node_cpt("B", entries(&[])));
+    assert!(message.contains("Node B has no CPT entries"), "{message}");
+    assert_eq!(network.fingerprint(), fresh.fingerprint());
+}
+
+#[wasm_bindgen_test]
+fn recorded_queries_replay_and_flag_changed_results() {
+    let mut network = CompiledNetwork::new(nodes(chain(0.9))).unwrap();
+    network.start_recording(2.0).unwrap();
+    // Unseeded, so the replay must reuse the seed that was drawn.
+    network
+        .compute_marginals(options(r#"{"numSamples": 500}"#))
+        .unwrap();
+    network
+        .compute_marginals(options(r#"{"numSamples": 500, "seed": 1}"#))
+        .unwrap();
+    network
+        .compute_intervention(options(r#"{"numSamples": 500, "seed": 2}"#), "A")
+        .unwrap();
+    let log = network.query_log().unwrap();
+    let entries = Array::from(&js_sys::JSON::parse(&log).unwrap());
+    // Capacity 2 keeps the latest two.
+    assert_eq!(entries.length(), 2);
+    assert_eq!(
+        get(&entries.get(1), "kind").as_string().as_deref(),
+        Some("intervention")
+    );
+
+    let replayed = Array::from(&network.replay(&log).unwrap());
+    assert!(
+        replayed
+            .iter()
+            .all(|outcome| get(&outcome, "changed") == JsValue::FALSE)
+    );
+
+    // Every digest covers B, whose CPT changed.
+    network
+        .update_node_cpt(
+            "B",
+            [entry(r#"{"A": true}"#, 0.2), entry(r#"{"A": false}"#, 0.1)]
+                .into_iter()
+                .collect::<Array>()
+                .into(),
+        )
+        .unwrap();
+    let replayed = Array::from(&network.replay(&log).unwrap());
+    assert!(
+        replayed
+            .iter()
+            .all(|outcome| get(&outcome, "changed") == JsValue::TRUE)
+    );
+
+    network.stop_recording();
+    assert!(network.query_log().is_err());
+}
+
+#[wasm_bindgen_test]
+fn cpt_csv_import_applies_good_nodes_and_reports_bad_rows() {
+    let network = nodes(vec![
+        node("A", vec![entry("{}", 0.5)]),
+        node(
+            "B",
+            vec![entry(r#"{"A": true}"#, 0.5), entry(r#"{"A": false}"#, 0.5)],
+        ),
+        node("C", vec![entry(r#"{"A": null}"#, 0.6)]),
+        node("D", vec![entry("{}", 0.5)]),
+        node("E", vec![entry("{}", 0.5)]),
+    ]);
+    let csv = "Node,A,Probability\r\n\
+               A,,0.3\r\n\
+               \"B\",TRUE,\"0.9\"\r\n\
+               B,any,0.2\r\n\
+               Bb,TRUE,0.5\r\n\
+               C,TRUE,0.7\r\n\
+               C,true,0.8\r\n\
+               D,FALSE,0.5\r\n\
+               E,,1.5\r\n";
+
+    let result = import_cpts_csv(network, csv).unwrap();
+    assert_eq!(
+        JSON::stringify(&get(&result, "updatedNodes"))
+            .unwrap()
+            .as_string()
+            .unwrap(),
+        r#"["A","B"]"#
+    );
+    let node_at = |i: u32| Array::from(&get(&result, "nodes")).get(i);
+    let entry_at = |node: u32, i: u32| Array::from(&get(&node_at(node), "cptEntries")).get(i);
+    assert!((get(&entry_at(0, 0), "probability").as_f64().unwrap() - 0.3).abs() < 1e-12);
+    assert!((get(&entry_at(1, 0), "probability").as_f64().unwrap() - 0.9).abs() < 1e-12);
+    assert!(get(&get(&entry_at(1, 1), "parentStates"), "A").is_null());
+    // C had a duplicate row, so it keeps its old table.
+    assert!((get(&entry_at(2, 0), "probability").as_f64().unwrap() - 0.6).abs() < 1e-12);
+
+    let errors = Array::from(&get(&result, "errors"));
+    let reported: Vec<(f64, String, String)> = errors
+        .iter()
+        .map(|error| {
+            (
+                get(&error, "line").as_f64().unwrap(),
+                get(&error, "nodeId").as_string().unwrap(),
+                get(&error, "message").as_string().unwrap(),
+            )
+        })
+        .collect();
+    let lines: Vec<(f64, &str)> = reported
+        .iter()
+        .map(|(line, node_id, _)| (*line, node_id.as_str()))
+        .collect();
+    assert_eq!(lines, [(5.0, "Bb"), (7.0, "C"), (8.0, "D"), (9.0, "E")]);
+    assert!(
+        reported[0].2.contains("did you mean 'B'?"),
+        "{}",
+        reported[0].2
+    );
+    assert!(reported[1].2.contains("line 6"), "{}", reported[1].2);
+    assert!(
+        reported[2].2.starts_with("Unknown parent A"),
+        "{}",
+        reported[2].2
+    );
+    assert!(reported[3].2.contains("[0, 1]"), "{}", reported[3].2);
+
+    let error = import_cpts_csv(nodes(vec![]), "node,A\nB,TRUE\n").unwrap_err();
+    assert_eq!(
+        error.as_string().unwrap(),
+        "CPT import failed: CSV header has no probability column"
+    );
+}
+
+#[wasm_bindgen_test]
+fn iv_effect_recovers_the_treatment_effect_despite_confounding() {
+    // U confounds X and Y; X raises P(Y) by 0.5 whatever U is.
+    let network = |z_to_y: bool, first_stage: f64| {
+        let y_entry = |x: bool, u: bool, p: f64| {
+            let z = if z_to_y { r#", "Z": null"# } else { "" };
+            entry(&format!(r#"{{"X": {x}, "U": {u}{z}}}"#), p)
+        };
+        nodes(vec![
+            node("Z", vec![entry("{}", 0.5)]),
+            node("U", vec![entry("{}", 0.5)]),
+            node(
+                "X",
+                vec![
+                    entry(r#"{"Z": false, "U": false}"#, 0.1),
+                    entry(r#"{"Z": true, "U": false}"#, 0.1 + first_stage),
+                    entry(r#"{"Z": false, "U": true}"#, 0.3),
+                    entry(r#"{"Z": true, "U": true}"#, 0.3 + first_stage),
+                ],
+            ),
+            node(
+                "Y",
+                vec![
+                    y_entry(false, false, 0.2),
+                    y_entry(true, false, 0.7),
+                    y_entry(false, true, 0.4),
+                    y_entry(true, true, 0.9),
+                ],
+            ),
+        ])
+    };
+
+    let late = compute_iv_effect(network(false, 0.6), 100_000.0, "Z", "X", "Y").unwrap();
+    assert!((late - 0.5).abs() < 0.03, "{late}");
+
+    let error = error_message(compute_iv_effect(network(true, 0.6), 1000.0, "Z", "X", "Y"));
+    assert!(error.contains("exclusion restriction"), "{error}");
+    let error = error_message(compute_iv_effect(
+        network(false, 0.0),
+        1000.0,
+        "Z",
+        "X",
+        "Y",
+    ));
+    assert!(error.contains("too weak"), "{error}");
+    let error = compute_iv_effect(network(false, 0.6), 1000.0, "z", "X", "Y").unwrap_err();
+    assert_eq!(error_code(&error).1, "instrument");
+}
+
+#[wasm_bindgen_test]
+fn parents_fixed_to_one_value_in_every_reachable_entry_are_warned_about() {
+    let network = || {
+        nodes(vec![
+            node("A", vec![entry("{}", 0.5)]),
+            node("B", vec![entry("{}", 0.5)]),
+            // A=false is written, but the catch-all before it always wins.
+            node(
+                "C",
+                vec![
+                    entry(r#"{"A": true, "B": null}"#, 0.9),
+                    entry(r#"{"A": null, "B": null}"#, 0.2),
+                    entry(r#"{"A": false, "B": true}"#, 0.7),
+                ],
+            ),
+            // Both values of A are covered, so A is fine.
+            node(
+                "D",
+                vec![entry(r#"{"A": true}"#, 0.9), entry(r#"{"A": false}"#, 0.1)],
+            ),
+        ])
+    };
+    let fixed_parents = |options: JsValue| {
+        Array::from(&validate_network_wasm(network(), options).unwrap())
+            .iter()
+            .filter(|issue| !get(issue, "fixedParent").is_undefined())
+            .collect::<Vec<_>>()
+    };
+
+    let issues = fixed_parents(JsValue::UNDEFINED);
+    assert_eq!(issues.len(), 1);
+    let issue = &issues[0];
+    assert_eq!(
+        get(issue, "severity").as_string().as_deref(),
+        Some("warning")
+    );
+    assert_eq!(get(issue, "nodeId").as_string().as_deref(), Some("C"));
+    let fixed = get(issue, "fixedParent");
+    assert_eq!(get(&fixed, "parentId").as_string().as_deref(), Some("A"));
+    assert_eq!(get(&fixed, "value").as_bool(), Some(true));
+    assert_eq!(
+        JSON::stringify(&get(&fixed, "entryIndices"))
+            .unwrap()
+            .as_string()
+            .as_deref(),
+        Some("[0]")
+    );
+
+    let acknowledged = options(r#"{"acknowledgedParents": [{"nodeId": "C", "parentId": "A"}]}"#);
+    assert!(fixed_parents(acknowledged).is_empty());
+    assert!(fixed_parents(options(r#"{"fixedParents": false}"#)).is_empty());
+}
+
+#[wasm_bindgen_test]
+fn optimal_single_intervention_maximizes_the_utility_marginal() {
+    let network = || {
+        nodes(vec![
+            node("A", vec![entry("{}", 0.5)]),
+            node("B", vec![entry("{}", 0.5)]),
+            node(
+                "U",
+                vec![
+                    entry(r#"{"A": true, "B": null}"#, 0.3),
+                    entry(r#"{"A": false, "B": true}"#, 0.9),
+                    entry(r#"{"A": false, "B": false}"#, 0.5),
+                ],
+            ),
+        ])
+    };
+    let candidates = || vec!["A".to_string(), "B".to_string()];
+
+    // do(A=false) gives 0.7 and do(B=true) 0.6, against 0.3 and 0.4.
+    let optimal =
+        compute_optimal_single_intervention(network(), 50_000.0, candidates(), "U").unwrap();
+    assert_eq!(get(&optimal, "nodeId").as_string().as_deref(), Some("A"));
+    assert_eq!(get(&optimal, "value").as_bool(), Some(false));
+    let utility = get(&optimal, "expectedUtility").as_f64().unwrap();
+    assert!((utility - 0.7).abs() < 0.02, "{utility}");
+
+    // U ignores C, so both arms tie and do(C=false) wins.
+    let unrelated = nodes(vec![
+        node("C", vec![entry("{}", 0.5)]),
+        node("U", vec![entry("{}", 0.4)]),
+    ]);
+    let optimal =
+        compute_optimal_single_intervention(unrelated, 1000.0, vec!["C".to_string()], "U").unwrap();
+    assert_eq!(get(&optimal, "value").as_bool(), Some(false));
+
+    let error = compute_optimal_single_intervention(network(), 100.0, vec!["a".to_string()], "U")
+        .unwrap_err();
+    assert_eq!(error_code(&error).1, "intervention");
+}
+
+#[wasm_bindgen_test]
+fn compact_diff_reports_node_edge_and_probability_changes() {
+    let before = to_compact(nodes(vec![
+        node("A", vec![entry("{}", 0.3)]),
+        node("B", vec![entry(r#"{"A": null}"#, 0.5)]),
+        node(
+            "C",
+            vec![entry(r#"{"A": true}"#, 0.9), entry(r#"{"A": false}"#, 0.1)],
+        ),
+        node("D", vec![entry("{}", -0.0)]),
+    ]))
+    .unwrap();
+    // Listed in another order, with C's entries reordered and rewritten,
+    // A's probability changed, B dropped for E, and D now depending on A.
+    let after = to_compact(nodes(vec![
+        node(
+            "D",
+            vec![entry(r#"{"A": true}"#, 0.2), entry(r#"{"A": false}"#, 0.0)],
+        ),
+        node(
+            "C",
+            vec![entry(r#"{"A": false}"#, 0.1), entry(r#"{"A": null}"#, 0.9)],
+        ),
+        node("A", vec![entry("{}", 0.35)]),
+        node("E", vec![entry("{}", 0.5)]),
+    ]))
+    .unwrap();
+
+    let diff = diff_compact(&before, &after).unwrap();
+    let json = |key: &str| {
+        JSON::stringify(&get(&diff, key))
+            .unwrap()
+            .as_string()
+            .unwrap()
+    };
+    assert_eq!(json("addedNodes"), r#"["E"]"#);
+    assert_eq!(json("removedNodes"), r#"["B"]"#);
+    assert_eq!(json("addedEdges"), r#"[{"parentId":"A","childId":"D"}]"#);
+    assert_eq!(json("removedEdges"), r#"[{"parentId":"A","childId":"B"}]"#);
+    let changed = Array::from(&get(&diff, "changedNodes"));
+    let change = |i: u32| {
+        let change = changed.get(i);
+        (
+            get(&change, "nodeId").as_string().unwrap(),
+            get(&change, "changedProbabilities").as_f64().unwrap(),
+            get(&change, "maxAbsChange").as_f64().unwrap(),
+        )
+    };
+    assert_eq!(changed.length(), 2);
+    let (id, count, max) = change(0);
+    assert_eq!((id.as_str(), count), ("A", 1.0));
+    assert!((max - 0.05).abs() < 1e-9);
+    let (id, count, max) = change(1);
+    assert_eq!((id.as_str(), count), ("D", 1.0));
+    assert!((max - 0.2).abs() < 1e-9);
+
+    // Negative zero encodes as zero, so equal networks give equal bytes.
+    let zero = |p: f64| to_compact(nodes(vec![node("A", vec![entry("{}", p)])])).unwrap();
+    assert_eq!(zero(-0.0), zero(0.0));
+    let unchanged = diff_compact(&before, &before).unwrap();
+    assert_eq!(Array::from(&get(&unchanged, "changedNodes")).length(), 0);
+}
+
+#[wasm_bindgen_test]
+fn positivity_check_flags_nearly_deterministic_treatment() {
+    let network = || {
+        nodes(vec![
+            node("Age", vec![entry("{}", 0.4)]),
+            node("Sex", vec![entry("{}", 0.5)]),
+            node(
+                "Treated",
+                vec![
+                    entry(r#"{"Age": true, "Sex": true}"#, 0.999),
+                    entry(r#"{"Age": true, "Sex": false}"#, 0.6),
+                    entry(r#"{"Age": false, "Sex": null}"#, 0.3),
+                ],
+            ),
+        ])
+    };
+    let covariates = || vec!["Age".to_string(), "Sex".to_string()];
+
+    let violations =
+        Array::from(&check_positivity(network(), "Treated", covariates(), 0.01).unwrap());
+    assert_eq!(violations.length(), 1);
+    let violation = violations.get(0);
+    let assignment = get(&violation, "covariateAssignment");
+    assert_eq!(get(&assignment, "Age").as_bool(), Some(true));
+    assert_eq!(get(&assignment, "Sex").as_bool(), Some(true));
+    let p = get(&violation, "treatmentProbability").as_f64().unwrap();
+    assert!((p - 0.999).abs() < 1e-9, "{p}");
+
+    // Marginalizing Sex out averages the violation away.
+    let violations = check_positivity(network(), "Treated", vec!["Age".to_string()], 0.01).unwrap();
+    assert_eq!(Array::from(&violations).length(), 0);
+
+    let message = error_message(check_positivity(network(), "Treated", covariates(), 0.6));
+    assert!(message.contains("[0, 0.5]"), "{message}");
+}
+
+#[wasm_bindgen_test]
+fn likelihood_ratio_test_matches_the_analytic_two_node_statistic() {
+    let rows = |a: bool, b: bool, count: usize| {
+        (0..count).map(move |_| {
+            let row = Object::new();
+            set(&row, "A", &JsValue::from_bool(a));
+            set(&row, "B", &JsValue::from_bool(b));
+            JsValue::from(row)
+        })
+    };
+    let data: Array = rows(true, true, 30)
+        .chain(rows(true, false, 10))
+        .chain(rows(false, true, 20))
+        .chain(rows(false, false, 40))
+        .collect();
+    // Maximum-likelihood parameters: P(A) = 0.4 in both, P(B) = 0.5 under
+    // H0, and P(B | A) = 0.75, P(B | not A) = 1/3 under H1.
+    let h0 = nodes(vec![
+        node("A", vec![entry("{}", 0.4)]),
+        node("B", vec![entry(r#"{"A": null}"#, 0.5)]),
+    ]);
+    let h1 = nodes(vec![
+        node("A", vec![entry("{}", 0.4)]),
+        node(
+            "B",
+            vec![
+                entry(r#"{"A": true}"#, 0.75),
+                entry(r#"{"A": false}"#, 1.0 / 3.0),
+            ],
+        ),
+    ]);
+
+    let test = likelihood_ratio_test_wasm(h0, h1, data.clone().into()).unwrap();
+    let expected = 30.0 * (0.75f64 / 0.5).ln()
+        + 10.0 * (0.25f64 / 0.5).ln()
+        + 20.0 * ((1.0f64 / 3.0) / 0.5).ln()
+        + 40.0 * ((2.0f64 / 3.0) / 0.5).ln();
+    let log_lr = get(&test, "logLr").as_f64().unwrap();
+    assert!((log_lr - expected).abs() < 1e-9, "{log_lr}");
+    assert_eq!(get(&test, "df").as_f64(), Some(1.0));
+    // erfc(sqrt(log_lr)), the chi-squared(1) tail at 2 * log_lr.
+    let p_value = get(&test, "pValue").as_f64().unwrap();
+    assert!((p_value - 3.258_188e-5).abs() < 1e-9, "{p_value}");
+
+    let same = || nodes(vec![node("A", vec![entry("{}", 0.4)])]);
+    let message = error_message(likelihood_ratio_test_wasm(same(), same(), data.into()));
+    assert!(message.contains("more free parameters"), "{message}");
+}
+
+#[wasm_bindgen_test]
+fn zero_smoothing_changes_weights_only_for_zeros_on_the_evidence_path() {
+    // B is impossible when A is true; D has a zero too, but is not observed.
+    let network = || {
+        nodes(vec![
+            node("A", vec![entry("{}", 0.5)]),
+            node(
+                "B",
+                vec![entry(r#"{"A": true}"#, 0.0), entry(r#"{"A": false}"#, 0.5)],
+            ),
+            node(
+                "D",
+                vec![entry(r#"{"A": true}"#, 0.0), entry(r#"{"A": false}"#, 0.8)],
+            ),
+        ])
+    };
+    let query = |evidence: &str, zero_handling: &str| {
+        let json = format!(
+            r#"{{"numSamples": 20000, "seed": 5, "algorithm": "likelihoodWeighting",
+                "assumptions": {{"evidence": {evidence}}}, "zeroHandling": "{zero_handling}"}}"#
+        );
+        let result = compute_marginals_with_options(network(), options(&json)).unwrap();
+        let substitutions = get(&get(&result, "meta"), "zeroSubstitutions").as_f64();
+        (get(&result, "marginals"), substitutions)
+    };
+
+    // A zero on the observed node: strict rules A out, smoothing does not.
+    let (strict, substitutions) = query(r#"{"B": true}"#, "strict");
+    assert!(marginal(&strict, "A").abs() < 1e-12);
+    assert_eq!(substitutions, None);
+    let (smooth, substitutions) = query(r#"{"B": true}"#, "smooth(0.001)");
+    // 0.5 * 0.001 / (0.5 * 0.001 + 0.5 * 0.5)
+    let p = marginal(&smooth, "A");
+    assert!(p > 0.0 && (p - 0.001_996).abs() < 0.001, "{p}");
+    // About half the samples draw A = true and need a substitution.
+    let substitutions = substitutions.unwrap();
+    assert!((substitutions - 10_000.0).abs() < 500.0, "{substitutions}");
+    // D's zero is drawn, not weighed, so it stays false whenever A is true.
+    let d_given_a = marginal(&smooth, "D");
+    assert!((d_given_a - 0.8 * (1.0 - p)).abs() < 0.02, "{d_given_a}");
+
+    // Evidence whose path has no zero: the two modes agree exactly.
+    let (strict, _) = query(r#"{"A": false}"#, "strict");
+    let (smooth, substitutions) = query(r#"{"A": false}"#, "smooth(0.001)");
+    assert_eq!(substitutions, Some(0.0));
+    for id in ["A", "B", "D"] {
+        assert!(
+            (marginal(&strict, id) - marginal(&smooth, id)).abs() < 1e-12,
+            "{id}"
+        );
+    }
+
+    let message = error_message(compute_marginals_with_options(
+        network(),
+        options(r#"{"numSamples": 10, "zeroHandling": "smooth(2)"}"#),
+    ));
+    assert!(message.contains("(0, 1)"), "{message}");
+}
+
+#[wasm_bindgen_test]
+fn previewing_a_node_treats_its_parents_as_independent() {
+    // B copies A, so C = A and B is true half the time, not a quarter.
+    let mut network = CompiledNetwork::new(nodes(vec![
+        node("A", vec![entry("{}", 0.5)]),
+        node(
+            "B",
+            vec![entry(r#"{"A": true}"#, 1.0), entry(r#"{"A": false}"#, 0.0)],
+        ),
+        node("C", vec![entry(r#"{"A": null, "B": null}"#, 0.5)]),
+    ]))
+    .unwrap();
+    let draft = || -> JsValue {
+        [
+            entry(r#"{"A": true, "B": true}"#, 1.0),
+            entry(r#"{"A": null, "B": null}"#, 0.0),
+        ]
+        .into_iter()
+        .collect::<Array>()
+        .into()
+    };
+
+    let approximate = network
+        .preview_node_marginal("C", draft(), options(r#"{"numSamples": 20000}"#))
+        .unwrap();
+    assert!((get(&approximate, "marginal").as_f64().unwrap() - 0.25).abs() < 0.02);
+    assert_eq!(
+        get(&approximate, "method").as_string().unwrap(),
+        "independentParents"
+    );
+    assert_eq!(get(&approximate, "approximate").as_bool(), Some(true));
+    assert_eq!(
+        get(&approximate, "priorSource").as_string().unwrap(),
+        "prior"
+    );
+
+    let exact = network
+        .preview_node_marginal(
+            "C",
+            draft(),
+            options(r#"{"exact": true, "numSamples": 20000}"#),
+        )
+        .unwrap();
+    assert!((get(&exact, "marginal").as_f64().unwrap() - 0.5).abs() < 0.02);
+    assert_eq!(get(&exact, "method").as_string().unwrap(), "sampled");
+    assert_eq!(get(&exact, "approximate").as_bool(), Some(false));
+
+    // The network itself is unchanged.
+    let info = get_node_info(&network, "C").unwrap();
+    assert_eq!(get(&info, "numCptEntries").as_f64(), Some(1.0));
+
+    let invalid: JsValue = [entry("{}", 1.5)].into_iter().collect::<Array>().into();
+    let message = error_message(network.preview_node_marginal("C", invalid, JsValue::UNDEFINED));
+    assert!(message.contains("[0, 1]"), "{message}");
+}
+
+#[wasm_bindgen_test]
+fn query_validation_flags_missing_ids_and_uninformative_evidence() {
+    let network = CompiledNetwork::new(nodes(vec![
+        node("A", vec![entry("{}", 0.3)]),
+        node(
+            "B",
+            vec![entry(r#"{"A": true}"#, 0.9), entry(r#"{"A": false}"#, 0.1)],
+        ),
+        node(
+            "C",
+            vec![entry(r#"{"B": true}"#, 0.8), entry(r#"{"B": false}"#, 0.2)],
+        ),
+        node("D", vec![entry("{}", 0.5)]),
+    ]))
+    .unwrap();
+    let strings = |ids: &[&str]| ids.iter().map(|&id| id.to_owned()).collect::<Vec<_>>();
+
+    // Observing B screens C off from A, and D is unconnected.
+    let blocked = validate_query(
+        &network,
+        Some("A".to_owned()),
+        strings(&["B", "D", "Z"]),
+        strings(&["C"]),
+    )
+    .unwrap();
+    assert_eq!(get(&blocked, "allIdsExist").as_bool(), Some(false));
+    assert_eq!(
+        Array::from(&get(&blocked, "missingIds")).to_vec(),
+        vec!["Z"]
+    );
+    assert_eq!(
+        get(&blocked, "interventionAffectsQuery").as_bool(),
+        Some(false)
+    );
+    let informative = get(&blocked, "evidenceInformativeness");
+    assert_eq!(get(&informative, "B").as_bool(), Some(true));
+    assert_eq!(get(&informative, "D").as_bool(), Some(false));
+
+    let open = validate_query(&network, Some("A".to_owned()), vec![], strings(&["C"])).unwrap();
+    assert_eq!(get(&open, "allIdsExist").as_bool(), Some(true));
+    assert_eq!(get(&open, "interventionAffectsQuery").as_bool(), Some(true));
+
+    // Intervening on a leaf cannot reach its ancestors.
+    let leaf = validate_query(&network, Some("C".to_owned()), vec![], strings(&["A"])).unwrap();
+    assert_eq!(
+        get(&leaf, "interventionAffectsQuery").as_bool(),
+        Some(false)
+    );
+}
+
+#[wasm_bindgen_test]
+fn raw_samples_carry_their_layout_fingerprint() {
+    let map = index_map(nodes(chain(0.9))).unwrap();
+    assert_eq!(get(&get(&map, "idToIndex"), "B").as_f64(), Some(1.0));
+    let order: Vec<String> = Array::from(&get(&map, "topoOrder"))
+        .iter()
+        .map(|id| id.as_string().unwrap())
+        .collect();
+    assert_eq!(order, ["A", "B", "C"]);
+    assert_eq!(
+        get(&map, "fingerprint").as_string().unwrap(),
+        layout_fingerprint(&order)
+    );
+
+    let mut network = CompiledNetwork::new(nodes(chain(0.9))).unwrap();
+    let exported = network.export_raw_samples(2000.0, Some(5.0)).unwrap();
+    let columns = network.read_raw_samples(&exported).unwrap();
+    let a = Array::from(&get(&columns, "A"));
+    assert_eq!(a.length(), 2000);
+    let share: u32 = a
+        .iter()
+        .map(|value| u32::from(value.as_bool() == Some(true)))
+        .sum();
+    assert!((f64::from(share) / 2000.0 - 0.3).abs() < 0.05, "{share}");
+
+    // New probabilities keep every node in place.
+    let fingerprint = network.layout_fingerprint();
+    network.update_nodes(nodes(chain(0.5))).unwrap();
+    assert_eq!(network.layout_fingerprint(), fingerprint);
+    assert!(network.read_raw_samples(&exported).is_ok());
+
+    // Reversing A -> B moves A after B.
+    network
+        .update_nodes(nodes(vec![
+            node("B", vec![entry("{}", 0.5)]),
+            node(
+                "A",
+                vec![entry(r#"{"B": true}"#, 0.9), entry(r#"{"B": false}"#, 0.1)],
+            ),
+        ]))
+        .unwrap();
+    assert_ne!(network.layout_fingerprint(), fingerprint);
+    let error = network.read_raw_samples(&exported).unwrap_err();
+    assert_eq!(get(&error, "code").as_string().unwrap(), "LAYOUT_MISMATCH");
+    assert_eq!(
+        get(&get(&error, "details"), "found").as_string().unwrap(),
+        fingerprint
+    );
+    assert!(network.read_raw_samples(&exported[..5]).is_err());
+}
+
+#[wasm_bindgen_test]
+fn sample_size_bounds_follow_hoeffding_and_the_clt() {
+    let bounds = recommend_sample_size(0.01, 0.95).unwrap();
+    // ln(40) / (2 * 0.01^2) = 18444.4 and (1.96 / 0.02)^2 = 9604.
+    assert_eq!(get(&bounds, "hoeffding").as_f64(), Some(18445.0));
+    assert_eq!(get(&bounds, "clt").as_f64(), Some(9604.0));
+    assert_eq!(get(&bounds, "recommended").as_f64(), Some(19208.0));
+
+    let message = error_message(recommend_sample_size(0.0, 0.95));
+    assert!(message.contains("epsilon"), "{message}");
+    assert!(recommend_sample_size(0.01, 1.0).is_err());
+}
+
+#[wasm_bindgen_test]
+fn scenarios_complete_a_sketch_by_intervention_or_evidence() {
+    let sketch = || options(r#"{"B": true}"#);
+    let share_of_a = |result: &JsValue| {
+        let scenarios = Array::from(&get(result, "scenarios"));
+        let a_true: u32 = scenarios
+            .iter()
+            .map(|scenario| {
+                let values = Array::from(&get(&scenario, "values"));
+                assert_eq!(values.get(1).as_bool(), Some(true));
+                u32::from(values.get(0).as_bool() == Some(true))
+            })
+            .sum();
+        f64::from(a_true) / f64::from(scenarios.length())
+    };
+
+    let imposed = complete_scenarios(
+        nodes(chain(0.9)),
+        sketch(),
+        20000.0,
+        Some(1.0),
+        JsValue::UNDEFINED,
+    )
+    .unwrap();
+    assert!((share_of_a(&imposed) - 0.3).abs() < 0.02);
+    assert!(get(&imposed, "acceptanceRate").is_undefined());
+
+    let explained = complete_scenarios(
+        nodes(chain(0.9)),
+        sketch(),
+        20000.0,
+        Some(1.0),
+        JsValue::from_str("evidence"),
+    )
+    .unwrap();
+    // P(A | B) = 0.27 / 0.34, and a third of the draws agree.
+    assert!((share_of_a(&explained) - 0.27 / 0.34).abs() < 0.02);
+    let rate = get(&explained, "acceptanceRate").as_f64().unwrap();
+    assert!((rate - 0.34).abs() < 0.02, "{rate}");
+
+    // P(A) P(B | A) P(C), with C a coin flip.
+    let first = Array::from(&get(&explained, "scenarios")).get(0);
+    let values = Array::from(&get(&first, "values"));
+    let a = values.get(0).as_bool().unwrap();
+    let expected = if a { 0.3 * 0.9 } else { 0.7 * 0.1 } * 0.5_f64;
+    let log_probability = get(&first, "logProbability").as_f64().unwrap();
+    assert!((log_probability - expected.ln()).abs() < 1e-6);
+
+    let error = complete_scenarios(
+        nodes(chain(0.9)),
+        options(r#"{"Bee": true}"#),
+        10.0,
+        None,
+        JsValue::UNDEFINED,
+    )
+    .unwrap_err();
+    assert_eq!(
+        error_code(&error),
+        ("NODE_NOT_FOUND".into(), "scenario".into())
+    );
+    assert!(
+        complete_scenarios(
+            nodes(chain(0.9)),
+            sketch(),
+            10.0,
+            None,
+            JsValue::from_str("maybe")
+        )
+        .is_err()
+    );
+}
+
+fn diamond() -> JsValue {
+    nodes(vec![
+        node("A", vec![entry("{}", 0.5)]),
+        node(
+            "B",
+            vec![entry(r#"{"A": true}"#, 0.8), entry(r#"{"A": false}"#, 0.2)],
+        ),
+        node(
+            "C",
+            vec![entry(r#"{"A": true}"#, 0.7), entry(r#"{"A": false}"#, 0.3)],
+        ),
+        node(
+            "D",
+            vec![
+                entry(r#"{"B": true, "C": true}"#, 0.9),
+                entry(r#"{"B": true, "C": false}"#, 0.5),
+                entry(r#"{"B": false, "C": true}"#, 0.5),
+                entry(r#"{"B": false, "C": false}"#, 0.1),
+            ],
+        ),
+    ])
+}
+
+#[wasm_bindgen_test]
+fn complexity_metrics_measure_markov_blankets_and_treewidth() {
+    // B's spouse C is in its blanket through their common child D.
+    assert_eq!(markov_blanket(diamond(), "B").unwrap(), ["A", "C", "D"]);
+
+    let metrics = get_network_complexity_metrics(diamond()).unwrap();
+    assert!((get(&metrics, "averageMarkovBlanketSize").as_f64().unwrap() - 2.5).abs() < 1e-12);
+    assert_eq!(get(&metrics, "maxMarkovBlanketSize").as_f64(), Some(3.0));
+    let distribution: Vec<f64> = Array::from(&get(&metrics, "markovBlanketSizeDistribution"))
+        .iter()
+        .map(|count| count.as_f64().unwrap())
+        .collect();
+    assert_eq!(distribution, [0.0, 0.0, 2.0, 2.0]);
+    assert_eq!(get(&metrics, "treewidthEstimate").as_f64(), Some(2.0));
+    assert_eq!(get(&metrics, "totalCptEntries").as_f64(), Some(9.0));
+}
+
+#[wasm_bindgen_test]
+fn d_separation_explanations_name_what_blocks_each_trail() {
+    let strings = |ids: &[&str]| ids.iter().map(|&id| id.to_owned()).collect::<Vec<_>>();
+    let ids = |value: JsValue| -> Vec<String> {
+        Array::from(&value)
+            .iter()
+            .map(|id| id.as_string().unwrap())
+            .collect()
+    };
+    let trail = |explanation: &JsValue, through: &str| {
+        Array::from(&get(explanation, "trails"))
+            .iter()
+            .find(|trail| ids(get(trail, "path"))[1] == through)
+            .expect("trail through node")
+    };
+
+    let explanation = explain_d_separation(diamond(), "B", "C", strings(&["A"])).unwrap();
+    assert_eq!(get(&explanation, "dSeparated").as_bool(), Some(true));
+    assert_eq!(Array::from(&get(&explanation, "trails")).length(), 2);
+    let fork = trail(&explanation, "A");
+    assert_eq!(ids(get(&fork, "blockingEvidence")), ["A"]);
+    let collider = trail(&explanation, "D");
+    assert_eq!(ids(get(&collider, "closedColliders")), ["D"]);
+    assert_eq!(get(&collider, "blocked").as_bool(), Some(true));
+
+    // Observing D opens the collider.
+    let opened = explain_d_separation(diamond(), "B", "C", strings(&["A", "D"])).unwrap();
+    assert_eq!(get(&opened, "dSeparated").as_bool(), Some(false));
+    assert_eq!(get(&trail(&opened, "D"), "blocked").as_bool(), Some(false));
+
+    let error = explain_d_separation(diamond(), "B", "C", strings(&["Z"])).unwrap_err();
+    assert_eq!(error_code(&error).0, "NODE_NOT_FOUND");
+}
+
+#[wasm_bindgen_test]
+fn parameterizations_of_one_structure_are_compared() {
+    // The second collaborator lists the nodes in another order.
+    let mut reordered = chain(0.5);
+    reordered.reverse();
+    let comparison = compare_parameterizations(
+        nodes(chain(0.9)),
+        nodes(reordered),
+        20000.0,
+        options(r#"{"targetId": "B", "threshold": 0.1}"#),
+    )
+    .unwrap();
+
+    // 0.3 * 0.9 + 0.07 against 0.3 * 0.5 + 0.07, with common random numbers.
+    let largest = Array::from(&get(&comparison, "marginalDifferences")).get(0);
+    assert_eq!(get(&largest, "nodeId").as_string().unwrap(), "B");
+    assert!((get(&largest, "difference").as_f64().unwrap() + 0.12).abs() < 0.01);
+
+    let entries = Array::from(&get(&comparison, "entryDifferences"));
+    assert_eq!(entries.length(), 1);
+    assert_eq!(get(&entries.get(0), "entryIndex").as_f64(), Some(0.0));
+    assert!((get(&entries.get(0), "difference").as_f64().unwrap() + 0.4).abs() < 1e-12);
+    assert_eq!(get(&comparison, "unmatchedEntries").as_f64(), Some(0.0));
+
+    // d P(B) / d P(B | A) = P(A) = 0.3.
+    let impact = Array::from(&get(&comparison, "targetImpacts")).get(0);
+    assert!((get(&impact, "sensitivity").as_f64().unwrap() - 0.3).abs() < 0.03);
+    assert!((get(&impact, "targetShift").as_f64().unwrap() + 0.12).abs() < 0.015);
+
+    let reversed = nodes(vec![
+        node("B", vec![entry("{}", 0.5)]),
+        node(
+            "A",
+            vec![entry(r#"{"B": true}"#, 0.9), entry(r#"{"B": false}"#, 0.1)],
+        ),
+    ]);
+    let message = error_message(compare_parameterizations(
+        nodes(vec![
+            node("A", vec![entry("{}", 0.3)]),
+            chain(0.9).remove(1),
+        ]),
+        reversed,
+        1000.0,
+        JsValue::UNDEFINED,
+    ));
+    assert!(message.contains("Structures differ"), "{message}");
+    assert!(message.contains("A has parents"), "{message}");
+}
+
+#[wasm_bindgen_test]
+fn expected_shortfall_averages_the_worst_outcomes() {
+    // P(B | do(A = true)) = 0.9, so the worst 10% are all false.
+    let tail =
+        compute_expected_shortfall(nodes(chain(0.9)), 20000.0, "A", true, "B", 0.05).unwrap();
+    assert!((get(&tail, "expectedShortfall").as_f64().unwrap()).abs() < 1e-12);
+    assert!((get(&tail, "probabilityTrue").as_f64().unwrap() - 0.9).abs() < 0.01);
+
+    // Half the worst 20% are false: (0.2 - 0.1) / 0.2.
+    let wider =
+        compute_expected_shortfall(nodes(chain(0.9)), 20000.0, "A", true, "B", 0.2).unwrap();
+    assert!((get(&wider, "expectedShortfall").as_f64().unwrap() - 0.5).abs() < 0.05);
+
+    assert!(compute_expected_shortfall(nodes(chain(0.9)), 100.0, "A", true, "B", 0.0).is_err());
+    let error =
+        compute_expected_shortfall(nodes(chain(0.9)), 100.0, "Z", true, "B", 0.1).unwrap_err();
+    assert_eq!(
+        error_code(&error),
+        ("NODE_NOT_FOUND".into(), "intervention".into())
+    );
+}
+
+#[wasm_bindgen_test]
+fn same_nodes_and_seed_reproduce_marginals_and_fingerprint() {
+    // Many interchangeable roots, so any order-dependence in compiling would
+    // show up as a different fingerprint or random stream.
+    let network = || {
+        let mut roots: Vec<JsValue> = (0..8)
+            .map(|i| {
+                node(
+                    &format!("R{i}"),
+                    vec![entry("{}", 0.1 + 0.1 * f64::from(i))],
+                )
+            })
+            .collect();
+        roots.push(node(
+            "Y",
+            vec![
+                entry(r#"{"R0": true, "R7": null}"#, 0.9),
+                entry(r#"{"R0": false, "R7": true}"#, 0.6),
+                entry(r#"{"R0": false, "R7": false}"#, 0.2),
+            ],
+        ));
+        nodes(roots)
+    };
+    let query = r#"{ "numSamples": 500, "seed": 11 }"#;
+
+    let first = compute_marginals_with_options(network(), options(query)).unwrap();
+    let fingerprint = |result: &JsValue| {
+        get(&get(result, "provenance"), "fingerprint")
+            .as_string()
+            .unwrap()
+    };
+    for _ in 0..5 {
+        let again = compute_marginals_with_options(network(), options(query)).unwrap();
+        assert_eq!(fingerprint(&again), fingerprint(&first));
+        for id in ["R0", "R3", "R7", "Y"] {
+            let (a, b) = (
+                marginal(&get(&first, "marginals"), id),
+                marginal(&get(&again, "marginals"), id),
+            );
+            assert!((a - b).abs() < f64::EPSILON, "{id}: {a} vs {b}");
+        }
+    }
+}
+
+#[wasm_bindgen_test]
+fn pc_keeps_an_edge_exactly_when_the_g_test_rejects_independence() {
+    let ids: JsValue = ["X", "Y"]
+        .iter()
+        .map(|&id| JsValue::from_str(id))
+        .collect::<Array>()
+        .into();
+    let has_edge = |counts: [f64; 4], alpha: f64| {
+        // `exact_rows` repeats each configuration `2000 * weight` times.
+        let weights = counts.map(|count| count / 2000.0);
+        let learned =
+            learn_structure(exact_rows(&["X", "Y"], &weights), ids.clone(), alpha).unwrap();
+        !learned_parents(&learned, "X").is_empty() || !learned_parents(&learned, "Y").is_empty()
+    };
+
+    // G = 2 (114 ln(57/50) + 86 ln(43/50)) = 3.9329 on one degree of
+    // freedom, so p = 0.04735.
+    let weak = [57.0, 43.0, 43.0, 57.0];
+    assert!(has_edge(weak, 0.048));
+    assert!(!has_edge(weak, 0.047));
+    // Exactly independent counts give G = 0 and p = 1.
+    assert!(!has_edge([40.0, 10.0, 160.0, 40.0], 0.99));
+    assert!(has_edge([90.0, 10.0, 10.0, 90.0], 1e-10));
+}
+
+/// Rows in exact proportion to `weights`, one per assignment of `ids` with
+/// bit `i` of the index the value of `ids[i]`.
+fn exact_rows(ids: &[&str], weights: &[f64]) -> JsValue {
+    let rows: Vec<String> = weights
+        .iter()
+        .enumerate()
+        .flat_map(|(assignment, &weight)| {
+            let row = ids
+                .iter()
+                .enumerate()
+                .map(|(i, id)| format!(r#""{id}": {}"#, assignment >> i & 1 == 1))
+                .collect::<Vec<_>>()
+                .join(", ");
+            #[allow(clippy::cast_possible_truncation, clippy::cast_sign_loss)]
+            let count = (weight * 2000.0).round() as usize;
+            std::iter::repeat_n(format!("{{{row}}}"), count)
+        })
+        .collect();
+    JSON::parse(&format!("[{}]", rows.join(","))).unwrap()
+}
+
+fn learned_parents(learned: &JsValue, id: &str) -> Vec<String> {
+    let node = Array::from(learned)
+        .iter()
+        .find(|node| get(node, "_id").as_string().as_deref() == Some(id))
+        .unwrap();
+    let entries = Array::from(&get(&node, "cptEntries"));
+    assert_eq!(entries.length(), 1);
+    let mut parents: Vec<String> =
+        js_sys::Object::keys(&get(&entries.get(0), "parentStates").into())
+            .iter()
+            .map(|key| key.as_string().unwrap())
+            .collect();
+    parents.sort();
+    parents
+}
+
+#[wasm_bindgen_test]
+fn pc_recovers_a_chain_and_a_collider() {
+    let ids = |ids: &[&str]| -> JsValue {
+        ids.iter()
+            .map(|&id| JsValue::from_str(id))
+            .collect::<Array>()
+            .into()
+    };
+    // A -> B -> C with P(A) = 0.5, P(B | A) = P(C | B) = 0.8 and 0.2.
+    let chain: Vec<f64> = (0..8)
+        .map(|assignment: usize| {
+            let (a, b, c) = (assignment & 1, assignment >> 1 & 1, assignment >> 2 & 1);
+            let step = |from, to| if from == to { 0.8 } else { 0.2 };
+            0.5 * step(a, b) * step(b, c)
+        })
+        .collect();
+    // Listing B last once tempted the orientation into A -> B <- C.
+    let learned = learn_structure(
+        exact_rows(&["A", "B", "C"], &chain),
+        ids(&["A", "C", "B"]),
+        0.05,
+    )
+    .unwrap();
+    let mut edges: Vec<[String; 2]> = ["A", "B", "C"]
+        .iter()
+        .flat_map(|&child| {
+            learned_parents(&learned, child)
+                .into_iter()
+                .map(move |parent| {
+                    let mut edge = [parent, child.to_string()];
+                    edge.sort();
+                    edge
+                })
+        })
+        .collect();
+    edges.sort();
+    assert_eq!(edges, [["A", "B"], ["B", "C"]]);
+    assert!(
+        learned_parents(&learned, "B").len() <= 1,
+        "B is no collider"
+    );
+
+    // A -> C <- B with independent fair coins and C an OR of them.
+    let collider: Vec<f64> = (0..8)
+        .map(|assignment: usize| {
+            let (a, b, c) = (assignment & 1, assignment >> 1 & 1, assignment >> 2 & 1);
+            let p_c = if a == 1 || b == 1 { 0.9 } else { 0.1 };
+            0.25 * if c == 1 { p_c } else { 1.0 - p_c }
+        })
+        .collect();
+    let learned = learn_structure(
+        exact_rows(&["A", "B", "C"], &collider),
+        ids(&["A", "B", "C"]),
+        0.05,
+    )
+    .unwrap();
+    assert_eq!(learned_parents(&learned, "C"), ["A", "B"]);
+    assert!(learned_parents(&learned, "A").is_empty());
+    assert!(learned_parents(&learned, "B").is_empty());
+}
+
+#[wasm_bindgen_test]
+fn zero_confounder_strength_collapses_the_bounds_to_the_original_effect() {
+    let bounds =
+        compute_sensitivity_to_confounding(nodes(chain(0.9)), 2000.0, "A", "B", 0.0).unwrap();
+    let original = get(&bounds, "originalAte").as_f64().unwrap();
+    assert!((original - 0.8).abs() < 0.1, "{original}");
+    assert_eq!(get(&bounds, "lowerAte").as_f64(), Some(original));
+    assert_eq!(get(&bounds, "upperAte").as_f64(), Some(original));
+}
+
+#[wasm_bindgen_test]
+fn confounding_a_node_with_too_many_entries_names_the_limit() {
+    let parents: Vec<String> = (0..7).map(|i| format!("P{i}")).collect();
+    let mut network: Vec<JsValue> = parents
+        .iter()
+        .map(|id| node(id, vec![entry("{}", 0.5)]))
+        .collect();
+    let treatment_entries = (0..1 << parents.len())
+        .map(|state: usize| {
+            let states: serde_json::Map<String, serde_json::Value> = parents
+                .iter()
+                .enumerate()
+                .map(|(i, id)| (id.clone(), (state & 1 << i != 0).into()))
+                .collect();
+            entry(&serde_json::Value::Object(states).to_string(), 0.5)
+        })
+        .collect();
+    network.push(node("X", treatment_entries));
+    network.push(node(
+        "Y",
+        vec![entry(r#"{"X": true}"#, 0.8), entry(r#"{"X": false}"#, 0.2)],
+    ));
+    let message = error_message(compute_sensitivity_to_confounding(
+        nodes(network),
+        100.0,
+        "X",
+        "Y",
+        0.5,
+    ));
+    assert!(
+        message.contains("Confounding node X splits its CPT into 256 entries, more than the 255"),
+        "{message}"
+    );
+}
+
+#[wasm_bindgen_test]
+fn relaxed_references_resolve_unless_the_entry_already_names_the_target() {
+    let nfc = "Caf\u{e9}";
+    let nfd = "Cafe\u{301}";
+    let child = |entries: Vec<JsValue>| {
+        nodes(vec![
+            node("AI Takeover", vec![entry("{}", 0.1)]),
+            node(nfc, vec![entry("{}", 0.5)]),
+            node("Outcome", entries),
+        ])
+    };
+    let strict = || options(r#"{"strictIds": true}"#);
+    let relaxed_matches = |network: JsValue| -> Vec<String> {
+        Array::from(&validate_network_wasm(network, strict()).unwrap())
+            .iter()
+            .map(|issue| get(&issue, "message").as_string().unwrap())
+            .filter(|message| message.contains("which only matches"))
+            .collect()
+    };
+
+    let relaxed = || {
+        child(vec![
+            entry(&format!(r#"{{"ai takeover": true, "{nfd}": true}}"#), 0.9),
+            entry("{}", 0.2),
+        ])
+    };
+    let matches = relaxed_matches(relaxed());
+    assert_eq!(matches.len(), 2, "{matches:?}");
+    assert!(
+        matches[1].contains(r#"parent "ai takeover", which only matches "AI Takeover""#),
+        "{matches:?}"
+    );
+    let result = resolve_relaxed_ids(relaxed()).unwrap();
+    assert_eq!(get(&result, "resolved").as_f64(), Some(2.0));
+    assert_eq!(Array::from(&get(&result, "conflicts")).length(), 0);
+    let states = get(
+        &get(&Array::from(&get(&result, "nodes")).get(2), "cptEntries"),
+        "0",
+    );
+    let states = get(&states, "parentStates");
+    assert_eq!(get(&states, "AI Takeover"), JsValue::TRUE);
+    assert_eq!(get(&states, nfc), JsValue::TRUE);
+    assert!(relaxed_matches(get(&result, "nodes")).is_empty());
+
+    let near_duplicates = nodes(vec![
+        node("AI Takeover", vec![entry("{}", 0.1)]),
+        node("AI takeover", vec![entry("{}", 0.1)]),
+        node(nfc, vec![entry("{}", 0.5)]),
+        node(nfd, vec![entry("{}", 0.5)]),
+    ]);
+    let warnings: Vec<String> =
+        Array::from(&validate_network_wasm(near_duplicates, strict()).unwrap())
+            .iter()
+            .map(|issue| get(&issue, "message").as_string().unwrap())
+            .collect();
+    assert_eq!(
+        warnings,
+        [
+            r#"Node IDs "AI Takeover" and "AI takeover" differ only by case or Unicode normalization"#
+                .to_string(),
+            format!("Node IDs {nfc:?} and {nfd:?} differ only by case or Unicode normalization"),
+        ]
+    );
+
+    // Renaming would overwrite the state the entry gives the exact ID.
+    for (relaxed_id, exact_id) in [("ai takeover", "AI Takeover"), (nfd, nfc)] {
+        let colliding = child(vec![
+            entry(
+                &format!(r#"{{"{exact_id}": true, "{relaxed_id}": false}}"#),
+                0.9,
+            ),
+            entry("{}", 0.2),
+        ]);
+        let result = resolve_relaxed_ids(colliding).unwrap();
+        assert_eq!(get(&result, "resolved").as_f64(), Some(0.0));
+        let conflicts = Array::from(&get(&result, "conflicts"));
+        assert_eq!(conflicts.length(), 1);
+        let message = get(&conflicts.get(0), "message").as_string().unwrap();
+        assert!(
+            message.contains(&format!(
+                "Node Outcome entry 0 references both {relaxed_id:?} and {exact_id:?}"
+            )),
+            "{message}"
+        );
+        let entry = get(
+            &get(&Array::from(&get(&result, "nodes")).get(2), "cptEntries"),
+            "0",
+        );
+        let states = get(&entry, "parentStates");
+        assert_eq!(get(&states, exact_id), JsValue::TRUE);
+        assert_eq!(get(&states, relaxed_id), JsValue::FALSE);
+    }
+}
+
+#[wasm_bindgen_test]
+fn self_check_compares_engines_on_the_ancestors_that_fit() {
+    let result = self_check(
+        nodes(chain(0.9)),
+        options(r#"{"numSamples": 20000, "seed": 1}"#),
+    )
+    .unwrap();
+    let report = get(&result, "report");
+    assert_eq!(get(&report, "passed"), JsValue::TRUE);
+    assert_eq!(
+        JSON::stringify(&get(&report, "subnetwork")).unwrap(),
+        r#"["A","B","C"]"#
+    );
+    assert_eq!(Array::from(&get(&report, "skippedTargets")).length(), 0);
+    assert!((get(&report, "evidenceProbability").as_f64().unwrap() - 1.0).abs() < 1e-12);
+
+    let result = self_check(
+        nodes(chain(0.9)),
+        options(
+            r#"{"numSamples": 20000, "seed": 1, "targets": ["A"],
+                "assumptions": {"evidence": {"B": true}}}"#,
+        ),
+    )
+    .unwrap();
+    let report = get(&result, "report");
+    assert_eq!(get(&report, "passed"), JsValue::TRUE);
+    // C is neither a target nor upstream of the evidence.
+    assert_eq!(
+        JSON::stringify(&get(&report, "subnetwork")).unwrap(),
+        r#"["A","B"]"#
+    );
+    // 0.3 * 0.9 + 0.7 * 0.1
+    assert!((get(&report, "evidenceProbability").as_f64().unwrap() - 0.34).abs() < 1e-6);
+    let a = Array::from(&get(&report, "nodes")).get(0);
+    assert!((get(&a, "exact").as_f64().unwrap() - 0.27 / 0.34).abs() < 1e-6);
+
+    // N19 has 19 ancestors, more than exact enumeration takes, so it is
+    // skipped while the short prefix up to N03 is still checked.
+    let ids: Vec<String> = (0..20).map(|i| format!("N{i:02}")).collect();
+    let edges: Vec<(&str, &str)> = ids
+        .windows(2)
+        .map(|pair| (pair[0].as_str(), pair[1].as_str()))
+        .collect();
+    let result = self_check(
+        dag(&edges),
+        options(r#"{"numSamples": 2000, "seed": 1, "targets": ["N03", "N19"]}"#),
+    )
+    .unwrap();
+    let report = get(&result, "report");
+    assert_eq!(
+        JSON::stringify(&get(&report, "skippedTargets")).unwrap(),
+        r#"["N19"]"#
+    );
+    assert_eq!(
+        JSON::stringify(&get(&report, "subnetwork")).unwrap(),
+        r#"["N00","N01","N02","N03"]"#
+    );
+    assert_eq!(get(&report, "passed"), JsValue::TRUE);
+}
+
+#[wasm_bindgen_test]
+fn annealed_log_evidence_matches_enumeration() {
+    // P(B) = 0.3 * 0.9 + 0.7 * 0.1, P(not A, B) = 0.7 * 0.1, and C is a
+    // fair coin whatever B is.
+    for (evidence, probability) in [
+        (r#"{"B": true}"#, 0.34),
+        (r#"{"A": false, "B": true}"#, 0.07),
+        (r#"{"B": true, "C": false}"#, 0.17),
+    ] {
+        let log_evidence = compute_log_evidence(nodes(chain(0.9)), options(evidence), 1000.0, 50.0)
+            .unwrap()
+            .as_f64()
+            .unwrap();
+        // The estimate's standard deviation is at most about 0.04 here, and
+        // the seed is not fixed.
+        assert!(
+            (log_evidence - f64::ln(probability)).abs() < 0.2,
+            "{evidence}: {log_evidence}"
+        );
+    }
+}
+
+#[wasm_bindgen_test]
+fn monotonicity_names_the_parent_context_that_flips_the_effect() {
+    // P(Y | X, Z) by (X, Z); Z is X's only parent.
+    let network = |table: [f64; 4]| {
+        nodes(vec![
+            node("Z", vec![entry("{}", 0.7)]),
+            node(
+                "X",
+                vec![entry(r#"{"Z": true}"#, 0.6), entry(r#"{"Z": false}"#, 0.4)],
+            ),
+            node(
+                "Y",
+                vec![
+                    entry(r#"{"X": true, "Z": true}"#, table[0]),
+                    entry(r#"{"X": false, "Z": true}"#, table[1]),
+                    entry(r#"{"X": true, "Z": false}"#, table[2]),
+                    entry(r#"{"X": false, "Z": false}"#, table[3]),
+                ],
+            ),
+        ])
+    };
+
+    let result = check_monotonicity(network([0.9, 0.5, 0.6, 0.2]), 20000.0, "X", "Y").unwrap();
+    assert_eq!(get(&result, "isMonotone"), JsValue::TRUE);
+    assert!((get(&result, "fractionMonotone").as_f64().unwrap() - 1.0).abs() < 1e-12);
+    assert!(get(&result, "violatingContext").is_null());
+
+    // X raises Y by 0.4 when Z is true but lowers it by 0.5 when Z is false,
+    // which happens 30% of the time.
+    let result = check_monotonicity(network([0.9, 0.5, 0.2, 0.7]), 20000.0, "X", "Y").unwrap();
+    assert_eq!(get(&result, "isMonotone"), JsValue::FALSE);
+    let fraction = get(&result, "fractionMonotone").as_f64().unwrap();
+    assert!((fraction - 0.7).abs() < 0.02, "{fraction}");
+    assert_eq!(
+        JSON::stringify(&get(&result, "violatingContext")).unwrap(),
+        r#"{"Z":false}"#
+    );
+}
+
+/// `(severity, nodeId, message)` of each issue found by the check named
+/// `check` alone when `only`, or by every other default check otherwise.
+/// A network-wide issue has an empty `nodeId`.
+fn validation_issues(network: JsValue, check: &str, only: bool) -> Vec<(String, String, String)> {
+    let checks = [
+        "references",
+        "probabilities",
+        "cptCompleteness",
+        "cycle",
+        "fixedParents",
+    ];
+    let toggles: Vec<String> = checks
+        .iter()
+        .map(|&name| format!(r#""{name}": {}"#, (name == check) == only))
+        .collect();
+    let toggles = options(&format!("{{{}}}", toggles.join(", ")));
+    Array::from(&validate_network_wasm(network, toggles).unwrap())
+        .iter()
+        .map(|issue| {
+            (
+                get(&issue, "severity").as_string().unwrap(),
+                get(&issue, "nodeId").as_string().unwrap_or_default(),
+                get(&issue, "message").as_string().unwrap(),
+            )
+        })
+        .collect()
+}
+
+fn issue(severity: &str, node_id: &str, message: &str) -> (String, String, String) {
+    (severity.into(), node_id.into(), message.into())
+}
+
+#[wasm_bindgen_test]
+fn reference_check_reports_duplicates_and_missing_parents() {
+    let network = || {
+        nodes(vec![
+            node("A", vec![entry("{}", 0.5)]),
+            node("A", vec![entry("{}", 0.5)]),
+            node(
+                "B",
+                vec![entry(r#"{"Ghost": true}"#, 0.5), entry("{}", 0.1)],
+            ),
+        ])
+    };
+    let found = validation_issues(network(), "references", true);
+    assert_eq!(
+        found,
+        [
+            issue("error", "A", "Duplicate node ID A"),
+            issue(
+                "error",
+                "B",
+                "Node B references parent Ghost which is not in the node array"
+            ),
+        ]
+    );
+    let others = validation_issues(network(), "references", false);
+    assert!(
+        found.iter().all(|issue| !others.contains(issue)),
+        "{others:?}"
+    );
+}
+
+#[wasm_bindgen_test]
+fn probability_check_reports_bad_values_and_entries_outside_the_bounds() {
+    let network = || {
+        let bounded = node("B", vec![entry("{}", 0.1)]);
+        set(
+            bounded.unchecked_ref(),
+            "probabilityFloor",
+            &JsValue::from_f64(0.2),
+        );
+        nodes(vec![
+            node("A", vec![entry("{}", 1.5)]),
+            bounded,
+            node("C", vec![entry("{}", 1e-40)]),
+        ])
+    };
+    let found = validation_issues(network(), "probabilities", true);
+    assert_eq!(
+        found,
+        [
+            issue(
+                "error",
+                "A",
+                "Node A CPT entry 0 has probability 1.5, outside [0, 1]"
+            ),
+            issue(
+                "warning",
+                "B",
+                "Node B CPT entry 0 has P(true) 0.1, outside the node's own floor and \
+                 ceiling; it will be sampled as 0.2"
+            ),
+            issue(
+                "error",
+                "C",
+                "Node C CPT entry 0 has probability 1e-40, which is too small to represent; \
+                 use 0 instead"
+            ),
+        ]
+    );
+    let others = validation_issues(network(), "probabilities", false);
+    assert!(
+        found.iter().all(|issue| !others.contains(issue)),
+        "{others:?}"
+    );
+}
+
+#[wasm_bindgen_test]
+fn completeness_check_reports_empty_and_partial_tables() {
+    let network = || {
+        nodes(vec![
+            node("A", vec![entry("{}", 0.5)]),
+            node("B", vec![entry(r#"{"A": true}"#, 0.5)]),
+            node("C", vec![]),
+        ])
+    };
+    let found = validation_issues(network(), "cptCompleteness", true);
+    assert_eq!(
+        found,
+        [
+            issue(
+                "error",
+                "B",
+                "Node B has no CPT entry for some parent states, e.g. {A=false}; \
+                 suggest_cpt_completion can propose them"
+            ),
+            issue(
+                "error",
+                "C",
+                "Node C has no CPT entries; add a default probability, an entry with no \
+                 parent states"
+            ),
+        ]
+    );
+    let others = validation_issues(network(), "cptCompleteness", false);
+    assert!(
+        found.iter().all(|issue| !others.contains(issue)),
+        "{others:?}"
+    );
+}
+
+#[wasm_bindgen_test]
+fn cycle_check_names_the_nodes_on_or_below_the_cycle() {
+    let network = || dag(&[("A", "B"), ("B", "A"), ("B", "C"), ("R", "A")]);
+    let found = validation_issues(network(), "cycle", true);
+    assert_eq!(
+        found,
+        [issue(
+            "error",
+            "",
+            "Cycle detected in Bayesian network; nodes on or downstream of it: {A, B, C}"
+        )]
+    );
+    let others = validation_issues(network(), "cycle", false);
+    assert!(
+        found.iter().all(|issue| !others.contains(issue)),
+        "{others:?}"
+    );
+}
+
+#[wasm_bindgen_test]
+fn probability_bounds_move_sampled_and_exact_marginals_alike() {
+    let network = |floor: f64, ceiling: f64| {
+        let child = node(
+            "B",
+            vec![entry(r#"{"A": true}"#, 0.9), entry(r#"{"A": false}"#, 0.05)],
+        );
+        set(
+            child.unchecked_ref(),
+            "probabilityFloor",
+            &JsValue::from_f64(floor),
+        );
+        set(
+            child.unchecked_ref(),
+            "probabilityCeiling",
+            &JsValue::from_f64(ceiling),
+        );
+        nodes(vec![node("A", vec![entry("{}", 0.3)]), child])
+    };
+    // Without bounds P(B) = 0.3 * 0.9 + 0.7 * 0.05 = 0.305.
+    for (floor, ceiling, expected) in [
+        (0.0, 1.0, 0.305),
+        (0.2, 1.0, 0.3 * 0.9 + 0.7 * 0.2),
+        (0.2, 0.5, 0.3 * 0.5 + 0.7 * 0.2),
+    ] {
+        let result = self_check(
+            network(floor, ceiling),
+            options(r#"{"numSamples": 20000, "seed": 2}"#),
+        )
+        .unwrap();
+        let report = get(&result, "report");
+        assert_eq!(get(&report, "passed"), JsValue::TRUE);
+        let b = Array::from(&get(&report, "nodes")).get(1);
+        assert!((get(&b, "exact").as_f64().unwrap() - expected).abs() < 1e-6);
+        assert!((get(&b, "sampled").as_f64().unwrap() - expected).abs() < 0.02);
+    }
+
+    let issues = validation_issues(network(0.2, 1.0), "probabilities", true);
+    assert_eq!(
+        issues,
+        [issue(
+            "warning",
+            "B",
+            "Node B CPT entry 1 has P(true) 0.05, outside the node's own floor and ceiling; \
+             it will be sampled as 0.2"
+        )]
+    );
+
+    for (floor, ceiling, expected) in [
+        (
+            0.6,
+            0.4,
+            "Node B has probability floor 0.6 above its ceiling 0.4",
+        ),
+        (1.5, 1.0, "Node B has probability floor 1.5, outside [0, 1]"),
+        (
+            0.0,
+            -0.1,
+            "Node B has probability ceiling -0.1, outside [0, 1]",
+        ),
+    ] {
+        let message = error_message(compute_marginals(network(floor, ceiling), 100.0, None));
+        assert!(message.contains(expected), "{message}");
+        let issues = validation_issues(network(floor, ceiling), "probabilities", true);
+        assert_eq!(issues, [issue("error", "B", expected)]);
+    }
+}
+
+#[wasm_bindgen_test]
+fn effect_percentiles_of_deterministic_outcomes_are_exact() {
+    let network = nodes(vec![
+        node("X", vec![entry("{}", 0.5)]),
+        node("U", vec![entry("{}", 0.8)]),
+        // Copies X, so every unit's effect is 1.
+        node(
+            "Same",
+            vec![entry(r#"{"X": true}"#, 1.0), entry(r#"{"X": false}"#, 0.0)],
+        ),
+        // Negates X: every effect is -1.
+        node(
+            "Opposite",
+            vec![entry(r#"{"X": true}"#, 0.0), entry(r#"{"X": false}"#, 1.0)],
+        ),
+        // Ignores X: every effect is 0.
+        node("Unrelated", vec![entry("{}", 0.5)]),
+        // Copies X for the 80% of units with U and negates it otherwise.
+        node(
+            "Mixed",
+            vec![
+                entry(r#"{"U": true, "X": true}"#, 1.0),
+                entry(r#"{"U": true, "X": false}"#, 0.0),
+                entry(r#"{"U": false, "X": true}"#, 0.0),
+                entry(r#"{"U": false, "X": false}"#, 1.0),
+            ],
+        ),
+    ]);
+    let outcomes: Array = ["Same", "Opposite", "Unrelated", "Mixed"]
+        .iter()
+        .map(|&id| JsValue::from_str(id))
+        .collect();
+    let percentiles: Array = [0.0, 50.0, 100.0]
+        .iter()
+        .map(|&q| JsValue::from_f64(q))
+        .collect();
+    let result =
+        compute_effect_percentiles(network, 1000.0, "X", outcomes.into(), percentiles.into())
+            .unwrap();
+    let values = |id: &str| -> Vec<f64> {
+        Array::from(&get(&result, id))
+            .iter()
+            .map(|value| value.as_f64().unwrap())
+            .collect()
+    };
+    assert_eq!(values("Same"), [1.0, 1.0, 1.0]);
+    assert_eq!(values("Opposite"), [-1.0, -1.0, -1.0]);
+    assert_eq!(values("Unrelated"), [0.0, 0.0, 0.0]);
+    // The smallest, middle and largest of the per-unit effects.
+    assert_eq!(values("Mixed"), [-1.0, 1.0, 1.0]);
+}
+
+#[wasm_bindgen_test]
+fn csv_parameters_are_row_frequencies_over_the_skeleton() {
+    let skeleton = || {
+        let hierarchical = entry("{}", 0.0);
+        set(
+            hierarchical.unchecked_ref(),
+            "probabilityParams",
+            &JSON::parse(r#"{ "hyperparameterId": "H", "pHigh": 0.9, "pLow": 0.1 }"#).unwrap(),
+        );
+        nodes(vec![
+            node("A", vec![entry("{}", 0.5)]),
+            node(
+                "B",
+                vec![entry(r#"{"A": true}"#, 0.5), entry(r#"{"A": false}"#, 0.33)],
+            ),
+            node("C", vec![entry("{}", 0.2)]),
+            node("H", vec![entry("{}", 0.5)]),
+            node("X", vec![hierarchical]),
+        ])
+    };
+    let learn = |csv: &str| learn_parameters_from_csv_string(skeleton(), &JsValue::from_str(csv));
+    let probability = |nodes: &JsValue, node: u32, entry: u32| {
+        let entries = Array::from(&get(&Array::from(nodes).get(node), "cptEntries"));
+        get(&entries.get(entry), "probability").as_f64().unwrap()
+    };
+
+    // No row has A false, and C has no column; Extra is ignored. B is missing
+    // on the last row, which still counts for A.
+    let csv = "A,B,Extra,H,X\n\
+               true,true,1,1,1\n\
+               1,1,0,1,1\n\
+               true,false,0,0,0\n\
+               \"true\",1,0,1,1\n\
+               true,?,1,0,1\n";
+    let learned = learn(csv).unwrap();
+    assert!((probability(&learned, 0, 0) - 1.0).abs() < 1e-12);
+    assert!((probability(&learned, 1, 0) - 0.75).abs() < 1e-12);
+    assert!((probability(&learned, 1, 1) - 0.33).abs() < 1e-12);
+    assert!((probability(&learned, 2, 0) - 0.2).abs() < 1e-12);
+    assert!((probability(&learned, 3, 0) - 0.6).abs() < 1e-12);
+    let entries = Array::from(&get(&Array::from(&learned).get(4), "cptEntries"));
+    let params = get(&entries.get(0), "probabilityParams");
+    assert_eq!(get(&params, "pHigh").as_f64(), Some(0.9));
+    assert_eq!(get(&params, "pLow").as_f64(), Some(0.1));
+
+    let message = error_message(learn("A,B\ntrue,false\n\ntrue\n"));
+    assert!(
+        message.contains("CSV line 4 has 1 cells but the header has 2"),
+        "{message}"
+    );
+    let message = error_message(learn("A,B\ntrue,yes\n"));
+    assert!(
+        message.contains(r#"CSV line 2 has value "yes"; expected true, false, 1, 0 or ?"#),
+        "{message}"
+    );
+}
+
+#[wasm_bindgen_test]
+fn proportional_fitting_reaches_the_targets_or_says_it_did_not() {
+    let result = fit_marginals(
+        nodes(chain(0.9)),
+        options(r#"{"A": 0.5, "C": 0.7}"#),
+        50000.0,
+        20.0,
+        0.02,
+    )
+    .unwrap();
+    assert!(get(&result, "iterations").as_f64().unwrap() >= 1.0);
+    let fitted = compute_marginals_with_options(
+        get(&result, "nodes"),
+        options(r#"{"numSamples": 50000, "seed": 4}"#),
+    )
+    .unwrap();
+    let marginals = get(&fitted, "marginals");
+    assert!((marginal(&marginals, "A") - 0.5).abs() < 0.03);
+    assert!((marginal(&marginals, "C") - 0.7).abs() < 0.03);
+    // Only the targeted nodes' entries change.
+    let b = Array::from(&get(
+        &Array::from(&get(&result, "nodes")).get(1),
+        "cptEntries",
+    ));
+    assert!((get(&b.get(0), "probability").as_f64().unwrap() - 0.9).abs() < 1e-12);
+
+    // A certain node cannot be rescaled.
+    let certain = nodes(vec![node("A", vec![entry("{}", 1.0)])]);
+    let message = error_message(fit_marginals(
+        certain,
+        options(r#"{"A": 0.5}"#),
+        1000.0,
+        3.0,
+        0.02,
+    ));
+    assert!(
+        message.contains("Marginals did not converge within 3 iterations (largest gap 0.5)"),
+        "{message}"
+    );
+}
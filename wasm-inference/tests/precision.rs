@@ -0,0 +1,207 @@
+//! Sampling accuracy against exact answers: each network is small enough to
+//! enumerate, so the marginals `compute_marginals` estimates from 100,000
+//! samples must land within 0.01 of the enumerated ones, with and without an
+//! intervention. A regression in `sample()`, in `serialize_network()`'s
+//! topological order, or in CPT pattern matching shows up here as a miss far
+//! outside sampling noise (the standard error is at most 0.0016).
+//!
+//! Run with `cargo test --target wasm32-unknown-unknown`.
+#![cfg(target_arch = "wasm32")]
+
+use js_sys::{Array, Map, Object, Reflect};
+use wasm_bindgen::{JsCast, JsValue};
+use wasm_bindgen_test::wasm_bindgen_test;
+use wasm_inference::compute_marginals;
+
+const NUM_SAMPLES: f64 = 100_000.0;
+const TOLERANCE: f64 = 0.01;
+
+/// A node with a full table: `table[i]` is `P(true)` when bit `j` of `i`
+/// gives the state of `parents[j]`.
+struct Cpt {
+    id: &'static str,
+    parents: Vec<&'static str>,
+    table: Vec<f64>,
+}
+
+fn root(id: &'static str, p: f64) -> Cpt {
+    Cpt {
+        id,
+        parents: Vec::new(),
+        table: vec![p],
+    }
+}
+
+fn child(id: &'static str, parents: Vec<&'static str>, table: Vec<f64>) -> Cpt {
+    assert_eq!(table.len(), 1 << parents.len(), "{id}");
+    Cpt { id, parents, table }
+}
+
+fn set(target: &Object, key: &str, value: &JsValue) {
+    Reflect::set(target, &JsValue::from_str(key), value).unwrap();
+}
+
+/// The network as the JS payload, listed in reverse so the sampler has to
+/// order it itself.
+fn payload(network: &[Cpt]) -> JsValue {
+    network
+        .iter()
+        .rev()
+        .map(|cpt| {
+            let entries: Array = cpt
+                .table
+                .iter()
+                .enumerate()
+                .map(|(assignment, &p)| {
+                    let parent_states = Object::new();
+                    for (j, parent) in cpt.parents.iter().enumerate() {
+                        let state = assignment & (1 << j) != 0;
+                        set(&parent_states, parent, &JsValue::from_bool(state));
+                    }
+                    let entry = Object::new();
+                    set(&entry, "parentStates", &parent_states);
+                    set(&entry, "probability", &JsValue::from_f64(p));
+                    JsValue::from(entry)
+                })
+                .collect();
+            let node = Object::new();
+            set(&node, "_id", &JsValue::from_str(cpt.id));
+            set(&node, "cptEntries", &entries);
+            JsValue::from(node)
+        })
+        .collect::<Array>()
+        .into()
+}
+
+/// `P(node = true)` for every node, by enumerating every joint assignment,
+/// with `intervention` replacing its node's CPT by the fixed value.
+fn exact_marginals(network: &[Cpt], intervention: Option<(&str, bool)>) -> Vec<f64> {
+    let index = |id: &str| network.iter().position(|cpt| cpt.id == id).unwrap();
+    let mut marginals = vec![0.0; network.len()];
+    for joint in 0..1usize << network.len() {
+        let is_true = |node: usize| joint & (1 << node) != 0;
+        let probability: f64 = network
+            .iter()
+            .enumerate()
+            .map(|(node, cpt)| {
+                let p_true = match intervention {
+                    Some((id, value)) if id == cpt.id => f64::from(u8::from(value)),
+                    _ => {
+                        let assignment = cpt
+                            .parents
+                            .iter()
+                            .enumerate()
+                            .filter(|&(_, parent)| is_true(index(parent)))
+                            .fold(0, |assignment, (j, _)| assignment | 1 << j);
+                        cpt.table[assignment]
+                    }
+                };
+                if is_true(node) { p_true } else { 1.0 - p_true }
+            })
+            .product();
+        for (node, marginal) in marginals.iter_mut().enumerate() {
+            if is_true(node) {
+                *marginal += probability;
+            }
+        }
+    }
+    marginals
+}
+
+fn assert_close(name: &str, network: &[Cpt], estimated: &JsValue, exact: &[f64]) {
+    let estimated = estimated
+        .dyn_ref::<Map>()
+        .expect("marginals are returned as a Map");
+    for (cpt, &exact) in network.iter().zip(exact) {
+        let estimate = estimated.get(&JsValue::from_str(cpt.id)).as_f64().unwrap();
+        assert!(
+            (estimate - exact).abs() < TOLERANCE,
+            "{name}: P({id}) estimated {estimate}, exactly {exact}",
+            id = cpt.id
+        );
+    }
+}
+
+/// Checks the plain marginals, then `do(intervened = true)` and
+/// `do(intervened = false)`.
+fn check(name: &str, network: &[Cpt], intervened: &str) {
+    let estimated = compute_marginals(payload(network), NUM_SAMPLES, None).unwrap();
+    assert_close(name, network, &estimated, &exact_marginals(network, None));
+
+    let result =
+        compute_marginals(payload(network), NUM_SAMPLES, Some(intervened.to_string())).unwrap();
+    for (key, value) in [("trueCase", true), ("falseCase", false)] {
+        let arm = Reflect::get(&result, &JsValue::from_str(key)).unwrap();
+        let exact = exact_marginals(network, Some((intervened, value)));
+        assert_close(
+            &format!("{name}, do({intervened}={value})"),
+            network,
+            &arm,
+            &exact,
+        );
+    }
+}
+
+#[wasm_bindgen_test]
+fn two_node_marginals_match_enumeration() {
+    let network = [root("A", 0.3), child("B", vec!["A"], vec![0.2, 0.9])];
+    check("two-node", &network, "A");
+}
+
+#[wasm_bindgen_test]
+fn chain_marginals_match_enumeration() {
+    let network = [
+        root("A", 0.6),
+        child("B", vec!["A"], vec![0.1, 0.7]),
+        child("C", vec!["B"], vec![0.25, 0.95]),
+        child("D", vec!["C"], vec![0.5, 0.05]),
+    ];
+    check("chain", &network, "B");
+}
+
+#[wasm_bindgen_test]
+fn diamond_marginals_match_enumeration() {
+    let network = [
+        root("A", 0.4),
+        child("B", vec!["A"], vec![0.2, 0.8]),
+        child("C", vec!["A"], vec![0.6, 0.3]),
+        child("D", vec!["B", "C"], vec![0.05, 0.5, 0.6, 0.99]),
+    ];
+    check("diamond", &network, "B");
+}
+
+#[wasm_bindgen_test]
+fn collider_marginals_match_enumeration() {
+    let network = [
+        root("A", 0.35),
+        root("B", 0.55),
+        child("C", vec!["A", "B"], vec![0.02, 0.7, 0.4, 0.97]),
+        child("D", vec!["C"], vec![0.15, 0.85]),
+    ];
+    check("collider", &network, "C");
+}
+
+/// The Asia network of Lauritzen and Spiegelhalter (1988), with `Either`
+/// the deterministic OR of tuberculosis and lung cancer.
+#[wasm_bindgen_test]
+fn asia_marginals_match_enumeration() {
+    let network = [
+        root("Asia", 0.01),
+        root("Smoking", 0.5),
+        child("Tuberculosis", vec!["Asia"], vec![0.01, 0.05]),
+        child("LungCancer", vec!["Smoking"], vec![0.01, 0.1]),
+        child("Bronchitis", vec!["Smoking"], vec![0.3, 0.6]),
+        child(
+            "Either",
+            vec!["Tuberculosis", "LungCancer"],
+            vec![0.0, 1.0, 1.0, 1.0],
+        ),
+        child("XRay", vec!["Either"], vec![0.05, 0.98]),
+        child(
+            "Dyspnoea",
+            vec!["Either", "Bronchitis"],
+            vec![0.1, 0.7, 0.8, 0.9],
+        ),
+    ];
+    check("asia", &network, "Smoking");
+}